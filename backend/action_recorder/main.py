@@ -5,6 +5,8 @@ import base64
 import torch
 import pandas as pd
 import gzip
+import threading
+import unicodedata
 from flask import Flask, request, jsonify
 from PIL import Image
 from cryptography.hazmat.primitives import hashes
@@ -12,6 +14,7 @@ from cryptography.hazmat.primitives.kdf.pbkdf2 import PBKDF2HMAC
 from cryptography.fernet import Fernet
 
 # Import your actual utility functions – ensure they work with a PIL image.
+import util.utils as omni_utils
 from util.utils import get_som_labeled_img, check_ocr_box, get_caption_model_processor, get_yolo_model
 
 
@@ -36,6 +39,90 @@ print(f"Models loaded on {device}")
 
 DEFAULT_ENCRYPTION_PASSWORD = "applebear"
 
+# ---------------------------------------------------------------------------
+# OCR Language Packs
+# ---------------------------------------------------------------------------
+# util.utils builds English-only OCR readers at import time. Requests may carry a
+# language hint (BCP 47, e.g. "ja", "zh-TW", "ar"); the matching readers are built
+# once, cached, and swapped in for that request. METIS_OCR_LANGUAGES preloads
+# packs at startup (comma-separated) so the first request isn't slow.
+
+# Hint -> (PaddleOCR lang, EasyOCR languages). EasyOCR always keeps English as
+# well, since UIs mix it into every script.
+OCR_LANGUAGE_PACKS = {
+    "en": ("en", ["en"]),
+    "zh": ("ch", ["ch_sim", "en"]),
+    "zh-cn": ("ch", ["ch_sim", "en"]),
+    "zh-tw": ("chinese_cht", ["ch_tra", "en"]),
+    "ja": ("japan", ["ja", "en"]),
+    "ko": ("korean", ["ko", "en"]),
+    "ar": ("arabic", ["ar", "en"]),
+    "fa": ("fa", ["fa", "en"]),
+    "ur": ("ur", ["ur", "en"]),
+    "he": ("en", ["en"]),  # Neither engine ships Hebrew; English keeps Latin UI text readable
+    "ru": ("ru", ["ru", "en"]),
+    "uk": ("uk", ["uk", "en"]),
+    "hi": ("hi", ["hi", "en"]),
+    "th": ("th", ["th", "en"]),
+    "el": ("el", ["en"]),
+    "fr": ("fr", ["fr", "en"]),
+    "de": ("german", ["de", "en"]),
+    "es": ("es", ["es", "en"]),
+    "pt": ("pt", ["pt", "en"]),
+    "it": ("it", ["it", "en"]),
+}
+RTL_LANGUAGES = {"ar", "fa", "ur", "he"}
+
+_ocr_readers = {}  # pack key -> (paddle reader, easyocr reader)
+_ocr_lock = threading.Lock()  # The readers are module globals in util.utils
+
+
+def language_pack_key(hint):
+    """Normalizes a language hint to a key of OCR_LANGUAGE_PACKS (English if unknown)."""
+    if not hint:
+        return "en"
+    hint = hint.strip().lower().replace("_", "-")
+    if hint in OCR_LANGUAGE_PACKS:
+        return hint
+    base = hint.split("-")[0]
+    return base if base in OCR_LANGUAGE_PACKS else "en"
+
+
+def ocr_readers(pack_key):
+    """PaddleOCR and EasyOCR readers for a language pack, built on first use."""
+    if pack_key not in _ocr_readers:
+        if pack_key == "en":
+            _ocr_readers[pack_key] = (omni_utils.paddle_ocr, omni_utils.reader)
+        else:
+            import easyocr
+            from paddleocr import PaddleOCR
+            paddle_lang, easyocr_langs = OCR_LANGUAGE_PACKS[pack_key]
+            print(f"Loading OCR language pack '{pack_key}' (paddle: {paddle_lang}, easyocr: {easyocr_langs})")
+            _ocr_readers[pack_key] = (
+                PaddleOCR(lang=paddle_lang, use_angle_cls=False, use_gpu=device.type == "cuda", show_log=False),
+                easyocr.Reader(easyocr_langs, gpu=device.type == "cuda"),
+            )
+    return _ocr_readers[pack_key]
+
+
+def is_rtl_text(text):
+    """Whether most letters in `text` belong to a right-to-left script."""
+    letters = [c for c in text if c.isalpha()]
+    rtl = sum(1 for c in letters if unicodedata.bidirectional(c) in ("R", "AL"))
+    return bool(letters) and rtl * 2 > len(letters)
+
+
+def to_logical_order(texts, pack_key, use_paddleocr):
+    """PaddleOCR reads right-to-left scripts in visual (left-to-right) order;
+    reverse those lines so content is stored in reading order."""
+    if not use_paddleocr or pack_key not in RTL_LANGUAGES:
+        return texts
+    return [t[::-1] if is_rtl_text(t) else t for t in texts]
+
+
+for _hint in filter(None, os.environ.get("METIS_OCR_LANGUAGES", "").split(",")):
+    ocr_readers(language_pack_key(_hint))
+
 # ---------------------------------------------------------------------------
 # Encryption Helper Functions
 # ---------------------------------------------------------------------------
@@ -53,12 +140,13 @@ def encrypt_data(data: bytes, password: str, salt: bytes) -> bytes:
 # ---------------------------------------------------------------------------
 # Updated Image Processing Function (in-memory, no temporary file)
 # ---------------------------------------------------------------------------
-def process_image_file(image_input, box_threshold, iou_threshold, use_paddleocr, imgsz, icon_process_batch_size):
+def process_image_file(image_input, box_threshold, iou_threshold, use_paddleocr, imgsz, icon_process_batch_size, language=None):
     """
     Processes the input PIL image entirely in memory.
     1. Converts the image to RGB if necessary.
     2. Uses an in-memory buffer to avoid writing a temporary file.
-    3. Passes a PIL image to the OCR and object detection functions.
+    3. Passes a PIL image to the OCR and object detection functions, reading
+       text with the language pack for `language` (a BCP 47 hint, English if unset).
     Returns:
       - parsed_content_list: List of dicts (with fields like bounding boxes, interactivity, etc.)
       - parsed_content_str: A text summary of parsed content.
@@ -76,16 +164,20 @@ def process_image_file(image_input, box_threshold, iou_threshold, use_paddleocr,
     image_for_processing = Image.open(buffer)
     
     # Run OCR (assumes check_ocr_box now accepts a PIL image).
+    pack_key = language_pack_key(language)
     try:
-        ocr_bbox_rslt, _ = check_ocr_box(
-            image_for_processing,
-            display_img=False,
-            output_bb_format="xyxy",
-            goal_filtering=None,
-            easyocr_args={'paragraph': False, 'text_threshold': 0.9},
-            use_paddleocr=use_paddleocr,
-        )
+        with _ocr_lock:
+            omni_utils.paddle_ocr, omni_utils.reader = ocr_readers(pack_key)
+            ocr_bbox_rslt, _ = check_ocr_box(
+                image_for_processing,
+                display_img=False,
+                output_bb_format="xyxy",
+                goal_filtering=None,
+                easyocr_args={'paragraph': False, 'text_threshold': 0.9},
+                use_paddleocr=use_paddleocr,
+            )
         ocr_text, ocr_bbox = ocr_bbox_rslt
+        ocr_text = to_logical_order(ocr_text, pack_key, use_paddleocr)
     except Exception as e:
         print("Error during OCR:", e)
         raise e
@@ -127,6 +219,18 @@ def process_image_file(image_input, box_threshold, iou_threshold, use_paddleocr,
 # Flask App Setup – In-memory processing (no temporary file saving)
 # ---------------------------------------------------------------------------
 app = Flask(__name__)
+app.json.ensure_ascii = False  # Send non-Latin content as UTF-8, not \u escapes
+
+BACKEND_VERSION = "1.0"  # Reported by /health; bump when the processImage contract changes
+
+@app.route("/health", methods=["GET"])
+def api_health():
+    """
+    Endpoint: GET /health
+    Readiness probe for the Metis app. Models load before the server starts
+    listening, so any answer means processImage requests can be served.
+    """
+    return jsonify({"status": "ok", "version": BACKEND_VERSION, "device": device.type})
 
 @app.route("/api/processImage", methods=["POST"])
 def api_process_image():
@@ -135,11 +239,14 @@ def api_process_image():
     Accepts:
       - multipart/form-data with key 'image'
       - or JSON with key 'image' containing a base64-encoded image string.
+      - optional 'language' (form field or JSON key): BCP 47 hint for OCR.
     Returns a JSON object with:
       - parsed_content: Text summary of parsed content.
       - parsed_content_table: An HTML table representation.
     """
+    language = None
     if 'image' in request.files:
+        language = request.form.get("language")
         file = request.files['image']
         try:
             image_input = Image.open(file)
@@ -148,6 +255,7 @@ def api_process_image():
     else:
         data = request.get_json(silent=True) or {}
         image_b64 = data.get("image")
+        language = data.get("language")
         if not image_b64:
             return jsonify({"error": "No image provided"}), 400
         try:
@@ -166,7 +274,7 @@ def api_process_image():
     start = time.time()
     try:
         parsed_content_list, parsed_content_str = process_image_file(
-            image_input, box_threshold, iou_threshold, use_paddleocr, imgsz, icon_process_batch_size
+            image_input, box_threshold, iou_threshold, use_paddleocr, imgsz, icon_process_batch_size, language
         )
     except Exception as e:
         return jsonify({"error": f"Processing error: {str(e)}"}), 500
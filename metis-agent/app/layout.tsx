@@ -5,6 +5,7 @@ import { TopBar } from "@/components/layout/topbar";
 import { ThemeProvider } from "@/components/theme-provider";
 import { MindMapProvider } from "@/components/MindMapContext";
 import { RecordingProvider } from "@/components/RecordingContext";
+import { StatusAnnouncer } from "@/components/StatusAnnouncer";
 import React from "react";
 
 export const metadata: Metadata = {
@@ -19,6 +20,7 @@ export default function RootLayout({ children }: { children: React.ReactNode })
         <ThemeProvider attribute="class" defaultTheme="system" enableSystem disableTransitionOnChange>
           <MindMapProvider>
             <RecordingProvider>
+              <StatusAnnouncer />
               <div className="flex h-screen w-screen">
                 <Sidebar />
                 <div className="flex flex-col flex-1">
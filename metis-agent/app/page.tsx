@@ -20,6 +20,7 @@ import { useRecordingContext } from "@/components/RecordingContext"; // Ensure t
 import { automationManager } from "@/lib/automation-manager";
 import { actionAnalyzer } from "@/lib/action-analyzer";
 import { invoke } from "@tauri-apps/api/core"; // Ensure invoke is imported
+import { listen } from "@tauri-apps/api/event";
 import { actionExecutor } from "@/lib/action-executor"; // Assuming this might be used elsewhere, keep if needed
 
 // Register Chart.js components.
@@ -38,6 +39,60 @@ export default function DashboardPage() {
   const [activeAutomations, setActiveAutomations] = useState<any[]>([]);
   const [suggestions, setSuggestions] = useState<any[]>([]);
   const [isCommandLoading, setIsCommandLoading] = useState(false);
+  // The agent's current <think> text, streamed while the LLM answers
+  const [thought, setThought] = useState("");
+  // What the model is shown of the screen for the next task
+  const [vision, setVision] = useState<"off" | "screenshot" | "both">("off");
+  // Dry run: the agent plans as usual but only reports what it would do
+  const [dryRun, setDryRun] = useState(false);
+  // Latest step of the running task, from the task://action-executed events
+  const [progress, setProgress] = useState<{ iteration: number; action: string; success: boolean; error: string | null } | null>(null);
+  // A running task frozen with pause_task, waiting for resume_task
+  const [paused, setPaused] = useState(false);
+  const [plannedActions, setPlannedActions] = useState<{ iteration: number; action: string; description: string }[]>([]);
+  // Tail of the backend log file (get_recent_logs), shown on demand
+  const [logLines, setLogLines] = useState<string[] | null>(null);
+
+  useEffect(() => {
+    let unlisten: (() => void) | undefined;
+    let iteration = -1;
+    listen<{ iteration: number; text: string; done: boolean }>("agent://thought-chunk", (event) => {
+      const { iteration: current, text } = event.payload;
+      // A new loop iteration starts a new thought
+      const continuing = current === iteration;
+      iteration = current;
+      setThought((previous) => (continuing ? previous + text : text));
+    })
+      .then((fn) => {
+        unlisten = fn;
+      })
+      .catch((err) => console.error("Failed to listen for agent thoughts:", err));
+    return () => unlisten?.();
+  }, []);
+
+  useEffect(() => {
+    let unlisten: (() => void) | undefined;
+    listen<{ iteration: number; action: string; description: string; thought: string }>("agent://planned-action", (event) => {
+      setPlannedActions((previous) => [...previous, event.payload]);
+    })
+      .then((fn) => {
+        unlisten = fn;
+      })
+      .catch((err) => console.error("Failed to listen for planned actions:", err));
+    return () => unlisten?.();
+  }, []);
+
+  useEffect(() => {
+    let unlisten: (() => void) | undefined;
+    listen<{ iteration: number; action: string; success: boolean; error: string | null }>("task://action-executed", (event) => {
+      setProgress(event.payload);
+    })
+      .then((fn) => {
+        unlisten = fn;
+      })
+      .catch((err) => console.error("Failed to listen for task progress:", err));
+    return () => unlisten?.();
+  }, []);
 
   // Fetch initial data
   useEffect(() => {
@@ -102,12 +157,26 @@ export default function DashboardPage() {
     }
   };
 
+  // start_act returns a task ID right away; poll until the task finishes.
+  // Resolves with its result message, rejects with its error.
+  const waitForTask = async (id: string): Promise<string> => {
+    for (;;) {
+      const status = await invoke<{ state: "running" | "completed" | "failed"; message: string | null; error: { category: string; kind: string; message: string } | null }>('get_task_status', { id });
+      if (status.state === "completed") return status.message ?? "";
+      if (status.state === "failed") throw status.error ?? status.message ?? "Task failed";
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+  };
+
   // --- Updated function to handle command execution OR action name update ---
   const handleCommandExecution = async () => {
     const trimmedCommand = command.trim();
     if (!trimmedCommand) return;
 
     setIsCommandLoading(true);
+    setThought("");
+    setPlannedActions([]);
+    setProgress(null);
 
     try {
       if (recording) {
@@ -124,11 +193,14 @@ export default function DashboardPage() {
         // --- Recording is INACTIVE: Execute the command as an action sequence ---
         console.log(`Recording inactive. Executing command: "${trimmedCommand}"`);
         // Invoke the Rust command that starts the action execution loop
-        const result = await invoke('start_act', { command: trimmedCommand });
+        const taskId = await invoke<string>(dryRun ? 'start_act_dry_run' : 'start_act', { command: trimmedCommand, vision });
+        const result = await waitForTask(taskId);
         console.log("Action execution result:", result);
 
         // Handle the result (success message or error string)
-        if (result === true || (typeof result === 'string' && result.startsWith("Task completed"))) {
+        if (typeof result === 'string' && result.startsWith("Dry run completed")) {
+          console.log("Dry run finished; planned actions are listed on the page.");
+        } else if (result === true || (typeof result === 'string' && result.startsWith("Task completed"))) {
           console.log("Command executed successfully.");
           // Maybe show success notification for action execution
         } else {
@@ -143,16 +215,51 @@ export default function DashboardPage() {
     } catch (err) {
       console.error("Failed to process command/update name:", err);
       // Show error notification to the user based on context
-      const errorMessage = typeof err === 'string' ? err : (err instanceof Error ? err.message : "An unknown error occurred");
+      // Commands fail with { category, kind, message } (MetisError in error.rs)
+      const errorMessage = typeof err === 'string' ? err
+        : err instanceof Error ? err.message
+        : (typeof err === 'object' && err !== null && 'message' in err) ? String((err as { message: unknown }).message)
+        : "An unknown error occurred";
       // TODO: Display this error nicely to the user (e.g., using a toast notification library)
       alert(`Error: ${errorMessage}`); // Simple alert for now
 
     } finally {
       setIsCommandLoading(false);
+      setPaused(false);
+    }
+  };
+
+  // Works without the global ESC listener (e.g. where rdev is blocked)
+  const handleCancelTask = async () => {
+    try {
+      await invoke('cancel_task');
+    } catch (err) {
+      console.error("Failed to cancel the task:", err);
+    }
+  };
+
+  const handlePauseToggle = async () => {
+    try {
+      await invoke(paused ? 'resume_task' : 'pause_task');
+      setPaused(!paused);
+    } catch (err) {
+      console.error("Failed to pause/resume the task:", err);
     }
   };
   // --- End of updated function ---
 
+  const handleToggleLogs = async () => {
+    if (logLines) {
+      setLogLines(null);
+      return;
+    }
+    try {
+      setLogLines(await invoke<string[]>('get_recent_logs', { lines: 200 }));
+    } catch (err) {
+      console.error("Failed to load logs:", err);
+    }
+  };
+
 
   // Handle stopping an automation (example)
   const handleStopAutomation = async (id: string) => {
@@ -361,6 +468,60 @@ export default function DashboardPage() {
           </Card>
         </div>
 
+        {/* Pause to take over the mouse and keyboard, then let the agent continue */}
+        {isCommandLoading && !recording && (
+            <div className="mt-8 flex items-center gap-2">
+              <Button variant="secondary" onClick={handlePauseToggle}>
+                {paused ? "Resume task" : "Pause task"}
+              </Button>
+              <Button variant="destructive" onClick={handleCancelTask}>
+                Stop task
+              </Button>
+              {paused && <span className="text-sm text-muted-foreground">Paused. The agent will continue from the current screen.</span>}
+              {!paused && progress && (
+                  <span className="text-sm text-muted-foreground">
+                    Step {progress.iteration + 1}: <code>{progress.action}</code>
+                    {progress.success ? "" : ` failed: ${progress.error}`}
+                  </span>
+              )}
+            </div>
+        )}
+
+        {/* Live reasoning while a command runs */}
+        {isCommandLoading && thought && (
+            <Card className="p-4 mt-8">
+              <h3 className="font-bold mb-2">Agent Reasoning</h3>
+              <p className="text-sm text-muted-foreground whitespace-pre-wrap">{thought}</p>
+            </Card>
+        )}
+
+        {/* What a dry run would have done, kept after it finishes for review */}
+        {plannedActions.length > 0 && (
+            <Card className="p-4 mt-8">
+              <h3 className="font-bold mb-2">Planned Actions (dry run)</h3>
+              <ol className="list-decimal list-inside space-y-1 text-sm">
+                {plannedActions.map((planned, idx) => (
+                    <li key={idx}>
+                      <code>{planned.action}</code>
+                      <span className="text-muted-foreground"> — {planned.description}</span>
+                    </li>
+                ))}
+              </ol>
+            </Card>
+        )}
+
+        <Card className="p-4 mt-8">
+          <div className="flex items-center justify-between">
+            <h3 className="font-bold">Logs</h3>
+            <Button variant="outline" size="sm" onClick={handleToggleLogs}>
+              {logLines ? "Hide" : "Show recent"}
+            </Button>
+          </div>
+          {logLines && (
+              <pre className="mt-2 max-h-64 overflow-y-auto text-xs whitespace-pre-wrap">{logLines.join("\n") || "No log lines yet."}</pre>
+          )}
+        </Card>
+
         {/* Parsed Elements Display (when recording) */}
         {recording && parsedElements && parsedElements.length > 0 && (
             <Card className="p-4 mt-8">
@@ -392,6 +553,31 @@ export default function DashboardPage() {
         {/* Fixed Command Box */}
         {/* Ensure parent div allows positioning relative to viewport or specific container */}
         <div className="fixed bottom-0 left-0 md:left-64 right-0 p-4 bg-background border-t"> {/* Added border-t, adjusted left margin for larger screens */}
+          {!recording && (
+            <div className="max-w-4xl mx-auto mb-2 flex justify-end items-center gap-2 text-sm text-muted-foreground">
+              <label htmlFor="vision-mode">Screen input</label>
+              <select
+                id="vision-mode"
+                value={vision}
+                onChange={(e) => setVision(e.target.value as typeof vision)}
+                disabled={isCommandLoading}
+                className="border border-input rounded-md bg-background px-2 py-1"
+              >
+                <option value="off">Parsed elements</option>
+                <option value="screenshot">Screenshot (no parser)</option>
+                <option value="both">Screenshot + parsed elements</option>
+              </select>
+              <label className="flex items-center gap-1">
+                <input
+                    type="checkbox"
+                    checked={dryRun}
+                    onChange={(e) => setDryRun(e.target.checked)}
+                    disabled={isCommandLoading}
+                />
+                Dry run
+              </label>
+            </div>
+          )}
           <div className="relative max-w-4xl mx-auto"> {/* Center and limit width */}
             <input
                 type="text"
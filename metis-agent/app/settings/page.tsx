@@ -19,6 +19,7 @@ export default function SettingsPage() {
   const [dataEncryption, setDataEncryption] = useState(true);
   const [localProcessing, setLocalProcessing] = useState(true);
   const [encryptionPassword, setEncryptionPassword] = useState("");
+  const [rememberPassword, setRememberPassword] = useState(false);
   
   // Integration settings
   const [vscodeIntegration, setVscodeIntegration] = useState(false);
@@ -63,7 +64,6 @@ export default function SettingsPage() {
         setTheme(settings.theme || "system");
         setDataEncryption(settings.dataEncryption !== false);
         setLocalProcessing(settings.localProcessing !== false);
-        setEncryptionPassword(settings.encryptionPassword || "");
         setVscodeIntegration(settings.vscodeIntegration === true);
         setOpenaiApiKey(settings.openaiApiKey || "");
         setWebdriverPath(settings.webdriverPath || "");
@@ -88,10 +88,6 @@ export default function SettingsPage() {
           setScriptExecutionPolicy(settings.scriptExecutionPolicy || scriptExecutionPolicy);
           setLoggingLevel(settings.loggingLevel || loggingLevel);
           setBaseFolder(settings.baseFolder || baseFolder);
-          // Don't override encryption password if it's already set
-          if (settings.encryptionPassword && !encryptionPassword) {
-            setEncryptionPassword(settings.encryptionPassword);
-          }
         }
       } catch (err) {
         console.warn("Could not load settings from backend, using localStorage:", err);
@@ -150,7 +146,6 @@ export default function SettingsPage() {
         theme,
         dataEncryption,
         localProcessing,
-        encryptionPassword,
         vscodeIntegration,
         openaiApiKey,
         webdriverPath,
@@ -159,6 +154,12 @@ export default function SettingsPage() {
         baseFolder
       };
       
+      // The password is never stored; the backend derives a key and keeps it in the OS keyring
+      if (encryptionPassword) {
+        await invoke("set_encryption_password", { password: encryptionPassword, remember: rememberPassword });
+        setEncryptionPassword("");
+      }
+
       // Save to localStorage as a fallback/cache
       localStorage.setItem("metisSettings", JSON.stringify(settings));
       
@@ -367,6 +368,14 @@ export default function SettingsPage() {
                   value={encryptionPassword}
                   onChange={(e) => setEncryptionPassword(e.target.value)}
                 />
+                <label className="flex items-center gap-2 text-sm mt-2">
+                  <input
+                    type="checkbox"
+                    checked={rememberPassword}
+                    onChange={(e) => setRememberPassword(e.target.checked)}
+                  />
+                  Remember on this device (stored in the OS keyring)
+                </label>
                 <p className="text-xs text-muted-foreground mt-1">
                   This password is used to encrypt your recorded actions. Keep it safe!
                 </p>
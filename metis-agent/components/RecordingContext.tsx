@@ -16,24 +16,54 @@ interface RecordingContextType {
 
 const RecordingContext = createContext<RecordingContextType | undefined>(undefined);
 
+// Commands fail with { category, kind, message } (MetisError in error.rs)
+const errorMessage = (err: unknown): string => {
+  if (err instanceof Error) return err.message;
+  if (typeof err === "object" && err !== null && "message" in err) return String((err as { message: unknown }).message);
+  return String(err);
+};
+
 export const RecordingProvider: React.FC<{ children: React.ReactNode }> = ({ children }) => {
   const [recording, setRecording] = useState(false);
   const [latestFrame, setLatestFrame] = useState<string | null>(null);
   const [parsedElements, setParsedElements] = useState<any[] | null>(null);
   const [error, setError] = useState<string | null>(null);
-  const [pollingInterval, setPollingInterval] = useState<NodeJS.Timeout | null>(null);
 
   useEffect(() => {
-    // Set up listeners for new frames
+    // The backend announces each new frame (frame://updated in recorder.rs); fetch it by ID
+    let unlisten: (() => void) | null = null;
+    let disposed = false;
+    let newestId = 0;
+    let frameUrl: string | null = null;
+    const showFrame = (url: string) => {
+      if (frameUrl) {
+        URL.revokeObjectURL(frameUrl);
+      }
+      frameUrl = url;
+      setLatestFrame(url);
+    };
     const setupEventListeners = async () => {
       try {
-        // Listen for new frames from the Tauri backend
-        await listen("new-frame", (event: any) => {
-          if (event.payload) {
-            setLatestFrame(`data:image/png;base64,${event.payload}`);
+        const stop = await listen<{ id: number; timestamp_ms: number }>("frame://updated", async (event) => {
+          const { id } = event.payload;
+          newestId = Math.max(newestId, id);
+          try {
+            // A small JPEG as raw bytes, shown through an object URL
+            const bytes = await invoke<ArrayBuffer>("get_frame_preview", { id });
+            // A newer frame may have arrived while this one was in flight
+            if (id === newestId && !disposed) {
+              showFrame(URL.createObjectURL(new Blob([bytes], { type: "image/jpeg" })));
+            }
+          } catch {
+            // Replaced by a newer frame before we fetched it; its own event follows
           }
         });
-        
+        if (disposed) {
+          stop();
+        } else {
+          unlisten = stop;
+        }
+
         // Check current recording status on mount
         checkRecordingStatus();
       } catch (err) {
@@ -45,8 +75,10 @@ export const RecordingProvider: React.FC<{ children: React.ReactNode }> = ({ chi
 
     // Clean up on unmount
     return () => {
-      if (pollingInterval) {
-        clearInterval(pollingInterval);
+      disposed = true;
+      unlisten?.();
+      if (frameUrl) {
+        URL.revokeObjectURL(frameUrl);
       }
     };
   }, []);
@@ -56,39 +88,11 @@ export const RecordingProvider: React.FC<{ children: React.ReactNode }> = ({ chi
     try {
       const isActive = await invoke<boolean>("is_recording_active");
       setRecording(isActive);
-      
-      if (isActive) {
-        startPolling();
-      }
     } catch (err) {
       console.error("Error checking recording status:", err);
     }
   };
 
-  const startPolling = () => {
-    // Initial delay before starting the interval
-    setTimeout(() => {
-      const interval = setInterval(async () => {
-        try {
-          const frame = await invoke<string>("get_latest_frame");
-          if (frame) {
-            setLatestFrame(`data:image/png;base64,${frame}`);
-          }
-        } catch (error) {
-          console.error("Error fetching latest frame:", error);
-        }
-      }, 500);
-      setPollingInterval(interval);
-    }, 2000);
-  };
-
-  const stopPolling = () => {
-    if (pollingInterval) {
-      clearInterval(pollingInterval);
-      setPollingInterval(null);
-    }
-  };
-
   const startRecording = async () => {
     try {
       setError(null);
@@ -102,10 +106,9 @@ export const RecordingProvider: React.FC<{ children: React.ReactNode }> = ({ chi
       console.log("Recording verified:", verifyResult);
       
       setRecording(true);
-      startPolling();
     } catch (err) {
       console.error("Error starting recording:", err);
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
     }
   };
 
@@ -113,19 +116,16 @@ export const RecordingProvider: React.FC<{ children: React.ReactNode }> = ({ chi
     try {
       setError(null);
       
-      // Stop recording using Tauri command
-      const result = await invoke<string>("stop_recording", { 
-        encryptionPassword: "applebear" 
-      });
+      // Stop recording using Tauri command (the encryption key is held by the backend)
+      const result = await invoke<string>("stop_recording");
       console.log("Recording stopped:", result);
       
       setRecording(false);
-      stopPolling();
       setLatestFrame(null);
       setParsedElements(null);
     } catch (err) {
       console.error("Error stopping recording:", err);
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
     }
   };
 
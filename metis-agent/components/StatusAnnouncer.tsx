@@ -0,0 +1,45 @@
+// metis-agent/components/StatusAnnouncer.tsx
+"use client";
+
+import React, { useEffect, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
+
+interface Announcement {
+  status: "recording_started" | "recording_stopped" | "action_starting" | "task_complete" | "task_failed";
+  message: string;
+  assertive: boolean;
+}
+
+// Screen-reader live regions for the backend's status announcements (announce.rs).
+// Visually hidden; failures go to the assertive region so they interrupt.
+export const StatusAnnouncer: React.FC = () => {
+  const [polite, setPolite] = useState("");
+  const [assertive, setAssertive] = useState("");
+
+  useEffect(() => {
+    let unlisten: (() => void) | undefined;
+    listen<Announcement>("status-announcement", (event) => {
+      const setRegion = event.payload.assertive ? setAssertive : setPolite;
+      // Clear first so a repeated message ("Clicking at 10, 20" twice) is still read
+      setRegion("");
+      setTimeout(() => setRegion(event.payload.message), 50);
+    })
+      .then((fn) => {
+        unlisten = fn;
+      })
+      .catch((err) => console.error("Failed to listen for status announcements:", err));
+
+    return () => unlisten?.();
+  }, []);
+
+  return (
+    <>
+      <div role="status" aria-live="polite" aria-atomic="true" className="sr-only">
+        {polite}
+      </div>
+      <div role="alert" aria-live="assertive" aria-atomic="true" className="sr-only">
+        {assertive}
+      </div>
+    </>
+  );
+};
@@ -25,6 +25,30 @@ export interface Automation {
   runCount: number;
 }
 
+// A task command run unattended whenever a cron expression matches
+export interface Schedule {
+  id: string;
+  command: string;
+  cron: string; // "minute hour day month weekday", or @hourly, @daily, ...
+  utc_offset_minutes: number;
+  created_ms: number;
+  next_run_ms?: number;
+  last_run_ms?: number;
+  last_task_id?: string; // Poll with get_task_status
+}
+
+// Runs a command for each new file matching `glob` in the folder `path`
+export interface WatchTrigger {
+  id: string;
+  path: string;
+  glob: string;
+  command_template: string; // {{file}}: the file's full path, {{name}}: its file name
+  created_ms: number;
+  last_file?: string;
+  last_run_ms?: number;
+  last_task_id?: string;
+}
+
 export interface AutomationSuggestion {
   name: string;
   description: string;
@@ -186,6 +210,71 @@ export class AutomationManager {
     }
   }
 
+  /**
+   * Run a task command on a cron schedule, in the user's current time zone
+   */
+  public async scheduleTask(command: string, cronExpr: string): Promise<Schedule> {
+    const utcOffsetMinutes = -new Date().getTimezoneOffset();
+    return await invoke<Schedule>("schedule_task", { command, cronExpr, utcOffsetMinutes });
+  }
+
+  /**
+   * Get all scheduled tasks
+   */
+  public async listSchedules(): Promise<Schedule[]> {
+    try {
+      return await invoke<Schedule[]>("list_schedules");
+    } catch (error) {
+      console.error("Failed to list schedules:", error);
+      return [];
+    }
+  }
+
+  /**
+   * Delete a scheduled task
+   */
+  public async deleteSchedule(id: string): Promise<boolean> {
+    try {
+      await invoke("delete_schedule", { id });
+      return true;
+    } catch (error) {
+      console.error("Failed to delete schedule:", error);
+      return false;
+    }
+  }
+
+  /**
+   * Run a command whenever a new file matching a glob lands in a folder
+   */
+  public async addWatchTrigger(path: string, glob: string, commandTemplate: string): Promise<WatchTrigger> {
+    return await invoke<WatchTrigger>("add_watch_trigger", { path, glob, commandTemplate });
+  }
+
+  /**
+   * Get all folder-watch triggers
+   */
+  public async listWatchTriggers(): Promise<WatchTrigger[]> {
+    try {
+      return await invoke<WatchTrigger[]>("list_watch_triggers");
+    } catch (error) {
+      console.error("Failed to list watch triggers:", error);
+      return [];
+    }
+  }
+
+  /**
+   * Delete a folder-watch trigger
+   */
+  public async deleteWatchTrigger(id: string): Promise<boolean> {
+    try {
+      await invoke("delete_watch_trigger", { id });
+      return true;
+    } catch (error) {
+      console.error("Failed to delete watch trigger:", error);
+      return false;
+    }
+  }
+
   /**
    * Register callback for when an automation runs
    */
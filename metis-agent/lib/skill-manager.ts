@@ -1,6 +1,6 @@
 // metis-agent/lib/skill-manager.ts
 import { invoke } from "@tauri-apps/api/core";
-import { open } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
 import { readFile } from "@tauri-apps/plugin-fs";
 
 export interface Skill {
@@ -15,6 +15,31 @@ export interface Skill {
   thumbnailUrl?: string;
   downloads: number;
   rating: number;
+  actionFolders: string[]; // Recording sessions that demonstrate the skill
+  parameters: SkillParameter[];
+  usage: SkillUsage; // Runs on this machine
+  localRating?: LocalRating; // The user's own rating
+}
+
+export interface SkillUsage {
+  runs: number;
+  successes: number;
+  lastRunMs?: number;
+}
+
+export interface LocalRating {
+  stars: number; // 1-5
+  comment: string;
+  ratedAt: number;
+}
+
+export type SkillSort = "name" | "recent" | "rating" | "usage" | "success_rate";
+
+export interface SkillParameter {
+  name: string; // Referenced as {{name}} in the description and recordings
+  description: string;
+  default?: string;
+  required: boolean;
 }
 
 export interface SkillBundle {
@@ -30,12 +55,27 @@ export interface SkillBundle {
   updatedAt: number;
   downloads: number;
   rating: number;
+  files: string[]; // .metisskill files, relative to the catalog
+  requires: SkillRef[];
+}
+
+export type SkillRef =
+  | { kind: "skill"; id: string; min_version?: string }
+  | { kind: "metis"; min_version: string }
+  | { kind: "os"; os: string[] };
+
+// One entry of the `unmet` list on an install_skill_bundle error
+export interface UnmetRequirement {
+  required_by: string;
+  requirement: string;
+  reason: string;
 }
 
 export interface SkillLearningProgress {
   skillId: string;
   progress: number; // 0-100
-  status: "not_started" | "in_progress" | "completed";
+  status: "not_started" | "in_progress" | "completed" | "failed";
+  message?: string;
   lastUpdated: number;
 }
 
@@ -60,23 +100,92 @@ export class SkillManager {
   /**
    * Get installed skills
    */
-  public async getInstalledSkills(): Promise<Skill[]> {
+  public async getInstalledSkills(sort?: SkillSort): Promise<Skill[]> {
     try {
-      const rawData = await invoke("get_installed_skills");
-      return JSON.parse(rawData as string) as Skill[];
+      return await invoke<Skill[]>("get_installed_skills", { sort });
     } catch (error) {
       console.error("Failed to get installed skills:", error);
       return [];
     }
   }
 
+  /**
+   * Rate a skill 1-5 stars; used to rank skills and bundles locally
+   */
+  public async rateSkill(skillId: string, stars: number, comment?: string): Promise<Skill> {
+    return await invoke<Skill>("rate_skill", { skillId, stars, comment });
+  }
+
+  /**
+   * Attach a finished recording session to a skill
+   */
+  public async attachRecording(skillId: string, sessionId: string): Promise<Skill> {
+    return await invoke<Skill>("attach_recording_to_skill", { skillId, sessionId });
+  }
+
+  /**
+   * Replace a skill's parameter list
+   */
+  public async updateSkillParameters(skillId: string, parameters: SkillParameter[]): Promise<Skill> {
+    return await invoke<Skill>("update_skill_parameters", { skillId, parameters });
+  }
+
+  /**
+   * Run a skill, filling its {{parameters}} from args; returns the task ID to poll with get_task_status
+   */
+  public async executeSkill(skillId: string, args?: Record<string, string>): Promise<string> {
+    return await invoke<string>("execute_skill", { skillId, args });
+  }
+
+  /**
+   * Run a skill (with its parameter defaults) whenever a chord like "Ctrl+Alt+1" is pressed while idle
+   */
+  public async bindSkillHotkey(skillId: string, chord: string): Promise<void> {
+    await invoke("bind_skill_hotkey", { skillId, chord });
+  }
+
+  /**
+   * Remove the skill binding of a chord
+   */
+  public async unbindSkillHotkey(chord: string): Promise<void> {
+    await invoke("unbind_skill_hotkey", { chord });
+  }
+
+  /**
+   * Save a skill and its recordings as a .metisskill file; returns the path written.
+   * The raw keystroke and trajectory logs are only included with includeRawLogs.
+   */
+  public async exportSkill(skillId: string, includeRawLogs = false): Promise<string | null> {
+    const path = await save({
+      defaultPath: "skill.metisskill",
+      filters: [{ name: "Metis skill", extensions: ["metisskill"] }]
+    });
+    if (!path) {
+      return null;
+    }
+    return await invoke<string>("export_skill", { skillId, path, includeRawLogs });
+  }
+
+  /**
+   * Install a skill from a .metisskill file
+   */
+  public async importSkill(): Promise<Skill | null> {
+    const selected = await open({
+      multiple: false,
+      filters: [{ name: "Metis skill", extensions: ["metisskill"] }]
+    });
+    if (!selected || Array.isArray(selected)) {
+      return null;
+    }
+    return await invoke<Skill>("import_skill", { path: selected });
+  }
+
   /**
    * Get skill bundles from marketplace
    */
-  public async getMarketplaceSkillBundles(page = 1, limit = 10): Promise<SkillBundle[]> {
+  public async getMarketplaceSkillBundles(page = 1, limit = 10, sort?: SkillSort): Promise<SkillBundle[]> {
     try {
-      const rawData = await invoke("get_marketplace_skill_bundles", { page, limit });
-      return JSON.parse(rawData as string) as SkillBundle[];
+      return await invoke<SkillBundle[]>("get_marketplace_skill_bundles", { page, limit, sort });
     } catch (error) {
       console.error("Failed to get marketplace skill bundles:", error);
       return [];
@@ -101,7 +210,9 @@ export class SkillManager {
    */
   public async installSkillBundle(bundleId: string): Promise<boolean> {
     try {
-      return await invoke("install_skill_bundle", { bundleId });
+      // Dependencies are installed too; an error's `unmet` lists what can't be satisfied
+      const installed = await invoke<Skill[]>("install_skill_bundle", { bundleId });
+      return installed.length > 0;
     } catch (error) {
       console.error("Failed to install skill bundle:", error);
       return false;
@@ -143,8 +254,9 @@ export class SkillManager {
       // Read the file
       const filePath = selected;
       
-      // Call Tauri command to process the file
-      return await invoke("process_learning_video", { filePath });
+      // Starts learning in the background; progress arrives via getLearningProgress
+      const skillId = await invoke<string>("process_learning_video", { filePath });
+      return skillId.length > 0;
     } catch (error) {
       console.error("Failed to upload learning video:", error);
       return false;
@@ -156,8 +268,7 @@ export class SkillManager {
    */
   public async getLearningProgress(): Promise<SkillLearningProgress[]> {
     try {
-      const rawData = await invoke("get_learning_progress");
-      return JSON.parse(rawData as string) as SkillLearningProgress[];
+      return await invoke<SkillLearningProgress[]>("get_learning_progress");
     } catch (error) {
       console.error("Failed to get learning progress:", error);
       return [];
@@ -1,108 +1,184 @@
-use std::path::{Path, PathBuf};
-// Removed unused PathBuf
-use std::fs::{self, OpenOptions}; // Removed unused File
-use std::io::{self, Write, Cursor}; // Removed unused Read and self import
+use std::path::PathBuf;
+use std::fs;
 use regex::Regex;
-use csv::{Reader, ReaderBuilder}; // Removed unused Writer (it's only used in create_main_csv below)
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use tokio::runtime::Runtime;
-// Removed unused Lazy
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
 use std::time::Duration;
+use image::{DynamicImage, ImageOutputFormat};
 
-// --- Enigo Imports ---
-// Corrected imports based on enigo 0.3.0 docs and errors
-use enigo::{Enigo, Button, Key, Keyboard, Mouse, Settings, Coordinate, Axis, Direction};
+// enigo's Key/Direction are the vocabulary of InputBackend; injection itself goes through the trait
+use enigo::{Key, Direction};
 // Removed MouseButton, Wheel
 
 // --- Network & Encoding Imports ---
-use reqwest::blocking::Client;
-use base64::engine::general_purpose::STANDARD;
-use base64::Engine;
-use serde_json::json;
 
 // --- Local Imports ---
-use crate::llm::get_llm;
-use crate::RECORDING_STATE;
+use tracing::{error, info, info_span, warn};
+use crate::llm::{self, LlmReply, ToolCall, ToolSpec};
+use crate::memory::{self, TaskTranscript};
+use crate::events;
+use crate::recorder::{self, SharedRecordingState};
 // Removed unused create_recording_paths
-use crate::capture_screen; // Keep capture_screen
-
-#[derive(Debug, Deserialize, Serialize)]
-struct MainCsvRecord {
-    query: String,
-    location: String,
-}
-
-// --- Global State for Escape Key ---
-static ACTION_INTERRUPTED: AtomicBool = AtomicBool::new(false);
-static ESC_LISTENER_RUNNING: AtomicBool = AtomicBool::new(false);
-
-/// Starts a background thread to listen for the Escape key.
-fn start_esc_listener() {
-    if ESC_LISTENER_RUNNING.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
-        println!("Starting ESC listener...");
-        thread::spawn(|| {
-            let callback = |event: rdev::Event| {
-                if let rdev::EventType::KeyPress(rdev::Key::Escape) = event.event_type {
-                    println!("Escape key detected!");
-                    ACTION_INTERRUPTED.store(true, Ordering::SeqCst);
-                }
-            };
+use crate::capture::CaptureBackend;
+use crate::perf::{self, Stage};
+use crate::clock::Clock;
+use crate::input::InputBackend;
+use crate::session;
+use crate::redact;
+use crate::elements;
+use crate::layout;
+use crate::language;
+use crate::cdp::{self, BrowserOp};
+use crate::storage;
+use crate::skills::{self, SkillRun};
+use crate::keystore;
+use crate::announce::{self, Status};
+use crate::net;
+use crate::audit::{self, AuditedInput};
+use crate::backend;
+use crate::parser;
+use crate::prompt;
+use crate::window_control::WindowOp;
+use crate::window_info;
+use crate::config::{self, ModelConfig, ParserEngine};
+use crate::coords::CoordinateMap;
+use crate::dedup;
+use crate::sync::LockExt;
+use crate::app_state::{AppInputState, ExecutionGuard, SharedAppState};
+use crate::error::{ActionError, CaptureError, LlmError, MetisError, ParserError};
+
+/// What `rel:`, `%:` and monitor-qualified coordinates are resolved against,
+/// read fresh every iteration. Points are in screenshot pixels (coords.rs).
+#[derive(Debug, Clone, Copy)]
+struct CoordinateFrame<'a> {
+    map: &'a CoordinateMap,
+    pointer: Option<(i32, i32)>, // Where the last executed action left the pointer
+    elements: Option<&'a str>,    // The screen CSV the LLM was shown, for element ids
+}
 
-            if let Err(error) = rdev::listen(callback) {
-                eprintln!("Error starting global ESC listener: {:?}", error);
-            }
-            println!("ESC listener thread finished.");
-            ESC_LISTENER_RUNNING.store(false, Ordering::SeqCst);
-        });
-    } else {
-        println!("ESC listener already running.");
+/// The bbox center of the element with this id in the frame's screen CSV, so
+/// the LLM names an element instead of working out its coordinates.
+fn element_point(id: &str, frame: &CoordinateFrame) -> Result<(i32, i32), ActionError> {
+    let id = id.trim();
+    frame
+        .elements
+        .and_then(|csv| parser::element_center(csv, id))
+        .ok_or_else(|| ActionError::UnknownElementId(id.to_string()))
+}
+
+/// A point that may name the monitor it is on: "(monitor,x,y)" is pixel (x, y)
+/// of that monitor; anything else is a point in the screenshot (parse_coordinate).
+/// Naming the monitor the screenshot shows is the same as naming none.
+fn parse_point(coord_str: &str, frame: &CoordinateFrame) -> Result<(Option<usize>, i32, i32), ActionError> {
+    let re = Regex::new(r"^\s*\(\s*(\d+)\s*,\s*(-?\d+)\s*,\s*(-?\d+)\s*\)\s*$").expect("valid monitor point regex");
+    let Some(caps) = re.captures(coord_str) else {
+        let (x, y) = parse_coordinate(coord_str, frame)?;
+        return Ok((None, x, y));
+    };
+    let invalid = || ActionError::InvalidCoordinate(coord_str.to_string());
+    let monitor = caps[1].parse::<usize>().map_err(|_| invalid())?;
+    let x = caps[2].parse::<i32>().map_err(|_| invalid())?;
+    let y = caps[3].parse::<i32>().map_err(|_| invalid())?;
+    on_monitor(monitor, x, y, frame)
+}
+
+/// Checks pixel (x, y) against the recorded monitor's size.
+fn on_monitor(monitor: usize, x: i32, y: i32, frame: &CoordinateFrame) -> Result<(Option<usize>, i32, i32), ActionError> {
+    let monitors = &frame.map.monitors;
+    let geometry = monitors.get(monitor).ok_or(ActionError::UnknownMonitor { monitor, count: monitors.len() })?;
+    if frame.map.shown == Some(monitor) {
+        return Ok((None, x, y)); // Screenshot pixels; validate_action checks them
     }
+    let (width, height) = (geometry.width as i32, geometry.height as i32);
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return Err(ActionError::OutOfBounds { x, y, width, height });
+    }
+    Ok((Some(monitor), x, y))
 }
 
-/// Stops the Escape key listener (Placeholder)
-fn stop_esc_listener() {
-    println!("Stopping ESC listener (Note: rdev thread might persist until app exit).");
-    ACTION_INTERRUPTED.store(false, Ordering::SeqCst);
+/// Helper to parse coordinates: "(x,y)" in pixels, "rel:(dx,dy)" from the last
+/// pointer position, or "%:(x,y)" as percentages of the screen size
+fn parse_coordinate(coord_str: &str, frame: &CoordinateFrame) -> Result<(i32, i32), ActionError> {
+    let trimmed = coord_str.trim();
+    if let Some(rest) = trimmed.strip_prefix("rel:") {
+        let (dx, dy) = parse_pixels(rest)?;
+        let (x, y) = frame.pointer.ok_or(ActionError::UnknownPointer)?;
+        return Ok((x.saturating_add(dx), y.saturating_add(dy)));
+    }
+    if let Some(rest) = trimmed.strip_prefix("%:") {
+        let re = Regex::new(r"^\(\s*(\d+(?:\.\d+)?)\s*,\s*(\d+(?:\.\d+)?)\s*\)$").expect("valid percentage regex");
+        let invalid = || ActionError::InvalidCoordinate(coord_str.to_string());
+        let caps = re.captures(rest.trim()).ok_or_else(invalid)?;
+        let percent = |i: usize| caps[i].parse::<f32>().ok().filter(|p| *p <= 100.0).ok_or_else(invalid);
+        let (px, py) = (percent(1)?, percent(2)?);
+        let (width, height) = frame.map.image;
+        // 100% is the last pixel, not one past it
+        let scale = |p: f32, size: i32| ((p / 100.0 * size as f32).round() as i32).min(size - 1).max(0);
+        return Ok((scale(px, width), scale(py, height)));
+    }
+    parse_pixels(trimmed)
 }
 
-/// Helper to parse coordinate strings like "(x,y)"
-fn parse_coordinate(coord_str: &str) -> Result<(i32, i32), String> {
+/// Helper to parse pixel coordinate strings like "(x,y)"
+fn parse_pixels(coord_str: &str) -> Result<(i32, i32), ActionError> {
     // Using lazy_static or once_cell could optimize regex compilation, but fine for now
-    let re = Regex::new(r"\(\s*(-?\d+)\s*,\s*(-?\d+)\s*\)").map_err(|e| e.to_string())?;
+    let re = Regex::new(r"\(\s*(-?\d+)\s*,\s*(-?\d+)\s*\)").expect("valid coordinate regex");
+    let invalid = || ActionError::InvalidCoordinate(coord_str.to_string());
     if let Some(caps) = re.captures(coord_str) {
-        let x = caps.get(1).unwrap().as_str().parse::<i32>().map_err(|e| e.to_string())?;
-        let y = caps.get(2).unwrap().as_str().parse::<i32>().map_err(|e| e.to_string())?;
+        let x = caps[1].parse::<i32>().map_err(|_| invalid())?;
+        let y = caps[2].parse::<i32>().map_err(|_| invalid())?;
         Ok((x, y))
     } else {
-        Err(format!("Invalid coordinate format: {}", coord_str))
+        Err(invalid())
     }
 }
 
+/// Helper to parse gesture values like "(x,y,value)" or "(monitor,x,y,value)";
+/// returns the point (see parse_point) and the raw value
+fn parse_gesture<'v>(value_str: &'v str, frame: &CoordinateFrame) -> Result<(Option<usize>, i32, i32, &'v str), ActionError> {
+    let re = Regex::new(r"^\s*\(\s*(?:(\d+)\s*,\s*)?(-?\d+)\s*,\s*(-?\d+)\s*,\s*([^)\s]+)\s*\)\s*$").expect("valid gesture regex");
+    let invalid = || ActionError::InvalidCoordinate(value_str.to_string());
+    let caps = re.captures(value_str).ok_or_else(invalid)?;
+    let x = caps[2].parse::<i32>().map_err(|_| invalid())?;
+    let y = caps[3].parse::<i32>().map_err(|_| invalid())?;
+    let value = caps.get(4).map_or("", |m| m.as_str());
+    let (monitor, x, y) = match caps.get(1) {
+        Some(monitor) => on_monitor(monitor.as_str().parse::<usize>().map_err(|_| invalid())?, x, y, frame)?,
+        None => (None, x, y),
+    };
+    Ok((monitor, x, y, value))
+}
+
 // Helper enum to distinguish between special keys and single characters
-#[derive(Debug)]
-enum ParsedKey {
+#[derive(Debug, Clone)]
+pub enum ParsedKey {
     Key(Key),
     Char(char),
 }
 
 /// Helper to parse key strings like "'a'" or "'Shift'"
 /// Returns ParsedKey::Key for special keys, ParsedKey::Char for single chars
-fn parse_key(key_str: &str) -> Result<ParsedKey, String> {
+fn parse_key(key_str: &str) -> Result<ParsedKey, ActionError> {
     let trimmed = key_str.trim();
     if !trimmed.starts_with('\'') || !trimmed.ends_with('\'') || trimmed.len() < 3 {
-        return Err(format!("Invalid key format: {}", key_str));
+        return Err(ActionError::InvalidKey(key_str.to_string()));
     }
-    let key_inner = &trimmed[1..trimmed.len() - 1];
+    parse_key_name(&trimmed[1..trimmed.len() - 1])
+}
 
+/// Maps an unquoted key name or single character to a key
+pub fn parse_key_name(key_inner: &str) -> Result<ParsedKey, ActionError> {
     match key_inner {
         // Map common names to Enigo Keys
         "Alt" | "alt" => Ok(ParsedKey::Key(Key::Alt)),
         "Backspace" | "backspace" => Ok(ParsedKey::Key(Key::Backspace)),
         "CapsLock" | "capslock" => Ok(ParsedKey::Key(Key::CapsLock)),
-        "Control" | "ctrl" | "control" => Ok(ParsedKey::Key(Key::Control)),
+        "Control" | "Ctrl" | "ctrl" | "control" => Ok(ParsedKey::Key(Key::Control)),
         "Delete" | "del" | "delete" => Ok(ParsedKey::Key(Key::Delete)),
         "DownArrow" | "down" => Ok(ParsedKey::Key(Key::DownArrow)),
         "End" | "end" => Ok(ParsedKey::Key(Key::End)),
@@ -127,184 +203,1107 @@ fn parse_key(key_str: &str) -> Result<ParsedKey, String> {
         s if s.chars().count() == 1 => {
             Ok(ParsedKey::Char(s.chars().next().unwrap()))
         },
-        _ => Err(format!("Unknown or unsupported key: '{}'", key_inner)),
+        _ => Err(ActionError::UnknownKey(key_inner.to_string())),
     }
 }
 
+/// Helper to parse chords like "'ctrl+shift+t'": modifiers, then the key they apply to
+fn parse_chord(value_str: &str) -> Result<(Vec<Key>, ParsedKey), ActionError> {
+    let chord = unquote(value_str).ok_or_else(|| ActionError::InvalidKey(value_str.to_string()))?;
+    chord_keys(chord, value_str)
+}
 
-/// Executes a single action based on the input string.
-/// Returns Ok(true) to continue, Ok(false) for "done", Err on failure.
-fn do_action(action_str: &str, enigo: &mut Enigo) -> Result<bool, String> {
-    println!("Executing action: {}", action_str);
+/// Splits an unquoted chord like "ctrl+shift+t"; `value_str` is what errors quote.
+fn chord_keys(chord: &str, value_str: &str) -> Result<(Vec<Key>, ParsedKey), ActionError> {
+    // A trailing "++" means the final key is '+' itself
+    let (modifiers, last) = match chord.strip_suffix("++") {
+        Some(rest) => (rest, "+"),
+        None => chord.rsplit_once('+').unwrap_or(("", chord)),
+    };
+    if last.trim().is_empty() {
+        return Err(ActionError::InvalidKey(value_str.to_string()));
+    }
+    let modifiers = modifiers
+        .split('+')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| match parse_key_name(name)? {
+            ParsedKey::Key(key) => Ok(key),
+            ParsedKey::Char(c) => Err(ActionError::UnsupportedChar { action: "keys", ch: c }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((modifiers, parse_key_name(last.trim())?))
+}
+
+
+/// Helper to parse element targets like "'OK'" or "'OK','button'"
+fn parse_element(value_str: &str) -> Result<(String, Option<String>), ActionError> {
+    let re = Regex::new(r"^\s*'([^']+)'\s*(?:,\s*'([^']+)'\s*)?$").expect("valid element regex");
+    let caps = re.captures(value_str).ok_or_else(|| ActionError::InvalidFormat(value_str.to_string()))?;
+    Ok((caps[1].to_string(), caps.get(2).map(|m| m.as_str().to_string())))
+}
+
+/// Parses `id,'text'` for type_into.
+fn parse_type_into(value_str: &str) -> Result<(&str, &str), ActionError> {
+    let (id, text) = value_str.split_once(',').ok_or_else(|| ActionError::InvalidFormat(value_str.to_string()))?;
+    let text = unquote(text).ok_or_else(|| ActionError::InvalidFormat(value_str.to_string()))?;
+    Ok((id, text))
+}
+
+/// Parses `'selector','text'`; the text may itself contain quotes.
+fn parse_fill(value_str: &str) -> Result<BrowserOp, ActionError> {
+    let re = Regex::new(r"^\s*'(.+?)'\s*,\s*'(.*)'\s*$").expect("valid fill regex");
+    let caps = re.captures(value_str).ok_or_else(|| ActionError::InvalidFormat(value_str.to_string()))?;
+    Ok(BrowserOp::Fill(caps[1].to_string(), caps[2].to_string()))
+}
+
+/// The action grammar as shown to the LLM, both in the main prompt and in
+/// correction prompts after an invalid action.
+pub const ACTION_GRAMMAR: &str = "\
+Valid action commands and their required value formats:\n\
+* `click:(x,y)` - Click instantly at absolute pixel coordinates (x, y). Derive coordinates from the CSV data (e.g., center of a bbox: ((col_min+col_max)/2, (row_min+row_max)/2)).\n\
+  Anywhere a point `(x,y)` is expected (click, click_down, drag, move, long_press) you may also write `rel:(dx,dy)`, an offset in pixels from where the previous action left the pointer, or `%:(x,y)`, percentages of the screen width and height. Example: `click:%:(50,50)` clicks the screen center, `drag:rel:(200,0)` drags 200 pixels right.\n\
+  When a Displays section lists several monitors, `(monitor,x,y)` is pixel (x, y) of that monitor instead of the screenshot, e.g. `click:(1,200,300)`; hover, pinch and two_finger_scroll take the monitor first as well, e.g. `pinch:(1,640,400,2.0)`.\n\
+* `click_down:(x,y)` - Press and hold the left mouse button at absolute pixel coordinates (x, y).\n\
+* `click_up:nil` - Release the held left mouse button. The value must be exactly `nil`.\n\
+* `drag:(x,y)` - Move the mouse to absolute pixel coordinates (x, y) WHILE the button is held down (use after `click_down`).\n\
+* `move:(x,y)` - Move the mouse to absolute pixel coordinates (x, y) without pressing any button.\n\
+* `hover:(x,y,ms)` - Move the mouse to (x, y) and rest there for `ms` milliseconds, to reveal tooltips or hover menus without clicking. Example: `hover:(320,40,1000)`.\n\
+* `tap:'key'` - Press and release a keyboard key. The key name or character MUST be enclosed in single quotes. Common keys: 'a', 'b', '1', 'Enter', 'Shift', 'Control', 'Alt', 'Escape', 'Backspace', 'Tab', 'Space', 'ArrowUp', 'ArrowDown', 'ArrowLeft', 'ArrowRight', 'F5', etc.\n\
+* `tap_down:'key'` - Press and HOLD a keyboard key (typically for modifiers like 'Shift', 'Control', 'Alt'). Use single quotes.\n\
+* `tap_up:'key'` - Release a held keyboard key. Use single quotes.\n\
+* `keys:'mod+mod+key'` - Press a keyboard shortcut in one step: the modifiers are held, the last key is tapped, then all are released. Use single quotes. Example: `keys:'ctrl+c'`, `keys:'ctrl+shift+t'`, `keys:'alt+Tab'`.\n\
+* `scroll:amount` - Scroll vertically by the specified integer `amount`. Positive values scroll down, negative values scroll up. Example: `scroll:10`, `scroll:-5`.\n\
+* `hscroll:amount` - Scroll horizontally, e.g. across wide spreadsheets or timelines. Positive values scroll right, negative values scroll left. Example: `hscroll:5`, `hscroll:-3`.\n\
+* `wait:milliseconds` - Do nothing for the given time, e.g. while a page or dialog is still loading. Example: `wait:2000`. Long waits are capped (10 seconds by default).\n\
+* `type:'text to type'` - Type the provided sequence of characters exactly. The text MUST be enclosed in single quotes.\n\
+* `click_element:id` - Click the center of the element with this id in the Current Screen State CSV, e.g. `click_element:12`. Prefer it to working out a bbox center for `click:(x,y)`.\n\
+* `type_into:id,'text'` - Click the element with this id from the CSV, then type the text into it, e.g. `type_into:7,'hello world'`.\n\
+* `click_element:'name'` or `click_element:'name','role'` - Press the element with this name (and role) from the Accessible Elements list, e.g. `click_element:'OK','button'`. More reliable than coordinates for native controls; only use it when that list is present, and fall back to `click:(x,y)` if it fails.\n\
+* `focus_window:'title'` - Bring the window whose title contains this text to the front, restoring it if minimized. Faster and more reliable than clicking the taskbar. Example: `focus_window:'Untitled - Notepad'`.\n\
+* `minimize_window:'title'` - Minimize the window whose title contains this text.\n\
+* `maximize_window:'title'` - Maximize the window whose title contains this text.\n\
+* `launch:'application'` - Start a program by name, or open a web link, file or folder with its default app, instead of looking for an icon to click. Example: `launch:'firefox'`, `launch:'https://example.com'`. Only programs the user has allowed can be started; if the launch is refused, open it through the UI instead.\n\
+* `browser_navigate:'url'` - In the browser, load this http(s) address in the tab in front (starting a browser if none is running) and wait for the page to finish loading. Example: `browser_navigate:'https://example.com/login'`.\n\
+* `browser_click:'selector'` - In the browser tab in front, click the element matching this CSS selector, e.g. `browser_click:'#submit'`, `browser_click:'button[type=\"submit\"]'`. The Web Page Elements list gives a selector for elements that have one.\n\
+* `browser_fill:'selector','text'` - Replace the contents of the input matching this CSS selector with the text, e.g. `browser_fill:'input[name=\"email\"]','ada@example.com'`.\n\
+  The browser_ actions work on web pages only, and only when the browser bridge is enabled; if one fails, fall back to `click:(x,y)` and `type:'text'`.\n\
+* `long_press:(x,y)` - Touch and hold at absolute pixel coordinates (x, y) for about a second, e.g. to open a context menu in a touch-first app.\n\
+* `pinch:(x,y,scale)` - Two-finger pinch centered on (x, y). A scale above 1 zooms in, below 1 zooms out. Example: `pinch:(640,400,2.0)`, `pinch:(640,400,0.5)`.\n\
+* `two_finger_scroll:(x,y,amount)` - Two-finger scroll at (x, y), for touch-first apps and maps that ignore the mouse wheel. Positive values scroll down, negative values scroll up. Example: `two_finger_scroll:(640,400,5)`.\n\
+* `done:'completion message'` - Stop the execution loop and report the outcome. The message MUST be enclosed in single quotes.\n";
+
+/// Stands in for ACTION_GRAMMAR when the actions are offered as tools.
+pub const TOOL_GRAMMAR: &str = "\
+The actions are tools: take the next action by calling exactly one of them right after the closing </think> tag, instead of writing it as text.\n\
+* Points (x, y) are pixels of the screenshot; derive them from the CSV data (e.g., the center of a bbox). When a Displays section lists several monitors, give `monitor` to mean pixel (x, y) of that monitor instead.\n\
+* `click_element` and `type_into` take an element id from the Current Screen State CSV; `click_element` also takes a name and role from the Accessible Elements list, for native controls.\n\
+* The browser_ tools work on web pages only, and only when the browser bridge is enabled; if one fails, fall back to `click` and `type`.\n\
+* Call `done` with a completion message once the command is fulfilled.\n";
+
+/// The actions of the grammar as tools for LLMs with native tool use, under
+/// the same names. Points are plain pixels: rel: and %: are grammar shorthands.
+pub fn action_tools() -> Vec<ToolSpec> {
+    let int = |description: &str| json!({ "type": "integer", "description": description });
+    let string = |description: &str| json!({ "type": "string", "description": description });
+    let object = |properties: Value, required: &[&str]| json!({ "type": "object", "properties": properties, "required": required });
+    // A point's x, y and optional monitor, plus the tool's own arguments
+    let point = |extra: Value, required: &[&str]| {
+        let mut properties = json!({
+            "x": int("Pixels from the left edge of the screenshot"),
+            "y": int("Pixels from the top edge of the screenshot"),
+            "monitor": int("Monitor number from the Displays section, when x and y are that monitor's pixels; leave out for the screenshot"),
+        });
+        if let (Some(properties), Value::Object(extra)) = (properties.as_object_mut(), extra) {
+            properties.extend(extra);
+        }
+        object(properties, &[&["x", "y"], required].concat())
+    };
+    let key = || object(json!({ "key": string("A key name such as Enter, Shift, Escape, Tab or F5, or a single character") }), &["key"]);
+    let window = || object(json!({ "title": string("Text contained in the window's title") }), &["title"]);
+    let tool = |name, description, parameters| ToolSpec { name, description, parameters };
+    vec![
+        tool("click", "Click at a point.", point(json!({}), &[])),
+        tool("click_down", "Press and hold the left mouse button at a point.", point(json!({}), &[])),
+        tool("click_up", "Release the held left mouse button.", object(json!({}), &[])),
+        tool("drag", "Move the mouse to a point while the button is held down (after click_down).", point(json!({}), &[])),
+        tool("move", "Move the mouse to a point without pressing any button.", point(json!({}), &[])),
+        tool(
+            "hover",
+            "Move the mouse to a point and rest there, to reveal tooltips or hover menus without clicking.",
+            point(json!({ "ms": int("Milliseconds to rest there") }), &["ms"]),
+        ),
+        tool("tap", "Press and release a keyboard key.", key()),
+        tool("tap_down", "Press and hold a keyboard key, typically a modifier like 'Shift' or 'Control'.", key()),
+        tool("tap_up", "Release a held keyboard key.", key()),
+        tool(
+            "keys",
+            "Press a keyboard shortcut in one step: the modifiers are held, the last key is tapped, then all are released.",
+            object(json!({ "keys": string("The shortcut, e.g. ctrl+c, ctrl+shift+t, alt+Tab") }), &["keys"]),
+        ),
+        tool("scroll", "Scroll vertically.", object(json!({ "amount": int("Positive scrolls down, negative up") }), &["amount"])),
+        tool("hscroll", "Scroll horizontally.", object(json!({ "amount": int("Positive scrolls right, negative left") }), &["amount"])),
+        tool(
+            "wait",
+            "Do nothing for a while, e.g. while a page or dialog is still loading. Long waits are capped (10 seconds by default).",
+            object(json!({ "ms": int("Milliseconds to wait") }), &["ms"]),
+        ),
+        tool("type", "Type text into the focused control, exactly as given.", object(json!({ "text": string("The text to type") }), &["text"])),
+        tool(
+            "click_element",
+            "Click an element: by its id in the Current Screen State CSV, or by its name (and role) in the Accessible Elements list. Prefer it to working out a bbox center for click.",
+            object(
+                json!({
+                    "id": string("The element's id in the CSV"),
+                    "name": string("The element's name in the Accessible Elements list, instead of an id"),
+                    "role": string("The element's role in that list, e.g. 'button'; optional with a name"),
+                }),
+                &[],
+            ),
+        ),
+        tool(
+            "type_into",
+            "Click the element with this id from the CSV, then type the text into it.",
+            object(json!({ "id": string("The element's id in the CSV"), "text": string("The text to type") }), &["id", "text"]),
+        ),
+        tool("focus_window", "Bring a window to the front, restoring it if minimized. Faster and more reliable than clicking the taskbar.", window()),
+        tool("minimize_window", "Minimize a window.", window()),
+        tool("maximize_window", "Maximize a window.", window()),
+        tool(
+            "launch",
+            "Start a program by name, or open a web link, file or folder with its default app, instead of looking for an icon to click. Only programs the user has allowed can be started.",
+            object(json!({ "target": string("A program name, link or path, e.g. 'firefox', 'https://example.com'") }), &["target"]),
+        ),
+        tool(
+            "browser_navigate",
+            "In the browser, load an http(s) address in the tab in front (starting a browser if none is running) and wait for the page to load.",
+            object(json!({ "url": string("The address to load") }), &["url"]),
+        ),
+        tool(
+            "browser_click",
+            "In the browser tab in front, click the element matching a CSS selector. The Web Page Elements list gives a selector for elements that have one.",
+            object(json!({ "selector": string("A CSS selector, e.g. '#submit'") }), &["selector"]),
+        ),
+        tool(
+            "browser_fill",
+            "In the browser tab in front, replace the contents of the input matching a CSS selector with the text.",
+            object(json!({ "selector": string("A CSS selector, e.g. 'input[name=\"email\"]'"), "text": string("The new contents") }), &["selector", "text"]),
+        ),
+        tool("long_press", "Touch and hold at a point for about a second, e.g. to open a context menu in a touch-first app.", point(json!({}), &[])),
+        tool(
+            "pinch",
+            "Two-finger pinch centered on a point.",
+            point(json!({ "scale": { "type": "number", "description": "Above 1 zooms in, below 1 zooms out" } }), &["scale"]),
+        ),
+        tool(
+            "two_finger_scroll",
+            "Two-finger scroll at a point, for touch-first apps and maps that ignore the mouse wheel.",
+            point(json!({ "amount": int("Positive scrolls down, negative up") }), &["amount"]),
+        ),
+        tool("done", "Stop and report the outcome of the command.", object(json!({ "message": string("The completion message") }), &["message"])),
+    ]
+}
+
+// How long long_press holds the touch down
+const LONG_PRESS_HOLD: Duration = Duration::from_millis(800);
+
+// How often a `wait:` action checks for ESC
+const WAIT_SLICE: Duration = Duration::from_millis(100);
+
+// How long the loop waits for a locked/sleeping session before aborting the task
+const MAX_SESSION_PAUSE: Duration = Duration::from_secs(10 * 60);
+
+// Safety break for runaway task loops
+const MAX_ITERATIONS: u32 = 100;
+
+// How many invalid actions in a row are answered with a correction prompt before giving up
+const MAX_CONSECUTIVE_INVALID_ACTIONS: u32 = 3;
+
+// How many actions in a row may fail while running before the task gives up
+const MAX_CONSECUTIVE_FAILED_ACTIONS: u32 = 3;
+
+// LLM decisions kept for reuse when the same prompt comes up again (settings.llm.cache_responses)
+const RESPONSE_CACHE_SIZE: usize = 64;
+
+// The reasoning inside <think> streams to the UI while the LLM is still answering
+pub const THOUGHT_CHUNK_EVENT: &str = "agent://thought-chunk";
+
+// Live progress of a task, one set per loop iteration
+pub const ITERATION_EVENT: &str = "task://iteration";
+pub const THOUGHT_EVENT: &str = "task://thought";
+pub const ACTION_EXECUTED_EVENT: &str = "task://action-executed";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IterationStarted {
+    pub iteration: u32,
+    pub elapsed_ms: u64, // Since the task started
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskThought {
+    pub iteration: u32,
+    pub thought: String,
+    pub action: String, // The action the LLM chose, not yet validated
+    pub llm_latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionExecuted {
+    pub iteration: u32,
+    pub action: String,
+    pub success: bool,
+    pub error: Option<String>, // Why it was rejected or failed
+    pub latency_ms: u64,       // Running the action itself
+    pub iteration_ms: u64,     // The whole iteration, capture to action
+}
+
+impl ActionExecuted {
+    fn emit(iteration: u32, action: &str, result: Result<(), &ActionError>, latency: Duration, iteration_time: Duration) {
+        events::emit(ACTION_EXECUTED_EVENT, ActionExecuted {
+            iteration,
+            action: action.to_string(),
+            success: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+            latency_ms: latency.as_millis() as u64,
+            iteration_ms: iteration_time.as_millis() as u64,
+        });
+    }
+}
+
+// In a dry run each action the agent would have taken is sent here instead of executed
+pub const PLANNED_ACTION_EVENT: &str = "agent://planned-action";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedAction {
+    pub iteration: u32,
+    pub action: String,      // As the LLM wrote it
+    pub description: String, // Spoken-style summary, as in status announcements
+    pub thought: String,
+}
+
+// Replies keyed by response_key, most recently used first
+static RESPONSE_CACHE: Lazy<Mutex<VecDeque<(String, LlmReply)>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// What a reply depends on: the model, and the rendered prompt (the command,
+/// previous actions and parsed screen) with the screenshot, if one is attached.
+fn response_key(llm: &dyn llm::LlmProvider, prompt: &str, screenshot_png: Option<&[u8]>) -> String {
+    let mut hasher = Sha256::new();
+    for part in [llm.name().as_bytes(), llm.model().as_bytes(), prompt.as_bytes(), screenshot_png.unwrap_or_default()] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn cached_response(key: &str) -> Option<LlmReply> {
+    let mut cache = RESPONSE_CACHE.lock_or_recover();
+    let position = cache.iter().position(|(cached, _)| cached == key)?;
+    let entry = cache.remove(position)?;
+    let response = entry.1.clone();
+    cache.push_front(entry);
+    Some(response)
+}
+
+fn cache_response(key: String, response: LlmReply) {
+    let mut cache = RESPONSE_CACHE.lock_or_recover();
+    cache.retain(|(cached, _)| *cached != key);
+    cache.push_front((key, response));
+    cache.truncate(RESPONSE_CACHE_SIZE);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThoughtChunk {
+    pub iteration: u32,
+    pub text: String, // New thought text since the previous chunk
+    pub done: bool,   // The thought is complete (</think> seen, or the reply ended)
+}
+
+/// Turns a streamed LLM reply into thought-chunk events for one loop iteration.
+struct ThoughtStream {
+    iteration: u32,
+    reply: String,
+    emitted: usize, // Bytes of thought already sent
+    done: bool,
+}
+
+impl ThoughtStream {
+    fn new(iteration: u32) -> Self {
+        ThoughtStream { iteration, reply: String::new(), emitted: 0, done: false }
+    }
+
+    fn push(&mut self, chunk: &str) {
+        self.reply.push_str(chunk);
+        if self.done {
+            return;
+        }
+        let Some(start) = self.reply.find("<think>") else {
+            return;
+        };
+        let body = &self.reply[start + "<think>".len()..];
+        let (thought, done) = match body.find("</think>") {
+            Some(end) => (&body[..end], true),
+            // Hold back what may be the start of the closing tag
+            None => (&body[..body.rfind('<').filter(|&i| !body[i..].contains('>')).unwrap_or(body.len())], false),
+        };
+        if thought.len() > self.emitted || done {
+            let text = thought[self.emitted.min(thought.len())..].to_string();
+            self.emitted = thought.len();
+            self.done = done;
+            events::emit(THOUGHT_CHUNK_EVENT, ThoughtChunk { iteration: self.iteration, text, done });
+        }
+    }
+
+    /// Closes the thought if the reply ended without a closing tag.
+    fn finish(&mut self) {
+        if !self.done && self.emitted > 0 {
+            self.done = true;
+            events::emit(THOUGHT_CHUNK_EVENT, ThoughtChunk { iteration: self.iteration, text: String::new(), done: true });
+        }
+    }
+}
+
+/// A fully parsed action, ready to execute.
+#[derive(Debug, Clone)]
+enum Action {
+    Click(i32, i32),
+    ClickDown(i32, i32),
+    ClickUp,
+    Drag(i32, i32),
+    Move(i32, i32),
+    Hover(i32, i32, u64), // Point, milliseconds to rest there
+    Tap(ParsedKey),
+    TapDown(Key),
+    TapUp(Key),
+    Keys(Vec<Key>, ParsedKey), // Held modifiers, then the tapped key
+    Scroll(i32),
+    HScroll(i32), // Positive is right
+    Wait(u64), // Milliseconds
+    Type(String),
+    TypeInto(i32, i32, String), // Click at the point, then type
+    ClickElement(String, Option<String>), // Accessible name, optional control type
+    Window(WindowOp, String), // Operation, title to match
+    Launch(String), // Program name, link or path
+    Browser(BrowserOp),
+    LongPress(i32, i32),
+    Pinch(i32, i32, f32),          // Center, scale (> 1 zooms in)
+    TwoFingerScroll(i32, i32, i32), // Point, units (positive is down)
+    OnMonitor(usize, Box<Action>), // The inner action's point is that monitor's pixel
+    Done(String),
+}
+
+/// Wraps an action parsed from a monitor-qualified point.
+fn qualify(monitor: Option<usize>, action: Action) -> Action {
+    match monitor {
+        Some(monitor) => Action::OnMonitor(monitor, Box::new(action)),
+        None => action,
+    }
+}
+
+/// Strips one pair of surrounding single quotes, if present.
+fn unquote(value: &str) -> Option<&str> {
+    let trimmed = value.trim();
+    if trimmed.starts_with('\'') && trimmed.ends_with('\'') && trimmed.len() >= 2 {
+        Some(&trimmed[1..trimmed.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Parses an action string against the action grammar without executing it.
+fn parse_action(action_str: &str, frame: &CoordinateFrame) -> Result<Action, ActionError> {
     let parts: Vec<&str> = action_str.splitn(2, ':').collect();
     if parts.len() != 2 {
-        return Err(format!("Invalid action format: {}", action_str));
+        return Err(ActionError::InvalidFormat(action_str.to_string()));
     }
-    let action_type = parts[0];
+    let action_type = parts[0].trim();
     let value_str = parts[1];
 
     match action_type {
-        "click" => {
-            let (x, y) = parse_coordinate(value_str)?;
-            enigo.move_mouse(x, y, Coordinate::Abs).map_err(|e| e.to_string())?;
-            // Use Button::Left instead of MouseButton::Left
-            enigo.button(Button::Left, Direction::Click).map_err(|e| e.to_string())?;
-            Ok(true)
-        }
-        "click_down" => {
-            let (x, y) = parse_coordinate(value_str)?;
-            enigo.move_mouse(x, y, Coordinate::Abs).map_err(|e| e.to_string())?;
-            enigo.button(Button::Left, Direction::Press).map_err(|e| e.to_string())?;
-            Ok(true)
-        }
+        "click" => parse_point(value_str, frame).map(|(m, x, y)| qualify(m, Action::Click(x, y))),
+        "click_down" => parse_point(value_str, frame).map(|(m, x, y)| qualify(m, Action::ClickDown(x, y))),
         "click_up" => {
-            if value_str != "nil" {
-                eprintln!("Warning: click_up value is ignored, expected 'nil', got '{}'", value_str);
+            if value_str.trim() != "nil" {
+                warn!("click_up value is ignored, expected 'nil', got '{}'", value_str);
             }
-            enigo.button(Button::Left, Direction::Release).map_err(|e| e.to_string())?;
-            Ok(true)
-        }
-        "drag" => {
-            let (x, y) = parse_coordinate(value_str)?;
-            enigo.move_mouse(x, y, Coordinate::Abs).map_err(|e| e.to_string())?;
-            Ok(true)
-        }
-        "tap" => {
-            match parse_key(value_str)? {
-                ParsedKey::Key(key) => enigo.key(key, Direction::Click).map_err(|e| e.to_string())?,
-                ParsedKey::Char(c) => enigo.text(&c.to_string()).map_err(|e| e.to_string())?, // Use text for single chars
+            Ok(Action::ClickUp)
+        }
+        "drag" => parse_point(value_str, frame).map(|(m, x, y)| qualify(m, Action::Drag(x, y))),
+        "move" => parse_point(value_str, frame).map(|(m, x, y)| qualify(m, Action::Move(x, y))),
+        "hover" => {
+            let (m, x, y, ms) = parse_gesture(value_str, frame)?;
+            ms.parse::<u64>()
+                .map(|ms| qualify(m, Action::Hover(x, y, ms)))
+                .map_err(|_| ActionError::InvalidValue { action: "hover", value: ms.to_string() })
+        }
+        "tap" => parse_key(value_str).map(Action::Tap),
+        // tap_down/up only make sense for specific keys; text() is an atomic type
+        "tap_down" => match parse_key(value_str)? {
+            ParsedKey::Key(key) => Ok(Action::TapDown(key)),
+            ParsedKey::Char(c) => Err(ActionError::UnsupportedChar { action: "tap_down", ch: c }),
+        },
+        "tap_up" => match parse_key(value_str)? {
+            ParsedKey::Key(key) => Ok(Action::TapUp(key)),
+            ParsedKey::Char(c) => Err(ActionError::UnsupportedChar { action: "tap_up", ch: c }),
+        },
+        "keys" => parse_chord(value_str).map(|(modifiers, key)| Action::Keys(modifiers, key)),
+        "scroll" => value_str.trim().parse::<i32>()
+            .map(Action::Scroll)
+            .map_err(|_| ActionError::InvalidValue { action: "scroll", value: value_str.to_string() }),
+        "hscroll" => value_str.trim().parse::<i32>()
+            .map(Action::HScroll)
+            .map_err(|_| ActionError::InvalidValue { action: "hscroll", value: value_str.to_string() }),
+        "wait" => value_str.trim().parse::<u64>()
+            .map(Action::Wait)
+            .map_err(|_| ActionError::InvalidValue { action: "wait", value: value_str.to_string() }),
+        "type" => unquote(value_str)
+            .map(|text| Action::Type(text.to_string()))
+            .ok_or_else(|| ActionError::InvalidFormat(value_str.to_string())),
+        // An unquoted id is an element of the screen CSV; a quoted name is an accessible element
+        "click_element" if !value_str.trim_start().starts_with('\'') => {
+            element_point(value_str, frame).map(|(x, y)| Action::Click(x, y))
+        }
+        "click_element" => parse_element(value_str)
+            .map(|(name, control_type)| Action::ClickElement(name, control_type)),
+        "type_into" => {
+            let (id, text) = parse_type_into(value_str)?;
+            element_point(id, frame).map(|(x, y)| Action::TypeInto(x, y, text.to_string()))
+        }
+        "focus_window" | "minimize_window" | "maximize_window" => {
+            let op = match action_type {
+                "focus_window" => WindowOp::Focus,
+                "minimize_window" => WindowOp::Minimize,
+                _ => WindowOp::Maximize,
+            };
+            unquote(value_str)
+                .filter(|title| !title.trim().is_empty())
+                .map(|title| Action::Window(op, title.to_string()))
+                .ok_or_else(|| ActionError::InvalidFormat(value_str.to_string()))
+        }
+        "launch" => unquote(value_str)
+            .filter(|target| !target.trim().is_empty())
+            .map(|target| Action::Launch(target.trim().to_string()))
+            .ok_or_else(|| ActionError::InvalidFormat(value_str.to_string())),
+        "browser_navigate" => unquote(value_str)
+            .filter(|url| !url.trim().is_empty())
+            .map(|url| Action::Browser(BrowserOp::Navigate(url.trim().to_string())))
+            .ok_or_else(|| ActionError::InvalidFormat(value_str.to_string())),
+        "browser_click" => unquote(value_str)
+            .filter(|selector| !selector.trim().is_empty())
+            .map(|selector| Action::Browser(BrowserOp::Click(selector.trim().to_string())))
+            .ok_or_else(|| ActionError::InvalidFormat(value_str.to_string())),
+        "browser_fill" => parse_fill(value_str).map(Action::Browser),
+        "long_press" => parse_point(value_str, frame).map(|(m, x, y)| qualify(m, Action::LongPress(x, y))),
+        "pinch" => {
+            let (m, x, y, scale) = parse_gesture(value_str, frame)?;
+            match scale.parse::<f32>() {
+                Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(qualify(m, Action::Pinch(x, y, scale))),
+                _ => Err(ActionError::InvalidValue { action: "pinch", value: scale.to_string() }),
             }
-            Ok(true)
-        }
-        "tap_down" => {
-            match parse_key(value_str)? {
-                ParsedKey::Key(key) => enigo.key(key, Direction::Press).map_err(|e| e.to_string())?,
-                // tap_down doesn't make sense for text(), only for specific keys. Error? Or press equivalent char?
-                // Let's treat single char tap_down/up as an error for now, as enigo.text() is atomic type.
-                ParsedKey::Char(c) => return Err(format!("'tap_down' action is not supported for single character '{}'. Use specific Key names like 'Shift'.", c)),
+        }
+        "two_finger_scroll" => {
+            let (m, x, y, units) = parse_gesture(value_str, frame)?;
+            units.parse::<i32>()
+                .map(|units| qualify(m, Action::TwoFingerScroll(x, y, units)))
+                .map_err(|_| ActionError::InvalidValue { action: "two_finger_scroll", value: units.to_string() })
+        }
+        "done" => {
+            let done_message = unquote(value_str).unwrap_or_else(|| value_str.trim());
+            Ok(Action::Done(done_message.to_string()))
+        }
+        _ => Err(ActionError::UnknownAction(action_type.to_string())),
+    }
+}
+
+/// A tool call's arguments, read by name.
+struct ToolArgs<'a> {
+    tool: &'a str,
+    arguments: &'a serde_json::Map<String, Value>,
+}
+
+impl<'a> ToolArgs<'a> {
+    fn new(call: &'a ToolCall) -> Result<Self, ActionError> {
+        match &call.arguments {
+            Value::Object(arguments) => Ok(ToolArgs { tool: &call.name, arguments }),
+            other => Err(ActionError::InvalidArguments { tool: call.name.clone(), reason: format!("not a JSON object: {}", other) }),
+        }
+    }
+
+    fn invalid(&self, reason: String) -> ActionError {
+        ActionError::InvalidArguments { tool: self.tool.to_string(), reason }
+    }
+
+    fn get(&self, name: &str) -> Option<&'a Value> {
+        self.arguments.get(name).filter(|value| !value.is_null())
+    }
+
+    fn int(&self, name: &str) -> Result<i64, ActionError> {
+        let value = self.get(name).ok_or_else(|| self.invalid(format!("'{}' is missing", name)))?;
+        // Some models send whole numbers as 120.0
+        value
+            .as_i64()
+            .or_else(|| value.as_f64().filter(|v| v.fract() == 0.0 && v.abs() < i64::MAX as f64).map(|v| v as i64))
+            .ok_or_else(|| self.invalid(format!("'{}' must be an integer, got {}", name, value)))
+    }
+
+    fn i32(&self, name: &str) -> Result<i32, ActionError> {
+        let value = self.int(name)?;
+        i32::try_from(value).map_err(|_| self.invalid(format!("'{}' is out of range: {}", name, value)))
+    }
+
+    fn u64(&self, name: &str) -> Result<u64, ActionError> {
+        let value = self.int(name)?;
+        u64::try_from(value).map_err(|_| self.invalid(format!("'{}' must not be negative: {}", name, value)))
+    }
+
+    fn number(&self, name: &str) -> Result<f64, ActionError> {
+        let value = self.get(name).ok_or_else(|| self.invalid(format!("'{}' is missing", name)))?;
+        value.as_f64().ok_or_else(|| self.invalid(format!("'{}' must be a number, got {}", name, value)))
+    }
+
+    fn optional_string(&self, name: &str) -> Result<Option<&'a str>, ActionError> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(value) => value.as_str().map(Some).ok_or_else(|| self.invalid(format!("'{}' must be a string, got {}", name, value))),
+        }
+    }
+
+    fn string(&self, name: &str) -> Result<&'a str, ActionError> {
+        self.optional_string(name)?.ok_or_else(|| self.invalid(format!("'{}' is missing", name)))
+    }
+
+    /// A string that must say something, trimmed.
+    fn non_empty(&self, name: &str) -> Result<&'a str, ActionError> {
+        Some(self.string(name)?.trim()).filter(|value| !value.is_empty()).ok_or_else(|| self.invalid(format!("'{}' is empty", name)))
+    }
+
+    /// An element id, which models send as a number as often as a string.
+    fn id(&self, name: &str) -> Result<String, ActionError> {
+        match self.get(name) {
+            Some(Value::Number(id)) => Ok(id.to_string()),
+            _ => self.non_empty(name).map(str::to_string),
+        }
+    }
+
+    /// The point in x and y, on the monitor named by `monitor` if any (see parse_point).
+    fn point(&self, frame: &CoordinateFrame) -> Result<(Option<usize>, i32, i32), ActionError> {
+        let (x, y) = (self.i32("x")?, self.i32("y")?);
+        match self.get("monitor") {
+            Some(_) => {
+                let monitor = usize::try_from(self.int("monitor")?).map_err(|_| self.invalid("'monitor' must not be negative".to_string()))?;
+                on_monitor(monitor, x, y, frame)
             }
-            Ok(true)
+            None => Ok((None, x, y)),
+        }
+    }
+}
+
+/// Builds the action a tool call (see action_tools) asks for from its typed
+/// arguments, like parse_action does from a line of the grammar.
+fn parse_tool_call(call: &ToolCall, frame: &CoordinateFrame) -> Result<Action, ActionError> {
+    let args = ToolArgs::new(call)?;
+    let held_key = |action: &'static str| match parse_key_name(args.string("key")?)? {
+        ParsedKey::Key(key) => Ok(key),
+        ParsedKey::Char(c) => Err(ActionError::UnsupportedChar { action, ch: c }),
+    };
+    match call.name.as_str() {
+        "click" => args.point(frame).map(|(m, x, y)| qualify(m, Action::Click(x, y))),
+        "click_down" => args.point(frame).map(|(m, x, y)| qualify(m, Action::ClickDown(x, y))),
+        "click_up" => Ok(Action::ClickUp),
+        "drag" => args.point(frame).map(|(m, x, y)| qualify(m, Action::Drag(x, y))),
+        "move" => args.point(frame).map(|(m, x, y)| qualify(m, Action::Move(x, y))),
+        "hover" => {
+            let (m, x, y) = args.point(frame)?;
+            Ok(qualify(m, Action::Hover(x, y, args.u64("ms")?)))
+        }
+        "tap" => parse_key_name(args.string("key")?).map(Action::Tap),
+        "tap_down" => held_key("tap_down").map(Action::TapDown),
+        "tap_up" => held_key("tap_up").map(Action::TapUp),
+        "keys" => {
+            let keys = args.non_empty("keys")?;
+            chord_keys(keys, keys).map(|(modifiers, key)| Action::Keys(modifiers, key))
         }
-        "tap_up" => {
-            match parse_key(value_str)? {
-                ParsedKey::Key(key) => enigo.key(key, Direction::Release).map_err(|e| e.to_string())?,
-                ParsedKey::Char(c) => return Err(format!("'tap_up' action is not supported for single character '{}'. Use specific Key names like 'Shift'.", c)),
+        "scroll" => args.i32("amount").map(Action::Scroll),
+        "hscroll" => args.i32("amount").map(Action::HScroll),
+        "wait" => args.u64("ms").map(Action::Wait),
+        "type" => args.string("text").map(|text| Action::Type(text.to_string())),
+        "click_element" => match (args.get("id"), args.optional_string("name")?) {
+            (Some(_), _) => element_point(&args.id("id")?, frame).map(|(x, y)| Action::Click(x, y)),
+            (None, Some(name)) if !name.trim().is_empty() => {
+                let role = args.optional_string("role")?.map(str::trim).filter(|role| !role.is_empty());
+                Ok(Action::ClickElement(name.trim().to_string(), role.map(str::to_string)))
             }
-            Ok(true)
-        }
-        "scroll" => {
-            let units = value_str.parse::<i32>().map_err(|e| format!("Invalid scroll value: {}. {}", value_str, e))?;
-            // Use enigo.scroll with Axis::Vertical instead of enigo.wheel
-            enigo.scroll(units, Axis::Vertical).map_err(|e| e.to_string())?;
-            Ok(true)
-        }
-        "type" => {
-            let trimmed = value_str.trim();
-            if !trimmed.starts_with('\'') || !trimmed.ends_with('\'') || trimmed.len() < 2 {
-                return Err(format!("Invalid type format: {}", value_str));
+            (None, _) => Err(args.invalid("give the element's 'id' or its 'name'".to_string())),
+        },
+        "type_into" => {
+            let text = args.string("text")?;
+            element_point(&args.id("id")?, frame).map(|(x, y)| Action::TypeInto(x, y, text.to_string()))
+        }
+        "focus_window" => args.non_empty("title").map(|title| Action::Window(WindowOp::Focus, title.to_string())),
+        "minimize_window" => args.non_empty("title").map(|title| Action::Window(WindowOp::Minimize, title.to_string())),
+        "maximize_window" => args.non_empty("title").map(|title| Action::Window(WindowOp::Maximize, title.to_string())),
+        "launch" => args.non_empty("target").map(|target| Action::Launch(target.to_string())),
+        "browser_navigate" => args.non_empty("url").map(|url| Action::Browser(BrowserOp::Navigate(url.to_string()))),
+        "browser_click" => args.non_empty("selector").map(|selector| Action::Browser(BrowserOp::Click(selector.to_string()))),
+        "browser_fill" => {
+            let selector = args.non_empty("selector")?;
+            Ok(Action::Browser(BrowserOp::Fill(selector.to_string(), args.string("text")?.to_string())))
+        }
+        "long_press" => args.point(frame).map(|(m, x, y)| qualify(m, Action::LongPress(x, y))),
+        "pinch" => {
+            let (m, x, y) = args.point(frame)?;
+            match args.number("scale")? as f32 {
+                scale if scale.is_finite() && scale > 0.0 => Ok(qualify(m, Action::Pinch(x, y, scale))),
+                scale => Err(ActionError::InvalidValue { action: "pinch", value: scale.to_string() }),
             }
-            let text_to_type = &trimmed[1..trimmed.len() - 1];
-            enigo.text(text_to_type).map_err(|e| e.to_string())?;
-            Ok(true)
         }
-        "done" => {
-            let trimmed = value_str.trim();
-            let done_message = if trimmed.starts_with('\'') && trimmed.ends_with('\'') && trimmed.len() >= 2 {
-                &trimmed[1..trimmed.len() - 1]
-            } else {
-                trimmed
-            };
-            println!("Action loop finished: {}", done_message);
-            Ok(false)
+        "two_finger_scroll" => {
+            let (m, x, y) = args.point(frame)?;
+            Ok(qualify(m, Action::TwoFingerScroll(x, y, args.i32("amount")?)))
         }
-        _ => Err(format!("Unknown action type: {}", action_type)),
+        "done" => args.string("message").map(|message| Action::Done(message.trim().to_string())),
+        other => Err(ActionError::UnknownAction(other.to_string())),
     }
 }
 
+impl Action {
+    /// The action with its point converted to screen coordinates: from
+    /// screenshot pixels, or from its monitor's pixels.
+    fn mapped(&self, map: &CoordinateMap) -> Action {
+        match self {
+            Action::OnMonitor(monitor, action) => action.moved(|x, y| map.monitor_point(*monitor, x, y)),
+            _ => self.moved(|x, y| map.screen_point(x, y)),
+        }
+    }
+
+    fn moved(&self, to: impl Fn(i32, i32) -> (i32, i32)) -> Action {
+        let mut action = self.clone();
+        if let Action::Click(x, y)
+        | Action::ClickDown(x, y)
+        | Action::Drag(x, y)
+        | Action::Move(x, y)
+        | Action::Hover(x, y, _)
+        | Action::TypeInto(x, y, _)
+        | Action::LongPress(x, y)
+        | Action::Pinch(x, y, _)
+        | Action::TwoFingerScroll(x, y, _) = &mut action
+        {
+            (*x, *y) = to(*x, *y);
+        }
+        action
+    }
 
-/// Captures screen, sends to Python backend, returns CSV content.
-fn get_screen_csv() -> Result<String, String> {
-    println!("Capturing screen for CSV conversion...");
-    let screenshot = capture_screen().map_err(|e| format!("Screen capture failed: {}", e))?;
+    /// Whether the action clicks something, for post-action verification.
+    fn clicks(&self) -> bool {
+        match self {
+            Action::Click(..) | Action::ClickElement(..) | Action::TypeInto(..) | Action::Browser(BrowserOp::Click(_)) => true,
+            Action::OnMonitor(_, action) => action.clicks(),
+            _ => false,
+        }
+    }
 
-    let mut buffer = Cursor::new(Vec::new());
-    screenshot.write_to(&mut buffer, image::ImageOutputFormat::Png)
-        .map_err(|e| format!("Failed to write PNG to buffer: {}", e))?;
+    /// Where the action puts the pointer in the screenshot, if anywhere.
+    fn point(&self) -> Option<(i32, i32)> {
+        match *self {
+            Action::Click(x, y)
+            | Action::ClickDown(x, y)
+            | Action::Drag(x, y)
+            | Action::Move(x, y)
+            | Action::Hover(x, y, _)
+            | Action::TypeInto(x, y, _)
+            | Action::LongPress(x, y)
+            | Action::Pinch(x, y, _)
+            | Action::TwoFingerScroll(x, y, _) => Some((x, y)),
+            _ => None,
+        }
+    }
 
-    let image_base64 = STANDARD.encode(buffer.get_ref());
+    /// The base for `rel:` coordinates once the action ran, `before` being the
+    /// base it ran with. Off the captured monitor the pointer leaves the screenshot.
+    fn pointer_after(&self, before: Option<(i32, i32)>) -> Option<(i32, i32)> {
+        match self {
+            Action::OnMonitor(..) => None,
+            _ => self.point().or(before),
+        }
+    }
+}
 
-    let client = Client::builder()
-        .timeout(Duration::from_secs(120))
-        .build()
-        .map_err(|e| format!("Failed to build reqwest client: {}", e))?;
+/// Parses `action_str`, resolving relative coordinates, and checks any coordinates
+/// against the screen bounds.
+fn validate_action(action_str: &str, frame: &CoordinateFrame) -> Result<Action, ActionError> {
+    in_bounds(parse_action(action_str, frame)?, frame)
+}
 
-    let payload = json!({ "image": image_base64 });
+/// Like validate_action, for an action the LLM asked for with a tool call.
+fn validate_tool_call(call: &ToolCall, frame: &CoordinateFrame) -> Result<Action, ActionError> {
+    in_bounds(parse_tool_call(call, frame)?, frame)
+}
 
-    println!("Sending image to Python backend...");
-    let resp = client
-        .post("http://localhost:5001/api/processImage")
-        .json(&payload)
-        .send()
-        .map_err(|e| format!("Failed to send request to Python backend: {}", e))?;
+fn in_bounds(action: Action, frame: &CoordinateFrame) -> Result<Action, ActionError> {
+    if let Some((x, y)) = action.point() {
+        let (width, height) = frame.map.image;
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return Err(ActionError::OutOfBounds { x, y, width, height });
+        }
+    }
+    Ok(action)
+}
 
-    // --- Fix for E0382 ---
-    // Get status *before* consuming the body with .text() or .json()
-    let status = resp.status();
-    println!("Received response status: {}", status);
+/// Builds the follow-up prompt section telling the LLM its last action failed
+/// while running, so it can try another way.
+fn failure_prompt(action_str: &str, error: &ActionError, grammar: &str) -> String {
+    format!(
+        "--- Failed Action ---\n\
+         Your previous action `{action_str}` failed while running ({kind}): {error}\n\
+         Part of it may have happened; check the current screen. Don't repeat it unchanged: \
+         correct it, or reach the goal another way, with exactly one action from this grammar:\n\
+         {grammar}",
+        action_str = action_str,
+        kind = error.kind(),
+        error = error,
+        grammar = grammar,
+    )
+}
 
-    if !status.is_success() {
-        // Now consume the body safely to get the error message
-        let error_body = resp.text().unwrap_or_else(|_| "Could not read error body".to_string());
-        // Use the stored status variable
-        return Err(format!("Python backend returned error {}: {}", status, error_body));
+/// An executed action, remembered until the next capture shows what it did.
+struct ExecutedAction {
+    action: String,       // As the LLM wrote it
+    clicked: bool,        // Whether it clicked, where no change usually means a miss
+    frame_before: String, // dedup hash of the screen it was planned against
+}
+
+impl ExecutedAction {
+    /// The Last Action Result section, comparing the screen before the action
+    /// with `frame_after`, the hash of the capture that followed it.
+    fn verification(&self, frame_after: &str) -> String {
+        let outcome = if !dedup::same_screen(&self.frame_before, frame_after) {
+            "The screen changed after it."
+        } else if self.clicked {
+            "The screen did NOT change after it: the click probably hit nothing that responds. \
+             Don't click the same spot again; pick another element, use keyboard navigation, or wait if something is loading."
+        } else {
+            "The screen did NOT change after it."
+        };
+        format!(
+            "--- Last Action Result ---\n\
+             Your previous action was `{action_str}`. {outcome}\n",
+            action_str = self.action,
+            outcome = outcome,
+        )
     }
-    // --- End Fix ---
+}
+
+/// Builds the follow-up prompt section telling the LLM why its last action was rejected.
+fn correction_prompt(action_str: &str, error: &ActionError, screen: (i32, i32), grammar: &str) -> String {
+    format!(
+        "--- Invalid Action ---\n\
+         Your previous action `{action_str}` was NOT executed because it is invalid ({kind}): {error}\n\
+         The screen is {width}x{height} pixels; coordinates must lie inside it.\n\
+         Respond again with a <think></think> block followed by exactly one action from this grammar:\n\
+         {grammar}",
+        action_str = action_str,
+        kind = error.kind(),
+        error = error,
+        width = screen.0,
+        height = screen.1,
+        grammar = grammar,
+    )
+}
+
+/// Short spoken description of an action for status announcements, leaving out typed text and keys (they may be passwords).
+fn describe_action(action: &Action) -> String {
+    match action {
+        Action::Click(x, y) => format!("Clicking at {}, {}", x, y),
+        Action::ClickDown(x, y) => format!("Pressing the mouse at {}, {}", x, y),
+        Action::ClickUp => "Releasing the mouse".to_string(),
+        Action::Drag(x, y) => format!("Dragging to {}, {}", x, y),
+        Action::Move(x, y) => format!("Moving the mouse to {}, {}", x, y),
+        Action::Hover(x, y, _) => format!("Hovering at {}, {}", x, y),
+        Action::Tap(ParsedKey::Key(key)) => format!("Pressing {:?}", key),
+        Action::Tap(ParsedKey::Char(_)) | Action::Type(_) | Action::TypeInto(..) => "Typing text".to_string(),
+        Action::TapDown(key) => format!("Holding {:?}", key),
+        Action::TapUp(key) => format!("Releasing {:?}", key),
+        Action::Keys(modifiers, key) => {
+            let mut names: Vec<String> = modifiers.iter().map(|m| format!("{:?}", m)).collect();
+            names.push(match key {
+                ParsedKey::Key(key) => format!("{:?}", key),
+                ParsedKey::Char(c) => c.to_uppercase().to_string(),
+            });
+            format!("Pressing {}", names.join("+"))
+        }
+        Action::Scroll(units) if *units < 0 => "Scrolling up".to_string(),
+        Action::Scroll(_) => "Scrolling down".to_string(),
+        Action::HScroll(units) if *units < 0 => "Scrolling left".to_string(),
+        Action::HScroll(_) => "Scrolling right".to_string(),
+        Action::Wait(ms) => format!("Waiting {} milliseconds", ms),
+        Action::ClickElement(name, _) => format!("Clicking {}", name),
+        Action::Window(WindowOp::Focus, title) => format!("Switching to {}", title),
+        Action::Window(WindowOp::Minimize, title) => format!("Minimizing {}", title),
+        Action::Window(WindowOp::Maximize, title) => format!("Maximizing {}", title),
+        Action::Launch(target) => format!("Opening {}", target),
+        Action::Browser(BrowserOp::Navigate(url)) => format!("Opening {}", url),
+        Action::Browser(BrowserOp::Click(selector)) => format!("Clicking {}", selector),
+        Action::Browser(BrowserOp::Fill(selector, _)) => format!("Filling {}", selector),
+        Action::LongPress(x, y) => format!("Long-pressing at {}, {}", x, y),
+        Action::Pinch(_, _, scale) if *scale > 1.0 => "Zooming in".to_string(),
+        Action::Pinch(..) => "Zooming out".to_string(),
+        Action::TwoFingerScroll(..) => "Scrolling".to_string(),
+        Action::OnMonitor(monitor, action) => format!("{} on monitor {}", describe_action(action), monitor),
+        Action::Done(_) => "Finishing".to_string(),
+    }
+}
+
+/// Executes a single parsed action.
+/// Returns Ok(true) to continue, Ok(false) for "done", Err on failure.
+fn do_action(action: &Action, map: &CoordinateMap, input: &mut dyn InputBackend, clock: &dyn Clock, app_state: &SharedAppState) -> Result<bool, ActionError> {
+    let action = &action.mapped(map);
+    info!("Executing action: {:?}", action);
+    match action {
+        Action::Click(x, y) => {
+            input.move_mouse(*x, *y)?;
+            input.left_button(Direction::Click)?;
+        }
+        Action::ClickDown(x, y) => {
+            input.move_mouse(*x, *y)?;
+            input.left_button(Direction::Press)?;
+        }
+        Action::ClickUp => input.left_button(Direction::Release)?,
+        Action::Drag(x, y) | Action::Move(x, y) => input.move_mouse(*x, *y)?,
+        Action::Hover(x, y, ms) => {
+            input.move_mouse(*x, *y)?;
+            wait(app_state, clock, capped_wait(*ms));
+        }
+        Action::Tap(ParsedKey::Key(key)) => input.key(*key, Direction::Click)?,
+        // Sent as the key that types `c` on the active layout, so chords like
+        // Control+'a' work on AZERTY/QWERTZ; text() covers characters no key makes
+        Action::Tap(ParsedKey::Char(c)) => input.key(Key::Unicode(*c), Direction::Click)
+            .or_else(|_| input.text(&c.to_string()))?,
+        Action::TapDown(key) => input.key(*key, Direction::Press)?,
+        Action::TapUp(key) => input.key(*key, Direction::Release)?,
+        Action::Keys(modifiers, key) => press_chord(input, modifiers, key)?,
+        Action::Scroll(units) => input.scroll(*units)?,
+        Action::HScroll(units) => input.hscroll(*units)?,
+        Action::Wait(ms) => wait(app_state, clock, capped_wait(*ms)),
+        Action::Type(text) => input.text(text)?,
+        Action::TypeInto(x, y, text) => {
+            input.move_mouse(*x, *y)?;
+            input.left_button(Direction::Click)?;
+            input.text(text)?;
+        }
+        Action::ClickElement(name, control_type) => input.activate_element(name, control_type.as_deref())?,
+        Action::Window(op, title) => input.control_window(title, *op)?,
+        Action::Launch(target) => input.launch(target)?,
+        Action::Browser(op) => input.browser(op)?,
+        Action::LongPress(x, y) => input.long_press(*x, *y, LONG_PRESS_HOLD)?,
+        Action::Pinch(x, y, scale) => input.pinch(*x, *y, *scale)?,
+        Action::TwoFingerScroll(x, y, units) => input.two_finger_scroll(*x, *y, *units)?,
+        Action::OnMonitor(..) => {} // mapped() above resolved it to the inner action
+        Action::Done(message) => {
+            info!("Action loop finished: {}", message);
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Parses and performs one action from the grammar outside the task loop (the
+/// MCP server's tools), its coordinates in the pixels of the screenshot `map`
+/// was recorded for, and element ids in the screen CSV `elements`. `rel:`
+/// coordinates aren't available. Returns the action's description.
+pub fn perform(action_str: &str, map: &CoordinateMap, elements: Option<&str>, input: &mut dyn InputBackend, clock: &dyn Clock, app_state: &SharedAppState) -> Result<String, ActionError> {
+    let frame = CoordinateFrame { map, pointer: None, elements };
+    let action = validate_action(action_str, &frame)?;
+    let description = describe_action(&action);
+    do_action(&action, map, input, clock, app_state)?;
+    Ok(description)
+}
+
+fn task_paused(app_state: &SharedAppState) -> bool {
+    app_state.lock_or_recover().input_state() == AppInputState::Paused
+}
+
+/// Blocks while the user has the task paused (pause_task). Returns false if they
+/// pressed ESC meanwhile, which aborts the task.
+fn wait_while_paused(app_state: &SharedAppState, clock: &dyn Clock) -> bool {
+    info!("Task paused; waiting for resume.");
+    loop {
+        {
+            let state = app_state.lock_or_recover();
+            if state.action_interrupted {
+                return false;
+            }
+            if state.input_state() != AppInputState::Paused {
+                info!("Task resumed.");
+                return true;
+            }
+        }
+        clock.sleep(WAIT_SLICE);
+    }
+}
+
+/// `ms` as a duration, cut down to the configured longest wait.
+fn capped_wait(ms: u64) -> Duration {
+    let limit = config::get().timings.max_wait_ms;
+    if ms > limit {
+        info!("Capping wait of {} ms to the {} ms limit.", ms, limit);
+    }
+    Duration::from_millis(ms.min(limit))
+}
+
+/// Sleeps for `duration` in short slices, returning early if the user pressed ESC.
+pub fn wait(app_state: &SharedAppState, clock: &dyn Clock, duration: Duration) {
+    let deadline = clock.now() + duration;
+    while !app_state.lock_or_recover().action_interrupted {
+        let remaining = deadline.saturating_duration_since(clock.now());
+        if remaining.is_zero() {
+            break;
+        }
+        clock.sleep(remaining.min(WAIT_SLICE));
+    }
+}
+
+/// Holds `modifiers` in order, taps `key`, then releases the modifiers in
+/// reverse, even when a step in between fails.
+fn press_chord(input: &mut dyn InputBackend, modifiers: &[Key], key: &ParsedKey) -> Result<(), ActionError> {
+    let mut held = 0;
+    let mut result = Ok(());
+    for modifier in modifiers {
+        result = input.key(*modifier, Direction::Press);
+        if result.is_err() {
+            break;
+        }
+        held += 1;
+    }
+    if result.is_ok() {
+        result = match key {
+            ParsedKey::Key(key) => input.key(*key, Direction::Click),
+            // Layout-aware like tap, so ctrl+'z' is the key that types z
+            ParsedKey::Char(c) => input.key(Key::Unicode(*c), Direction::Click),
+        };
+    }
+    for modifier in modifiers[..held].iter().rev() {
+        if let Err(e) = input.key(*modifier, Direction::Release) {
+            warn!("Failed to release {:?} after a chord: {}", modifier, e);
+        }
+    }
+    result
+}
+
+/// How the model sees the screen during a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisionMode {
+    /// Only the CSV parsed by the Python backend
+    #[default]
+    Off,
+    /// Only the raw screenshot, sent to a multimodal model; no backend needed
+    Screenshot,
+    /// The screenshot plus the parsed CSV
+    Both,
+}
+
+impl VisionMode {
+    pub fn parses(self) -> bool {
+        self != VisionMode::Screenshot
+    }
+
+    fn attaches(self) -> bool {
+        self != VisionMode::Off
+    }
+}
+
+/// Captures the target's screen, sends to Python backend, returns CSV content.
+pub fn get_screen_csv(screen: &dyn CaptureBackend) -> Result<String, ParserError> {
+    info!("Capturing screen ({}) for CSV conversion...", screen.name());
+    let screenshot = screen.capture()?.ok_or(CaptureError::NoMonitors)?;
+    parse_screenshot(&screenshot)
+}
+
+/// Parses an already captured screenshot with the configured engine, returns CSV content.
+pub fn parse_screenshot(screenshot: &DynamicImage) -> Result<String, ParserError> {
+    if config::get().parser.engine == ParserEngine::Native {
+        return parser::parse(screenshot).map(|csv| redact::redact(&csv).into_owned());
+    }
+    // PNG + base64 are streamed straight into the request body
+    let payload = backend::image_payload_from_image(screenshot)?;
+
+    let client = net::blocking_client(Duration::from_secs(120))?;
+
+    info!("Sending image to Python backend...");
+    // Non-success statuses come back as errors, after any retries
+    let resp = backend::post_image_payload(&client, payload)?;
+    info!("Received response status: {}", resp.status());
 
     // Consume body to get JSON
     let json_resp: serde_json::Value = resp.json()
-        .map_err(|e| format!("Failed to parse JSON response from Python backend: {}", e))?;
+        .map_err(|e| ParserError::InvalidResponse(e.to_string()))?;
 
     if let Some(parsed_content) = json_resp.get("parsed_content").and_then(|v| v.as_str()) {
-        println!("Successfully received CSV data from backend.");
-        Ok(parsed_content.to_string())
+        info!("Successfully received CSV data from backend.");
+        Ok(redact::redact(parsed_content).into_owned()) // Scrubbed before it can reach a prompt
     } else {
-        Err("Python backend response missing 'parsed_content' field or it's not a string".to_string())
+        Err(ParserError::MissingContent)
     }
 }
 
+/// Encodes a screenshot as PNG for attaching to the LLM request.
+pub fn encode_png(screenshot: &DynamicImage) -> Result<Vec<u8>, ParserError> {
+    let mut png = Vec::new();
+    perf::time(Stage::Encode, || screenshot.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png))
+        .map_err(|e| ParserError::Encode(e.to_string()))?;
+    Ok(png)
+}
+
 
 // Renamed from start_action - This is the main loop controller
-pub fn execute_task_loop(initial_command: String) -> Result<String, String> {
+#[allow(clippy::too_many_arguments)]
+pub fn execute_task_loop(
+    initial_command: String,
+    app_state: &SharedAppState,
+    recording: &SharedRecordingState,
+    clock: &dyn Clock,
+    input: &mut dyn InputBackend,
+    screen: &dyn CaptureBackend,
+    vision: VisionMode,
+    dry_run: bool, // Plan and report actions without injecting any input
+    skill: Option<&SkillRun>, // A skill's recordings, given to the LLM ahead of any matched by the command
+    model: &ModelConfig, // This task's model and sampling, over the settings'
+) -> Result<String, MetisError> {
     let mut start_string: String = String::from("");
-    let client = gemini_rs::Client::new(
-        std::env::var("GEMINI_API_KEY")
-            .expect("GEMINI_API_KEY environment variable not set")
-    );
-    println!("Starting action loop for command: {}", initial_command);
-    ACTION_INTERRUPTED.store(false, Ordering::SeqCst);
-    start_esc_listener();
-
-    let mut enigo = Enigo::new(&Settings::default()).map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+    let llm = llm::provider_from_config(model)?;
+    llm::begin_task();
+    info!("Starting action loop for command: {} (LLM: {} {})", initial_command, llm.name(), llm.model());
+    // With native tool use the LLM calls the actions as tools instead of writing them out
+    let tools = if config::get().llm.tool_use { action_tools() } else { Vec::new() };
+    let grammar = if tools.is_empty() { ACTION_GRAMMAR } else { TOOL_GRAMMAR };
+    // Holds ExecutingAction for the whole run (the global listener watches for ESC
+    // in that state) and returns to Idle on every exit path
+    let _execution = ExecutionGuard::acquire(app_state).map_err(|e| MetisError::State(format!("Cannot start task: {}", e)))?;
+
+    // Every synthetic input of this run goes into the hash-chained audit log
+    let run_id = audit::new_run_id();
+    let _task = info_span!("task", run = %run_id, dry_run).entered();
+    info!("Run {}: injecting input through the {} backend.", run_id, input.name());
+    if screen.local_display() {
+        info!("Keyboard layout: {}", layout::active().as_deref().unwrap_or("unknown"));
+    }
+    let mut audited_input = AuditedInput::new(input, run_id);
+    let input: &mut dyn InputBackend = &mut audited_input;
 
     // --- Determine Base Folder ---
     let base_folder_path: PathBuf; // Use PathBuf for easier joining
     { // Scope for the mutex lock
-        let mut state = RECORDING_STATE.lock().unwrap(); // Lock mutably to potentially update state
+        let mut state = recording.lock_or_recover(); // Lock mutably to potentially update state
         if let Some(folder_str) = &state.base_folder {
             // If already set in state (e.g., from start_recording), use it
             base_folder_path = PathBuf::from(folder_str);
-            println!("Using base folder from state: {}", base_folder_path.display());
+            info!("Using base folder from state: {}", base_folder_path.display());
         } else {
             // If not set, determine the default path NOW
-            println!("Base folder not set in state, determining default...");
-            // Ensure get_default_base_folder is accessible here!
-            // Use crate::get_default_base_folder() if it's in main.rs
-            let default_folder = crate::get_default_base_folder();
+            info!("Base folder not set in state, determining default...");
+            let default_folder = recorder::get_default_base_folder();
 
-            // --- Crucial Check: Does the default folder *exist*? ---
-            // While execute_task_loop *needs* it, main.csv might not exist yet.
-            // Let's proceed but handle downstream errors (like missing main.csv)
-            println!("Using default base folder: {}", default_folder.display());
+            // No recordings yet is fine: the session database is created on first open
+            info!("Using default base folder: {}", default_folder.display());
 
             // Optionally store it back in the state for this session
             // This avoids recalculating if execute_task_loop is somehow called multiple times
@@ -315,127 +1314,218 @@ pub fn execute_task_loop(initial_command: String) -> Result<String, String> {
     // --- End Determine Base Folder ---
 
 
-    // Now use base_folder_path (PathBuf) instead of base_folder (String)
-    let main_csv_path = base_folder_path.join("main.csv");
     let encrypted_dir = base_folder_path.join("encrypted_csv");
+    info!("Base folder path being used: {}", base_folder_path.display());
+    info!("Encrypted CSV dir: {}", encrypted_dir.display());
 
-    // Add check for main.csv existence here, using the determined path
-    if !main_csv_path.exists() {
-        stop_esc_listener();
-        return Err(format!(
-            "main.csv does not exist in the expected folder: {}",
-            main_csv_path.display()
-        ));
-    }
-
-
-    println!("Base folder path being used: {}", base_folder_path.display());
-    println!("Main CSV path: {}", main_csv_path.display());
-    println!("Encrypted CSV dir: {}", encrypted_dir.display());
-
-
-
-    // --- 1. Find related context from main.csv based on initial_command ---
-    if !main_csv_path.exists() {
-        stop_esc_listener(); // Stop listener if we exit early
-        return Err("main.csv does not exist in the base folder".into());
-    }
-    let mut rdr = ReaderBuilder::new().has_headers(true).from_path(&main_csv_path)
-        .map_err(|e| format!("Failed to read main.csv: {}", e))?;
-
-    let command_words: Vec<&str> = initial_command.split_whitespace().collect();
-    let mut matching_locations = HashSet::new();
+    // --- 1. Find related recordings in the session database based on initial_command ---
+    let matching_locations = storage::open(&base_folder_path)
+        .and_then(|db| storage::find_locations(&db, &initial_command))
+        .inspect_err(|e| error!("Failed to search the session database: {}", e))?;
 
-    #[derive(Debug, Deserialize)] // Define struct locally if not already globally available
-    struct MainCsvRecordForLoop {
-        query: String,
-        location: String,
-    }
-
-    for result in rdr.deserialize::<MainCsvRecordForLoop>() { // Specify type for deserialization
-        let record = match result {
-            Ok(record) => record,
-            Err(e) => { eprintln!("Error parsing main.csv record: {}", e); continue; }
-        };
-        let mut matching_words = 0;
-        for word in command_words.iter() {
-            if record.query.to_lowercase().contains(&word.to_lowercase()) {
-                matching_words += 1;
-            }
-        }
-        // Adjust matching threshold if needed (e.g., >= 1 for any overlap)
-        if matching_words >= 1 {
-            matching_locations.insert(record.location);
-        }
-    }
-
-    if matching_locations.is_empty() {
-        println!("Warning: No matching historical queries found in main.csv for '{}'. Proceeding with current screen only.", initial_command);
+    let skill_folders: &[String] = skill.map_or(&[], |skill| &skill.folders);
+    if matching_locations.is_empty() && skill_folders.is_empty() {
+        warn!("No matching historical queries found for '{}'. Proceeding with current screen only.", initial_command);
     } else {
-        println!("Found related historical action folders: {:?}", matching_locations);
+        info!("Found related historical action folders: {:?} (skill: {:?})", matching_locations, skill_folders);
     }
+    let locations: Vec<String> = skill_folders
+        .iter()
+        .cloned()
+        .chain(matching_locations.into_iter().filter(|location| !skill_folders.contains(location)))
+        .collect();
 
 
-    // --- 2. Gather historical context from matched folders ---
+    // --- 2. Gather historical context from the skill's and matched folders ---
     let mut historical_context = String::new();
-    for location in matching_locations {
+    for location in locations {
         let location_path = encrypted_dir.join(&location);
         if location_path.is_dir() {
             match fs::read_dir(location_path) {
                 Ok(entries) => {
                     for entry in entries.filter_map(Result::ok) {
                         let path = entry.path();
-                        if path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("csv") {
-                            match fs::read_to_string(&path) {
-                                Ok(content) => {
-                                    historical_context.push_str(&format!("--- Context from {} ---\n", path.display()));
-                                    historical_context.push_str(&content);
-                                    historical_context.push_str("\n\n");
-                                },
-                                Err(e) => eprintln!("Warning: Failed to read context file {}: {}", path.display(), e)
-                            }
+                        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                        if !path.is_file() {
+                            continue;
+                        }
+                        // Processed CSVs are sealed when a password was unlocked while recording
+                        let content = if name.ends_with(".csv") {
+                            fs::read_to_string(&path).map_err(|e| e.to_string())
+                        } else if name.ends_with(&format!(".csv{}", keystore::SEALED_SUFFIX)) {
+                            keystore::read_sealed(&path).map_err(|e| e.to_string())
+                        } else {
+                            continue;
+                        };
+                        match content {
+                            Ok(mut content) => {
+                                // A skill's recordings may hold {{placeholders}} for its arguments
+                                if let Some(skill) = skill.filter(|skill| skill.folders.contains(&location)) {
+                                    content = skills::substitute(&content, &skill.arguments).into_owned();
+                                }
+                                historical_context.push_str(&format!("--- Context from {} ---\n", path.display()));
+                                // Recordings made before redaction existed may still hold raw PII
+                                historical_context.push_str(&redact::redact(&content));
+                                historical_context.push_str("\n\n");
+                            },
+                            Err(e) => warn!("Failed to read context file {}: {}", path.display(), e)
                         }
                     }
                 },
-                Err(e) => eprintln!("Warning: Failed to read directory for location {}: {}", location, e)
+                Err(e) => warn!("Failed to read directory for location {}: {}", location, e)
             }
         } else {
-            eprintln!("Warning: Matching location folder not found or not a directory: {}", location);
+            warn!("Matching location folder not found or not a directory: {}", location);
         }
     }
 
-    // Create Tokio runtime for asynchronous LLM calls
-    let rt = Runtime::new().map_err(|e| format!("Failed to create Tokio runtime: {}", e))?;
+    // What earlier runs of similar commands did, and this run's transcript for the next
+    let remembered = memory::recall(&base_folder_path, &initial_command);
+    let mut transcript = TaskTranscript::new(&base_folder_path, &initial_command, !dry_run);
+    let prompt_template = prompt::load(); // Read once, so an edit mid-task applies from the next task
 
     // --- 3. Start the Action Loop ---
     let mut loop_count = 0;
+    let mut pending_correction: Option<String> = None; // Set after an invalid or failed action, sent with the next prompt
+    let mut consecutive_invalid = 0;
+    let mut consecutive_failed = 0;
+    let mut pointer: Option<(i32, i32)> = None; // Base for rel: coordinates
+    let mut last_map: Option<CoordinateMap> = None;
+    let mut last_executed: Option<ExecutedAction> = None; // Verified against the next capture
+    let task_start = clock.now();
     loop {
-        println!("\n--- Action Loop Iteration {} ---", loop_count);
+        let _iteration = info_span!("iteration", n = loop_count).entered();
+        info!("--- Action Loop Iteration {} ---", loop_count);
+        let iteration_start = clock.now();
+        events::emit(ITERATION_EVENT, IterationStarted {
+            iteration: loop_count,
+            elapsed_ms: iteration_start.duration_since(task_start).as_millis() as u64,
+        });
 
-        // Check for ESC key interruption *before* doing work
-        if ACTION_INTERRUPTED.load(Ordering::SeqCst) {
-            println!("Action loop interrupted by user (Escape key).");
-            stop_esc_listener(); // Stop listener on interruption
-            return Err("Action interrupted by user.".to_string());
+        // Check for ESC / cancel_task interruption *before* doing work
+        if app_state.lock_or_recover().action_interrupted {
+            info!("Action loop interrupted by user (Escape key or cancel).");
+            transcript.stop("interrupted by the user");
+            return Err(MetisError::State("Action interrupted by user.".to_string()));
         }
 
-        // --- 3a. Get Current Screen State as CSV ---
-        let current_screen_csv = match get_screen_csv() {
-            Ok(csv) => csv,
-            Err(e) => {
-                eprintln!("Failed to get current screen CSV: {}", e);
-                // Decide how to handle this: retry, skip, or abort? Aborting for now.
-                stop_esc_listener(); // Stop listener on error
-                return Err(format!("Failed to get current screen CSV: {}", e));
+        if task_paused(app_state) && !wait_while_paused(app_state, clock) {
+            info!("Action loop interrupted by user (Escape key) while paused.");
+            transcript.stop("interrupted by the user");
+            return Err(MetisError::State("Action interrupted by user.".to_string()));
+        }
+
+        // Hold off while the machine is locked or just woke up; give up if it stays that way.
+        // A remote device keeps its own screen, so the host's lock state doesn't matter there.
+        if screen.local_display() && session::is_paused() {
+            info!("Session is {:?}; pausing action loop until it is active again.", session::state());
+            let interrupted = || app_state.lock_or_recover().action_interrupted;
+            if !session::wait_until_active(clock, MAX_SESSION_PAUSE, interrupted) {
+                return Err(MetisError::State("Action aborted: session stayed locked or asleep.".to_string()));
             }
+            info!("Session active again; resuming action loop.");
+        }
+
+        // Read every iteration: docking or a resolution change invalidates the old bounds
+        let screen_bounds = input.main_display().inspect_err(|e| error!("Failed to read screen size: {}", e))?;
+
+        // --- 3a. Get Current Screen State as CSV and/or a screenshot for the model ---
+        info!("Capturing screen ({}), vision mode {:?}...", screen.name(), vision);
+        let screenshot = screen
+            .capture()
+            .and_then(|shot| shot.ok_or(CaptureError::NoMonitors))
+            .inspect_err(|e| error!("Failed to capture the screen: {}", e))?;
+        let current_screen_csv = if vision.parses() {
+            match parse_screenshot(&screenshot) {
+                Ok(csv) => Some(csv),
+                Err(e) => {
+                    error!("Failed to get current screen CSV: {}", e);
+                    // Decide how to handle this: retry, skip, or abort? Aborting for now.
+                    return Err(e.into());
+                }
+            }
+        } else {
+            None
+        };
+        let screenshot_png = if vision.attaches() {
+            Some(encode_png(&screenshot)?)
+        } else {
+            None
         };
+        let (shot_width, shot_height) = (screenshot.width(), screenshot.height());
+        // Compared with the next capture to tell whether this iteration's action did anything
+        let frame_hash = dedup::hash(&screenshot);
+        let verification = last_executed.take().map(|executed| executed.verification(&frame_hash));
+        drop(screenshot);
+        // The LLM answers in screenshot pixels; the map converts them for the input backend
+        let map = if screen.local_display() {
+            CoordinateMap::for_local((shot_width, shot_height), screen_bounds)
+        } else {
+            CoordinateMap::new((shot_width, shot_height), screen_bounds, 1.0)
+        };
+        map.log_change(&mut last_map);
 
         // --- 3b. Combine Context ---
         let mut combined_context = String::new();
+        if let Some(tag) = language::hint() {
+            combined_context.push_str(&language::prompt_note(&tag));
+        }
         combined_context.push_str("--- Current Screen State ---\n");
-        combined_context.push_str(&current_screen_csv);
+        if screenshot_png.is_some() {
+            combined_context.push_str(&format!(
+                "A screenshot of the screen ({}x{} pixels) is attached. Pixel (0,0) is its top-left corner; use its pixel coordinates for actions.\n",
+                shot_width, shot_height
+            ));
+        }
+        if let Some(csv) = &current_screen_csv {
+            combined_context.push_str(csv);
+        }
         combined_context.push_str("\n\n");
 
+        if let Some(verification) = &verification {
+            combined_context.push_str(verification);
+            combined_context.push('\n');
+        }
+
+        // Other monitors can be targeted with (monitor,x,y) points
+        if let Some(displays) = map.to_context() {
+            combined_context.push_str("--- Displays ---\n");
+            combined_context.push_str(&displays);
+            combined_context.push('\n');
+        }
+
+        // Which application is in front, so the LLM knows what it is looking at
+        if let Some(window) = screen.local_display().then(window_info::foreground).flatten() {
+            combined_context.push_str("--- Active Window ---\n");
+            combined_context.push_str(&window_info::to_context(&window));
+            combined_context.push('\n');
+        }
+
+        // Native controls as the OS accessibility tree sees them, where available
+        let accessible_elements = if screen.local_display() {
+            elements::snapshot(elements::MAX_CONTEXT_ELEMENTS)
+        } else {
+            Vec::new()
+        };
+        if !accessible_elements.is_empty() {
+            combined_context.push_str("--- Accessible Elements (foreground window) ---\n");
+            combined_context.push_str(&redact::redact(&elements::to_context(&accessible_elements)));
+            combined_context.push('\n');
+        }
+
+        // The focused browser tab's DOM, when a DevTools-enabled Chromium is in front
+        if let Some(page_elements) = screen.local_display().then(cdp::page_context).flatten() {
+            combined_context.push_str("--- Web Page Elements (focused browser tab) ---\n");
+            combined_context.push_str(&redact::redact(&page_elements));
+            combined_context.push('\n');
+        }
+
+        if let Some(remembered) = &remembered {
+            combined_context.push_str("--- Earlier Runs of Similar Tasks ---\n");
+            combined_context.push_str(remembered);
+            combined_context.push('\n');
+        }
+
         if !historical_context.is_empty() {
             combined_context.push_str("--- Relevant Historical Actions ---\n");
             combined_context.push_str(&historical_context);
@@ -443,52 +1533,72 @@ pub fn execute_task_loop(initial_command: String) -> Result<String, String> {
             combined_context.push_str("--- No Relevant Historical Actions Found ---\n");
         }
 
+        if let Some(correction) = pending_correction.take() {
+            combined_context.push('\n');
+            combined_context.push_str(&correction);
+        }
+
 
         // --- 3c. Prepare Prompt and Call LLM ---
-        // Updated prompt to request thought process and action
-        let llm_prompt = format!(
-            // Start with the user's command
-            "The command given to you was: {initial_command}\n\n\
-             Previous actions: {start_string}\n
-             Below is the Current Screen State (as CSV data with columns including id, class, column_min, rhello hows it goinnghexa ow_min, column_max, row_max, width, height, content) and may include Relevant Historical Actions:\n\n{combined_context}\n\n\
-             Based on this information, perform the following steps:\n\
-             1. First, provide a brief explanation (1-3 sentences) of your reasoning and the intended action, enclosed within <think></think> tags. Refer to element details (like id, class, content, or coordinates) from the CSV context in your reasoning.\n\
-             2. Immediately following the closing </think> tag, provide the single next action command using the exact format specified below.\n\n\
-             Valid action commands and their required value formats:\n\
-             * `click:(x,y)` - Click instantly at absolute pixel coordinates (x, y). Derive coordinates from the CSV data (e.g., center of a bbox: ((col_min+col_max)/2, (row_min+row_max)/2)).\n\
-             * `click_down:(x,y)` - Press and hold the left mouse button at absolute pixel coordinates (x, y).\n\
-             * `click_up:nil` - Release the held left mouse button. The value must be exactly `nil`.\n\
-             * `drag:(x,y)` - Move the mouse to absolute pixel coordinates (x, y) WHILE the button is held down (use after `click_down`).\n\
-             * `tap:'key'` - Press and release a keyboard key. The key name or character MUST be enclosed in single quotes. Common keys: 'a', 'b', '1', 'Enter', 'Shift', 'Control', 'Alt', 'Escape', 'Backspace', 'Tab', 'Space', 'ArrowUp', 'ArrowDown', 'ArrowLeft', 'ArrowRight', 'F5', etc.\n\
-             * `tap_down:'key'` - Press and HOLD a keyboard key (typically for modifiers like 'Shift', 'Control', 'Alt'). Use single quotes.\n\
-             * `tap_up:'key'` - Release a held keyboard key. Use single quotes.\n\
-             * `scroll:amount` - Scroll vertically by the specified integer `amount`. Positive values scroll down, negative values scroll up. Example: `scroll:10`, `scroll:-5`.\n\
-             * `type:'text to type'` - Type the provided sequence of characters exactly. The text MUST be enclosed in single quotes.\n\
-             * `done:'completion message'` - Stop the execution loop and report the outcome. The message MUST be enclosed in single quotes.\n\n\
-             Examples of the required output format:\n\
-             <think>User wants to log in. I see a button component (id: 5, class: Compo, row_min: 250, col_min: 100, row_max: 280, col_max: 150, content: 'Login'). I will click its approximate center.</think>click:(125,265)\n\
-             <think>The input field (id: 3, class: Compo, row_min: 100, col_min: 80, row_max: 120, col_max: 280) seems to be for the username based on nearby text. I will type 'testuser'.</think>type:'testuser'\n\
-             <think>The required information is below the current view. I need to scroll down the page significantly.</think>scroll:15\n\
-             <think>I see the text 'Welcome, testuser!' (id: 12, class: Text). The login was successful, fulfilling the command.</think>done:'Login successful.'\n\n\
-             Your Response:", // The comma separating format string from arguments comes AFTER the whole string
-
-            // Variables to substitute (using named arguments)
-            initial_command = initial_command,
-            combined_context = combined_context
-        );
-
-        println!("Sending prompt to LLM...");
-        // Optional: Log part of the prompt for debugging
-        // println!("LLM Prompt (start): {}", &llm_prompt[..std::cmp::min(llm_prompt.len(), 500)]);
+        let llm_prompt = prompt::render(&prompt_template, &initial_command, &start_string, &combined_context, grammar);
+
+        // An unchanged screen, command and history get the decision made for them last time
+        let cache_key = config::get().llm.cache_responses.then(|| response_key(llm.as_ref(), &llm_prompt, screenshot_png.as_deref()));
+        let cached = cache_key.as_deref().and_then(cached_response);
+
+        // Checked before each call, so a task overruns its budget by one call at most
+        if cached.is_none() {
+            if let Some(reason) = llm::budget_exceeded() {
+                warn!("Stopping the task: {}", reason);
+                transcript.stop(reason.clone());
+                return Err(MetisError::State(format!("Task stopped: {}.", reason)));
+            }
+        }
 
-        // Call the LLM asynchronously within the Tokio runtime
-        let llm_result = rt.block_on(get_llm(llm_prompt, initial_command.clone(),&client)); // Pass refined prompt
+        let mut thoughts = ThoughtStream::new(loop_count);
+        let llm_start = clock.now();
+        let llm_result = match cached {
+            Some(response) => {
+                info!("Screen and history are unchanged; reusing the previous LLM decision.");
+                thoughts.push(&response.text);
+                Ok(response)
+            }
+            None => {
+                info!("Sending prompt to LLM...");
+                // Optional: Log part of the prompt for debugging
+                // info!("LLM Prompt (start): {}", &llm_prompt[..std::cmp::min(llm_prompt.len(), 500)]);
+                perf::time(Stage::Llm, || {
+                    let on_chunk = &mut |chunk: &str| thoughts.push(chunk);
+                    if tools.is_empty() {
+                        llm::get_llm(llm_prompt, initial_command.clone(), screenshot_png.as_deref(), llm.as_ref(), on_chunk).map(|text| LlmReply { text, call: None }) // Pass refined prompt
+                    } else {
+                        llm::get_llm_with_tools(llm_prompt, initial_command.clone(), screenshot_png.as_deref(), llm.as_ref(), &tools, on_chunk)
+                    }
+                })
+            }
+        };
+        thoughts.finish();
 
 
         // --- 3d. Parse LLM Response and Extract Action ---
-        let (thought_process, action_to_perform) = match llm_result {
-            Ok(response) => {
-                println!("Raw LLM Response: {}", response);
+        let llm_response = llm_result.as_ref().ok().cloned(); // Cached once its action has run
+        let (thought_process, action_to_perform, tool_call) = match llm_result {
+            Ok(LlmReply { text: response, call: Some(call) }) => {
+                info!("Raw LLM Response: {}", response);
+                info!("LLM called the tool: {}", call);
+                start_string.push_str(&response);
+                start_string.push_str(&call.to_string());
+                // The reasoning is the text around the call, in <think> tags if the LLM kept to them
+                let thought = match (response.find("<think>"), response.find("</think>")) {
+                    (Some(start), Some(end)) if start < end => response[start + "<think>".len()..end].trim(),
+                    _ => response.trim(),
+                };
+                info!("LLM Thought: {}", thought);
+                (thought.to_string(), call.to_string(), Some(call))
+            }
+            // Answered in text: the grammar, or a model that didn't call a tool it was offered
+            Ok(LlmReply { text: response, call: None }) => {
+                info!("Raw LLM Response: {}", response);
                 start_string.push_str(&response);
 
                 // Find the closing tag
@@ -500,143 +1610,245 @@ pub fn execute_task_loop(initial_command: String) -> Result<String, String> {
                         if start_tag_index < end_tag_index {
                             response[start_tag_index + think_start_tag.len()..end_tag_index].trim()
                         } else {
-                            eprintln!("Warning: Found <think> tag after </think> tag.");
+                            warn!("Found <think> tag after </think> tag.");
                             ""
                         }
                     } else {
-                        eprintln!("Warning: Found </think> tag but no matching <think> tag.");
+                        warn!("Found </think> tag but no matching <think> tag.");
                         ""
                     };
 
                     // Extract the action part after the tag
                     let action_part = response[end_tag_index + think_end_tag.len()..].trim();
 
-                    println!("LLM Thought: {}", thought);
+                    info!("LLM Thought: {}", thought);
                     if action_part.is_empty() {
-                        eprintln!("Error: LLM response had </think> tag but no action followed.");
-                        stop_esc_listener(); // Stop listener on error
-                        return Err("LLM returned thought but no action.".to_string());
+                        error!("LLM response had </think> tag but no action followed.");
+                        return Err(LlmError::MissingAction.into());
                     }
-                    (thought.to_string(), action_part.to_string())
+                    (thought.to_string(), action_part.to_string(), None)
 
                 } else {
                     // Fallback: No </think> tag found, assume entire response is the action
-                    eprintln!("Warning: LLM response did not contain '</think>' tag. Assuming entire response is the action.");
+                    warn!("LLM response did not contain '</think>' tag. Assuming entire response is the action.");
                     let action_part = response.trim();
                     if action_part.is_empty() {
-                        eprintln!("Error: LLM response was empty.");
-                        stop_esc_listener(); // Stop listener on error
-                        return Err("LLM returned an empty response.".to_string());
+                        error!("LLM response was empty.");
+                        return Err(LlmError::EmptyResponse.into());
                     }
-                    ("".to_string(), action_part.to_string()) // Empty thought, full response as action
+                    ("".to_string(), action_part.to_string(), None) // Empty thought, full response as action
                 }
             }
             Err(e) => {
-                eprintln!("Error getting LLM response: {}", e);
-                stop_esc_listener(); // Stop listener on error
-                return Err(format!("Error getting LLM response: {}", e));
+                error!("Error getting LLM response: {}", e);
+                return Err(e.into());
             }
         };
 
-        println!("Action to Perform: {}", action_to_perform);
+        info!("Action to Perform: {}", action_to_perform);
+        events::emit(THOUGHT_EVENT, TaskThought {
+            iteration: loop_count,
+            thought: thought_process.clone(),
+            action: action_to_perform.clone(),
+            llm_latency_ms: clock.now().duration_since(llm_start).as_millis() as u64,
+        });
 
         // --- 3e. Execute Action ---
         if action_to_perform.is_empty() {
             // Should be caught earlier now, but keep as safety check
-            eprintln!("Extracted action is empty. Stopping.");
-            stop_esc_listener(); // Stop listener on error
-            return Err("Extracted action was empty.".to_string());
+            warn!("Extracted action is empty. Stopping.");
+            return Err(LlmError::MissingAction.into());
+        }
+
+        // --- Validate against the grammar and screen bounds before touching input ---
+        let frame = CoordinateFrame { map: &map, pointer, elements: current_screen_csv.as_deref() };
+        let validated = match &tool_call {
+            Some(call) => validate_tool_call(call, &frame),
+            None => validate_action(&action_to_perform, &frame),
+        };
+        let action = match validated {
+            Ok(action) => {
+                consecutive_invalid = 0;
+                action
+            }
+            Err(e) => {
+                ActionExecuted::emit(loop_count, &action_to_perform, Err(&e), Duration::ZERO, clock.now().duration_since(iteration_start));
+                transcript.step(&thought_process, &action_to_perform, Some(&e));
+                consecutive_invalid += 1;
+                warn!("Rejected invalid action '{}' ({}/{}): {}", action_to_perform, consecutive_invalid, MAX_CONSECUTIVE_INVALID_ACTIONS, e);
+                if consecutive_invalid >= MAX_CONSECUTIVE_INVALID_ACTIONS {
+                    warn!("LLM produced {} invalid actions in a row; giving up.", consecutive_invalid);
+                    transcript.stop(format!("{} invalid actions in a row", consecutive_invalid));
+                    return Err(e.into());
+                }
+                pending_correction = Some(correction_prompt(&action_to_perform, &e, map.image, grammar));
+                loop_count += 1;
+                continue;
+            }
+        };
+
+        // The screen this action was planned against is gone if the session paused meanwhile
+        if screen.local_display() && session::is_paused() {
+            info!("Session paused before '{}' could run; re-planning after resume.", action_to_perform);
+            loop_count += 1;
+            continue;
+        }
+        // Same when the user paused the task while it was planning: they may have changed things
+        if task_paused(app_state) {
+            if !wait_while_paused(app_state, clock) {
+                transcript.stop("interrupted by the user");
+                return Err(MetisError::State("Action interrupted by user.".to_string()));
+            }
+            info!("Task paused before '{}' could run; re-planning against the current screen.", action_to_perform);
+            loop_count += 1;
+            continue;
+        }
+        // A cancel that arrived while the LLM was answering stops the action it chose
+        if app_state.lock_or_recover().action_interrupted {
+            info!("Task cancelled before '{}' could run.", action_to_perform);
+            transcript.stop("interrupted by the user");
+            return Err(MetisError::State("Action interrupted by user.".to_string()));
         }
 
-        match do_action(&action_to_perform, &mut enigo) {
+        if dry_run {
+            info!("Dry run: would perform '{}'", action_to_perform);
+            events::emit(PLANNED_ACTION_EVENT, PlannedAction {
+                iteration: loop_count,
+                action: action_to_perform.clone(),
+                description: describe_action(&action),
+                thought: thought_process.clone(),
+            });
+            if let Action::Done(message) = &action {
+                return Ok(format!("Dry run completed: {}", message));
+            }
+            // Nothing changed on screen, so the next plan builds on the previous actions alone
+            pointer = action.pointer_after(pointer);
+            loop_count += 1;
+            if loop_count > MAX_ITERATIONS {
+                return Err(MetisError::State("Loop safety break triggered.".to_string()));
+            }
+            continue;
+        }
+
+        if !matches!(action, Action::Done(_)) {
+            announce::announce(Status::ActionStarting, describe_action(&action));
+        }
+        let action_start = clock.now();
+        let outcome = do_action(&action, &map, input, clock, app_state);
+        ActionExecuted::emit(
+            loop_count,
+            &action_to_perform,
+            outcome.as_ref().map(|_| ()),
+            clock.now().duration_since(action_start),
+            clock.now().duration_since(iteration_start),
+        );
+        match outcome {
             Ok(true) => {
                 // Action successful, continue loop
-                println!("Action successful. Continuing loop.");
+                info!("Action successful. Continuing loop.");
+                transcript.step(&thought_process, &action_to_perform, None);
+                consecutive_failed = 0;
+                // Only decisions that ran are reused; a cached done would end a task unseen
+                if let (Some(key), Some(response)) = (cache_key, llm_response) {
+                    cache_response(key, response);
+                }
+                pointer = action.pointer_after(pointer);
+                // A wait is meant to let the screen change on its own, so it isn't checked
+                if !matches!(action, Action::Wait(_)) {
+                    last_executed = Some(ExecutedAction {
+                        action: action_to_perform.clone(),
+                        clicked: action.clicks(),
+                        frame_before: frame_hash.clone(),
+                    });
+                }
                 // Small delay after action to allow UI to update before next capture
-                thread::sleep(Duration::from_millis(500)); // Adjust delay as needed
+                clock.sleep(Duration::from_millis(config::get().timings.action_settle_ms));
+                perf::record(Stage::Iteration, clock.now().duration_since(iteration_start));
             }
             Ok(false) => {
                 // "done" action received, exit loop successfully
-                println!("'done' action received. Exiting loop.");
-                println!("Final thought before done: {}", thought_process); // Log final thought
-                stop_esc_listener(); // Stop listener on successful completion
-                let message = action_to_perform.splitn(2, ':').nth(1).unwrap_or("Done").trim_matches('\'');
+                info!("'done' action received. Exiting loop.");
+                info!("Final thought before done: {}", thought_process); // Log final thought
+                let message = match &action {
+                    Action::Done(message) => message.as_str(),
+                    _ => "Done",
+                };
+                transcript.finish(message);
                 return Ok(format!("Task completed: {}", message));
             }
             Err(e) => {
-                // Error executing action
-                eprintln!("Error executing action '{}': {}", action_to_perform, e);
-                eprintln!("Thought process leading to error: {}", thought_process); // Log thought on error
-                stop_esc_listener(); // Stop listener on error
-                return Err(format!("Error executing action '{}': {}", action_to_perform, e));
+                // Error executing action; the LLM gets to correct it, a few times
+                error!("Error executing action '{}': {}", action_to_perform, e);
+                error!("Thought process leading to error: {}", thought_process); // Log thought on error
+                transcript.step(&thought_process, &action_to_perform, Some(&e));
+                consecutive_failed += 1;
+                if consecutive_failed >= MAX_CONSECUTIVE_FAILED_ACTIONS {
+                    warn!("{} actions failed in a row; giving up.", consecutive_failed);
+                    transcript.stop(format!("{} actions failed in a row", consecutive_failed));
+                    return Err(e.into());
+                }
+                pointer = None; // Wherever the pointer ended up, it's no longer known
+                pending_correction = Some(failure_prompt(&action_to_perform, &e, grammar));
+                clock.sleep(Duration::from_millis(config::get().timings.action_settle_ms));
             }
         }
 
         // --- 3f. Loop Increment and Safety Break ---
         loop_count += 1;
-        // Adjust max iterations as needed
-        const MAX_ITERATIONS: u32 = 100;
         if loop_count > MAX_ITERATIONS {
-            eprintln!("Action loop reached maximum iterations ({}). Stopping.", MAX_ITERATIONS);
-            stop_esc_listener(); // Stop listener on loop break
-            return Err("Loop safety break triggered.".to_string());
+            warn!("Action loop reached maximum iterations ({}). Stopping.", MAX_ITERATIONS);
+            transcript.stop(format!("gave up after {} steps", MAX_ITERATIONS));
+            return Err(MetisError::State("Loop safety break triggered.".to_string()));
         }
     }
     // Note: The loop should only be exited via return statements inside it (Ok or Err)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state;
+    use crate::clock::ManualClock;
+    use crate::input::{InputCall, MockInput};
+
+    fn run(action: &Action) -> (bool, Vec<InputCall>) {
+        let mut input = MockInput::new(1920, 1080);
+        let map = CoordinateMap::identity(input.display);
+        let more = do_action(action, &map, &mut input, &ManualClock::new(), &app_state::new_shared()).unwrap();
+        (more, input.calls)
+    }
 
-// --- create_main_csv function (Keep as is, ensure csv crate is available) ---
-// Requires csv crate
-pub fn create_main_csv(base_folder: &Path, action_folder: &str) -> Result<(), std::io::Error> {
-    let main_csv_path = base_folder.join("main.csv");
-    let file_exists = main_csv_path.exists();
-
-    let next_default_index = if file_exists {
-        let mut rdr = match csv::Reader::from_path(&main_csv_path) {
-            Ok(rdr) => rdr,
-            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other,
-                                                     format!("Failed to read main.csv: {}", e))),
-        };
-        let mut highest_index = -1;
-        for result in rdr.records() {
-            if let Ok(record) = result {
-                if record.len() >= 1 {
-                    let query = &record[0];
-                    if query.starts_with("default_") {
-                        if let Ok(index) = query.trim_start_matches("default_").parse::<i32>() {
-                            highest_index = std::cmp::max(highest_index, index);
-                        }
-                    }
-                }
-            }
-        }
-        highest_index + 1
-    } else {
-        0
-    };
-
-    let needs_header = !file_exists;
-    // Use std::fs::File here
-    let file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(&main_csv_path)?;
-
-    // Ensure csv::Writer is imported if using create_main_csv
-    // Requires `use csv::WriterBuilder;` or `use csv::Writer;` depending on usage pattern
-    // Using WriterBuilder is generally preferred. Let's assume it's available or add `use csv::WriterBuilder;`
-    let mut wtr = csv::WriterBuilder::new()
-        .has_headers(needs_header)
-        .from_writer(file);
+    #[test]
+    fn click_moves_then_clicks() {
+        let (more, calls) = run(&Action::Click(120, 340));
+        assert!(more);
+        assert_eq!(calls, vec![InputCall::MoveMouse(120, 340), InputCall::LeftButton(Direction::Click)]);
+    }
 
-    if needs_header {
-        wtr.write_record(&["query", "location"])?;
+    #[test]
+    fn press_and_release_are_separate_calls() {
+        let (_, down) = run(&Action::ClickDown(10, 20));
+        assert_eq!(down, vec![InputCall::MoveMouse(10, 20), InputCall::LeftButton(Direction::Press)]);
+        let (_, up) = run(&Action::ClickUp);
+        assert_eq!(up, vec![InputCall::LeftButton(Direction::Release)]);
     }
 
-    let query = format!("default_{}", next_default_index);
-    wtr.write_record(&[&query, action_folder])?;
-    wtr.flush()?;
+    #[test]
+    fn chord_releases_modifiers_in_reverse_order() {
+        let (_, calls) = run(&Action::Keys(vec![Key::Control, Key::Shift], ParsedKey::Char('t')));
+        assert_eq!(calls, vec![
+            InputCall::Key(Key::Control, Direction::Press),
+            InputCall::Key(Key::Shift, Direction::Press),
+            InputCall::Key(Key::Unicode('t'), Direction::Click),
+            InputCall::Key(Key::Shift, Direction::Release),
+            InputCall::Key(Key::Control, Direction::Release),
+        ]);
+    }
 
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn done_ends_the_loop_without_input() {
+        let (more, calls) = run(&Action::Done("finished".to_string()));
+        assert!(!more);
+        assert!(calls.is_empty());
+    }
+}
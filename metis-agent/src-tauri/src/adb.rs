@@ -0,0 +1,365 @@
+// --- Android Device Target (adb) ---
+// A phone connected over adb can be recorded and driven like the desktop:
+// AdbCapture grabs frames with `screencap`, which go through the same parser
+// pipeline, and AdbInput turns the action grammar into `input tap/swipe/text/
+// keyevent`. While recording a device, `start_touch_watcher` follows the
+// touchscreen through `getevent` and captures a frame after each tap or swipe,
+// since the desktop input listener never sees touches on the phone.
+//
+// The adb binary is taken from METIS_ADB, falling back to `adb` on the PATH.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Output, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use enigo::{Direction, Key};
+use image::DynamicImage;
+use tracing::{error, info};
+
+use crate::capture::CaptureBackend;
+use crate::clock::SystemClock;
+use crate::error::{ActionError, CaptureError};
+use crate::input::InputBackend;
+use crate::recorder::{self, SharedRecordingState};
+use crate::window_control::WindowOp;
+
+// Touches that move less than this (in pixels) are taps, not swipes
+const TAP_SLOP: i32 = 20;
+const SWIPE_DURATION_MS: u32 = 300;
+const SCROLL_STEP: i32 = 100; // Pixels of finger travel per scroll unit
+// Let the UI react to a touch before capturing, like the desktop click delay
+const TOUCH_CAPTURE_DELAY: Duration = Duration::from_millis(500);
+const WATCHER_POLL: Duration = Duration::from_millis(250);
+
+fn adb_binary() -> String {
+    std::env::var("METIS_ADB").unwrap_or_else(|_| "adb".to_string())
+}
+
+/// `adb [-s serial] args...`
+fn adb(serial: Option<&str>, args: &[&str]) -> Command {
+    let mut command = Command::new(adb_binary());
+    if let Some(serial) = serial {
+        command.args(["-s", serial]);
+    }
+    command.args(args);
+    command
+}
+
+fn run(serial: Option<&str>, args: &[&str]) -> Result<Output, String> {
+    let output = adb(serial, args).output().map_err(|e| format!("Failed to run adb: {}", e))?;
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(format!("adb {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// Serials of devices in the `device` state.
+#[tauri::command]
+pub fn list_android_devices() -> Result<Vec<String>, String> {
+    let output = run(None, &["devices"])?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1) // "List of devices attached"
+        .filter_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [serial, "device", ..] => Some(serial.to_string()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Display size reported by `wm size`, preferring an override if one is set.
+fn display_size(serial: Option<&str>) -> Result<(i32, i32), String> {
+    let output = run(serial, &["shell", "wm", "size"])?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let parse = |line: &str| {
+        let (w, h) = line.rsplit(' ').next()?.trim().split_once('x')?;
+        Some((w.parse().ok()?, h.parse().ok()?))
+    };
+    let sizes: Vec<(i32, i32)> = text.lines().filter_map(parse).collect();
+    sizes.last().copied().ok_or_else(|| format!("Unexpected `wm size` output: {}", text.trim()))
+}
+
+/// Screenshots from the device.
+pub struct AdbCapture {
+    pub serial: Option<String>,
+}
+
+impl CaptureBackend for AdbCapture {
+    fn name(&self) -> &'static str {
+        "adb"
+    }
+
+    fn capture(&self) -> Result<Option<DynamicImage>, CaptureError> {
+        // exec-out keeps the PNG binary-clean (shell would mangle line endings)
+        let output = run(self.serial.as_deref(), &["exec-out", "screencap", "-p"]).map_err(CaptureError::Capture)?;
+        Ok(Some(image::load_from_memory(&output.stdout)?))
+    }
+
+    fn local_display(&self) -> bool {
+        false
+    }
+}
+
+/// Quotes a run of text for `input text` as one single-quoted word, since adb
+/// hands the command line to the device's `sh`. Spaces become `%s`, which
+/// `input` turns back into spaces. `text` sends line breaks and tabs as key
+/// events; any other control character is refused.
+fn quote_input_text(text: &str) -> Result<String, ActionError> {
+    if let Some(c) = text.chars().find(|c| c.is_control()) {
+        return Err(ActionError::TextEntry(format!("control character {:?} can't be typed on a device", c)));
+    }
+    Ok(format!("'{}'", text.replace(' ', "%s").replace('\'', r"'\''")))
+}
+
+fn keycode(key: Key) -> Option<&'static str> {
+    Some(match key {
+        Key::Return => "KEYCODE_ENTER",
+        Key::Backspace => "KEYCODE_DEL",
+        Key::Delete => "KEYCODE_FORWARD_DEL",
+        Key::Tab => "KEYCODE_TAB",
+        Key::Escape => "KEYCODE_BACK", // The closest thing a phone has
+        Key::Space => "KEYCODE_SPACE",
+        Key::UpArrow => "KEYCODE_DPAD_UP",
+        Key::DownArrow => "KEYCODE_DPAD_DOWN",
+        Key::LeftArrow => "KEYCODE_DPAD_LEFT",
+        Key::RightArrow => "KEYCODE_DPAD_RIGHT",
+        Key::Home => "KEYCODE_MOVE_HOME",
+        Key::End => "KEYCODE_MOVE_END",
+        Key::PageUp => "KEYCODE_PAGE_UP",
+        Key::PageDown => "KEYCODE_PAGE_DOWN",
+        _ => return None,
+    })
+}
+
+/// Touch input on the device. Mouse actions become taps and swipes.
+pub struct AdbInput {
+    serial: Option<String>,
+    pointer: (i32, i32),
+    pressed_at: Option<(i32, i32)>, // Where click_down started a swipe
+}
+
+impl AdbInput {
+    pub fn new(serial: Option<String>) -> Self {
+        AdbInput { serial, pointer: (0, 0), pressed_at: None }
+    }
+
+    fn shell_input(&self, args: &[&str]) -> Result<(), ActionError> {
+        let mut full = vec!["shell", "input"];
+        full.extend_from_slice(args);
+        run(self.serial.as_deref(), &full).map(|_| ()).map_err(ActionError::Device)
+    }
+
+    fn tap(&self, (x, y): (i32, i32)) -> Result<(), ActionError> {
+        self.shell_input(&["tap", &x.to_string(), &y.to_string()])
+    }
+
+    fn swipe(&self, from: (i32, i32), to: (i32, i32)) -> Result<(), ActionError> {
+        self.swipe_for(from, to, Duration::from_millis(SWIPE_DURATION_MS as u64))
+    }
+
+    fn swipe_for(&self, from: (i32, i32), to: (i32, i32), duration: Duration) -> Result<(), ActionError> {
+        let args = [from.0, from.1, to.0, to.1].map(|v| v.to_string());
+        let duration = duration.as_millis().to_string();
+        self.shell_input(&["swipe", &args[0], &args[1], &args[2], &args[3], &duration])
+    }
+}
+
+impl InputBackend for AdbInput {
+    fn name(&self) -> &'static str {
+        "adb"
+    }
+
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError> {
+        self.pointer = (x, y); // Nothing to do until the "button" is used
+        Ok(())
+    }
+
+    fn left_button(&mut self, direction: Direction) -> Result<(), ActionError> {
+        match direction {
+            Direction::Click => self.tap(self.pointer),
+            Direction::Press => {
+                self.pressed_at = Some(self.pointer);
+                Ok(())
+            }
+            Direction::Release => match self.pressed_at.take() {
+                Some(start) if (start.0 - self.pointer.0).abs() + (start.1 - self.pointer.1).abs() > TAP_SLOP => {
+                    self.swipe(start, self.pointer)
+                }
+                _ => self.tap(self.pointer),
+            },
+        }
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError> {
+        if direction == Direction::Release {
+            return Ok(()); // keyevent is a full press; the down half already sent it
+        }
+        if let Key::Unicode(c) = key {
+            return self.text(&c.to_string());
+        }
+        let code = keycode(key).ok_or_else(|| ActionError::UnknownKey(format!("{:?} (no Android keycode)", key)))?;
+        self.shell_input(&["keyevent", code])
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), ActionError> {
+        // Quote every run before sending any, so refused text types nothing
+        let text = text.replace("\r\n", "\n");
+        let runs = text.split(['\n', '\t']).map(quote_input_text).collect::<Result<Vec<_>, _>>()?;
+        let mut breaks = text.matches(['\n', '\t']);
+        for (i, run) in runs.iter().enumerate() {
+            if i > 0 {
+                let code = if breaks.next() == Some("\t") { "KEYCODE_TAB" } else { "KEYCODE_ENTER" };
+                self.shell_input(&["keyevent", code])?;
+            }
+            if run != "''" {
+                self.shell_input(&["text", run])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn scroll(&mut self, units: i32) -> Result<(), ActionError> {
+        let (width, height) = self.main_display()?;
+        self.two_finger_scroll(width / 2, height / 2, units)
+    }
+
+    fn hscroll(&mut self, units: i32) -> Result<(), ActionError> {
+        // Dragging the content left scrolls right
+        let (width, height) = self.main_display()?;
+        let (x, y) = (width / 2, height / 2);
+        let travel = (units * SCROLL_STEP).clamp(-width / 3, width / 3);
+        self.swipe((x, y), (x - travel, y))
+    }
+
+    fn main_display(&self) -> Result<(i32, i32), ActionError> {
+        display_size(self.serial.as_deref()).map_err(ActionError::Device)
+    }
+
+    fn activate_element(&mut self, _name: &str, _control_type: Option<&str>) -> Result<(), ActionError> {
+        Err(ActionError::ElementTargeting("Element targeting is not available on Android devices".to_string()))
+    }
+
+    fn control_window(&mut self, _title: &str, _op: WindowOp) -> Result<(), ActionError> {
+        Err(ActionError::WindowControl("Window control is not available on Android devices".to_string()))
+    }
+
+    fn launch(&mut self, _target: &str) -> Result<(), ActionError> {
+        Err(ActionError::Launch("Launching apps is not available on Android devices".to_string()))
+    }
+
+    fn long_press(&mut self, x: i32, y: i32, hold: Duration) -> Result<(), ActionError> {
+        self.swipe_for((x, y), (x, y), hold) // A swipe that goes nowhere is a long press
+    }
+
+    fn pinch(&mut self, _x: i32, _y: i32, _scale: f32) -> Result<(), ActionError> {
+        Err(ActionError::Gesture("Pinch needs two touch points; adb input injects one".to_string()))
+    }
+
+    fn two_finger_scroll(&mut self, x: i32, y: i32, units: i32) -> Result<(), ActionError> {
+        // Phones scroll with one finger; dragging the content up scrolls down
+        let (_, height) = self.main_display()?;
+        let travel = (units * SCROLL_STEP).clamp(-height / 3, height / 3);
+        self.swipe((x, y), (x, y - travel))
+    }
+}
+
+/// Touch panel coordinate range (ABS_MT_POSITION_X/Y max) from `getevent -lp`.
+fn touch_range(serial: Option<&str>) -> Option<(i32, i32)> {
+    let output = run(serial, &["shell", "getevent", "-lp"]).ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let max_of = |axis: &str| {
+        let line = text.lines().find(|l| l.contains(axis))?;
+        let rest = &line[line.find("max ")? + 4..];
+        rest.split(|c: char| !c.is_ascii_digit()).next()?.parse::<i32>().ok()
+    };
+    Some((max_of("ABS_MT_POSITION_X")?, max_of("ABS_MT_POSITION_Y")?))
+}
+
+/// Follows the touchscreen while recording `serial` and captures a frame after
+/// every tap or swipe. Ends on its own once the recording stops.
+pub fn start_touch_watcher(recording: &SharedRecordingState, serial: Option<String>, base_folder: String) {
+    let recording = Arc::clone(recording);
+    thread::spawn(move || {
+        let display = display_size(serial.as_deref()).ok();
+        let range = touch_range(serial.as_deref());
+        let to_display = |(x, y): (i32, i32)| match (display, range) {
+            (Some((w, h)), Some((max_x, max_y))) if max_x > 0 && max_y > 0 => (x * w / max_x, y * h / max_y),
+            _ => (x, y),
+        };
+
+        let mut child = match adb(serial.as_deref(), &["shell", "getevent", "-lq"]).stdout(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to start adb touch watcher: {}", e);
+                return;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else { return };
+        info!("Watching touches on Android device {}", serial.as_deref().unwrap_or("(default)"));
+        // getevent blocks until the next touch; end it as soon as the recording stops
+        let watched = Arc::clone(&recording);
+        thread::spawn(move || {
+            while recorder::is_recording_device(&watched) {
+                thread::sleep(WATCHER_POLL);
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+            info!("Android touch watcher stopped.");
+        });
+
+        let (mut position, mut down_at) = ((0, 0), None);
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let value = || fields.last().and_then(|v| i32::from_str_radix(v, 16).ok());
+            match fields.as_slice() {
+                [.., "ABS_MT_POSITION_X", _] => position.0 = value().unwrap_or(position.0),
+                [.., "ABS_MT_POSITION_Y", _] => position.1 = value().unwrap_or(position.1),
+                [.., "BTN_TOUCH", "DOWN"] => down_at = Some(position),
+                [.., "BTN_TOUCH", "UP"] => {
+                    let start = down_at.take().unwrap_or(position);
+                    let moved = (start.0 - position.0).abs() + (start.1 - position.1).abs();
+                    let label = if moved > TAP_SLOP { "Swipe" } else { "Tap" };
+                    let (touch, folder, capture) =
+                        (to_display(position), base_folder.clone(), AdbCapture { serial: serial.clone() });
+                    let recording = Arc::clone(&recording);
+                    thread::spawn(move || {
+                        let clock = SystemClock;
+                        thread::sleep(TOUCH_CAPTURE_DELAY);
+                        if let Err(e) = recorder::capture_device_frame(&recording, &folder, label, Some(touch), &clock, &capture) {
+                            error!("Failed to capture device frame: {}", e);
+                        }
+                    });
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_one_single_quoted_word() {
+        assert_eq!(quote_input_text("hello world").unwrap(), "'hello%sworld'");
+        assert_eq!(quote_input_text("a;b|c&d$(id)`id`").unwrap(), "'a;b|c&d$(id)`id`'");
+        assert_eq!(quote_input_text(r#"{a}[b]!c*?~#\""#).unwrap(), r#"'{a}[b]!c*?~#\"'"#);
+    }
+
+    #[test]
+    fn single_quotes_cannot_end_the_word() {
+        assert_eq!(quote_input_text("it's").unwrap(), r"'it'\''s'");
+        assert_eq!(quote_input_text("'; reboot; '").unwrap(), r"''\'';%sreboot;%s'\'''");
+    }
+
+    #[test]
+    fn control_characters_are_refused() {
+        for hostile in ["a\nreboot", "a\rreboot", "a\treboot", "a\0b", "a\u{1b}[2J", "a\u{7f}", "a\u{85}b"] {
+            assert!(matches!(quote_input_text(hostile), Err(ActionError::TextEntry(_))), "{:?}", hostile);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// --- Accessibility Status Announcements ---
+// Lets someone who can't see the screen supervise the agent. Milestones
+// (recording started/stopped, each action about to run, task finished) go out
+// as structured `status-announcement` events, which the frontend puts in ARIA
+// live regions for the platform screen reader. Those are only read while the
+// Metis window has focus, which it usually doesn't while the agent works, so
+// announcements can also be spoken through the OS speech service
+// (say / System.Speech / speech-dispatcher).
+
+use std::process::{Command, Stdio};
+use std::thread;
+
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::config::{self, AnnouncementSettings};
+use crate::events;
+
+pub const ANNOUNCEMENT_EVENT: &str = "status-announcement";
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    RecordingStarted,
+    RecordingStopped,
+    ActionStarting,
+    TaskComplete,
+    TaskFailed,
+}
+
+impl Status {
+    /// Failures interrupt whatever the screen reader is saying; the rest wait their turn.
+    fn assertive(self) -> bool {
+        matches!(self, Status::TaskFailed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Announcement {
+    pub status: Status,
+    pub message: String,
+    pub assertive: bool, // aria-live="assertive" rather than "polite"
+}
+
+/// Announces a status change, if announcements are enabled.
+pub fn announce(status: Status, message: impl Into<String>) {
+    let settings = config::get().announcements;
+    if !settings.enabled {
+        return;
+    }
+    let announcement = Announcement { status, message: message.into(), assertive: status.assertive() };
+    info!("Announcement ({:?}): {}", status, announcement.message);
+    if settings.speak {
+        speak(&announcement.message);
+    }
+    events::emit(ANNOUNCEMENT_EVENT, announcement);
+}
+
+/// Speaks `text` without waiting for it to finish.
+fn speak(text: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut c = Command::new("say");
+        c.arg(text);
+        c
+    } else if cfg!(target_os = "windows") {
+        // Passed through an environment variable so the text never has to be quoted for PowerShell
+        let mut c = Command::new("powershell");
+        c.args([
+            "-NoProfile",
+            "-Command",
+            "Add-Type -AssemblyName System.Speech; (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak($env:METIS_ANNOUNCEMENT)",
+        ])
+        .env("METIS_ANNOUNCEMENT", text);
+        c
+    } else {
+        let mut c = Command::new("spd-say"); // speech-dispatcher, shared with Orca
+        c.args(["--", text]);
+        c
+    };
+    match command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait()); // Reap it once it's done talking
+        }
+        Err(e) => error!("Failed to speak announcement: {}", e),
+    }
+}
+
+#[tauri::command]
+pub fn get_announcement_settings() -> Result<AnnouncementSettings, String> {
+    Ok(config::get().announcements)
+}
+
+#[tauri::command]
+pub fn update_announcement_settings(settings: AnnouncementSettings) -> Result<AnnouncementSettings, String> {
+    config::update(|s| s.announcements = settings).map(|s| s.announcements)
+}
@@ -0,0 +1,81 @@
+// --- Per-Application Recording Filter ---
+// Some applications must never end up in a recording: a password manager, a
+// banking app. settings.recording_filter lists them by process name (or lists
+// the only ones that may be recorded). A watcher checks the focused window twice
+// a second while either list is set; while a filtered app is in front the
+// listener drops input events and no screenshots, trajectory points or video
+// frames are taken, picking up again once focus moves to an allowed app. Delayed
+// captures check the window again themselves, since focus may have moved since.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::config::{self, RecordingFilterSettings};
+use crate::window_info::{self, WindowInfo};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+static BLOCKED: AtomicBool = AtomicBool::new(false);
+
+fn listed(process: &str, apps: &[String]) -> bool {
+    let stem = Path::new(process).file_stem().and_then(|s| s.to_str()).unwrap_or(process);
+    apps.iter()
+        .map(|entry| entry.trim())
+        .any(|entry| entry.eq_ignore_ascii_case(process) || entry.eq_ignore_ascii_case(stem))
+}
+
+fn active(settings: &RecordingFilterSettings) -> bool {
+    !settings.denied_apps.is_empty() || !settings.allowed_apps.is_empty()
+}
+
+/// Whether `window` may be recorded. With an allow list, a window that can't be
+/// identified is not.
+pub fn permits(window: Option<&WindowInfo>, settings: &RecordingFilterSettings) -> bool {
+    match window {
+        Some(window) => {
+            !listed(&window.process, &settings.denied_apps)
+                && (settings.allowed_apps.is_empty() || listed(&window.process, &settings.allowed_apps))
+        }
+        None => settings.allowed_apps.is_empty(),
+    }
+}
+
+/// Whether the app in front is filtered out right now (as of the last poll).
+pub fn blocked() -> bool {
+    BLOCKED.load(Ordering::Relaxed)
+}
+
+/// Starts the watcher thread; called once from main.
+pub fn start_watcher() {
+    thread::spawn(|| loop {
+        let settings = config::get().recording_filter;
+        let (next, process) = if active(&settings) {
+            let window = window_info::foreground();
+            (!permits(window.as_ref(), &settings), window.map(|w| w.process).unwrap_or_default())
+        } else {
+            (false, String::new())
+        };
+        if BLOCKED.swap(next, Ordering::Relaxed) != next {
+            if next {
+                info!("Recording paused: '{}' is excluded from recording", process);
+            } else {
+                info!("Recording resumed: '{}' may be recorded", process);
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[tauri::command]
+pub fn get_recording_filter() -> Result<RecordingFilterSettings, String> {
+    Ok(config::get().recording_filter)
+}
+
+#[tauri::command]
+pub fn update_recording_filter(settings: RecordingFilterSettings) -> Result<RecordingFilterSettings, String> {
+    config::update(|s| s.recording_filter = settings).map(|s| s.recording_filter)
+}
@@ -0,0 +1,165 @@
+// --- Shared Application State Machine ---
+// Idle <-> Recording and Idle <-> ExecutingAction are the legal transitions from
+// Idle, so recording and autonomous execution can never overlap. A running task
+// can be Paused and resumed; aborting it while paused goes straight to Idle.
+// Every transition is broadcast as an `app://state-changed` event so the UI
+// always reflects reality, and drives the always-on-top activity indicator.
+
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tauri::State;
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::events;
+use crate::indicator;
+use crate::sync::LockExt;
+
+pub const STATE_CHANGED_EVENT: &str = "app://state-changed";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AppInputState {
+    Idle,
+    Recording,
+    ExecutingAction,
+    Paused, // A task is running but waits for resume_task before its next step
+}
+
+impl AppInputState {
+    pub fn can_transition_to(self, next: AppInputState) -> bool {
+        use AppInputState::*;
+        matches!(
+            (self, next),
+            (Idle, Recording)
+                | (Recording, Idle)
+                | (Idle, ExecutingAction)
+                | (ExecutingAction, Idle)
+                | (ExecutingAction, Paused)
+                | (Paused, ExecutingAction)
+                | (Paused, Idle)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Error, Serialize)]
+#[error("Cannot go from {from:?} to {to:?}")]
+pub struct TransitionError {
+    pub from: AppInputState,
+    pub to: AppInputState,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct StateChanged {
+    from: AppInputState,
+    to: AppInputState,
+}
+
+// Holds state relevant across the entire application lifecycle
+pub struct GlobalAppState {
+    input_state: AppInputState, // Only changed through transition()
+    pub action_interrupted: bool, // Set by ESC or cancel_task; execute_task_loop aborts when it sees it
+}
+
+impl Default for GlobalAppState {
+    fn default() -> Self {
+        GlobalAppState {
+            input_state: AppInputState::Idle,
+            action_interrupted: false,
+        }
+    }
+}
+
+impl GlobalAppState {
+    pub fn input_state(&self) -> AppInputState {
+        self.input_state
+    }
+
+    /// Moves to `to` if the transition is legal, emitting a state-changed event.
+    pub fn transition(&mut self, to: AppInputState) -> Result<(), TransitionError> {
+        let from = self.input_state;
+        if !from.can_transition_to(to) {
+            return Err(TransitionError { from, to });
+        }
+        self.input_state = to;
+        if from == AppInputState::Idle && to == AppInputState::ExecutingAction {
+            self.action_interrupted = false; // Fresh run, forget any stale ESC press
+        }
+        info!("[State] {:?} -> {:?}", from, to);
+        events::emit(STATE_CHANGED_EVENT, StateChanged { from, to });
+        indicator::update(to);
+        Ok(())
+    }
+}
+
+// Created once in main() and registered with .manage(): commands receive it as
+// State<'_, SharedAppState>, and the global listener and task threads hold clones
+pub type SharedAppState = Arc<Mutex<GlobalAppState>>;
+
+pub fn new_shared() -> SharedAppState {
+    Arc::new(Mutex::new(GlobalAppState::default()))
+}
+
+/// Holds the app in ExecutingAction (or Paused) and returns it to Idle when dropped,
+/// so every exit path of the action loop (including panics) releases the state.
+pub struct ExecutionGuard(SharedAppState);
+
+impl ExecutionGuard {
+    pub fn acquire(state: &SharedAppState) -> Result<ExecutionGuard, TransitionError> {
+        state.lock_or_recover().transition(AppInputState::ExecutingAction)?;
+        Ok(ExecutionGuard(Arc::clone(state)))
+    }
+}
+
+impl Drop for ExecutionGuard {
+    fn drop(&mut self) {
+        let mut state = self.0.lock_or_recover();
+        if let Err(e) = state.transition(AppInputState::Idle) {
+            error!("Failed to leave ExecutingAction: {}", e);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_app_state(app_state: State<'_, SharedAppState>) -> Result<AppInputState, String> {
+    Ok(app_state.lock_or_recover().input_state())
+}
+
+/// Freezes the running task before its next step, so the user can take over the
+/// mouse and keyboard; the task continues from the screen as they leave it.
+#[tauri::command]
+pub fn pause_task(app_state: State<'_, SharedAppState>) -> Result<AppInputState, String> {
+    let mut state = app_state.lock_or_recover();
+    state.transition(AppInputState::Paused).map_err(|e| e.to_string())?;
+    Ok(state.input_state())
+}
+
+#[tauri::command]
+pub fn resume_task(app_state: State<'_, SharedAppState>) -> Result<AppInputState, String> {
+    let mut state = app_state.lock_or_recover();
+    if state.input_state() != AppInputState::Paused {
+        return Err(format!("No paused task to resume (state: {:?})", state.input_state()));
+    }
+    state.transition(AppInputState::ExecutingAction).map_err(|e| e.to_string())?;
+    Ok(state.input_state())
+}
+
+/// Stops the running task, like pressing ESC, for when the global key listener
+/// isn't available. The loop aborts at its next check, within one step.
+#[tauri::command]
+pub fn cancel_task(app_state: State<'_, SharedAppState>) -> Result<(), String> {
+    let mut state = app_state.lock_or_recover();
+    match state.input_state() {
+        AppInputState::ExecutingAction | AppInputState::Paused => {
+            info!("[State] Task cancelled from the UI.");
+            state.action_interrupted = true;
+            Ok(())
+        }
+        other => Err(format!("No task is running (state: {:?})", other)),
+    }
+}
+
+#[tauri::command]
+pub fn is_recording_active(app_state: State<'_, SharedAppState>) -> Result<bool, String> {
+    Ok(app_state.lock_or_recover().input_state() == AppInputState::Recording)
+}
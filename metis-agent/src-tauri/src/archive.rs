@@ -0,0 +1,356 @@
+// --- Zip Archives ---
+// Just enough of the zip format to share skills as one file: a writer that
+// deflates each entry into memory, and a reader for archives written by this
+// writer or by common zip tools (stored or deflated entries, no zip64, no
+// encryption). Entries are read through the central directory, and every
+// entry's size and CRC-32 are checked. The archive comment can be set and read
+// back separately, which is where provenance.rs stamps skill files.
+
+use std::io::{self, Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIG: u32 = 0x0605_4b50;
+const END_OF_CENTRAL_DIR_LEN: usize = 22;
+const VERSION: u16 = 20; // 2.0: deflate
+const UTF8_NAMES: u16 = 0x0800;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const DOS_DATE_1980: u16 = (1 << 5) | 1; // 1980-01-01; entries carry no meaningful time
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> io::Result<u16> {
+    bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(|| invalid("Truncated zip archive"))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> io::Result<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("Truncated zip archive"))
+}
+
+/// Builds a zip archive in memory.
+#[derive(Default)]
+pub struct ZipWriter {
+    out: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a deflated file. `name` uses forward slashes.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let too_big = |_| invalid(format!("{} is too large for a zip archive", name));
+        let (size, compressed_size) = (u32::try_from(data.len()).map_err(too_big)?, u32::try_from(compressed.len()).map_err(too_big)?);
+        let offset = u32::try_from(self.out.len()).map_err(too_big)?;
+        let crc = crc32fast::hash(data);
+        let name_len = u16::try_from(name.len()).map_err(|_| invalid(format!("Entry name too long: {}", name)))?;
+        self.entries = self.entries.checked_add(1).ok_or_else(|| invalid("Too many zip entries"))?;
+
+        // Fields shared by the local and central headers, from "version needed" on
+        let mut common = Vec::with_capacity(26);
+        for field in [VERSION, UTF8_NAMES, DEFLATED, 0, DOS_DATE_1980] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, compressed_size, size] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        common.extend_from_slice(&name_len.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes()); // Extra field length
+
+        self.out.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+        self.out.extend_from_slice(&common);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(&compressed);
+
+        self.central.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+        self.central.extend_from_slice(&VERSION.to_le_bytes()); // Version made by
+        self.central.extend_from_slice(&common);
+        for field in [0u16, 0, 0] {
+            self.central.extend_from_slice(&field.to_le_bytes()); // Comment length, disk, internal attributes
+        }
+        self.central.extend_from_slice(&0u32.to_le_bytes()); // External attributes
+        self.central.extend_from_slice(&offset.to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    /// The finished archive.
+    pub fn finish(mut self) -> io::Result<Vec<u8>> {
+        let too_big = |_| invalid("Archive is too large for a zip file");
+        let central_offset = u32::try_from(self.out.len()).map_err(too_big)?;
+        let central_size = u32::try_from(self.central.len()).map_err(too_big)?;
+        self.out.append(&mut self.central);
+        self.out.extend_from_slice(&END_OF_CENTRAL_DIR_SIG.to_le_bytes());
+        for field in [0u16, 0, self.entries, self.entries] {
+            self.out.extend_from_slice(&field.to_le_bytes());
+        }
+        self.out.extend_from_slice(&central_size.to_le_bytes());
+        self.out.extend_from_slice(&central_offset.to_le_bytes());
+        self.out.extend_from_slice(&0u16.to_le_bytes()); // Comment length
+        Ok(self.out)
+    }
+}
+
+/// Offset of the end of central directory record.
+fn end_record(bytes: &[u8]) -> io::Result<usize> {
+    // The end record sits at the very end, before a comment of up to 64 KiB
+    let earliest = bytes.len().saturating_sub(END_OF_CENTRAL_DIR_LEN + u16::MAX as usize);
+    (earliest..=bytes.len().saturating_sub(END_OF_CENTRAL_DIR_LEN))
+        .rev()
+        .find(|&at| u32_at(bytes, at).ok() == Some(END_OF_CENTRAL_DIR_SIG))
+        .ok_or_else(|| invalid("Not a zip archive"))
+}
+
+/// Splits an archive into the same archive without a comment, and its comment.
+/// None if `bytes` isn't an archive or has data after the comment.
+pub fn split_comment(bytes: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let end = end_record(bytes).ok()?;
+    let comment_len = u16_at(bytes, end + 20).ok()? as usize;
+    let comment = bytes.get(end + END_OF_CENTRAL_DIR_LEN..)?;
+    if comment.len() != comment_len {
+        return None;
+    }
+    let mut bare = bytes[..end + 20].to_vec();
+    bare.extend_from_slice(&0u16.to_le_bytes());
+    Some((bare, comment))
+}
+
+/// Sets the comment of an archive that has none (as ZipWriter writes them).
+pub fn with_comment(mut bytes: Vec<u8>, comment: &[u8]) -> io::Result<Vec<u8>> {
+    let end = end_record(&bytes)?;
+    if end + END_OF_CENTRAL_DIR_LEN != bytes.len() {
+        return Err(invalid("Archive already has a comment"));
+    }
+    let comment_len = u16::try_from(comment.len()).map_err(|_| invalid("Zip comment too long"))?;
+    bytes[end + 20..].copy_from_slice(&comment_len.to_le_bytes());
+    bytes.extend_from_slice(comment);
+    Ok(bytes)
+}
+
+/// Every file in the archive `bytes` as (name, contents), in archive order.
+/// Fails once the unpacked contents would exceed `max_total` bytes.
+pub fn read_all(bytes: &[u8], max_total: u64) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let end = end_record(bytes)?;
+    let entries = u16_at(bytes, end + 10)?;
+    let mut at = u32_at(bytes, end + 16)? as usize;
+
+    let mut files = Vec::with_capacity(entries as usize);
+    let mut remaining = max_total;
+    for _ in 0..entries {
+        if u32_at(bytes, at)? != CENTRAL_HEADER_SIG {
+            return Err(invalid("Corrupt zip central directory"));
+        }
+        let flags = u16_at(bytes, at + 8)?;
+        let method = u16_at(bytes, at + 10)?;
+        let crc = u32_at(bytes, at + 16)?;
+        let compressed_size = u32_at(bytes, at + 20)? as usize;
+        let size = u64::from(u32_at(bytes, at + 24)?);
+        let name_len = u16_at(bytes, at + 28)? as usize;
+        let extra_len = u16_at(bytes, at + 30)? as usize;
+        let comment_len = u16_at(bytes, at + 32)? as usize;
+        let local = u32_at(bytes, at + 42)? as usize;
+        let name = bytes.get(at + 46..at + 46 + name_len).ok_or_else(|| invalid("Truncated zip archive"))?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| invalid("Zip entry name is not UTF-8"))?;
+        at += 46 + name_len + extra_len + comment_len;
+
+        if flags & 1 != 0 {
+            return Err(invalid(format!("{} is encrypted", name)));
+        }
+        if size > remaining {
+            return Err(invalid("Archive unpacks to more than the allowed size"));
+        }
+        remaining -= size;
+        if u32_at(bytes, local)? != LOCAL_HEADER_SIG {
+            return Err(invalid(format!("Corrupt zip entry {}", name)));
+        }
+        let data_start = local + 30 + u16_at(bytes, local + 26)? as usize + u16_at(bytes, local + 28)? as usize;
+        let data = bytes.get(data_start..data_start + compressed_size).ok_or_else(|| invalid("Truncated zip archive"))?;
+
+        let mut contents = Vec::with_capacity(size as usize);
+        match method {
+            STORED => contents.extend_from_slice(data),
+            // One byte past the declared size is enough to catch an entry that lies about it
+            DEFLATED => {
+                DeflateDecoder::new(data).take(size + 1).read_to_end(&mut contents)?;
+            }
+            other => return Err(invalid(format!("{} uses unsupported compression method {}", name, other))),
+        }
+        if contents.len() as u64 != size || crc32fast::hash(&contents) != crc {
+            return Err(invalid(format!("{} is corrupt (size or checksum mismatch)", name)));
+        }
+        files.push((name, contents));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut zip = ZipWriter::new();
+        zip.add("skill.json", b"{\"name\": \"demo\"}").unwrap();
+        zip.add("recordings/0/parsed.csv", &b"x,y\n".repeat(1000)).unwrap();
+        zip.add("empty.txt", b"").unwrap();
+        zip.finish().unwrap()
+    }
+
+    /// Offset of the only central directory header, found by its signature.
+    fn central_header(bytes: &[u8]) -> usize {
+        bytes.windows(4).position(|w| w == CENTRAL_HEADER_SIG.to_le_bytes()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_in_order() {
+        let files = read_all(&sample(), u64::MAX).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["skill.json", "recordings/0/parsed.csv", "empty.txt"]);
+        assert_eq!(files[0].1, b"{\"name\": \"demo\"}");
+        assert_eq!(files[1].1, b"x,y\n".repeat(1000));
+        assert!(files[2].1.is_empty());
+    }
+
+    #[test]
+    fn reads_an_empty_archive_and_one_with_a_comment() {
+        assert!(read_all(&ZipWriter::new().finish().unwrap(), u64::MAX).unwrap().is_empty());
+        let mut bytes = sample();
+        let comment = b"made elsewhere";
+        let len_at = bytes.len() - 2;
+        bytes[len_at..].copy_from_slice(&(comment.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(comment);
+        assert_eq!(read_all(&bytes, u64::MAX).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn reads_stored_entries() {
+        // One stored entry, as `zip -0` writes it
+        let data = b"plain";
+        let crc = crc32fast::hash(data);
+        let mut common = Vec::new();
+        for field in [10u16, 0, STORED, 0, 0] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, data.len() as u32, data.len() as u32] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        common.extend_from_slice(&1u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        let mut bytes = LOCAL_HEADER_SIG.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&common);
+        bytes.push(b'a');
+        bytes.extend_from_slice(data);
+        let central_offset = bytes.len() as u32;
+        bytes.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+        bytes.extend_from_slice(&10u16.to_le_bytes());
+        bytes.extend_from_slice(&common);
+        bytes.extend_from_slice(&[0; 10]); // Comment length, disk, attributes
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // Local header offset
+        bytes.push(b'a');
+        let central_size = bytes.len() as u32 - central_offset;
+        bytes.extend_from_slice(&END_OF_CENTRAL_DIR_SIG.to_le_bytes());
+        for field in [0u16, 0, 1, 1] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&central_size.to_le_bytes());
+        bytes.extend_from_slice(&central_offset.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        assert_eq!(read_all(&bytes, u64::MAX).unwrap(), [("a".to_string(), data.to_vec())]);
+    }
+
+    #[test]
+    fn comments_split_off_cleanly() {
+        let bare = sample();
+        let commented = with_comment(bare.clone(), b"stamp").unwrap();
+        assert_eq!(read_all(&commented, u64::MAX).unwrap().len(), 3);
+        assert_eq!(split_comment(&commented).unwrap(), (bare.clone(), &b"stamp"[..]));
+        assert_eq!(split_comment(&bare).unwrap(), (bare.clone(), &b""[..]));
+        assert!(with_comment(commented.clone(), b"again").is_err());
+        let mut trailing = commented;
+        trailing.push(0);
+        assert!(split_comment(&trailing).is_none());
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_zip() {
+        assert!(read_all(b"", u64::MAX).is_err());
+        assert!(read_all(b"PK\x03\x04 not really a zip", u64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_archives() {
+        let bytes = sample();
+        // Cut into the end record, the central directory, and the entry data
+        for len in [bytes.len() - 1, central_header(&bytes) + 10, 40] {
+            assert!(read_all(&bytes[..len], u64::MAX).is_err(), "accepted {} of {} bytes", len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn rejects_offsets_past_the_end() {
+        let mut bytes = sample();
+        let end = bytes.len() - END_OF_CENTRAL_DIR_LEN;
+        bytes[end + 16..end + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_all(&bytes, u64::MAX).is_err());
+
+        let mut bytes = sample();
+        let central = central_header(&bytes);
+        bytes[central + 42..central + 46].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_all(&bytes, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_contents() {
+        let mut bytes = sample();
+        bytes[30 + "skill.json".len()] ^= 0xff; // First byte of the first entry's data
+        assert!(read_all(&bytes, u64::MAX).is_err());
+
+        let mut bytes = sample();
+        let central = central_header(&bytes);
+        bytes[central + 16] ^= 1; // CRC
+        assert!(read_all(&bytes, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_an_entry_that_understates_its_size() {
+        let mut bytes = sample();
+        let central = central_header(&bytes);
+        bytes[central + 24..central + 28].copy_from_slice(&1u32.to_le_bytes());
+        assert!(read_all(&bytes, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_encrypted_and_unknown_methods() {
+        let mut bytes = sample();
+        let central = central_header(&bytes);
+        bytes[central + 8] |= 1;
+        assert!(read_all(&bytes, u64::MAX).unwrap_err().to_string().contains("encrypted"));
+
+        let mut bytes = sample();
+        bytes[central + 10] = 12; // bzip2
+        assert!(read_all(&bytes, u64::MAX).unwrap_err().to_string().contains("compression method"));
+    }
+
+    #[test]
+    fn stops_at_the_size_limit() {
+        let bytes = sample();
+        let total = 16 + 4000;
+        assert!(read_all(&bytes, total).is_ok());
+        assert!(read_all(&bytes, total - 1).unwrap_err().to_string().contains("allowed size"));
+    }
+}
@@ -0,0 +1,179 @@
+// --- AT-SPI Element Provider (Linux) ---
+// GTK and Qt apps publish their widgets on the AT-SPI2 accessibility bus, a
+// separate D-Bus bus whose address the session bus hands out. The active window
+// is found among the registry's applications by its ACTIVE state, then walked
+// depth-first. Elements with an Action interface are pressed through DoAction;
+// the rest are reported by their Component extents for the caller to click.
+// `element_at` doesn't walk: it follows Component.GetAccessibleAtPoint down
+// from the active window, one call per level.
+
+use std::time::Duration;
+
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::blocking::Connection;
+use dbus::channel::Channel;
+use dbus::Path;
+
+use crate::elements::{self, Activation, ElementInfo, ElementProvider};
+use crate::error::ActionError;
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+const REGISTRY: &str = "org.a11y.atspi.Registry";
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+// What GetAccessibleAtPoint returns when no child is at the point
+const NULL_PATH: &str = "/org/a11y/atspi/null";
+const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+const COMPONENT: &str = "org.a11y.atspi.Component";
+const ACTION: &str = "org.a11y.atspi.Action";
+
+// AtspiStateType bits
+const STATE_ACTIVE: u32 = 1;
+const STATE_SHOWING: u32 = 25;
+const STATE_VISIBLE: u32 = 30;
+
+const COORD_TYPE_SCREEN: u32 = 0;
+const PRESS_ACTIONS: [&str; 3] = ["click", "press", "activate"];
+
+// Same cut-offs as the AX provider: deep trees are mostly document content
+const MAX_DEPTH: usize = 25;
+const MAX_VISITED: usize = 5_000;
+
+pub struct AtspiProvider;
+
+/// An accessible object: the owning app's bus name and the object path.
+type Node = (String, Path<'static>);
+
+fn atspi_error(e: dbus::Error) -> ActionError {
+    ActionError::ElementTargeting(format!("AT-SPI: {}", e))
+}
+
+/// Connects to the accessibility bus (not the session bus itself).
+fn connect() -> Result<Connection, ActionError> {
+    let session = Connection::new_session().map_err(atspi_error)?;
+    let (address,): (String,) = session
+        .with_proxy("org.a11y.Bus", "/org/a11y/bus", TIMEOUT)
+        .method_call("org.a11y.Bus", "GetAddress", ())
+        .map_err(atspi_error)?;
+    let mut channel = Channel::open_private(&address).map_err(atspi_error)?;
+    channel.register().map_err(atspi_error)?;
+    Ok(Connection::from(channel))
+}
+
+fn children(conn: &Connection, node: &Node) -> Vec<Node> {
+    conn.with_proxy(node.0.as_str(), node.1.clone(), TIMEOUT)
+        .method_call(ACCESSIBLE, "GetChildren", ())
+        .map(|(children,): (Vec<(String, Path<'static>)>,)| children)
+        .unwrap_or_default()
+}
+
+fn has_state(conn: &Connection, node: &Node, state: u32) -> bool {
+    conn.with_proxy(node.0.as_str(), node.1.clone(), TIMEOUT)
+        .method_call(ACCESSIBLE, "GetState", ())
+        .map(|(bits,): (Vec<u32>,)| bits.get((state / 32) as usize).is_some_and(|word| word & (1 << (state % 32)) != 0))
+        .unwrap_or(false)
+}
+
+/// The window with ACTIVE state among all registered applications.
+fn active_window(conn: &Connection) -> Result<Node, ActionError> {
+    let root: Node = (REGISTRY.to_string(), Path::from(ROOT_PATH));
+    children(conn, &root)
+        .iter()
+        .flat_map(|app| children(conn, app))
+        .find(|window| has_state(conn, window, STATE_ACTIVE))
+        .ok_or_else(|| ActionError::ElementTargeting("No active window on the accessibility bus".to_string()))
+}
+
+/// Every element under the active window, depth-first.
+fn foreground_elements(conn: &Connection) -> Result<Vec<Node>, ActionError> {
+    let mut found = Vec::new();
+    let mut pending = vec![(active_window(conn)?, 0)];
+    while let Some((node, depth)) = pending.pop() {
+        if found.len() >= MAX_VISITED {
+            break;
+        }
+        if depth < MAX_DEPTH {
+            pending.extend(children(conn, &node).into_iter().rev().map(|child| (child, depth + 1)));
+        }
+        found.push(node);
+    }
+    Ok(found)
+}
+
+/// Name, role and bounds of a named element that is showing on screen.
+fn describe(conn: &Connection, node: &Node) -> Option<ElementInfo> {
+    let proxy = conn.with_proxy(node.0.as_str(), node.1.clone(), TIMEOUT);
+    let name: String = proxy.get(ACCESSIBLE, "Name").ok()?;
+    if name.trim().is_empty() || !has_state(conn, node, STATE_SHOWING) || !has_state(conn, node, STATE_VISIBLE) {
+        return None;
+    }
+    let (role,): (String,) = proxy.method_call(ACCESSIBLE, "GetRoleName", ()).unwrap_or_default();
+    let ((x, y, width, height),): ((i32, i32, i32, i32),) =
+        proxy.method_call(COMPONENT, "GetExtents", (COORD_TYPE_SCREEN,)).ok()?;
+    (width > 0 && height > 0).then_some(ElementInfo { name, role, x, y, width, height })
+}
+
+/// The innermost named element at a screen point in the active window.
+fn hit_test(conn: &Connection, x: i32, y: i32) -> Result<Option<ElementInfo>, ActionError> {
+    let mut node = active_window(conn)?;
+    let mut hit = describe(conn, &node).filter(|info| info.contains(x, y));
+    for _ in 0..MAX_DEPTH {
+        let child: Result<((String, Path<'static>),), _> = conn
+            .with_proxy(node.0.as_str(), node.1.clone(), TIMEOUT)
+            .method_call(COMPONENT, "GetAccessibleAtPoint", (x, y, COORD_TYPE_SCREEN));
+        let Ok(((bus, path),)) = child else { break };
+        let bus = if bus.is_empty() { node.0.clone() } else { bus }; // Same app
+        if &*path == NULL_PATH || (bus == node.0 && path == node.1) {
+            break;
+        }
+        node = (bus, path);
+        if let Some(info) = describe(conn, &node) {
+            hit = Some(info);
+        }
+    }
+    Ok(hit)
+}
+
+/// Presses the element through its Action interface, if it has a press-like action.
+fn do_press(conn: &Connection, node: &Node) -> bool {
+    let proxy = conn.with_proxy(node.0.as_str(), node.1.clone(), TIMEOUT);
+    let Ok(count) = proxy.get::<i32>(ACTION, "NActions") else {
+        return false;
+    };
+    let press_index = (0..count).find(|&i| {
+        proxy
+            .method_call(ACTION, "GetName", (i,))
+            .is_ok_and(|(name,): (String,)| PRESS_ACTIONS.contains(&name.to_ascii_lowercase().as_str()))
+    });
+    press_index.is_some_and(|i| proxy.method_call(ACTION, "DoAction", (i,)).is_ok_and(|(done,): (bool,)| done))
+}
+
+impl ElementProvider for AtspiProvider {
+    fn name(&self) -> &'static str {
+        "atspi"
+    }
+
+    fn snapshot(&self, limit: usize) -> Result<Vec<ElementInfo>, ActionError> {
+        let conn = connect()?;
+        Ok(foreground_elements(&conn)?.iter().filter_map(|node| describe(&conn, node)).take(limit).collect())
+    }
+
+    fn activate(&self, name: &str, role: Option<&str>) -> Result<Activation, ActionError> {
+        let conn = connect()?;
+        for node in foreground_elements(&conn)? {
+            let Some(info) = describe(&conn, &node) else { continue };
+            if !elements::matches(&info.name, &info.role, name, role) {
+                continue;
+            }
+            if do_press(&conn, &node) {
+                return Ok(Activation::Invoked);
+            }
+            let (x, y) = info.center();
+            return Ok(Activation::Click(x, y));
+        }
+        Err(elements::not_found(name, role))
+    }
+
+    fn element_at(&self, x: i32, y: i32) -> Result<Option<ElementInfo>, ActionError> {
+        hit_test(&connect()?, x, y)
+    }
+}
@@ -0,0 +1,378 @@
+// --- Synthetic Input Audit Log ---
+// Every call the agent makes against the input backend is appended to
+// <data dir>/metis/audit.jsonl before control returns to the action loop. Each
+// entry carries the SHA-256 of the previous entry, so editing or deleting any
+// line breaks the chain from that point on and `verify_audit_log` reports it.
+// Typed text is never logged: an entry records its length and a SHA-256 salted
+// with a per-install secret (<data dir>/metis/audit.salt), so a known string can
+// be matched against the log without the log revealing what was typed. While
+// the OS reports secure (password) entry even the hash is left out.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use enigo::{Direction, Key};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use crate::error::ActionError;
+use crate::input::InputBackend;
+use crate::provenance;
+use crate::secure_input;
+use crate::sync::LockExt;
+use crate::window_control::WindowOp;
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const SALT_LEN: usize = 16;
+
+/// The hashed part of an entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub timestamp_ms: u64,
+    pub run_id: String,
+    pub backend: String,
+    pub call: String, // e.g. "move_mouse"
+    pub args: String, // Debug-formatted arguments
+    pub ok: bool,
+    pub error: Option<String>,
+    pub prev_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditVerification {
+    pub entries: u64,
+    pub intact: bool,
+    pub first_broken_seq: Option<u64>, // First entry whose hash or link doesn't match
+}
+
+struct ChainHead {
+    seq: u64,
+    last_hash: String,
+}
+
+// Loaded lazily from the last line of the log so the chain continues across restarts
+static CHAIN_HEAD: Lazy<Mutex<ChainHead>> = Lazy::new(|| Mutex::new(load_head(&audit_log_path())));
+static TEXT_SALT: Lazy<Vec<u8>> = Lazy::new(load_salt);
+
+pub fn audit_log_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("metis")
+        .join("audit.jsonl")
+}
+
+fn salt_path() -> PathBuf {
+    audit_log_path().with_file_name("audit.salt")
+}
+
+/// The per-install salt for typed text, created on first use. If it can't be
+/// saved, this run's entries use a throwaway one and can't be matched later.
+fn load_salt() -> Vec<u8> {
+    let path = salt_path();
+    if let Some(salt) = fs::read(&path).ok().filter(|salt| salt.len() == SALT_LEN) {
+        return salt;
+    }
+    let salt = rand::random::<[u8; SALT_LEN]>().to_vec();
+    let saved = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, &salt));
+    if let Err(e) = saved {
+        error!("Failed to save the audit salt to {}: {}", path.display(), e);
+    }
+    salt
+}
+
+/// What the log records about typed text in place of the text itself.
+fn text_args(text: &str, salt: &[u8], secure: bool) -> String {
+    let chars = text.chars().count();
+    if secure {
+        return format!("{} chars into secure input", chars);
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(text.as_bytes());
+    format!("{} chars, salted sha256 {}", chars, hex::encode(hasher.finalize()))
+}
+
+fn hash_record(record: &AuditRecord) -> String {
+    let body = serde_json::to_string(record).expect("audit record serializes");
+    hex::encode(Sha256::digest(body.as_bytes()))
+}
+
+fn read_entries(path: &Path) -> Vec<Result<AuditEntry, String>> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn load_head(path: &Path) -> ChainHead {
+    match read_entries(path).into_iter().filter_map(Result::ok).next_back() {
+        Some(last) => ChainHead { seq: last.record.seq + 1, last_hash: last.hash },
+        None => ChainHead { seq: 0, last_hash: GENESIS_HASH.to_string() },
+    }
+}
+
+fn next_entry(head: &ChainHead, run_id: &str, backend: &str, call: &str, args: String, result: &Result<(), ActionError>) -> AuditEntry {
+    let record = AuditRecord {
+        seq: head.seq,
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        run_id: run_id.to_string(),
+        backend: backend.to_string(),
+        call: call.to_string(),
+        args,
+        ok: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        prev_hash: head.last_hash.clone(),
+    };
+    AuditEntry { hash: hash_record(&record), record }
+}
+
+fn write_entry(path: &Path, entry: &AuditEntry) -> std::io::Result<()> {
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+        .and_then(|mut file| {
+            let mut line = serde_json::to_string(entry)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())
+        })
+}
+
+/// Appends one entry to the chain. Failures are logged; they never block the action.
+fn append(run_id: &str, backend: &str, call: &str, args: String, result: &Result<(), ActionError>) {
+    let mut head = CHAIN_HEAD.lock_or_recover();
+    let entry = next_entry(&head, run_id, backend, call, args, result);
+    match write_entry(&audit_log_path(), &entry) {
+        Ok(()) => {
+            head.seq += 1;
+            head.last_hash = entry.hash;
+        }
+        Err(e) => error!("Failed to write audit entry: {}", e),
+    }
+}
+
+/// A fresh ID tying together every audit entry of one task run.
+pub fn new_run_id() -> String {
+    let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format!("run_{}_{:08x}", ts, rand::random::<u32>())
+}
+
+/// Wraps an InputBackend and audits every call made through it.
+pub struct AuditedInput<'a> {
+    inner: &'a mut dyn InputBackend,
+    run_id: String,
+}
+
+impl<'a> AuditedInput<'a> {
+    pub fn new(inner: &'a mut dyn InputBackend, run_id: String) -> Self {
+        AuditedInput { inner, run_id }
+    }
+
+    fn audited(&mut self, call: &str, args: String, f: impl FnOnce(&mut dyn InputBackend) -> Result<(), ActionError>) -> Result<(), ActionError> {
+        let result = f(&mut *self.inner);
+        append(&self.run_id, self.inner.name(), call, args, &result);
+        result
+    }
+}
+
+impl InputBackend for AuditedInput<'_> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError> {
+        self.audited("move_mouse", format!("({}, {})", x, y), |input| input.move_mouse(x, y))
+    }
+
+    fn left_button(&mut self, direction: Direction) -> Result<(), ActionError> {
+        self.audited("left_button", format!("{:?}", direction), |input| input.left_button(direction))
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError> {
+        self.audited("key", format!("{:?} {:?}", key, direction), |input| input.key(key, direction))
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), ActionError> {
+        let args = text_args(text, &TEXT_SALT, secure_input::active());
+        self.audited("text", args, |input| input.text(text))
+    }
+
+    fn scroll(&mut self, units: i32) -> Result<(), ActionError> {
+        self.audited("scroll", units.to_string(), |input| input.scroll(units))
+    }
+
+    fn hscroll(&mut self, units: i32) -> Result<(), ActionError> {
+        self.audited("hscroll", units.to_string(), |input| input.hscroll(units))
+    }
+
+    fn main_display(&self) -> Result<(i32, i32), ActionError> {
+        self.inner.main_display() // Read-only, not audited
+    }
+
+    fn activate_element(&mut self, name: &str, control_type: Option<&str>) -> Result<(), ActionError> {
+        self.audited("activate_element", format!("{:?} {:?}", name, control_type), |input| input.activate_element(name, control_type))
+    }
+
+    fn control_window(&mut self, title: &str, op: WindowOp) -> Result<(), ActionError> {
+        self.audited("control_window", format!("{:?} {:?}", op, title), |input| input.control_window(title, op))
+    }
+
+    fn launch(&mut self, target: &str) -> Result<(), ActionError> {
+        self.audited("launch", format!("{:?}", target), |input| input.launch(target))
+    }
+
+    fn long_press(&mut self, x: i32, y: i32, hold: Duration) -> Result<(), ActionError> {
+        self.audited("long_press", format!("({}, {}) {:?}", x, y, hold), |input| input.long_press(x, y, hold))
+    }
+
+    fn pinch(&mut self, x: i32, y: i32, scale: f32) -> Result<(), ActionError> {
+        self.audited("pinch", format!("({}, {}) x{}", x, y, scale), |input| input.pinch(x, y, scale))
+    }
+
+    fn two_finger_scroll(&mut self, x: i32, y: i32, units: i32) -> Result<(), ActionError> {
+        self.audited("two_finger_scroll", format!("({}, {}) {}", x, y, units), |input| input.two_finger_scroll(x, y, units))
+    }
+}
+
+/// Walks the whole chain, recomputing every hash and link.
+pub fn verify() -> AuditVerification {
+    verify_log(&audit_log_path())
+}
+
+fn verify_log(path: &Path) -> AuditVerification {
+    let mut expected_prev = GENESIS_HASH.to_string();
+    let mut entries = 0;
+    for (i, parsed) in read_entries(path).into_iter().enumerate() {
+        entries += 1;
+        let intact = match &parsed {
+            Ok(entry) => entry.record.prev_hash == expected_prev && hash_record(&entry.record) == entry.hash,
+            Err(_) => false,
+        };
+        if !intact {
+            return AuditVerification { entries, intact: false, first_broken_seq: Some(i as u64) };
+        }
+        if let Ok(entry) = parsed {
+            expected_prev = entry.hash;
+        }
+    }
+    AuditVerification { entries, intact: true, first_broken_seq: None }
+}
+
+#[tauri::command]
+pub fn verify_audit_log() -> Result<AuditVerification, String> {
+    Ok(verify())
+}
+
+/// Copies the audit log to `destination` and reports whether its chain is intact.
+#[tauri::command]
+pub fn export_audit_log(destination: String) -> Result<AuditVerification, String> {
+    let verification = verify();
+    let source = audit_log_path();
+    if !source.exists() {
+        return Err("No audit log has been written yet.".to_string());
+    }
+    fs::copy(&source, &destination).map_err(|e| format!("Failed to export audit log: {}", e))?;
+    provenance::stamp_file(Path::new(&destination), "audit-log")?;
+    info!("Exported audit log to {} (intact: {})", destination, verification.intact);
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log() -> PathBuf {
+        std::env::temp_dir().join(format!("metis_audit_{:08x}.jsonl", rand::random::<u32>()))
+    }
+
+    /// Writes a chain of `calls` to a fresh log, the way `append` does.
+    fn write_chain(calls: &[&str]) -> PathBuf {
+        let path = temp_log();
+        let mut head = ChainHead { seq: 0, last_hash: GENESIS_HASH.to_string() };
+        for call in calls {
+            let entry = next_entry(&head, "run_1", "mock", call, String::new(), &Ok(()));
+            write_entry(&path, &entry).unwrap();
+            head = ChainHead { seq: head.seq + 1, last_hash: entry.hash };
+        }
+        path
+    }
+
+    fn rewrite_lines(path: &Path, edit: impl FnOnce(&mut Vec<String>)) {
+        let mut lines: Vec<String> = fs::read_to_string(path).unwrap().lines().map(str::to_string).collect();
+        edit(&mut lines);
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn typed_text_is_not_logged() {
+        let args = text_args("hunter2", b"salt", false);
+        assert!(!args.contains("hunter2"));
+        assert!(args.starts_with("7 chars, "));
+        assert_eq!(args, text_args("hunter2", b"salt", false));
+        assert_ne!(args, text_args("hunter2", b"pepper", false));
+        assert_eq!(text_args("hunter2", b"salt", true), "7 chars into secure input");
+    }
+
+    #[test]
+    fn an_untouched_chain_verifies() {
+        let path = write_chain(&["move_mouse", "left_button", "text"]);
+        let verification = verify_log(&path);
+        assert!(verification.intact);
+        assert_eq!((verification.entries, verification.first_broken_seq), (3, None));
+        assert_eq!(load_head(&path).seq, 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn an_edited_entry_breaks_the_chain() {
+        let path = write_chain(&["move_mouse", "left_button", "text"]);
+        rewrite_lines(&path, |lines| lines[1] = lines[1].replace("left_button", "key"));
+        assert_eq!(verify_log(&path).first_broken_seq, Some(1));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_deleted_entry_breaks_the_chain() {
+        let path = write_chain(&["move_mouse", "left_button", "text"]);
+        rewrite_lines(&path, |lines| {
+            lines.remove(1);
+        });
+        assert_eq!(verify_log(&path).first_broken_seq, Some(1));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_rehashed_entry_still_breaks_the_link_after_it() {
+        let path = write_chain(&["move_mouse", "left_button", "text"]);
+        rewrite_lines(&path, |lines| {
+            let mut entry: AuditEntry = serde_json::from_str(&lines[1]).unwrap();
+            entry.record.call = "key".to_string();
+            entry.hash = hash_record(&entry.record);
+            lines[1] = serde_json::to_string(&entry).unwrap();
+        });
+        assert_eq!(verify_log(&path).first_broken_seq, Some(2));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn an_unparsable_line_breaks_the_chain() {
+        let path = write_chain(&["move_mouse", "left_button"]);
+        rewrite_lines(&path, |lines| lines[0].truncate(10));
+        assert_eq!(verify_log(&path).first_broken_seq, Some(0));
+        fs::remove_file(path).unwrap();
+    }
+}
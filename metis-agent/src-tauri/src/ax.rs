@@ -0,0 +1,297 @@
+// --- Accessibility (AX) Element Provider (macOS) ---
+// Walks the focused window of the frontmost app through the AXUIElement API.
+// Elements that list the AXPress action are pressed through it; the rest are
+// reported by AXPosition/AXSize for the caller to click. `element_at` asks AX
+// for the element at the point and climbs AXParent to a named one. Needs the
+// Accessibility permission (System Settings > Privacy & Security), which the
+// agent already requires to inject input.
+
+use std::ffi::c_void;
+
+use core_foundation::array::{CFArray, CFArrayRef};
+use core_foundation::base::{CFType, CFTypeRef, TCFType};
+use core_foundation::boolean::CFBoolean;
+use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+use core_foundation::number::CFNumber;
+use core_foundation::string::{CFString, CFStringRef};
+use tracing::error;
+
+use crate::elements::{self, Activation, ElementInfo, ElementProvider};
+use crate::error::ActionError;
+use crate::window_control::{self, WindowOp};
+
+type AXError = i32;
+const AX_SUCCESS: AXError = 0;
+const AX_VALUE_CG_POINT: u32 = 1;
+const AX_VALUE_CG_SIZE: u32 = 2;
+
+// Deep trees (web views) are cut off; the elements that matter are rarely this far down
+const MAX_DEPTH: usize = 25;
+const MAX_VISITED: usize = 5_000;
+
+#[repr(C)]
+#[derive(Default)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CGSize {
+    width: f64,
+    height: f64,
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+    fn AXUIElementCreateSystemWide() -> CFTypeRef;
+    fn AXUIElementCopyElementAtPosition(application: CFTypeRef, x: f32, y: f32, element: *mut CFTypeRef) -> AXError;
+    fn AXUIElementCopyAttributeValue(element: CFTypeRef, attribute: CFStringRef, value: *mut CFTypeRef) -> AXError;
+    fn AXUIElementCopyActionNames(element: CFTypeRef, names: *mut CFTypeRef) -> AXError;
+    fn AXUIElementPerformAction(element: CFTypeRef, action: CFStringRef) -> AXError;
+    fn AXValueGetValue(value: CFTypeRef, value_type: u32, value_ptr: *mut c_void) -> bool;
+    fn AXUIElementCreateApplication(pid: i32) -> CFTypeRef;
+    fn AXUIElementSetAttributeValue(element: CFTypeRef, attribute: CFStringRef, value: CFTypeRef) -> AXError;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGWindowListCopyWindowInfo(option: u32, relative_to_window: u32) -> CFArrayRef;
+}
+
+const CG_WINDOW_LIST_OPTION_ALL: u32 = 0; // Minimized windows too
+
+pub struct AxProvider;
+
+fn attribute(element: &CFType, name: &'static str) -> Option<CFType> {
+    let name = CFString::from_static_string(name);
+    let mut value: CFTypeRef = std::ptr::null();
+    // SAFETY: Copy rule; on success we own `value` and hand it to CFType
+    let err = unsafe { AXUIElementCopyAttributeValue(element.as_CFTypeRef(), name.as_concrete_TypeRef(), &mut value) };
+    (err == AX_SUCCESS && !value.is_null()).then(|| unsafe { CFType::wrap_under_create_rule(value) })
+}
+
+fn string_attribute(element: &CFType, name: &'static str) -> Option<String> {
+    attribute(element, name)?.downcast::<CFString>().map(|s| s.to_string())
+}
+
+fn ax_value<T: Default>(element: &CFType, name: &'static str, value_type: u32) -> Option<T> {
+    let value = attribute(element, name)?;
+    let mut out = T::default();
+    // SAFETY: `out` matches the layout AX writes for `value_type`
+    unsafe { AXValueGetValue(value.as_CFTypeRef(), value_type, &mut out as *mut T as *mut c_void) }.then_some(out)
+}
+
+fn children(element: &CFType) -> Vec<CFType> {
+    let Some(array) = attribute(element, "AXChildren").and_then(|v| v.downcast::<CFArray<*const c_void>>()) else {
+        return Vec::new();
+    };
+    // SAFETY: array items are AXUIElements; Get rule, so each is retained
+    array.iter().map(|item| unsafe { CFType::wrap_under_get_rule(*item) }).collect()
+}
+
+fn supports_press(element: &CFType) -> bool {
+    let mut names: CFTypeRef = std::ptr::null();
+    if unsafe { AXUIElementCopyActionNames(element.as_CFTypeRef(), &mut names) } != AX_SUCCESS || names.is_null() {
+        return false;
+    }
+    let names = unsafe { CFType::wrap_under_create_rule(names) };
+    names.downcast::<CFArray<*const c_void>>().is_some_and(|array| {
+        array.iter().any(|item| unsafe { CFString::wrap_under_get_rule(*item as CFStringRef) }.to_string() == "AXPress")
+    })
+}
+
+/// Name, role and bounds of a named element with a position on screen.
+fn describe(element: &CFType) -> Option<ElementInfo> {
+    let name = string_attribute(element, "AXTitle")
+        .filter(|s| !s.trim().is_empty())
+        .or_else(|| string_attribute(element, "AXDescription"))
+        .filter(|s| !s.trim().is_empty())?;
+    let role = string_attribute(element, "AXRole").unwrap_or_default();
+    let origin: CGPoint = ax_value(element, "AXPosition", AX_VALUE_CG_POINT)?;
+    let size: CGSize = ax_value(element, "AXSize", AX_VALUE_CG_SIZE)?;
+    if size.width <= 0.0 || size.height <= 0.0 {
+        return None;
+    }
+    Some(ElementInfo {
+        name,
+        role,
+        x: origin.x as i32,
+        y: origin.y as i32,
+        width: size.width as i32,
+        height: size.height as i32,
+    })
+}
+
+fn ensure_trusted() -> Result<(), ActionError> {
+    if !unsafe { AXIsProcessTrusted() } {
+        return Err(ActionError::ElementTargeting("Accessibility permission has not been granted".to_string()));
+    }
+    Ok(())
+}
+
+/// The innermost named element at a screen point, in any app.
+fn hit_test(x: i32, y: i32) -> Result<Option<ElementInfo>, ActionError> {
+    ensure_trusted()?;
+    let system = unsafe { CFType::wrap_under_create_rule(AXUIElementCreateSystemWide()) };
+    let mut hit: CFTypeRef = std::ptr::null();
+    // SAFETY: Copy rule; on success we own `hit` and hand it to CFType
+    let err = unsafe { AXUIElementCopyElementAtPosition(system.as_CFTypeRef(), x as f32, y as f32, &mut hit) };
+    if err != AX_SUCCESS || hit.is_null() {
+        return Ok(None); // Nothing accessible there (desktop, a window that hides its tree)
+    }
+    let mut element = unsafe { CFType::wrap_under_create_rule(hit) };
+    for _ in 0..MAX_DEPTH {
+        if let Some(info) = describe(&element) {
+            return Ok(Some(info));
+        }
+        let Some(parent) = attribute(&element, "AXParent") else { break };
+        element = parent;
+    }
+    Ok(None)
+}
+
+/// Every element under the frontmost app's focused window, depth-first.
+fn foreground_elements() -> Result<Vec<CFType>, ActionError> {
+    ensure_trusted()?;
+    let system = unsafe { CFType::wrap_under_create_rule(AXUIElementCreateSystemWide()) };
+    let window = attribute(&system, "AXFocusedApplication")
+        .and_then(|app| attribute(&app, "AXFocusedWindow"))
+        .ok_or_else(|| ActionError::ElementTargeting("No focused window".to_string()))?;
+
+    let mut found = Vec::new();
+    let mut pending = vec![(window, 0)];
+    while let Some((element, depth)) = pending.pop() {
+        if found.len() >= MAX_VISITED {
+            break;
+        }
+        if depth < MAX_DEPTH {
+            pending.extend(children(&element).into_iter().rev().map(|child| (child, depth + 1)));
+        }
+        found.push(element);
+    }
+    Ok(found)
+}
+
+impl ElementProvider for AxProvider {
+    fn name(&self) -> &'static str {
+        "ax"
+    }
+
+    fn snapshot(&self, limit: usize) -> Result<Vec<ElementInfo>, ActionError> {
+        Ok(foreground_elements()?.iter().filter_map(describe).take(limit).collect())
+    }
+
+    fn activate(&self, name: &str, role: Option<&str>) -> Result<Activation, ActionError> {
+        for element in foreground_elements()? {
+            let Some(info) = describe(&element) else { continue };
+            if !elements::matches(&info.name, &info.role, name, role) {
+                continue;
+            }
+            if supports_press(&element) {
+                let press = CFString::from_static_string("AXPress");
+                let err = unsafe { AXUIElementPerformAction(element.as_CFTypeRef(), press.as_concrete_TypeRef()) };
+                if err == AX_SUCCESS {
+                    return Ok(Activation::Invoked);
+                }
+                error!("AXPress failed ({}); clicking '{}' instead.", err, info.name);
+            }
+            let (x, y) = info.center();
+            return Ok(Activation::Click(x, y));
+        }
+        Err(elements::not_found(name, role))
+    }
+
+    fn element_at(&self, x: i32, y: i32) -> Result<Option<ElementInfo>, ActionError> {
+        hit_test(x, y)
+    }
+}
+
+// --- Window control ---
+
+/// PIDs of apps that own a normal (layer 0) window.
+fn window_owner_pids() -> Vec<i32> {
+    let info = unsafe { CGWindowListCopyWindowInfo(CG_WINDOW_LIST_OPTION_ALL, 0) };
+    if info.is_null() {
+        return Vec::new();
+    }
+    // SAFETY: Copy rule; the array holds one dictionary per window
+    let windows: CFArray<*const c_void> = unsafe { CFArray::wrap_under_create_rule(info) };
+    let number = |window: &CFDictionary<CFString, CFType>, key: &'static str| {
+        window.find(CFString::from_static_string(key)).and_then(|v| v.downcast::<CFNumber>()).and_then(|n| n.to_i32())
+    };
+    let mut pids = Vec::new();
+    for item in windows.iter() {
+        let window: CFDictionary<CFString, CFType> = unsafe { CFDictionary::wrap_under_get_rule(*item as CFDictionaryRef) };
+        if number(&window, "kCGWindowLayer") != Some(0) {
+            continue; // Menu bar, Dock, overlays
+        }
+        if let Some(pid) = number(&window, "kCGWindowOwnerPID") {
+            if !pids.contains(&pid) {
+                pids.push(pid);
+            }
+        }
+    }
+    pids
+}
+
+fn set_attribute(element: &CFType, name: &'static str, value: &CFType) -> Result<(), ActionError> {
+    let name_ref = CFString::from_static_string(name);
+    let err = unsafe { AXUIElementSetAttributeValue(element.as_CFTypeRef(), name_ref.as_concrete_TypeRef(), value.as_CFTypeRef()) };
+    if err != AX_SUCCESS {
+        return Err(ActionError::WindowControl(format!("Setting {} failed (AX error {})", name, err)));
+    }
+    Ok(())
+}
+
+fn perform(element: &CFType, action: &'static str) -> Result<(), ActionError> {
+    let action_ref = CFString::from_static_string(action);
+    let err = unsafe { AXUIElementPerformAction(element.as_CFTypeRef(), action_ref.as_concrete_TypeRef()) };
+    if err != AX_SUCCESS {
+        return Err(ActionError::WindowControl(format!("{} failed (AX error {})", action, err)));
+    }
+    Ok(())
+}
+
+/// Focuses, minimizes or maximizes (zooms) the window best matching `title`.
+pub fn control_window(title: &str, op: WindowOp) -> Result<String, ActionError> {
+    if !unsafe { AXIsProcessTrusted() } {
+        return Err(ActionError::WindowControl("Accessibility permission has not been granted".to_string()));
+    }
+    let mut candidates = Vec::new();
+    for pid in window_owner_pids() {
+        let app = unsafe { CFType::wrap_under_create_rule(AXUIElementCreateApplication(pid)) };
+        let Some(windows) = attribute(&app, "AXWindows").and_then(|v| v.downcast::<CFArray<*const c_void>>()) else {
+            continue;
+        };
+        for item in windows.iter() {
+            // SAFETY: items are AXUIElements; Get rule, so each is retained
+            let window = unsafe { CFType::wrap_under_get_rule(*item) };
+            if let Some(window_title) = string_attribute(&window, "AXTitle").filter(|t| !t.trim().is_empty()) {
+                candidates.push(((app.clone(), window), window_title));
+            }
+        }
+    }
+    let ((app, window), matched) =
+        window_control::best_match(title, candidates).ok_or_else(|| ActionError::WindowNotFound(title.to_string()))?;
+
+    let yes = CFBoolean::true_value().as_CFType();
+    let no = CFBoolean::false_value().as_CFType();
+    match op {
+        WindowOp::Focus => {
+            set_attribute(&window, "AXMinimized", &no)?;
+            set_attribute(&app, "AXFrontmost", &yes)?;
+            perform(&window, "AXRaise")?;
+        }
+        WindowOp::Minimize => set_attribute(&window, "AXMinimized", &yes)?,
+        // macOS has no maximize; the green button's zoom is the closest
+        WindowOp::Maximize => {
+            let zoom = attribute(&window, "AXZoomButton")
+                .ok_or_else(|| ActionError::WindowControl(format!("{:?} cannot be zoomed", matched)))?;
+            perform(&zoom, "AXPress")?;
+        }
+    }
+    Ok(matched)
+}
@@ -0,0 +1,195 @@
+// --- Image-Processing Backend Client ---
+// Builds request bodies for the Python parser (localhost:5001 unless configured or pinned by policy). The PNG is
+// encoded straight into a base64 writer that appends to the JSON body, so the
+// only full-size copy held in memory is the body itself (previously PNG bytes,
+// a base64 String, and a serialized JSON String were all alive at once).
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::write::EncoderWriter;
+use image::codecs::png::PngEncoder;
+use image::{DynamicImage, ImageEncoder};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::config::{self, RetrySettings};
+use crate::error::ParserError;
+use crate::language;
+use crate::net;
+use crate::policy;
+use crate::perf::{self, Stage};
+
+pub const PROCESS_IMAGE_URL: &str = "http://localhost:5001/api/processImage";
+
+// The backend loads its models before listening, so an answer means it's ready
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The processImage endpoint in use: the configured one, unless an admin policy pins it.
+pub fn process_image_url() -> String {
+    policy::get().parser_endpoint.clone().unwrap_or_else(|| config::get().parser.backend_url)
+}
+
+const PAYLOAD_PREFIX: &[u8] = b"{\"image\":\"";
+
+/// Closes the image string and adds the session language hint, if any, for the parser's OCR.
+fn payload_suffix() -> Vec<u8> {
+    match language::hint() {
+        // Hints are validated language tags, so they need no JSON escaping either
+        Some(tag) => format!("\",\"language\":\"{}\"}}", tag).into_bytes(),
+        None => b"\"}".to_vec(),
+    }
+}
+
+/// Wraps whatever `write_image` streams into the encoder in the `{"image": "...", "language": "..."}`
+/// JSON body. Base64 output is JSON-safe, so no escaping is needed.
+fn build_payload(
+    size_hint: usize,
+    write_image: impl FnOnce(&mut EncoderWriter<'_, base64::engine::GeneralPurpose, Vec<u8>>) -> io::Result<()>,
+) -> io::Result<Vec<u8>> {
+    let suffix = payload_suffix();
+    let mut body = Vec::with_capacity(PAYLOAD_PREFIX.len() + size_hint * 4 / 3 + 4 + suffix.len());
+    body.extend_from_slice(PAYLOAD_PREFIX);
+
+    let mut encoder = EncoderWriter::new(body, &STANDARD);
+    write_image(&mut encoder)?;
+    let mut body = encoder.finish()?;
+
+    body.extend_from_slice(&suffix);
+    Ok(body)
+}
+
+/// PNG-encodes `image` directly into a ready-to-send JSON request body.
+pub fn image_payload_from_image(image: &DynamicImage) -> Result<Vec<u8>, ParserError> {
+    perf::time(Stage::Encode, || {
+        build_payload(0, |encoder| {
+            PngEncoder::new(encoder)
+                .write_image(image.as_bytes(), image.width(), image.height(), image.color())
+                .map_err(io::Error::other)
+        })
+    })
+    .map_err(|e| ParserError::Encode(e.to_string()))
+}
+
+/// Streams an already-encoded image file into a ready-to-send JSON request body.
+pub fn image_payload_from_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let size_hint = file.metadata().map(|m| m.len() as usize).unwrap_or(0);
+    perf::time(Stage::Encode, || {
+        build_payload(size_hint, |encoder| {
+            io::copy(&mut file, encoder)?;
+            encoder.flush()
+        })
+    })
+}
+
+/// Whether a failed attempt is worth repeating: the backend may still be
+/// starting, busy, or briefly unreachable. A refusal or a bad request is final.
+fn is_transient(error: &ParserError) -> bool {
+    match error {
+        ParserError::Request(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+        ParserError::Status { status, .. } => *status >= 500 || *status == 429 || *status == 408,
+        _ => false,
+    }
+}
+
+/// Wait before retry `retry` (1-based), with jitter.
+fn backoff(settings: &RetrySettings, retry: u32) -> Duration {
+    let base = settings.initial_backoff_ms.saturating_mul(1 << (retry - 1).min(16)).min(settings.max_backoff_ms);
+    let jitter = settings.jitter.clamp(0.0, 1.0) * (rand::random::<f64>() * 2.0 - 1.0);
+    Duration::from_millis((base as f64 * (1.0 + jitter)) as u64)
+}
+
+fn post_once(client: &Client, url: &str, payload: Vec<u8>) -> Result<Response, ParserError> {
+    let start = Instant::now();
+    let result = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(payload)
+        .send();
+    perf::record(Stage::Backend, start.elapsed());
+    let resp = result?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(ParserError::Status { status: status.as_u16(), body });
+    }
+    Ok(resp)
+}
+
+/// POSTs a prepared payload to the processImage endpoint, retrying transient
+/// failures per the parser's retry settings. Only a successful response is returned.
+pub fn post_image_payload(client: &Client, payload: Vec<u8>) -> Result<Response, ParserError> {
+    let url = process_image_url();
+    net::ensure_allowed(&url)?;
+    let settings = config::get().parser.retry;
+    let attempts = settings.attempts.max(1);
+    let mut payload = Some(payload);
+    let mut attempt = 1;
+    loop {
+        // The body is consumed by each send; only the last attempt gets the original
+        let body = if attempt == attempts { payload.take() } else { payload.clone() }.unwrap_or_default();
+        match post_once(client, &url, body) {
+            Ok(resp) => return Ok(resp),
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                let wait = backoff(&settings, attempt);
+                error!("Backend attempt {}/{} failed ({}); retrying in {:?}.", attempt, attempts, e, wait);
+                thread::sleep(wait);
+                attempt += 1;
+            }
+            Err(e) if attempt > 1 => return Err(ParserError::RetriesExhausted { attempts: attempt, last: Box::new(e) }),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendHealth {
+    pub url: String, // The processImage endpoint that was checked
+    pub latency_ms: u64,
+    pub version: Option<String>, // None for backends older than the /health route
+    pub device: Option<String>,  // "cuda" or "cpu"
+}
+
+#[derive(Deserialize)]
+struct HealthResponse {
+    version: Option<String>,
+    device: Option<String>,
+}
+
+/// Pings the backend's /health route (next to processImage) to see whether it is up.
+pub fn check_health() -> Result<BackendHealth, ParserError> {
+    let url = process_image_url();
+    let unavailable = |reason: String| ParserError::Unavailable { url: url.clone(), reason };
+    let health_url = Url::parse(&url)
+        .and_then(|u| u.join("/health"))
+        .map_err(|e| unavailable(format!("invalid endpoint: {}", e)))?;
+    net::ensure_allowed(health_url.as_str())?;
+    let client = net::blocking_client(HEALTH_TIMEOUT)?;
+
+    let start = Instant::now();
+    let resp = client.get(health_url).send().map_err(|e| unavailable(e.to_string()))?;
+    let latency_ms = start.elapsed().as_millis() as u64;
+    let status = resp.status();
+    // Older backends only serve processImage; any HTTP answer still means it's running
+    let info = if status == StatusCode::NOT_FOUND {
+        HealthResponse { version: None, device: None }
+    } else if status.is_success() {
+        resp.json().map_err(|e| ParserError::InvalidResponse(e.to_string()))?
+    } else {
+        return Err(unavailable(format!("health check returned {}", status)));
+    };
+    Ok(BackendHealth { url, latency_ms, version: info.version, device: info.device })
+}
+
+#[tauri::command]
+pub fn check_backend_health() -> Result<BackendHealth, String> {
+    check_health().map_err(|e| e.to_string())
+}
@@ -0,0 +1,129 @@
+// --- Screen Capture Backends ---
+// Every screenshot in the app (recorder, action loop, benchmark) goes through
+// `capture_screen`, which walks the backends in priority order and returns the
+// first frame it gets. New platforms plug in by implementing `CaptureBackend`.
+
+use std::time::Instant;
+
+use image::DynamicImage;
+use once_cell::sync::Lazy;
+use xcap::Monitor;
+use tracing::error;
+
+use crate::error::CaptureError;
+use crate::fast_capture;
+use crate::perf::{self, Stage};
+
+pub trait CaptureBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Grabs the primary display. `Ok(None)` means the backend can't serve this
+    /// session and the next one should be tried.
+    fn capture(&self) -> Result<Option<DynamicImage>, CaptureError>;
+    /// Whether frames come from this machine's own display, so desktop-only
+    /// context (accessibility tree, browser bridge, focus checks) applies.
+    fn local_display(&self) -> bool {
+        true
+    }
+}
+
+/// The local desktop through `capture_screen`, for callers that take any target.
+pub struct Desktop;
+
+impl CaptureBackend for Desktop {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    fn capture(&self) -> Result<Option<DynamicImage>, CaptureError> {
+        capture_screen().map(Some)
+    }
+}
+
+/// DXGI desktop duplication / XShm through the fast_capture worker.
+struct NativeBackend;
+
+impl CaptureBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn capture(&self) -> Result<Option<DynamicImage>, CaptureError> {
+        match fast_capture::capture() {
+            Some(result) => result.map(Some).map_err(CaptureError::Capture),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Generic cross-platform path; slower but works everywhere xcap does.
+struct XcapBackend;
+
+impl CaptureBackend for XcapBackend {
+    fn name(&self) -> &'static str {
+        "xcap"
+    }
+
+    fn capture(&self) -> Result<Option<DynamicImage>, CaptureError> {
+        let result = std::panic::catch_unwind(|| {
+            let monitors = Monitor::all().map_err(|e| CaptureError::Monitors(format!("{:?}", e)))?;
+
+            if monitors.is_empty() {
+                return Err(CaptureError::NoMonitors);
+            }
+
+            // Re-resolved on every capture, so docking/undocking picks up the new primary
+            let primary_monitor = monitors
+                .iter()
+                .find(|m| m.is_primary().unwrap_or(false))
+                .unwrap_or(&monitors[0]);
+            let xcap_image = primary_monitor.capture_image()
+                .map_err(|e| CaptureError::Capture(format!("{:?}", e)))?;
+
+            let width = xcap_image.width();
+            let height = xcap_image.height();
+            let raw = xcap_image.into_raw(); // Consumes image
+
+            image::ImageBuffer::<image::Rgba<u8>, Vec<u8>>::from_raw(width, height, raw)
+                .map(DynamicImage::ImageRgba8)
+                .ok_or_else(|| CaptureError::Capture("Failed to convert captured image to ImageBuffer".to_string()))
+        });
+
+        match result {
+            Ok(res) => res.map(Some),
+            Err(_) => Err(CaptureError::Panicked),
+        }
+    }
+}
+
+// Highest priority first; the last entry should always be able to serve a frame.
+// The native path steps aside on Wayland, where the Screenshot portal takes over.
+static BACKENDS: Lazy<Vec<Box<dyn CaptureBackend>>> = Lazy::new(|| {
+    let mut backends: Vec<Box<dyn CaptureBackend>> = vec![Box::new(NativeBackend)];
+    #[cfg(target_os = "linux")]
+    backends.push(Box::new(crate::wayland::ScreenshotPortalBackend));
+    backends.push(Box::new(XcapBackend));
+    backends
+});
+
+/// Captures a screenshot of the primary monitor using the first backend that can.
+pub fn capture_screen() -> Result<DynamicImage, CaptureError> {
+    let capture_start = Instant::now();
+    let mut last_error = None;
+
+    for backend in BACKENDS.iter() {
+        match backend.capture() {
+            Ok(Some(image)) => {
+                perf::record(Stage::Capture, capture_start.elapsed());
+                return Ok(image);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("{} capture failed, trying next backend: {}", backend.name(), e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    perf::record(Stage::Capture, capture_start.elapsed());
+    Err(last_error.unwrap_or(CaptureError::NoMonitors))
+}
@@ -0,0 +1,651 @@
+// --- Chromium DevTools Bridge ---
+// When a Chromium browser started with --remote-debugging-port is the foreground
+// app, web tasks go through the Chrome DevTools Protocol instead of the OS:
+// `page_context` lists the page's interactive DOM elements for the prompt, and
+// CdpInput dispatches clicks, keys and text straight into the page. The action
+// grammar doesn't change; screen coordinates are mapped into the page viewport.
+//
+// "Foreground" is decided by the page itself: the tab whose document is visible
+// and has focus. That is only true while the browser window is in front, so no
+// per-OS window inspection is needed. Anything CDP can't express faithfully
+// (modifier chords, keys without a DOM equivalent, no focused tab) goes to the
+// wrapped OS backend unchanged, and so do pointer events outside the page's
+// viewport (the tab strip, the address bar, other windows). The focused tab is
+// looked up at most every PAGE_CACHE_TTL, so a click doesn't cost a target list
+// and a script evaluation per event.
+//
+// The browser_* actions (BrowserOp) go further and skip pixels altogether: they
+// navigate, and click or fill elements found by CSS selector. They need the
+// focused tab, except browser_navigate, which starts a browser with debugging
+// on the bridge port (and its own profile in the storage root) when none is
+// running. Without the bridge they fail, and the LLM falls back to coordinates.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use enigo::{Direction, Key};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tungstenite::{Message, WebSocket};
+use tracing::{error, info};
+
+use crate::config;
+use crate::error::ActionError;
+use crate::input::InputBackend;
+use crate::net;
+use crate::recorder;
+use crate::window_control::WindowOp;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_PAGE_ELEMENTS: usize = 150;
+const BROWSER_START_TIMEOUT: Duration = Duration::from_secs(15);
+const PAGE_LOAD_TIMEOUT: Duration = Duration::from_secs(15);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// How long CdpInput trusts its last look at which tab is focused
+const PAGE_CACHE_TTL: Duration = Duration::from_millis(500);
+const PROFILE_DIR: &str = "browser-profile";
+
+// Tried in order when settings.browser_bridge.browser_path is unset
+#[cfg(target_os = "windows")]
+const BROWSERS: &[&str] = &[
+    r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe",
+    r"C:\Program Files\Microsoft\Edge\Application\msedge.exe",
+];
+#[cfg(target_os = "macos")]
+const BROWSERS: &[&str] = &[
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+    "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge",
+    "/Applications/Brave Browser.app/Contents/MacOS/Brave Browser",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const BROWSERS: &[&str] = &["google-chrome", "google-chrome-stable", "chromium", "chromium-browser", "microsoft-edge", "brave-browser"];
+
+// Page focus plus where the viewport sits on screen, in CSS pixels
+const FOCUS_SCRIPT: &str = "JSON.stringify({\
+    focused: document.visibilityState === 'visible' && document.hasFocus(),\
+    dpr: window.devicePixelRatio,\
+    left: window.screenX + (window.outerWidth - window.innerWidth) / 2,\
+    top: window.screenY + (window.outerHeight - window.innerHeight),\
+    width: window.innerWidth,\
+    height: window.innerHeight})";
+
+// Visible interactive elements with viewport-relative centers
+const ELEMENTS_SCRIPT: &str = "JSON.stringify(Array.from(document.querySelectorAll(\
+    'a[href], button, input, select, textarea, [role=button], [role=link], [role=tab], [role=menuitem], [onclick], [contenteditable=true]'))\
+    .map(e => { const r = e.getBoundingClientRect(); return { r, e }; })\
+    .filter(({ r }) => r.width > 0 && r.height > 0 && r.bottom > 0 && r.right > 0 && r.top < innerHeight && r.left < innerWidth)\
+    .slice(0, 150)\
+    .map(({ r, e }) => ({\
+        tag: e.tagName.toLowerCase(),\
+        type: e.getAttribute('type') || e.getAttribute('role') || '',\
+        text: (e.innerText || e.value || e.getAttribute('aria-label') || e.getAttribute('placeholder') || e.getAttribute('title') || '').trim().slice(0, 80),\
+        x: r.left + r.width / 2, y: r.top + r.height / 2,\
+        selector: e.id ? '#' + CSS.escape(e.id) : e.getAttribute('name') ? e.tagName.toLowerCase() + '[name=\"' + e.getAttribute('name') + '\"]' : '' })))";
+
+/// A browser action from the grammar (`browser_navigate:`, `browser_click:`,
+/// `browser_fill:`), performed in the focused tab.
+#[derive(Debug, Clone)]
+pub enum BrowserOp {
+    Navigate(String),
+    Click(String),        // CSS selector
+    Fill(String, String), // CSS selector, text replacing the field's contents
+}
+
+/// Scrolls the first element matching the selector into view and returns its
+/// viewport-relative center, or '' when nothing matches.
+fn locate_script(selector: &str) -> String {
+    format!(
+        "(() => {{ const e = document.querySelector({}); if (!e) return '';\
+         e.scrollIntoView({{ block: 'center', inline: 'center' }});\
+         const r = e.getBoundingClientRect(); return JSON.stringify({{ x: r.left + r.width / 2, y: r.top + r.height / 2 }}); }})()",
+        json!(selector)
+    )
+}
+
+/// Focuses the first element matching the selector and selects its contents,
+/// so inserted text replaces them. Returns 'ok', 'missing' or 'unfocusable'.
+fn focus_script(selector: &str) -> String {
+    format!(
+        "(() => {{ const e = document.querySelector({}); if (!e) return 'missing';\
+         e.scrollIntoView({{ block: 'center' }}); e.focus();\
+         if (typeof e.select === 'function') {{ e.select(); }} else {{\
+           const range = document.createRange(); range.selectNodeContents(e);\
+           const sel = getSelection(); sel.removeAllRanges(); sel.addRange(range); }}\
+         return e === document.activeElement || e.contains(document.activeElement) ? 'ok' : 'unfocusable'; }})()",
+        json!(selector)
+    )
+}
+
+#[derive(Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+/// Where a page's viewport sits on screen.
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct Viewport {
+    focused: bool,
+    dpr: f64,
+    left: f64,
+    top: f64,
+    width: f64, // CSS pixels
+    height: f64,
+}
+
+impl Viewport {
+    /// Whether a screen point falls inside the page rather than the browser chrome.
+    fn contains(self, x: i32, y: i32) -> bool {
+        let (x, y) = self.to_page(x, y);
+        (0.0..self.width).contains(&x) && (0.0..self.height).contains(&y)
+    }
+
+    fn to_page(self, x: i32, y: i32) -> (f64, f64) {
+        (x as f64 / self.dpr - self.left, y as f64 / self.dpr - self.top)
+    }
+
+    fn to_screen(self, x: f64, y: f64) -> (i32, i32) {
+        (((x + self.left) * self.dpr).round() as i32, ((y + self.top) * self.dpr).round() as i32)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PageElement {
+    tag: String,
+    #[serde(rename = "type")]
+    kind: String,
+    text: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    selector: String, // For browser_click/browser_fill, when the element has an id or name
+}
+
+fn browser_error(message: impl std::fmt::Display) -> ActionError {
+    ActionError::Browser(message.to_string())
+}
+
+/// One browser-level DevTools connection, with flattened sessions per tab.
+struct CdpClient {
+    socket: WebSocket<TcpStream>,
+    next_id: u64,
+    sessions: HashMap<String, String>, // targetId -> sessionId
+}
+
+impl CdpClient {
+    fn connect(port: u16) -> Result<CdpClient, ActionError> {
+        let version_url = format!("http://127.0.0.1:{}/json/version", port);
+        net::ensure_allowed(&version_url).map_err(browser_error)?;
+        let version: Value = net::blocking_client(TIMEOUT)
+            .and_then(|client| client.get(&version_url).send())
+            .and_then(|resp| resp.json())
+            .map_err(|e| browser_error(format!("No DevTools endpoint on port {}: {}", port, e)))?;
+        let ws_url = version["webSocketDebuggerUrl"]
+            .as_str()
+            .ok_or_else(|| browser_error("DevTools endpoint did not report a WebSocket URL"))?;
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).map_err(browser_error)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(browser_error)?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(browser_error)?;
+        let (socket, _) = tungstenite::client(ws_url, stream).map_err(browser_error)?;
+        Ok(CdpClient { socket, next_id: 0, sessions: HashMap::new() })
+    }
+
+    /// Sends one command and waits for its reply, skipping events in between.
+    fn call(&mut self, session: Option<&str>, method: &str, params: Value) -> Result<Value, ActionError> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut request = json!({ "id": id, "method": method, "params": params });
+        if let Some(session) = session {
+            request["sessionId"] = json!(session);
+        }
+        self.socket.send(Message::Text(request.to_string())).map_err(browser_error)?;
+        loop {
+            let Message::Text(text) = self.socket.read().map_err(browser_error)? else { continue };
+            let reply: Value = serde_json::from_str(&text).map_err(browser_error)?;
+            if reply["id"].as_u64() != Some(id) {
+                continue; // An event or a stale reply
+            }
+            if let Some(error) = reply.get("error") {
+                return Err(browser_error(format!("{} failed: {}", method, error)));
+            }
+            return Ok(reply["result"].clone());
+        }
+    }
+
+    fn evaluate(&mut self, session: &str, expression: &str) -> Result<String, ActionError> {
+        let result = self.call(Some(session), "Runtime.evaluate", json!({ "expression": expression, "returnByValue": true }))?;
+        result["result"]["value"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| browser_error("Page script returned no value"))
+    }
+
+    fn session_for(&mut self, target_id: &str) -> Result<String, ActionError> {
+        if let Some(session) = self.sessions.get(target_id) {
+            return Ok(session.clone());
+        }
+        let result = self.call(None, "Target.attachToTarget", json!({ "targetId": target_id, "flatten": true }))?;
+        let session = result["sessionId"].as_str().ok_or_else(|| browser_error("attachToTarget returned no session"))?.to_string();
+        self.sessions.insert(target_id.to_string(), session.clone());
+        Ok(session)
+    }
+
+    /// The session and viewport of the tab that is visible and focused, if any.
+    fn focused_page(&mut self) -> Result<Option<(String, Viewport)>, ActionError> {
+        let targets = self.call(None, "Target.getTargets", json!({}))?;
+        let page_ids: Vec<String> = targets["targetInfos"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|t| t["type"] == "page")
+            .filter_map(|t| t["targetId"].as_str().map(str::to_string))
+            .collect();
+        for target_id in page_ids {
+            let session = self.session_for(&target_id)?;
+            let Ok(state) = self.evaluate(&session, FOCUS_SCRIPT) else { continue };
+            let viewport: Viewport = serde_json::from_str(&state).map_err(browser_error)?;
+            if viewport.focused {
+                return Ok(Some((session, viewport)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn allowed_url(url: &str) -> Result<(), ActionError> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(ActionError::InvalidValue { action: "browser_navigate", value: url.to_string() });
+    }
+    if !config::get().launch.allow_urls {
+        return Err(ActionError::LaunchBlocked(format!("opening links is disabled ({})", url)));
+    }
+    net::ensure_allowed(url).map_err(|e| ActionError::LaunchBlocked(e.to_string()))
+}
+
+/// Starts a browser with DevTools on `port` and its own profile, opening `url`,
+/// and connects once the endpoint answers.
+fn start_browser(url: &str, port: u16, browser_path: Option<&str>) -> Result<CdpClient, ActionError> {
+    let profile = recorder::get_default_base_folder().join(PROFILE_DIR);
+    let candidates = match browser_path {
+        Some(path) => vec![path],
+        None => BROWSERS.to_vec(),
+    };
+    let mut started = None;
+    for browser in candidates {
+        let spawned = Command::new(browser)
+            .arg(format!("--remote-debugging-port={}", port))
+            .arg(format!("--user-data-dir={}", profile.display()))
+            .args(["--no-first-run", "--no-default-browser-check"])
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(mut child) => {
+                thread::spawn(move || child.wait()); // Reap it whenever it exits
+                started = Some(browser);
+                break;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(ActionError::Launch(format!("Failed to start {}: {}", browser, e))),
+        }
+    }
+    let browser = started.ok_or_else(|| ActionError::Launch("No Chromium-based browser found; set browser_bridge.browser_path".to_string()))?;
+    info!("Started {} with DevTools on port {}", browser, port);
+
+    let deadline = Instant::now() + BROWSER_START_TIMEOUT;
+    loop {
+        match CdpClient::connect(port) {
+            Ok(client) => return Ok(client),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// Interactive elements of the focused browser tab as prompt context, or None
+/// when the bridge is off or no DevTools-enabled browser is in front.
+pub fn page_context() -> Option<String> {
+    let bridge = config::get().browser_bridge;
+    if !bridge.enabled {
+        return None;
+    }
+    let mut client = CdpClient::connect(bridge.port).ok()?; // No debuggable browser running
+    let (session, viewport) = client.focused_page().ok()??;
+    let json = client.evaluate(&session, ELEMENTS_SCRIPT).map_err(|e| error!("Failed to read page elements: {}", e)).ok()?;
+    let elements: Vec<PageElement> = serde_json::from_str(&json).ok()?;
+    Some(
+        elements
+            .iter()
+            .take(MAX_PAGE_ELEMENTS)
+            .map(|e| {
+                let (x, y) = viewport.to_screen(e.x, e.y);
+                let kind = if e.kind.is_empty() { String::new() } else { format!("[{}]", e.kind) };
+                let selector = if e.selector.is_empty() { String::new() } else { format!(" selector={}", e.selector) };
+                format!("{}{} '{}' center=({},{}){}\n", e.tag, kind, e.text, x, y, selector)
+            })
+            .collect(),
+    )
+}
+
+/// DOM key name, code and Windows virtual key code for keys CDP can press.
+fn dom_key(key: Key) -> Option<(&'static str, &'static str, u32, Option<&'static str>)> {
+    Some(match key {
+        Key::Return => ("Enter", "Enter", 13, Some("\r")),
+        Key::Tab => ("Tab", "Tab", 9, None),
+        Key::Backspace => ("Backspace", "Backspace", 8, None),
+        Key::Escape => ("Escape", "Escape", 27, None),
+        Key::Delete => ("Delete", "Delete", 46, None),
+        Key::Space => (" ", "Space", 32, Some(" ")),
+        Key::Home => ("Home", "Home", 36, None),
+        Key::End => ("End", "End", 35, None),
+        Key::PageUp => ("PageUp", "PageUp", 33, None),
+        Key::PageDown => ("PageDown", "PageDown", 34, None),
+        Key::LeftArrow => ("ArrowLeft", "ArrowLeft", 37, None),
+        Key::UpArrow => ("ArrowUp", "ArrowUp", 38, None),
+        Key::RightArrow => ("ArrowRight", "ArrowRight", 39, None),
+        Key::DownArrow => ("ArrowDown", "ArrowDown", 40, None),
+        _ => return None,
+    })
+}
+
+fn is_modifier(key: Key) -> bool {
+    matches!(key, Key::Shift | Key::Control | Key::Alt | Key::Meta | Key::Option)
+}
+
+/// Routes input into the focused browser tab over CDP, falling back to `inner`.
+pub struct CdpInput<'a> {
+    inner: &'a mut dyn InputBackend,
+    port: u16,
+    client: Option<CdpClient>,
+    page: Option<(Option<(String, Viewport)>, Instant)>, // Last focused-tab lookup and when
+    pointer: (i32, i32),                  // Last screen position the agent moved to
+    button_down: bool,                    // Pressed over CDP, so released there too
+    held_modifiers: HashSet<String>,      // Chords go to the OS as a whole
+}
+
+impl<'a> CdpInput<'a> {
+    pub fn new(inner: &'a mut dyn InputBackend, port: u16) -> Self {
+        CdpInput { inner, port, client: None, page: None, pointer: (0, 0), button_down: false, held_modifiers: HashSet::new() }
+    }
+
+    /// The focused tab, as of at most PAGE_CACHE_TTL ago.
+    fn page(&mut self) -> Option<(String, Viewport)> {
+        if let Some((page, at)) = &self.page {
+            if at.elapsed() < PAGE_CACHE_TTL {
+                return page.clone();
+            }
+        }
+        let page = self.find_page();
+        self.page = Some((page.clone(), Instant::now()));
+        page
+    }
+
+    /// The focused tab if the pointer is over its page, or a press started there.
+    fn page_at_pointer(&mut self) -> Option<(String, Viewport)> {
+        let (x, y) = self.pointer;
+        let button_down = self.button_down;
+        self.page().filter(|(_, viewport)| button_down || viewport.contains(x, y))
+    }
+
+    /// The focused tab right now, reconnecting if the browser restarted.
+    fn find_page(&mut self) -> Option<(String, Viewport)> {
+        for _ in 0..2 {
+            if self.client.is_none() {
+                self.client = CdpClient::connect(self.port).ok();
+            }
+            let client = self.client.as_mut()?;
+            match client.focused_page() {
+                Ok(page) => return page,
+                Err(_) => self.client = None, // Stale socket; retry once with a fresh one
+            }
+        }
+        None
+    }
+
+    fn client(&mut self) -> Result<&mut CdpClient, ActionError> {
+        self.client.as_mut().ok_or_else(|| browser_error("DevTools connection lost"))
+    }
+
+    /// Calls into a tab, forgetting the cached tab if the call fails (closed, navigated away).
+    fn call(&mut self, session: &str, method: &str, params: Value) -> Result<Value, ActionError> {
+        let result = self.client().and_then(|client| client.call(Some(session), method, params));
+        if result.is_err() {
+            self.page = None;
+        }
+        result
+    }
+
+    /// Waits until a focused tab has finished loading, or PAGE_LOAD_TIMEOUT passes;
+    /// a page still loading then is left for the next screen capture to show.
+    fn wait_for_page(&mut self) {
+        let deadline = Instant::now() + PAGE_LOAD_TIMEOUT;
+        while Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL); // Let a navigation commit before asking
+            self.page = None;
+            let Some((session, _)) = self.page() else { continue };
+            let ready = self.client().and_then(|client| client.evaluate(&session, "document.readyState"));
+            if ready.is_ok_and(|state| state == "complete") {
+                return;
+            }
+        }
+        info!("Browser page still loading after {:?}", PAGE_LOAD_TIMEOUT);
+    }
+
+    fn navigate(&mut self, url: &str) -> Result<(), ActionError> {
+        allowed_url(url)?;
+        if let Some((session, _)) = self.page() {
+            self.call(&session, "Page.navigate", json!({ "url": url }))?;
+        } else {
+            match self.client.take().or_else(|| CdpClient::connect(self.port).ok()) {
+                // Running, but not in front: open a tab and bring it forward
+                Some(mut client) => {
+                    let target = client.call(None, "Target.createTarget", json!({ "url": url }))?;
+                    if let Some(id) = target["targetId"].as_str() {
+                        client.call(None, "Target.activateTarget", json!({ "targetId": id }))?;
+                    }
+                    self.client = Some(client);
+                }
+                None => {
+                    let browser_path = config::get().browser_bridge.browser_path;
+                    self.client = Some(start_browser(url, self.port, browser_path.as_deref())?);
+                }
+            }
+        }
+        self.wait_for_page();
+        Ok(())
+    }
+
+    fn click_selector(&mut self, selector: &str) -> Result<(), ActionError> {
+        let (session, viewport) = self.page().ok_or_else(|| browser_error("No DevTools-enabled browser tab is in front"))?;
+        let found = self.client()?.evaluate(&session, &locate_script(selector))?;
+        if found.is_empty() {
+            return Err(ActionError::ElementNotFound(format!("matching '{}'", selector)));
+        }
+        let center: Point = serde_json::from_str(&found).map_err(browser_error)?;
+        self.pointer = viewport.to_screen(center.x, center.y);
+        self.dispatch_mouse(&session, viewport, "mouseMoved", json!({}))?;
+        self.button_down = true;
+        self.dispatch_mouse(&session, viewport, "mousePressed", json!({ "clickCount": 1 }))?;
+        self.button_down = false;
+        self.dispatch_mouse(&session, viewport, "mouseReleased", json!({ "clickCount": 1 }))
+    }
+
+    fn fill_selector(&mut self, selector: &str, text: &str) -> Result<(), ActionError> {
+        let (session, _) = self.page().ok_or_else(|| browser_error("No DevTools-enabled browser tab is in front"))?;
+        let client = self.client()?;
+        match client.evaluate(&session, &focus_script(selector))?.as_str() {
+            "ok" => {}
+            "missing" => return Err(ActionError::ElementNotFound(format!("matching '{}'", selector))),
+            _ => return Err(browser_error(format!("The element matching '{}' can't take text", selector))),
+        }
+        if text.is_empty() {
+            return client.evaluate(&session, "String(document.execCommand('delete'))").map(|_| ());
+        }
+        client.call(Some(&session), "Input.insertText", json!({ "text": text })).map(|_| ())
+    }
+
+    fn dispatch_mouse(&mut self, session: &str, viewport: Viewport, kind: &str, extra: Value) -> Result<(), ActionError> {
+        let (x, y) = viewport.to_page(self.pointer.0, self.pointer.1);
+        let mut params = json!({ "type": kind, "x": x, "y": y, "button": "left", "buttons": u8::from(self.button_down) });
+        if let (Some(params), Some(extra)) = (params.as_object_mut(), extra.as_object()) {
+            params.extend(extra.clone());
+        }
+        self.call(session, "Input.dispatchMouseEvent", params).map(|_| ())
+    }
+}
+
+impl InputBackend for CdpInput<'_> {
+    fn name(&self) -> &'static str {
+        "cdp"
+    }
+
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError> {
+        self.pointer = (x, y);
+        match self.page_at_pointer() {
+            Some((session, viewport)) => self.dispatch_mouse(&session, viewport, "mouseMoved", json!({})),
+            None => self.inner.move_mouse(x, y),
+        }
+    }
+
+    fn left_button(&mut self, direction: Direction) -> Result<(), ActionError> {
+        let Some((session, viewport)) = self.page_at_pointer() else {
+            return self.inner.left_button(direction);
+        };
+        if matches!(direction, Direction::Press | Direction::Click) {
+            self.button_down = true;
+            self.dispatch_mouse(&session, viewport, "mousePressed", json!({ "clickCount": 1 }))?;
+        }
+        if matches!(direction, Direction::Release | Direction::Click) {
+            self.button_down = false;
+            self.dispatch_mouse(&session, viewport, "mouseReleased", json!({ "clickCount": 1 }))?;
+        }
+        Ok(())
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError> {
+        if is_modifier(key) {
+            let name = format!("{:?}", key);
+            match direction {
+                Direction::Press => {
+                    self.held_modifiers.insert(name);
+                }
+                Direction::Release => {
+                    self.held_modifiers.remove(&name);
+                }
+                Direction::Click => {}
+            }
+            return self.inner.key(key, direction);
+        }
+        let Some((dom_key, code, key_code, text)) = dom_key(key).filter(|_| self.held_modifiers.is_empty()) else {
+            return self.inner.key(key, direction);
+        };
+        let Some((session, _)) = self.page() else {
+            return self.inner.key(key, direction);
+        };
+        let event = |kind: &str| {
+            let mut params = json!({ "type": kind, "key": dom_key, "code": code, "windowsVirtualKeyCode": key_code });
+            if let (Some(text), "keyDown") = (text, kind) {
+                params["text"] = json!(text);
+            }
+            params
+        };
+        if matches!(direction, Direction::Press | Direction::Click) {
+            self.call(&session, "Input.dispatchKeyEvent", event("keyDown"))?;
+        }
+        if matches!(direction, Direction::Release | Direction::Click) {
+            self.call(&session, "Input.dispatchKeyEvent", event("keyUp"))?;
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), ActionError> {
+        if !self.held_modifiers.is_empty() {
+            return self.inner.text(text); // e.g. Control held + 'a': a shortcut, not text
+        }
+        let Some((session, _)) = self.page() else {
+            return self.inner.text(text);
+        };
+        self.call(&session, "Input.insertText", json!({ "text": text })).map(|_| ())
+    }
+
+    fn scroll(&mut self, units: i32) -> Result<(), ActionError> {
+        let Some((session, viewport)) = self.page_at_pointer() else {
+            return self.inner.scroll(units);
+        };
+        // One wheel notch is ~100 CSS pixels in Chromium
+        self.dispatch_mouse(&session, viewport, "mouseWheel", json!({ "deltaX": 0, "deltaY": units * 100 }))
+    }
+
+    fn hscroll(&mut self, units: i32) -> Result<(), ActionError> {
+        let Some((session, viewport)) = self.page_at_pointer() else {
+            return self.inner.hscroll(units);
+        };
+        self.dispatch_mouse(&session, viewport, "mouseWheel", json!({ "deltaX": units * 100, "deltaY": 0 }))
+    }
+
+    fn main_display(&self) -> Result<(i32, i32), ActionError> {
+        self.inner.main_display()
+    }
+
+    fn activate_element(&mut self, name: &str, control_type: Option<&str>) -> Result<(), ActionError> {
+        self.inner.activate_element(name, control_type)
+    }
+
+    fn control_window(&mut self, title: &str, op: WindowOp) -> Result<(), ActionError> {
+        self.inner.control_window(title, op)
+    }
+
+    fn launch(&mut self, target: &str) -> Result<(), ActionError> {
+        self.inner.launch(target)
+    }
+
+    fn browser(&mut self, op: &BrowserOp) -> Result<(), ActionError> {
+        match op {
+            BrowserOp::Navigate(url) => self.navigate(url),
+            BrowserOp::Click(selector) => self.click_selector(selector),
+            BrowserOp::Fill(selector, text) => self.fill_selector(selector, text),
+        }
+    }
+
+    // Gestures go to the OS, which knows whether the screen takes touch
+
+    fn long_press(&mut self, x: i32, y: i32, hold: Duration) -> Result<(), ActionError> {
+        self.inner.long_press(x, y, hold)
+    }
+
+    fn pinch(&mut self, x: i32, y: i32, scale: f32) -> Result<(), ActionError> {
+        self.inner.pinch(x, y, scale)
+    }
+
+    fn two_finger_scroll(&mut self, x: i32, y: i32, units: i32) -> Result<(), ActionError> {
+        self.inner.two_finger_scroll(x, y, units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_covers_the_page_but_not_the_browser_chrome() {
+        // A 1000x600 CSS-pixel page at (100, 80) on a 2x display
+        let viewport = Viewport { focused: true, dpr: 2.0, left: 100.0, top: 80.0, width: 1000.0, height: 600.0 };
+        assert!(viewport.contains(200, 160));
+        assert!(viewport.contains(2199, 1359));
+        assert!(!viewport.contains(200, 159)); // Address bar
+        assert!(!viewport.contains(2200, 400));
+        assert!(!viewport.contains(0, 0));
+        assert_eq!(viewport.to_screen(10.0, 20.0), (220, 200));
+        assert_eq!(viewport.to_page(220, 200), (10.0, 20.0));
+    }
+}
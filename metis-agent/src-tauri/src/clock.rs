@@ -0,0 +1,82 @@
+// --- Clock Abstraction ---
+// The recorder and the action loop take their notion of time from a Clock instead
+// of calling Instant/SystemTime/thread::sleep directly, so timing rules (capture
+// delays, typing-burst detection, loop pacing) can be driven deterministically.
+
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(test)]
+use crate::sync::LockExt;
+
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for measuring intervals.
+    fn now(&self) -> Instant;
+    /// Wall-clock time, for timestamps that end up on disk.
+    fn wall(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration);
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// A clock that only moves when told to. `sleep` advances it instantly instead of blocking.
+#[cfg(test)]
+pub struct ManualClock {
+    origin: Instant,
+    wall_origin: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            origin: Instant::now(),
+            wall_origin: SystemTime::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock_or_recover() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.origin + *self.elapsed.lock_or_recover()
+    }
+
+    fn wall(&self) -> SystemTime {
+        self.wall_origin + *self.elapsed.lock_or_recover()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
@@ -0,0 +1,460 @@
+// --- Persistent Settings ---
+// User settings live in <config dir>/metis/settings.toml, which can also be
+// edited by hand. Missing files and missing fields fall back to defaults, so
+// older settings files keep loading as new options are added. A settings.json
+// from earlier versions is read once, then kept as settings.json.migrated.
+// Read with `config::get()`; change with `config::update()`, which persists
+// immediately. The admin policy (policy.rs) is applied on top of the user's
+// values on every read.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::backend;
+use crate::policy;
+use crate::sync::LockExt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionSettings {
+    pub enabled: bool,
+    pub emails: bool,
+    pub credit_cards: bool,
+    pub ssns: bool,
+    pub api_keys: bool,               // Well-known token formats (AWS, GitHub, Slack, OpenAI, Google, JWTs)
+    pub custom_patterns: Vec<String>, // Extra regexes; matches become [REDACTED]
+}
+
+impl Default for RedactionSettings {
+    fn default() -> Self {
+        RedactionSettings { enabled: true, emails: true, credit_cards: true, ssns: true, api_keys: true, custom_patterns: Vec::new() }
+    }
+}
+
+/// Chromium DevTools bridge (cdp.rs); the browser must run with --remote-debugging-port.
+/// Off until the user turns it on: whatever answers on that port gets the agent's input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowserBridgeSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub browser_path: Option<String>, // For browser_navigate to start; found by name when unset
+}
+
+impl Default for BrowserBridgeSettings {
+    fn default() -> Self {
+        BrowserBridgeSettings { enabled: false, port: 9222, browser_path: None }
+    }
+}
+
+/// Screen-reader status announcements (announce.rs).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnnouncementSettings {
+    pub enabled: bool,
+    pub speak: bool, // Also speak through the OS speech service
+}
+
+impl Default for AnnouncementSettings {
+    fn default() -> Self {
+        AnnouncementSettings { enabled: true, speak: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoFormat {
+    Mp4,
+    Webm,
+}
+
+/// Continuous session video next to the event screenshots (video.rs).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub enabled: bool,
+    pub fps: u32,
+    pub format: VideoFormat,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        VideoSettings { enabled: false, fps: 2, format: VideoFormat::Mp4 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenshotFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+/// How recorded screenshots are encoded (screenshot.rs). Applies to the saved
+/// files and the live preview alike.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotSettings {
+    pub format: ScreenshotFormat,
+    pub quality: u8,            // 1-100, JPEG only; WebP is always written lossless
+    pub max_width: Option<u32>, // Downscale wider frames to this width, keeping the aspect ratio
+    pub dedup: bool,            // Link frames of an unchanged screen to the earlier image (dedup.rs)
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        ScreenshotSettings { format: ScreenshotFormat::Png, quality: 85, max_width: None, dedup: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmProviderKind {
+    #[default]
+    Gemini,
+    OpenAi,
+    Anthropic,
+    Local, // Any OpenAI-compatible server (Ollama, llama.cpp, vLLM, LM Studio)
+}
+
+/// Which LLM plans the actions (llm.rs). Unset fields use the provider's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmSettings {
+    pub provider: LlmProviderKind,
+    pub model: Option<String>,
+    pub base_url: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>, // Per reply
+    // Prices for cost accounting, in USD per million tokens; unset uses the
+    // built-in price of known models
+    pub input_usd_per_mtok: Option<f64>,
+    pub output_usd_per_mtok: Option<f64>,
+    // Reuse the last decision when the screen, command and previous actions
+    // are unchanged, instead of asking again
+    pub cache_responses: bool,
+    // Offer the actions as native tools and take the LLM's typed call, instead
+    // of asking for a line of the action grammar
+    pub tool_use: bool,
+}
+
+impl LlmSettings {
+    pub fn model_config(&self) -> ModelConfig {
+        ModelConfig { model: self.model.clone(), temperature: self.temperature, top_p: self.top_p, max_tokens: self.max_tokens }
+    }
+}
+
+/// The model and its sampling parameters, set in the LLM settings or for one
+/// task (start_act). Unset fields fall back to the settings, then to the
+/// provider's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelConfig {
+    pub model: Option<String>,
+    pub temperature: Option<f32>, // 0-2
+    pub top_p: Option<f32>,       // Above 0, up to 1
+    pub max_tokens: Option<u32>,
+}
+
+impl ModelConfig {
+    /// This config with its unset fields taken from `fallback`.
+    pub fn or(self, fallback: ModelConfig) -> ModelConfig {
+        ModelConfig {
+            model: self.model.filter(|m| !m.trim().is_empty()).or(fallback.model),
+            temperature: self.temperature.or(fallback.temperature),
+            top_p: self.top_p.or(fallback.top_p),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+            return Err("Temperature must be between 0 and 2".to_string());
+        }
+        if self.top_p.is_some_and(|p| !(p > 0.0 && p <= 1.0)) {
+            return Err("top_p must be above 0 and at most 1".to_string());
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be above zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Limits on what one task may spend on the LLM; unset means unlimited.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskBudget {
+    pub max_tokens: Option<u64>, // Prompt and response tokens together
+    pub max_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParserEngine {
+    #[default]
+    Backend, // The Python service at the processImage endpoint
+    Native,  // parser.rs: tesseract OCR plus edge-based widget detection, in process
+}
+
+/// How requests to the Python backend are retried (backend.rs). The wait before
+/// retry n is initial_backoff_ms * 2^(n-1), capped at max_backoff_ms, then varied
+/// by up to ±jitter of itself so parallel clients don't retry in lockstep.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrySettings {
+    pub attempts: u32, // Including the first; 1 disables retrying
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub jitter: f64, // 0.0-1.0
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        RetrySettings { attempts: 3, initial_backoff_ms: 500, max_backoff_ms: 8_000, jitter: 0.2 }
+    }
+}
+
+/// Which screen parser turns screenshots into element CSV.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParserSettings {
+    pub engine: ParserEngine,
+    pub backend_url: String,   // processImage endpoint of the Python backend
+    pub ocr_languages: String, // Tesseract language packs, e.g. "eng+deu"
+    pub retry: RetrySettings,
+}
+
+impl Default for ParserSettings {
+    fn default() -> Self {
+        ParserSettings {
+            engine: ParserEngine::Backend,
+            backend_url: backend::PROCESS_IMAGE_URL.to_string(),
+            ocr_languages: "eng".to_string(),
+            retry: RetrySettings::default(),
+        }
+    }
+}
+
+/// Where recordings are kept. Unset means <Downloads>/screenshots.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageSettings {
+    pub base_folder: Option<String>,
+}
+
+/// How long recordings are kept and how much space they may take (retention.rs).
+/// An admin policy's retention_days still applies when it is stricter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    pub days: Option<u32>,   // Delete recordings older than this
+    pub max_gb: Option<f64>, // Delete the oldest recordings while Metis uses more than this
+}
+
+/// Delays, in milliseconds, that give the UI time to settle before a capture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimingSettings {
+    pub click_capture_delay_ms: u64,  // Recording: after a mouse press or release
+    pub scroll_capture_delay_ms: u64, // Recording: after a wheel event
+    pub action_settle_ms: u64,        // Task loop: after each action, before the next screenshot
+    pub max_wait_ms: u64,             // Task loop: longest `wait:` the agent may ask for
+}
+
+impl Default for TimingSettings {
+    fn default() -> Self {
+        TimingSettings { click_capture_delay_ms: 500, scroll_capture_delay_ms: 1000, action_settle_ms: 500, max_wait_ms: 10_000 }
+    }
+}
+
+/// What the `launch:` action may start (launcher.rs).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaunchSettings {
+    pub allowed_apps: Vec<String>, // Program names or paths, e.g. "firefox"
+    pub allow_urls: bool,          // http(s) links, in the default browser
+    pub allow_files: bool,         // Existing files and folders, with their default app
+}
+
+impl Default for LaunchSettings {
+    fn default() -> Self {
+        LaunchSettings { allowed_apps: Vec::new(), allow_urls: true, allow_files: false }
+    }
+}
+
+/// Applications that are (not) recorded (app_filter.rs). Entries match the focused
+/// window's process name, ignoring case and any extension.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingFilterSettings {
+    pub denied_apps: Vec<String>,  // Never recorded, e.g. "1Password", "KeePassXC"
+    pub allowed_apps: Vec<String>, // When non-empty, only these are recorded
+}
+
+/// Where skill bundles are listed (marketplace.rs): the URL or path of a JSON
+/// catalog. Unset means there is no marketplace.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketplaceSettings {
+    pub catalog_url: Option<String>,
+}
+
+/// A chord that runs a skill while Metis is idle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillHotkey {
+    pub chord: String,
+    pub skill_id: String,
+}
+
+/// Global key chords (hotkey.rs), like "Ctrl+Alt+R". An empty chord is off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeySettings {
+    pub record_toggle: String, // Starts or stops a recording
+    pub skills: Vec<SkillHotkey>,
+}
+
+impl Default for HotkeySettings {
+    fn default() -> Self {
+        HotkeySettings { record_toggle: "Ctrl+Alt+R".to_string(), skills: Vec::new() }
+    }
+}
+
+/// Local HTTP trigger server (webhook.rs), on 127.0.0.1 only. Off by default;
+/// every request must carry `token` as a bearer token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub token: Option<String>, // Generated when the server is first enabled
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        WebhookSettings { enabled: false, port: 7878, token: None }
+    }
+}
+
+/// Task memory (memory.rs): transcripts of past runs, recalled for similar commands.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemorySettings {
+    pub enabled: bool,
+    pub recalled_runs: usize, // Most similar past runs put in the prompt
+    pub kept_runs: usize,     // Older runs are deleted
+}
+
+impl Default for MemorySettings {
+    fn default() -> Self {
+        MemorySettings { enabled: true, recalled_runs: 3, kept_runs: 200 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub redaction: RedactionSettings,
+    pub local_only: bool, // Strict local-only mode: no outbound network at all
+    pub browser_bridge: BrowserBridgeSettings,
+    pub announcements: AnnouncementSettings,
+    pub video: VideoSettings,
+    pub screenshots: ScreenshotSettings,
+    pub llm: LlmSettings,
+    pub task_budget: TaskBudget,
+    pub memory: MemorySettings,
+    pub parser: ParserSettings,
+    pub storage: StorageSettings,
+    pub retention: RetentionSettings,
+    pub timings: TimingSettings,
+    pub launch: LaunchSettings,
+    pub recording_filter: RecordingFilterSettings,
+    pub marketplace: MarketplaceSettings,
+    pub hotkeys: HotkeySettings,
+    pub webhook: WebhookSettings,
+}
+
+static SETTINGS: Lazy<Mutex<Settings>> = Lazy::new(|| Mutex::new(load()));
+
+pub fn settings_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("metis")
+        .join("settings.toml")
+}
+
+fn load() -> Settings {
+    let path = settings_path();
+    match fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content).unwrap_or_else(|e| {
+            warn!("Invalid settings file {}, using defaults: {}", path.display(), e);
+            Settings::default()
+        }),
+        Err(_) => migrate_json().unwrap_or_default(), // First run, or first run since settings were TOML
+    }
+}
+
+/// Reads settings.json from before settings were TOML, saving them as TOML.
+fn migrate_json() -> Option<Settings> {
+    let legacy = settings_path().with_extension("json");
+    let content = fs::read_to_string(&legacy).ok()?;
+    let settings: Settings = serde_json::from_str(&content)
+        .map_err(|e| warn!("Invalid settings file {}, using defaults: {}", legacy.display(), e))
+        .ok()?;
+    match save(&settings) {
+        Ok(()) => {
+            if let Err(e) = fs::rename(&legacy, legacy.with_extension("json.migrated")) {
+                warn!("Failed to rename {}: {}", legacy.display(), e);
+            }
+            info!("Migrated {} to {}", legacy.display(), settings_path().display());
+        }
+        Err(e) => warn!("{}", e), // Still used for this run; retried next start
+    }
+    Some(settings)
+}
+
+fn save(settings: &Settings) -> Result<(), String> {
+    let path = settings_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create settings folder: {}", e))?;
+    }
+    let content = toml::to_string_pretty(settings).map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// A snapshot of the effective settings (user settings with the admin policy applied).
+pub fn get() -> Settings {
+    let mut settings = SETTINGS.lock_or_recover().clone();
+    policy::apply(&mut settings);
+    settings
+}
+
+/// Applies `change` to the settings and persists the result.
+pub fn update(change: impl FnOnce(&mut Settings)) -> Result<Settings, String> {
+    let mut settings = SETTINGS.lock_or_recover();
+    let mut next = settings.clone();
+    change(&mut next);
+    save(&next)?;
+    *settings = next.clone();
+    policy::apply(&mut next); // Callers see what is actually in force
+    Ok(next)
+}
+
+#[tauri::command]
+pub fn get_settings() -> Result<Settings, String> {
+    Ok(get())
+}
+
+#[tauri::command]
+pub fn update_settings(settings: Settings) -> Result<Settings, String> {
+    update(|s| *s = settings)
+}
@@ -0,0 +1,98 @@
+// --- Coordinate Mapping ---
+// The LLM picks coordinates in the pixels of the screenshot it was shown (the
+// parsed CSV's bboxes are in the same pixels), but the input backend moves the
+// pointer in its own screen coordinates. On HiDPI displays the two disagree:
+// macOS captures physical pixels while enigo works in points, and Windows and
+// X11 with display scaling can do the same. A click at a bbox center then lands
+// at twice the intended distance from the corner.
+//
+// Each capture records a CoordinateMap from the screenshot's size, the input
+// backend's screen size and the display's scale factor at that moment.
+// Actions are validated in screenshot pixels and converted through the map
+// just before do_action injects them. Where the sizes match, the map does nothing.
+//
+// The map also records the monitor layout at capture. With several monitors the
+// LLM may write `(monitor, x, y)` for pixel (x, y) of another monitor than the
+// one captured; those points are offset by that monitor's origin instead.
+
+use tracing::info;
+
+use crate::display::{self, MonitorGeometry};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoordinateMap {
+    pub image: (i32, i32),  // Screenshot size, the LLM's coordinate space
+    pub screen: (i32, i32), // The input backend's main display size
+    pub scale_factor: f32,  // The OS scale factor of the captured display
+    pub monitors: Vec<MonitorGeometry>, // What monitor-qualified points index
+    pub shown: Option<usize>,           // Which of them the screenshot is of
+}
+
+impl CoordinateMap {
+    /// The map for a screenshot of `image` size taken while the input backend
+    /// reported `screen`.
+    pub fn new(image: (u32, u32), screen: (i32, i32), scale_factor: f32) -> Self {
+        let image = (image.0.max(1) as i32, image.1.max(1) as i32);
+        CoordinateMap { image, screen, scale_factor, monitors: Vec::new(), shown: None }
+    }
+
+    /// For a screenshot of the local primary display, with its scale factor and
+    /// the current monitor layout.
+    pub fn for_local(image: (u32, u32), screen: (i32, i32)) -> Self {
+        let geometry = display::current();
+        let shown = geometry.primary().and_then(|primary| geometry.monitors.iter().position(|m| m == primary));
+        let scale_factor = geometry.primary().map_or(1.0, |m| m.scale_factor);
+        CoordinateMap { monitors: geometry.monitors, shown, ..Self::new(image, screen, scale_factor) }
+    }
+
+    /// Screenshot pixels and screen coordinates are the same.
+    pub fn identity(screen: (i32, i32)) -> Self {
+        CoordinateMap { image: screen, screen, scale_factor: 1.0, monitors: Vec::new(), shown: None }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.image == self.screen
+    }
+
+    /// Converts a point in screenshot pixels to screen coordinates, keeping it on screen.
+    pub fn screen_point(&self, x: i32, y: i32) -> (i32, i32) {
+        if self.is_identity() {
+            return (x, y);
+        }
+        let axis = |v: i32, from: i32, to: i32| {
+            let mapped = (v as i64 * to as i64 + from as i64 / 2) / from as i64;
+            mapped.clamp(0, (to - 1).max(0) as i64) as i32
+        };
+        (axis(x, self.image.0, self.screen.0), axis(y, self.image.1, self.screen.1))
+    }
+
+    /// Converts pixel (x, y) of a recorded monitor to screen coordinates.
+    pub fn monitor_point(&self, monitor: usize, x: i32, y: i32) -> (i32, i32) {
+        self.monitors.get(monitor).map_or((x, y), |m| (m.x.saturating_add(x), m.y.saturating_add(y)))
+    }
+
+    /// The Displays section of the prompt, when there is more than one monitor.
+    pub fn to_context(&self) -> Option<String> {
+        if self.monitors.len() < 2 {
+            return None;
+        }
+        let mut context = String::new();
+        for (i, m) in self.monitors.iter().enumerate() {
+            let shown = if self.shown == Some(i) { ", shown in the screenshot" } else { "" };
+            context.push_str(&format!("Monitor {}: {}x{} pixels at ({},{}){}\n", i, m.width, m.height, m.x, m.y, shown));
+        }
+        Some(context)
+    }
+
+    /// Logs the map when it differs from the last one logged, so scaled
+    /// displays show up in the logs once per change rather than per iteration.
+    pub fn log_change(&self, last: &mut Option<CoordinateMap>) {
+        if last.as_ref() != Some(self) && !self.is_identity() {
+            info!(
+                "Screenshot is {}x{} but the screen is {}x{} (scale factor {}); mapping action coordinates",
+                self.image.0, self.image.1, self.screen.0, self.screen.1, self.scale_factor
+            );
+        }
+        *last = Some(self.clone());
+    }
+}
@@ -0,0 +1,167 @@
+// --- Encryption Primitives ---
+// AES-256-GCM (from ring) for data and Argon2id for turning the user's password
+// into a key; PBKDF2-HMAC-SHA256 remains only to open keys derived before
+// Argon2id was used. Sealed blobs are `nonce || ciphertext || tag`, so a blob
+// carries everything but the key (and any associated data it was bound to)
+// needed to open it. Keys and decrypted plaintext are wiped from memory when
+// dropped.
+
+use std::num::NonZeroU32;
+
+use argon2::{Algorithm, Argon2, Block, Params, Version};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::CryptoError;
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+// RFC 9106's second recommended setting, for machines that can't spare 2 GiB
+pub const ARGON2ID: Kdf = Kdf::Argon2id { memory_kib: 64 * 1024, passes: 3, lanes: 4 };
+
+/// How a password is stretched into a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kdf {
+    Argon2id { memory_kib: u32, passes: u32, lanes: u32 },
+    Pbkdf2Sha256 { iterations: u32 },
+}
+
+#[derive(Clone)]
+pub struct Key(pub [u8; KEY_LEN]);
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Key(..)") // Never print key material
+    }
+}
+
+pub fn random_bytes<const N: usize>() -> Result<[u8; N], CryptoError> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| CryptoError::Crypto("System random generator failed".to_string()))?;
+    Ok(bytes)
+}
+
+pub fn derive_key(password: &str, salt: &[u8], kdf: Kdf) -> Result<Key, CryptoError> {
+    let mut key = Key([0u8; KEY_LEN]);
+    match kdf {
+        Kdf::Argon2id { memory_kib, passes, lanes } => {
+            let params = Params::new(memory_kib, passes, lanes, Some(KEY_LEN))
+                .map_err(|e| CryptoError::Crypto(format!("Invalid Argon2id parameters: {}", e)))?;
+            let mut memory = Zeroizing::new(vec![Block::default(); params.block_count()]); // Holds password-derived state
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into_with_memory(password.as_bytes(), salt, &mut key.0, &mut *memory)
+                .map_err(|e| CryptoError::Crypto(format!("Argon2id failed: {}", e)))?;
+        }
+        Kdf::Pbkdf2Sha256 { iterations } => {
+            let iterations = NonZeroU32::new(iterations.max(1)).expect("iterations is non-zero");
+            pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password.as_bytes(), &mut key.0);
+        }
+    }
+    Ok(key)
+}
+
+fn aead_key(key: &Key) -> Result<LessSafeKey, CryptoError> {
+    UnboundKey::new(&AES_256_GCM, &key.0)
+        .map(LessSafeKey::new)
+        .map_err(|_| CryptoError::Crypto("Invalid key length".to_string()))
+}
+
+/// Encrypts `plaintext` under `key` with a fresh random nonce.
+pub fn seal(key: &Key, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    seal_with_aad(key, plaintext, &[])
+}
+
+/// Like seal, binding the blob to `aad`: open_with_aad only opens it with the same bytes.
+pub fn seal_with_aad(key: &Key, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let nonce_bytes = random_bytes::<NONCE_LEN>()?;
+    let mut in_out = Zeroizing::new(plaintext.to_vec()); // Wiped if sealing fails
+    aead_key(key)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::from(aad), &mut *in_out)
+        .map_err(|_| CryptoError::Crypto("Encryption failed".to_string()))?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + in_out.len());
+    sealed.extend_from_slice(&nonce_bytes);
+    sealed.extend_from_slice(&in_out);
+    Ok(sealed)
+}
+
+/// Decrypts a blob produced by `seal`. Fails on a wrong key or any tampering.
+pub fn open(key: &Key, sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    open_with_aad(key, sealed, &[])
+}
+
+/// Decrypts a blob produced by `seal_with_aad` with the same `aad`.
+pub fn open_with_aad(key: &Key, sealed: &[u8], aad: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(CryptoError::Crypto("Encrypted data is truncated".to_string()));
+    }
+    let (nonce_bytes, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce_bytes)
+        .map_err(|_| CryptoError::Crypto("Invalid nonce".to_string()))?;
+    let mut in_out = Zeroizing::new(ciphertext.to_vec());
+    let plaintext_len = aead_key(key)?
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| CryptoError::Decrypt)?
+        .len();
+    in_out.truncate(plaintext_len); // Drop the tag; the buffer stays zeroizing
+    Ok(in_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap enough for debug builds; the cost settings don't change the algorithm
+    const TEST_ARGON2ID: Kdf = Kdf::Argon2id { memory_kib: 64, passes: 1, lanes: 1 };
+
+    #[test]
+    fn seal_round_trips_and_detects_tampering() {
+        let key = Key(random_bytes().unwrap());
+        let sealed = seal(&key, b"secret").unwrap();
+        assert_ne!(seal(&key, b"secret").unwrap(), sealed); // Fresh nonce every time
+        assert_eq!(open(&key, &sealed).unwrap().as_slice(), b"secret");
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(open(&key, &tampered), Err(CryptoError::Decrypt)));
+        assert!(matches!(open(&key, &sealed[..NONCE_LEN]), Err(CryptoError::Decrypt)));
+        assert!(matches!(open(&Key(random_bytes().unwrap()), &sealed), Err(CryptoError::Decrypt)));
+    }
+
+    #[test]
+    fn argon2id_depends_on_password_and_salt() {
+        let key = derive_key("hunter2", b"0123456789abcdef", TEST_ARGON2ID).unwrap();
+        assert_eq!(key.0, derive_key("hunter2", b"0123456789abcdef", TEST_ARGON2ID).unwrap().0);
+        assert_ne!(key.0, derive_key("hunter3", b"0123456789abcdef", TEST_ARGON2ID).unwrap().0);
+        assert_ne!(key.0, derive_key("hunter2", b"fedcba9876543210", TEST_ARGON2ID).unwrap().0);
+        assert_ne!(key.0, derive_key("hunter2", b"0123456789abcdef", Kdf::Pbkdf2Sha256 { iterations: 1 }).unwrap().0);
+    }
+
+    #[test]
+    fn invalid_argon2id_parameters_are_an_error() {
+        let kdf = Kdf::Argon2id { memory_kib: 1, passes: 1, lanes: 1 };
+        assert!(matches!(derive_key("hunter2", b"0123456789abcdef", kdf), Err(CryptoError::Crypto(_))));
+    }
+
+    #[test]
+    fn associated_data_must_match() {
+        let key = Key(random_bytes().unwrap());
+        let sealed = seal_with_aad(&key, b"secret", b"rec_1/a.csv").unwrap();
+        assert_eq!(open_with_aad(&key, &sealed, b"rec_1/a.csv").unwrap().as_slice(), b"secret");
+        assert!(matches!(open_with_aad(&key, &sealed, b"rec_1/b.csv"), Err(CryptoError::Decrypt)));
+        assert!(matches!(open(&key, &sealed), Err(CryptoError::Decrypt)));
+    }
+}
@@ -0,0 +1,56 @@
+// --- Near-Duplicate Frames ---
+// A burst of clicks on a screen that doesn't change produces a run of identical
+// screenshots, each of which used to be saved, parsed by the backend and fed to
+// the LLM. Frames get a difference hash (dHash): the image shrunk to a small
+// grayscale grid, one bit per horizontal neighbour pair saying which is brighter.
+// Two frames whose hashes differ in at most a few bits show the same screen.
+//
+// The grid is 16x16 rather than the usual 8x8 so that small but meaningful
+// changes (a typed word, a toggled checkbox) still flip bits on a large monitor.
+
+use image::DynamicImage;
+
+const GRID: u32 = 16;
+// Bits (of 256) two frames may differ in and still count as the same screen
+const MAX_DISTANCE: u32 = 2;
+// Bits two frames must differ in to count as a different scene (a new window or page)
+const SCENE_DISTANCE: u32 = 24;
+
+/// The frame's dHash as 64 hex digits.
+pub fn hash(image: &DynamicImage) -> String {
+    let small = image.thumbnail_exact(GRID + 1, GRID).to_luma8();
+    let mut bits = [0u64; (GRID * GRID / 64) as usize];
+    for y in 0..GRID {
+        for x in 0..GRID {
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                let i = (y * GRID + x) as usize;
+                bits[i / 64] |= 1 << (i % 64);
+            }
+        }
+    }
+    bits.iter().map(|word| format!("{:016x}", word)).collect()
+}
+
+fn distance(a: &str, b: &str) -> Option<u32> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let mut total = 0;
+    for (x, y) in a.as_bytes().chunks(16).zip(b.as_bytes().chunks(16)) {
+        let x = u64::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok()?;
+        let y = u64::from_str_radix(std::str::from_utf8(y).ok()?, 16).ok()?;
+        total += (x ^ y).count_ones();
+    }
+    Some(total)
+}
+
+/// Whether two frame hashes show the same screen. Unreadable hashes never match.
+pub fn same_screen(a: &str, b: &str) -> bool {
+    distance(a, b).is_some_and(|d| d <= MAX_DISTANCE)
+}
+
+/// Whether `b` starts a new scene after `a`: a large change, not just typing or a
+/// hover effect. Unreadable hashes always do.
+pub fn scene_changed(a: &str, b: &str) -> bool {
+    distance(a, b).map_or(true, |d| d > SCENE_DISTANCE)
+}
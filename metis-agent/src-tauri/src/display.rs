@@ -0,0 +1,107 @@
+// --- Display Geometry Watcher ---
+// Docking, undocking and resolution changes move monitors around mid-session.
+// A background thread polls the monitor layout; on a change it bumps the
+// geometry generation, rebuilds the fast-capture target, and emits
+// `app://display-changed`. Frames record the geometry they were captured under.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use xcap::Monitor;
+use tracing::info;
+
+use crate::events;
+use crate::fast_capture;
+use crate::sync::LockExt;
+
+pub const DISPLAY_CHANGED_EVENT: &str = "app://display-changed";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitorGeometry {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DisplayGeometry {
+    pub generation: u64, // Incremented on every layout change
+    pub monitors: Vec<MonitorGeometry>,
+}
+
+impl DisplayGeometry {
+    pub fn primary(&self) -> Option<&MonitorGeometry> {
+        self.monitors.iter().find(|m| m.primary).or_else(|| self.monitors.first())
+    }
+}
+
+static DISPLAY_GEOMETRY: Lazy<Mutex<DisplayGeometry>> = Lazy::new(|| {
+    Mutex::new(DisplayGeometry { generation: 0, monitors: query_monitors().unwrap_or_default() })
+});
+
+pub fn current() -> DisplayGeometry {
+    DISPLAY_GEOMETRY.lock_or_recover().clone()
+}
+
+fn query_monitors() -> Result<Vec<MonitorGeometry>, String> {
+    // xcap can panic on some platforms while displays are being reconfigured
+    let result = std::panic::catch_unwind(|| {
+        let monitors = Monitor::all().map_err(|e| format!("{:?}", e))?;
+        Ok(monitors
+            .iter()
+            .map(|m| MonitorGeometry {
+                name: m.name().unwrap_or_default(),
+                x: m.x().unwrap_or(0),
+                y: m.y().unwrap_or(0),
+                width: m.width().unwrap_or(0),
+                height: m.height().unwrap_or(0),
+                scale_factor: m.scale_factor().unwrap_or(1.0),
+                primary: m.is_primary().unwrap_or(false),
+            })
+            .collect())
+    });
+    result.unwrap_or_else(|_| Err("Panic while enumerating monitors".to_string()))
+}
+
+/// Starts the watcher thread; called once from main.
+pub fn start_watcher() {
+    thread::spawn(|| loop {
+        thread::sleep(POLL_INTERVAL);
+
+        let monitors = match query_monitors() {
+            Ok(monitors) if !monitors.is_empty() => monitors,
+            _ => continue, // Mid-reconfiguration; check again next tick
+        };
+
+        let changed = {
+            let mut geometry = DISPLAY_GEOMETRY.lock_or_recover();
+            if geometry.monitors == monitors {
+                None
+            } else {
+                geometry.generation += 1;
+                geometry.monitors = monitors;
+                Some(geometry.clone())
+            }
+        };
+
+        if let Some(geometry) = changed {
+            info!("[Display] Layout changed (generation {}): {:?}", geometry.generation, geometry.primary());
+            fast_capture::reset();
+            events::emit(DISPLAY_CHANGED_EVENT, geometry);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_display_geometry() -> Result<DisplayGeometry, String> {
+    Ok(current())
+}
@@ -0,0 +1,125 @@
+// --- Accessibility Element Providers ---
+// Vision parsing only sees pixels. The OS accessibility tree knows what each
+// control is called, what it is, and exactly where it sits, and can press it
+// without synthesizing a click. Each platform has one ElementProvider over its
+// native API (UI Automation on Windows, AX on macOS, AT-SPI on Linux);
+// `snapshot` feeds the foreground window's elements into the executor's context,
+// `element_at` tags recorded frames with what was under the mouse (a hit test
+// through the platform API, since it runs on every captured frame), and
+// `activate` backs the `click_element` action. Where no provider exists all of
+// them come back empty and the executor falls back to coordinates.
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::error::ActionError;
+
+// Upper bound on elements listed in one prompt; big windows have thousands
+pub const MAX_CONTEXT_ELEMENTS: usize = 150;
+
+/// One element of the foreground window, in screen pixels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementInfo {
+    pub name: String,
+    pub role: String, // e.g. "button", "AXButton"
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl ElementInfo {
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+}
+
+/// What `activate` did with the element it found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Invoked,         // Pressed through the accessibility API
+    Click(i32, i32), // Can't be pressed directly; click this screen point instead
+}
+
+pub trait ElementProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Named, visible elements of the foreground window, at most `limit`.
+    fn snapshot(&self, limit: usize) -> Result<Vec<ElementInfo>, ActionError>;
+    /// Presses the first element matching `name` (and `role`, if given).
+    fn activate(&self, name: &str, role: Option<&str>) -> Result<Activation, ActionError>;
+    /// The innermost named element at the screen point, hit-tested by the
+    /// platform rather than found by walking the whole tree.
+    fn element_at(&self, x: i32, y: i32) -> Result<Option<ElementInfo>, ActionError>;
+}
+
+/// Whether `name`/`role` as written by the LLM refers to this element.
+/// Roles match case-insensitively with or without the platform's "AX" prefix.
+pub fn matches(element_name: &str, element_role: &str, name: &str, role: Option<&str>) -> bool {
+    if !element_name.trim().eq_ignore_ascii_case(name.trim()) {
+        return false;
+    }
+    role.map_or(true, |wanted| {
+        let strip = |r: &str| r.trim().trim_start_matches("AX").to_ascii_lowercase();
+        strip(element_role) == strip(wanted)
+    })
+}
+
+pub fn not_found(name: &str, role: Option<&str>) -> ActionError {
+    ActionError::ElementNotFound(match role {
+        Some(kind) => format!("{} '{}'", kind, name),
+        None => format!("'{}'", name),
+    })
+}
+
+/// The provider for this platform, if there is one.
+pub fn provider() -> Option<&'static dyn ElementProvider> {
+    #[cfg(target_os = "windows")]
+    return Some(&crate::uia::UiaProvider);
+    #[cfg(target_os = "macos")]
+    return Some(&crate::ax::AxProvider);
+    #[cfg(target_os = "linux")]
+    return Some(&crate::atspi::AtspiProvider);
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    return None;
+}
+
+pub fn activate(name: &str, role: Option<&str>) -> Result<Activation, ActionError> {
+    provider()
+        .ok_or_else(|| ActionError::ElementTargeting("No accessibility provider on this platform".to_string()))?
+        .activate(name, role)
+}
+
+/// The foreground window's elements, or none if unavailable (never fails the caller).
+pub fn snapshot(limit: usize) -> Vec<ElementInfo> {
+    let Some(provider) = provider() else {
+        return Vec::new();
+    };
+    provider.snapshot(limit).unwrap_or_else(|e| {
+        error!("Failed to read accessibility elements via {}: {}", provider.name(), e);
+        Vec::new()
+    })
+}
+
+/// The element under a screen point, or none if unavailable (never fails the caller).
+pub fn element_at(x: i32, y: i32) -> Option<ElementInfo> {
+    let provider = provider()?;
+    provider.element_at(x, y).unwrap_or_else(|e| {
+        error!("Failed to read accessibility element at ({}, {}) via {}: {}", x, y, provider.name(), e);
+        None
+    })
+}
+
+/// Renders elements as prompt context, one per line.
+pub fn to_context(elements: &[ElementInfo]) -> String {
+    elements
+        .iter()
+        .map(|e| {
+            let (cx, cy) = e.center();
+            format!("{} '{}' center=({},{}) size={}x{}\n", e.role, e.name, cx, cy, e.width, e.height)
+        })
+        .collect()
+}
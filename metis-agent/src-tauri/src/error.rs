@@ -0,0 +1,385 @@
+// --- Typed Error Types ---
+// One enum per subsystem. Each serializes as { category, kind, message } so the
+// frontend can branch on `kind` instead of pattern-matching error strings.
+// MetisError wraps them for the commands that can fail in more than one subsystem.
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+
+use crate::app_state::TransitionError;
+
+macro_rules! impl_serialize {
+    ($ty:ident, $category:literal) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut state = serializer.serialize_struct(stringify!($ty), 3)?;
+                state.serialize_field("category", $category)?;
+                state.serialize_field("kind", self.kind())?;
+                state.serialize_field("message", &self.to_string())?;
+                state.end()
+            }
+        }
+    };
+}
+
+/// A request refused by local-only mode or the admin policy.
+#[derive(Debug, Error)]
+#[error("{reason} blocks outbound request to {url}")]
+pub struct BlockedRequest {
+    pub url: String,
+    pub reason: &'static str,
+}
+
+/// Errors from grabbing pixels off the screen.
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("No monitors found")]
+    NoMonitors,
+    #[error("Failed to get monitors: {0}")]
+    Monitors(String),
+    #[error("Failed to capture image: {0}")]
+    Capture(String),
+    #[error("Panic occurred during screen capture")]
+    Panicked,
+    #[error("Image error: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+impl CaptureError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CaptureError::NoMonitors => "no_monitors",
+            CaptureError::Monitors(_) => "monitors",
+            CaptureError::Capture(_) => "capture",
+            CaptureError::Panicked => "panicked",
+            CaptureError::Image(_) => "image",
+        }
+    }
+}
+
+impl_serialize!(CaptureError, "capture");
+
+/// Errors from the image-processing (screen parser) backend.
+#[derive(Debug, Error)]
+pub enum ParserError {
+    #[error("Screen capture failed: {0}")]
+    Capture(#[from] CaptureError),
+    #[error("Failed to encode image payload: {0}")]
+    Encode(String),
+    #[error("Failed to send request to Python backend: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Python backend returned error {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("Failed to parse JSON response from Python backend: {0}")]
+    InvalidResponse(String),
+    #[error("Python backend response missing 'parsed_content' field or it's not a string")]
+    MissingContent,
+    #[error("Text recognition failed: {0}")]
+    Ocr(String),
+    #[error(transparent)]
+    Blocked(#[from] BlockedRequest),
+    #[error("Parser backend is not running at {url} ({reason}). Start it, or switch the parser engine to native.")]
+    Unavailable { url: String, reason: String },
+    #[error("{last} (gave up after {attempts} attempts)")]
+    RetriesExhausted { attempts: u32, last: Box<ParserError> },
+}
+
+impl ParserError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParserError::Capture(_) => "capture",
+            ParserError::Encode(_) => "encode",
+            ParserError::Request(_) => "request",
+            ParserError::Status { .. } => "status",
+            ParserError::InvalidResponse(_) => "invalid_response",
+            ParserError::MissingContent => "missing_content",
+            ParserError::Ocr(_) => "ocr",
+            ParserError::Blocked(_) => "blocked",
+            ParserError::Unavailable { .. } => "unavailable",
+            ParserError::RetriesExhausted { last, .. } => last.kind(),
+        }
+    }
+}
+
+impl_serialize!(ParserError, "parser");
+
+/// Errors from talking to the LLM.
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("{0} environment variable not set")]
+    MissingApiKey(&'static str),
+    #[error("LLM request failed: {0}")]
+    Provider(#[from] gemini_rs::Error),
+    #[error("LLM request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("LLM API error: {0}")]
+    Api(String),
+    #[error("Failed to create async runtime: {0}")]
+    Runtime(String),
+    #[error("LLM returned an empty response.")]
+    EmptyResponse,
+    #[error("LLM returned thought but no action.")]
+    MissingAction,
+    #[error(transparent)]
+    Blocked(#[from] BlockedRequest),
+}
+
+impl LlmError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LlmError::MissingApiKey(_) => "missing_api_key",
+            LlmError::Provider(_) => "provider",
+            LlmError::Http(_) => "http",
+            LlmError::Api(_) => "api",
+            LlmError::Runtime(_) => "runtime",
+            LlmError::EmptyResponse => "empty_response",
+            LlmError::MissingAction => "missing_action",
+            LlmError::Blocked(_) => "blocked",
+        }
+    }
+}
+
+impl_serialize!(LlmError, "llm");
+
+/// Errors from parsing or executing a single agent action.
+#[derive(Debug, Error)]
+pub enum ActionError {
+    #[error("Invalid action format: {0}")]
+    InvalidFormat(String),
+    #[error("Invalid coordinate format: {0}")]
+    InvalidCoordinate(String),
+    #[error("Invalid key format: {0}")]
+    InvalidKey(String),
+    #[error("Unknown or unsupported key: '{0}'")]
+    UnknownKey(String),
+    #[error("'{action}' action is not supported for single character '{ch}'. Use specific Key names like 'Shift'.")]
+    UnsupportedChar { action: &'static str, ch: char },
+    #[error("Invalid {action} value: {value}")]
+    InvalidValue { action: &'static str, value: String },
+    #[error("Unknown action type: {0}")]
+    UnknownAction(String),
+    #[error("Invalid arguments for {tool}: {reason}")]
+    InvalidArguments { tool: String, reason: String },
+    #[error("Coordinate ({x},{y}) is outside the {width}x{height} screen")]
+    OutOfBounds { x: i32, y: i32, width: i32, height: i32 },
+    #[error("Relative coordinates need a known pointer position; use absolute (x,y) coordinates first")]
+    UnknownPointer,
+    #[error("No monitor {monitor}; there are {count}, numbered from 0")]
+    UnknownMonitor { monitor: usize, count: usize },
+    #[error("No UI element {0} found in the foreground window")]
+    ElementNotFound(String),
+    #[error("No element with id {0} in the current screen CSV")]
+    UnknownElementId(String),
+    #[error("UI element targeting failed: {0}")]
+    ElementTargeting(String),
+    #[error("Browser bridge error: {0}")]
+    Browser(String),
+    #[error("Device error: {0}")]
+    Device(String),
+    #[error("Text entry failed: {0}")]
+    TextEntry(String),
+    #[error("Gesture failed: {0}")]
+    Gesture(String),
+    #[error("No window titled '{0}' found")]
+    WindowNotFound(String),
+    #[error("Window control failed: {0}")]
+    WindowControl(String),
+    #[error("Launch refused: {0}")]
+    LaunchBlocked(String),
+    #[error("Launch failed: {0}")]
+    Launch(String),
+    #[error("Input simulation failed: {0}")]
+    Input(#[from] enigo::InputError),
+}
+
+impl ActionError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ActionError::InvalidFormat(_) => "invalid_format",
+            ActionError::InvalidCoordinate(_) => "invalid_coordinate",
+            ActionError::InvalidKey(_) => "invalid_key",
+            ActionError::UnknownKey(_) => "unknown_key",
+            ActionError::UnsupportedChar { .. } => "unsupported_char",
+            ActionError::InvalidValue { .. } => "invalid_value",
+            ActionError::UnknownAction(_) => "unknown_action",
+            ActionError::InvalidArguments { .. } => "invalid_arguments",
+            ActionError::OutOfBounds { .. } => "out_of_bounds",
+            ActionError::UnknownPointer => "unknown_pointer",
+            ActionError::UnknownMonitor { .. } => "unknown_monitor",
+            ActionError::ElementNotFound(_) => "element_not_found",
+            ActionError::UnknownElementId(_) => "unknown_element_id",
+            ActionError::ElementTargeting(_) => "element_targeting",
+            ActionError::Browser(_) => "browser",
+            ActionError::Device(_) => "device",
+            ActionError::TextEntry(_) => "text_entry",
+            ActionError::Gesture(_) => "gesture",
+            ActionError::WindowNotFound(_) => "window_not_found",
+            ActionError::WindowControl(_) => "window_control",
+            ActionError::LaunchBlocked(_) => "launch_blocked",
+            ActionError::Launch(_) => "launch",
+            ActionError::Input(_) => "input",
+        }
+    }
+}
+
+impl_serialize!(ActionError, "action");
+
+/// Errors from encryption and key management.
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("No encryption password has been set")]
+    KeyNotSet,
+    #[error("An encryption password is set but not unlocked; enter it before saving recordings")]
+    Locked,
+    #[error("Incorrect encryption password")]
+    WrongPassword,
+    #[error("Failed to decrypt data (wrong key or corrupted file)")]
+    Decrypt,
+    #[error("OS keyring error: {0}")]
+    Keyring(String),
+    #[error("Encryption error: {0}")]
+    Crypto(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl CryptoError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CryptoError::KeyNotSet => "key_not_set",
+            CryptoError::Locked => "locked",
+            CryptoError::WrongPassword => "wrong_password",
+            CryptoError::Decrypt => "decrypt",
+            CryptoError::Keyring(_) => "keyring",
+            CryptoError::Crypto(_) => "crypto",
+            CryptoError::Io(_) => "io",
+        }
+    }
+}
+
+impl_serialize!(CryptoError, "crypto");
+
+/// Errors from the session database.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Failed to import main.csv: {0}")]
+    Import(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl StorageError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StorageError::Sqlite(_) => "sqlite",
+            StorageError::Import(_) => "import",
+            StorageError::Io(_) => "io",
+        }
+    }
+}
+
+impl_serialize!(StorageError, "storage");
+
+/// One requirement of a skill bundle that can't be met (marketplace.rs).
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UnmetRequirement {
+    pub required_by: String, // Bundle ID
+    pub requirement: String, // e.g. "skill excel-basics >= 1.2", "Metis >= 0.3", "OS windows"
+    pub reason: String,
+}
+
+/// A skill bundle that can't be installed, with everything standing in the way.
+#[derive(Debug, Error)]
+#[error("Cannot install skill bundle {bundle}: {}", describe_unmet(.unmet))]
+pub struct DependencyError {
+    pub bundle: String,
+    pub unmet: Vec<UnmetRequirement>,
+}
+
+fn describe_unmet(unmet: &[UnmetRequirement]) -> String {
+    unmet.iter().map(|u| format!("{} ({})", u.requirement, u.reason)).collect::<Vec<_>>().join("; ")
+}
+
+/// Error returned by the task and recording commands: one of the subsystem errors
+/// above, or a request that conflicts with what the app is doing. Serializes with
+/// its own category and the wrapped error's kind.
+#[derive(Debug, Error)]
+pub enum MetisError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Capture(#[from] CaptureError),
+    #[error(transparent)]
+    Backend(#[from] ParserError),
+    #[error(transparent)]
+    Llm(#[from] LlmError),
+    #[error(transparent)]
+    InvalidAction(#[from] ActionError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Dependencies(#[from] DependencyError),
+    #[error("{0}")]
+    InvalidArgument(String),
+    #[error("{0}")]
+    State(String),
+}
+
+impl MetisError {
+    pub fn category(&self) -> &'static str {
+        match self {
+            MetisError::Io(_) => "io",
+            MetisError::Capture(_) => "capture",
+            MetisError::Backend(_) => "backend",
+            MetisError::Llm(_) => "llm",
+            MetisError::InvalidAction(_) => "invalid_action",
+            MetisError::Storage(_) => "storage",
+            MetisError::Crypto(_) => "crypto",
+            MetisError::Dependencies(_) => "dependencies",
+            MetisError::InvalidArgument(_) => "invalid_argument",
+            MetisError::State(_) => "state",
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MetisError::Io(_) => "io",
+            MetisError::Capture(e) => e.kind(),
+            MetisError::Backend(e) => e.kind(),
+            MetisError::Llm(e) => e.kind(),
+            MetisError::InvalidAction(e) => e.kind(),
+            MetisError::Storage(e) => e.kind(),
+            MetisError::Crypto(e) => e.kind(),
+            MetisError::Dependencies(_) => "unmet_requirements",
+            MetisError::InvalidArgument(_) => "invalid_argument",
+            MetisError::State(_) => "state",
+        }
+    }
+}
+
+impl From<TransitionError> for MetisError {
+    fn from(e: TransitionError) -> Self {
+        MetisError::State(e.to_string())
+    }
+}
+
+impl Serialize for MetisError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let unmet = match self {
+            MetisError::Dependencies(e) => Some(&e.unmet),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("MetisError", 3 + usize::from(unmet.is_some()))?;
+        state.serialize_field("category", self.category())?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        if let Some(unmet) = unmet {
+            state.serialize_field("unmet", unmet)?; // The frontend lists what is missing
+        }
+        state.end()
+    }
+}
@@ -0,0 +1,122 @@
+// --- Input Event Log ---
+// Screenshots only capture the moments an input triggered; skill learning needs
+// the exact input sequence in between. While recording, every rdev event that
+// reaches the recorder (so not while the session is paused or Metis is in
+// front) is appended to events.jsonl in the action folder, one InputEvent per
+// line. During secure (password) entry key events are logged without the key.
+// Post-processing seals the file with the session key like the parsed CSVs.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use rdev::{Button, Event, EventType, Key};
+use serde::Serialize;
+use tracing::warn;
+
+pub const EVENTS_FILE: &str = "events.jsonl";
+
+#[derive(Debug, Serialize)]
+pub struct InputEvent {
+    pub timestamp_ms: u64, // When the OS delivered the event, Unix milliseconds
+    #[serde(rename = "type")]
+    pub kind: &'static str, // rdev event type, e.g. "KeyPress", "ButtonRelease", "MouseMove"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>, // rdev key name, e.g. "KeyA", "ShiftLeft"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keycode: Option<u32>, // Platform key code, for keys rdev has no name for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>, // What the key press typed in the active layout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub button: Option<String>, // "Left", "Right", "Middle" or "Unknown(n)"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta_x: Option<i64>, // Wheel movement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta_y: Option<i64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub secure: bool, // Typed during secure text entry; key and text are withheld
+}
+
+impl InputEvent {
+    fn new(timestamp_ms: u64, kind: &'static str) -> Self {
+        InputEvent {
+            timestamp_ms,
+            kind,
+            key: None,
+            keycode: None,
+            text: None,
+            button: None,
+            x: None,
+            y: None,
+            delta_x: None,
+            delta_y: None,
+            secure: false,
+        }
+    }
+
+    fn with_key(mut self, key: Key, text: Option<&str>, secure: bool) -> Self {
+        if secure {
+            self.secure = true;
+            return self;
+        }
+        match key {
+            Key::Unknown(code) => self.keycode = Some(code),
+            key => self.key = Some(format!("{:?}", key)),
+        }
+        self.text = text.filter(|t| !t.is_empty()).map(str::to_string);
+        self
+    }
+
+    fn with_button(mut self, button: Button) -> Self {
+        self.button = Some(format!("{:?}", button));
+        self
+    }
+
+    /// The log entry for `event`. `secure` withholds which key was pressed.
+    pub fn from_rdev(event: &Event, secure: bool) -> Self {
+        let timestamp_ms = event.time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        match event.event_type {
+            EventType::KeyPress(key) => Self::new(timestamp_ms, "KeyPress").with_key(key, event.name.as_deref(), secure),
+            EventType::KeyRelease(key) => Self::new(timestamp_ms, "KeyRelease").with_key(key, None, secure),
+            EventType::ButtonPress(button) => Self::new(timestamp_ms, "ButtonPress").with_button(button),
+            EventType::ButtonRelease(button) => Self::new(timestamp_ms, "ButtonRelease").with_button(button),
+            EventType::MouseMove { x, y } => InputEvent { x: Some(x), y: Some(y), ..Self::new(timestamp_ms, "MouseMove") },
+            EventType::Wheel { delta_x, delta_y } => {
+                InputEvent { delta_x: Some(delta_x), delta_y: Some(delta_y), ..Self::new(timestamp_ms, "Wheel") }
+            }
+        }
+    }
+}
+
+/// An open events.jsonl. Buffered, since mouse moves arrive many times a second.
+pub struct EventLog {
+    writer: BufWriter<File>,
+}
+
+impl EventLog {
+    pub fn open(action_folder: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(action_folder.join(EVENTS_FILE))?;
+        Ok(EventLog { writer: BufWriter::new(file) })
+    }
+
+    pub fn record(&mut self, event: &InputEvent) {
+        let written = serde_json::to_writer(&mut self.writer, event)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+        if let Err(e) = written {
+            warn!("Failed to log input event: {}", e);
+        }
+    }
+
+    /// Flushes what is buffered; call when the recording stops.
+    pub fn finish(mut self) {
+        if let Err(e) = self.writer.flush() {
+            warn!("Failed to flush the input event log: {}", e);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+// --- Frontend Event Emission ---
+// Background threads (listener, recorder, action loop) have no AppHandle of their
+// own, so the handle is stashed here once during setup and events are emitted
+// through it. Emitting before setup (or in headless use) is a silent no-op.
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tracing::error;
+
+static APP_HANDLE: OnceCell<AppHandle> = OnceCell::new();
+
+/// Stores the app handle; called once from the Tauri setup hook.
+pub fn init(handle: AppHandle) {
+    let _ = APP_HANDLE.set(handle);
+}
+
+/// Emits `event` to every webview, logging (not failing) on error.
+pub fn emit<S: Serialize + Clone>(event: &str, payload: S) {
+    if let Some(handle) = APP_HANDLE.get() {
+        if let Err(e) = handle.emit(event, payload) {
+            error!("Failed to emit event '{}': {}", event, e);
+        }
+    }
+}
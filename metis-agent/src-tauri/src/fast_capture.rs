@@ -0,0 +1,170 @@
+// --- Platform-Native Fast Capture ---
+// DXGI desktop duplication on Windows and XShm on X11 (both through scrap) are
+// much faster than xcap's generic path. scrap's Capturer is not Send, so a single
+// worker thread owns it and serves capture requests over a channel.
+// After a display change (or a failed grab) the worker rebuilds its Capturer,
+// since a Capturer is bound to one display mode.
+
+use std::io::ErrorKind;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{bounded, Sender};
+use image::{DynamicImage, ImageBuffer, Rgba};
+use once_cell::sync::Lazy;
+use tracing::{error, info};
+
+type CaptureReply = Sender<Result<DynamicImage, String>>;
+
+enum Request {
+    Capture(CaptureReply),
+    Reset, // Display geometry changed; rebuild the Capturer
+}
+
+// None when the fast path isn't usable on this machine/session
+static FAST_CAPTURE: Lazy<Option<Sender<Request>>> = Lazy::new(start_worker);
+
+/// Captures the primary display through the native fast path.
+/// Returns None when the fast path is unavailable so the caller can fall back to xcap.
+pub fn capture() -> Option<Result<DynamicImage, String>> {
+    let requests = FAST_CAPTURE.as_ref()?;
+    let (reply_tx, reply_rx) = bounded(1);
+    if requests.send(Request::Capture(reply_tx)).is_err() {
+        return None; // Worker thread is gone
+    }
+    reply_rx.recv_timeout(Duration::from_secs(2)).ok()
+}
+
+/// Tells the worker to rebuild its Capturer for the current primary display.
+pub fn reset() {
+    if let Some(requests) = FAST_CAPTURE.as_ref() {
+        let _ = requests.send(Request::Reset);
+    }
+}
+
+/// Whether the current session can use DXGI/XShm at all.
+fn session_supported() -> bool {
+    if cfg!(target_os = "windows") {
+        return true;
+    }
+    if cfg!(target_os = "linux") {
+        // XShm needs a real X server; under Wayland it would only see XWayland clients
+        let is_wayland = std::env::var("XDG_SESSION_TYPE").map(|t| t == "wayland").unwrap_or(false);
+        return !is_wayland && std::env::var_os("DISPLAY").is_some();
+    }
+    false
+}
+
+fn new_capturer() -> std::io::Result<scrap::Capturer> {
+    scrap::Display::primary().and_then(scrap::Capturer::new)
+}
+
+fn start_worker() -> Option<Sender<Request>> {
+    if !session_supported() {
+        info!("Fast capture not supported in this session, using xcap.");
+        return None;
+    }
+
+    let (request_tx, request_rx) = bounded::<Request>(4);
+    let (ready_tx, ready_rx) = bounded::<bool>(1);
+
+    thread::spawn(move || {
+        let capturer = match new_capturer() {
+            Ok(capturer) => {
+                let _ = ready_tx.send(true);
+                capturer
+            }
+            Err(e) => {
+                error!("Fast capture init failed, using xcap: {}", e);
+                let _ = ready_tx.send(false);
+                return;
+            }
+        };
+        info!("Fast capture worker started ({}x{}).", capturer.width(), capturer.height());
+
+        let mut capturer = Some(capturer); // None until rebuilt after a reset or failure
+        let mut last_frame: Option<DynamicImage> = None;
+        for request in request_rx.iter() {
+            match request {
+                Request::Reset => {
+                    info!("Fast capture: display changed, rebuilding capturer.");
+                    capturer = None;
+                    last_frame = None;
+                }
+                Request::Capture(reply) => {
+                    if capturer.is_none() {
+                        capturer = new_capturer()
+                            .map_err(|e| error!("Fast capture: failed to rebuild capturer: {}", e))
+                            .ok();
+                    }
+                    let result = match capturer.as_mut() {
+                        Some(active) => grab_frame(active, &mut last_frame),
+                        None => Err("Fast capture unavailable for the current display".to_string()),
+                    };
+                    if result.is_err() {
+                        // Mode changes invalidate the capturer; try a fresh one next time
+                        capturer = None;
+                        last_frame = None;
+                    }
+                    let _ = reply.send(result);
+                }
+            }
+        }
+    });
+
+    match ready_rx.recv() {
+        Ok(true) => Some(request_tx),
+        _ => None,
+    }
+}
+
+fn grab_frame(capturer: &mut scrap::Capturer, last_frame: &mut Option<DynamicImage>) -> Result<DynamicImage, String> {
+    let (width, height) = (capturer.width(), capturer.height());
+
+    // X11 requests are pipelined one frame ahead, so the first frame() returns the
+    // image requested on the previous call. Drain it to get a fresh one.
+    if cfg!(target_os = "linux") {
+        let _ = capturer.frame();
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(100);
+    loop {
+        match capturer.frame() {
+            Ok(frame) => {
+                let image = bgra_to_image(&frame, width, height)?;
+                *last_frame = Some(image.clone());
+                return Ok(image);
+            }
+            // DXGI reports WouldBlock when nothing changed since the last frame
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                if let Some(image) = last_frame {
+                    return Ok(image.clone());
+                }
+                if Instant::now() > deadline {
+                    return Err("Timed out waiting for first frame".to_string());
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => return Err(format!("Fast capture failed: {}", e)),
+        }
+    }
+}
+
+/// Converts a packed BGRA frame (rows may be padded) into an RGBA image.
+fn bgra_to_image(frame: &[u8], width: usize, height: usize) -> Result<DynamicImage, String> {
+    if height == 0 || frame.len() < width * height * 4 {
+        return Err(format!("Unexpected frame size {} for {}x{}", frame.len(), width, height));
+    }
+    let stride = frame.len() / height;
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in frame.chunks(stride).take(height) {
+        for px in row[..width * 4].chunks_exact(4) {
+            rgba.extend_from_slice(&[px[2], px[1], px[0], 255]);
+        }
+    }
+
+    ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(width as u32, height as u32, rgba)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| "Failed to convert frame to ImageBuffer".to_string())
+}
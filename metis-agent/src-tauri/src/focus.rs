@@ -0,0 +1,42 @@
+// --- Metis Window Focus Tracking ---
+// While a Metis window is in the foreground, input goes to our own UI (naming an
+// action, stopping the recording, ...). Those events must not trigger captures,
+// and a delayed capture that lands while Metis is in front would only record our
+// own window. Tauri reports focus changes per window; we track which of ours
+// currently have focus.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use tauri::{Window, WindowEvent};
+
+use crate::indicator;
+use crate::sync::LockExt;
+
+// Labels of focused Metis windows (a set, so out-of-order focus events between
+// two of our windows can't leave a stale entry)
+static FOCUSED_WINDOWS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Whether a Metis window is the foreground window.
+pub fn metis_focused() -> bool {
+    !FOCUSED_WINDOWS.lock_or_recover().is_empty()
+}
+
+/// Window event hook, registered with `Builder::on_window_event`.
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    // The recording indicator is ours but shows nothing worth excluding
+    if indicator::is_indicator(window.label()) {
+        return;
+    }
+    let label = window.label().to_string();
+    match event {
+        WindowEvent::Focused(true) => {
+            FOCUSED_WINDOWS.lock_or_recover().insert(label);
+        }
+        WindowEvent::Focused(false) | WindowEvent::Destroyed => {
+            FOCUSED_WINDOWS.lock_or_recover().remove(&label);
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,119 @@
+// --- Frame Sidecar Index ---
+// Per-screenshot metadata (when, what triggered it, which action folder, where
+// the mouse was) lives in images/index.jsonl, one FrameMeta per line. Image files
+// get opaque names, so labels like "KeyPress_KeyA" no longer have to survive a
+// round trip through split('_').
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::display::MonitorGeometry;
+use crate::elements::ElementInfo;
+use crate::sync::LockExt;
+use crate::window_info::WindowInfo;
+
+pub const INDEX_FILE: &str = "index.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameMeta {
+    pub file: String,          // Image file name, relative to the images dir
+    pub timestamp_ms: u64,     // Capture time, Unix milliseconds
+    pub action: String,        // Input event that triggered the capture, e.g. "MousePress"
+    pub action_folder: String, // Recording action folder the frame belongs to
+    pub mouse: Option<(i32, i32)>,
+    #[serde(default)]
+    pub display_generation: u64, // Bumped on every monitor layout change
+    #[serde(default)]
+    pub display: Option<MonitorGeometry>, // Primary monitor the frame was captured from
+    #[serde(default)]
+    pub sensitive: bool, // Captured during secure (password) text entry
+    #[serde(default)]
+    pub element: Option<ElementInfo>, // Accessible element under the mouse, if the OS exposes one
+    #[serde(default)]
+    pub device: Option<String>, // adb serial ("" = default device) for Android recordings
+    #[serde(default)]
+    pub layout: Option<String>, // Keyboard layout key labels were produced with (layout.rs)
+    #[serde(default)]
+    pub session: Option<String>, // Recording session ID, for artifact provenance
+    #[serde(default)]
+    pub window: Option<WindowInfo>, // Focused window when the frame was captured (window_info.rs)
+    #[serde(default)]
+    pub hash: Option<String>, // Perceptual hash of the screen (dedup.rs)
+    #[serde(default)]
+    pub duplicate: bool, // Same screen as the frame before it; `file` is that frame's image
+}
+
+// Capture threads run concurrently; serialize index writes so lines never interleave
+static INDEX_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+static FRAME_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A unique image file name for a frame captured at `timestamp_ms`, encoded as `extension`.
+pub fn frame_file_name(timestamp_ms: u64, extension: &str) -> String {
+    format!("frame_{}_{}.{}", timestamp_ms, FRAME_SEQ.fetch_add(1, Ordering::Relaxed), extension)
+}
+
+/// Appends one frame's metadata to the index in `images_dir`.
+pub fn append(images_dir: &Path, meta: &FrameMeta) -> io::Result<()> {
+    let mut line = serde_json::to_string(meta)?;
+    line.push('\n');
+
+    let _guard = INDEX_LOCK.lock_or_recover();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(images_dir.join(INDEX_FILE))?;
+    file.write_all(line.as_bytes())
+}
+
+/// Reads every frame in the index, oldest first. Malformed lines are skipped with a warning.
+pub fn load(images_dir: &Path) -> io::Result<Vec<FrameMeta>> {
+    let path = images_dir.join(INDEX_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = {
+        let _guard = INDEX_LOCK.lock_or_recover();
+        fs::read_to_string(&path)?
+    };
+    let mut frames: Vec<FrameMeta> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(meta) => Some(meta),
+            Err(e) => {
+                warn!("Skipping malformed frame index line: {}", e);
+                None
+            }
+        })
+        .collect();
+    frames.sort_by_key(|meta| meta.timestamp_ms);
+    Ok(frames)
+}
+
+/// Replaces the index with `frames` (e.g. the ones left after processing).
+pub fn rewrite(images_dir: &Path, frames: &[FrameMeta]) -> io::Result<()> {
+    let mut content = String::new();
+    for meta in frames {
+        content.push_str(&serde_json::to_string(meta)?);
+        content.push('\n');
+    }
+
+    let _guard = INDEX_LOCK.lock_or_recover();
+    let path = images_dir.join(INDEX_FILE);
+    if content.is_empty() {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    } else {
+        fs::write(path, content)
+    }
+}
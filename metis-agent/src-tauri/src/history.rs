@@ -0,0 +1,148 @@
+// --- Session History ---
+// Lets the frontend browse past recordings. A session's ID is its action folder
+// under encrypted_csv (what the database calls its location). Names, creation
+// times and processed-frame timestamps come from the session database; folders
+// the database doesn't know about (e.g. from before it existed) are listed too,
+// named after the folder.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+use tauri::State;
+use tracing::info;
+
+use crate::error::MetisError;
+use crate::keystore;
+use crate::recorder::{self, SharedRecordingState};
+use crate::retention;
+use crate::storage::{self, SessionRecord};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub id: String,   // Action folder name
+    pub name: String, // The query the session was saved under
+    pub recording_id: Option<String>,
+    pub created_ms: u64,
+    pub frame_count: usize, // Processed frames
+    pub duration_ms: u64,   // First to last processed frame
+    pub disk_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionDetail {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    pub encrypted: bool,    // Files are sealed; decrypt_session reads them
+    pub files: Vec<String>, // Everything in the action folder, sorted
+}
+
+fn sessions_dir(base: &Path) -> PathBuf {
+    base.join("encrypted_csv")
+}
+
+fn validate_id(id: &str) -> Result<(), MetisError> {
+    if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+        return Err(MetisError::InvalidArgument(format!("Invalid session ID '{}'", id)));
+    }
+    Ok(())
+}
+
+fn summarize(base: &Path, record: SessionRecord) -> SessionSummary {
+    let duration_ms = match (record.first_frame_ms, record.last_frame_ms) {
+        (Some(first), Some(last)) => (last - first).max(0) as u64,
+        _ => 0,
+    };
+    SessionSummary {
+        disk_bytes: retention::size_of(&sessions_dir(base).join(&record.location)),
+        id: record.location,
+        name: record.query,
+        recording_id: record.recording_id,
+        created_ms: record.created_ms.max(0) as u64,
+        frame_count: record.frame_count,
+        duration_ms,
+    }
+}
+
+/// An action folder with no database row.
+fn unindexed(folder: &Path) -> SessionSummary {
+    let id = folder.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let created_ms = fs::metadata(folder)
+        .and_then(|m| m.created().or_else(|_| m.modified()))
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64);
+    SessionSummary {
+        name: id.clone(),
+        id,
+        recording_id: None,
+        created_ms,
+        frame_count: 0,
+        duration_ms: 0,
+        disk_bytes: retention::size_of(folder),
+    }
+}
+
+fn all_sessions(base: &Path) -> Result<Vec<SessionSummary>, MetisError> {
+    let db = storage::open(base)?;
+    let mut sessions: Vec<SessionSummary> = storage::list_sessions(&db)?
+        .into_iter()
+        // Rows whose folder is gone (deleted by hand) are not worth showing
+        .filter(|record| sessions_dir(base).join(&record.location).is_dir())
+        .map(|record| summarize(base, record))
+        .collect();
+    let known: HashSet<String> = sessions.iter().map(|s| s.id.clone()).collect();
+    if let Ok(entries) = fs::read_dir(sessions_dir(base)) {
+        for path in entries.filter_map(Result::ok).map(|e| e.path()) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            if path.is_dir() && !known.contains(&name) {
+                sessions.push(unindexed(&path));
+            }
+        }
+    }
+    sessions.sort_by(|a, b| b.created_ms.cmp(&a.created_ms).then_with(|| b.id.cmp(&a.id)));
+    Ok(sessions)
+}
+
+/// Every recorded session, newest first.
+#[tauri::command]
+pub fn list_sessions() -> Result<Vec<SessionSummary>, MetisError> {
+    all_sessions(&recorder::get_default_base_folder())
+}
+
+#[tauri::command]
+pub fn get_session(id: String) -> Result<SessionDetail, MetisError> {
+    validate_id(&id)?;
+    let base = recorder::get_default_base_folder();
+    let summary = all_sessions(&base)?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| MetisError::InvalidArgument(format!("No session '{}'", id)))?;
+    let folder = sessions_dir(&base).join(&id);
+    let mut files: Vec<String> = fs::read_dir(&folder)?
+        .filter_map(Result::ok)
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    let encrypted = files.iter().any(|f| f.ends_with(keystore::SEALED_SUFFIX));
+    Ok(SessionDetail { summary, encrypted, files })
+}
+
+/// Deletes a session's folder and its database rows. The recording in progress can't be deleted.
+#[tauri::command]
+pub fn delete_session(recording: State<'_, SharedRecordingState>, id: String) -> Result<(), MetisError> {
+    validate_id(&id)?;
+    if recorder::active_action_folder(&recording).as_deref() == Some(id.as_str()) {
+        return Err(MetisError::State(format!("Session '{}' is still being recorded", id)));
+    }
+    let base = recorder::get_default_base_folder();
+    let folder = sessions_dir(&base).join(&id);
+    if folder.is_dir() {
+        fs::remove_dir_all(&folder)?;
+    }
+    storage::delete_session(&storage::open(&base)?, &id)?;
+    info!("Deleted session {}", id);
+    Ok(())
+}
@@ -0,0 +1,317 @@
+// --- Global Hotkeys ---
+// Key chords the global listener acts on from anywhere, even with the Metis
+// window hidden. settings.hotkeys.record_toggle (Ctrl+Alt+R unless changed)
+// starts a recording, verified at once since the user is already in the app
+// they want to record, or stops the one in progress. Chords bound to skills
+// (bind_skill_hotkey) run the skill, with its parameter defaults, but only while
+// Metis is Idle; otherwise they are ordinary key presses.
+//
+// A chord's keys never reach the recorder. Modifiers that could begin a chord
+// are held back until the next key shows whether they did: the chord's key
+// drops them (and the releases that follow), anything else lets them through
+// first, in order and with their original timestamps.
+
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+
+use rdev::{Event, EventType, Key};
+use tracing::{info, warn};
+
+use crate::app_state::{AppInputState, SharedAppState};
+use crate::config::{self, HotkeySettings, SkillHotkey};
+use crate::error::MetisError;
+use crate::recorder::{self, SharedRecordingState};
+use crate::skills;
+use crate::sync::LockExt;
+
+/// Starts the skill with its parameter defaults and returns the task ID.
+pub type RunSkill = fn(&SharedAppState, &SharedRecordingState, String) -> Result<String, MetisError>;
+
+const CTRL: u8 = 1;
+const ALT: u8 = 2;
+const SHIFT: u8 = 4;
+const META: u8 = 8;
+
+const LETTERS: [Key; 26] = [
+    Key::KeyA, Key::KeyB, Key::KeyC, Key::KeyD, Key::KeyE, Key::KeyF, Key::KeyG, Key::KeyH, Key::KeyI,
+    Key::KeyJ, Key::KeyK, Key::KeyL, Key::KeyM, Key::KeyN, Key::KeyO, Key::KeyP, Key::KeyQ, Key::KeyR,
+    Key::KeyS, Key::KeyT, Key::KeyU, Key::KeyV, Key::KeyW, Key::KeyX, Key::KeyY, Key::KeyZ,
+];
+const DIGITS: [Key; 10] = [
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+];
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+];
+const NAMED_KEYS: &[(&str, Key)] = &[
+    ("space", Key::Space),
+    ("enter", Key::Return),
+    ("return", Key::Return),
+    ("tab", Key::Tab),
+    ("backspace", Key::Backspace),
+    ("delete", Key::Delete),
+    ("insert", Key::Insert),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("up", Key::UpArrow),
+    ("down", Key::DownArrow),
+    ("left", Key::LeftArrow),
+    ("right", Key::RightArrow),
+    ("printscreen", Key::PrintScreen),
+    ("pause", Key::Pause),
+];
+
+fn modifier_bit(key: Key) -> Option<u8> {
+    match key {
+        Key::ControlLeft | Key::ControlRight => Some(CTRL),
+        Key::Alt | Key::AltGr => Some(ALT),
+        Key::ShiftLeft | Key::ShiftRight => Some(SHIFT),
+        Key::MetaLeft | Key::MetaRight => Some(META),
+        _ => None,
+    }
+}
+
+/// Modifiers plus one other key, e.g. "Ctrl+Alt+R".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    modifiers: u8,
+    key: Key,
+}
+
+impl Chord {
+    /// Parses "Ctrl+Shift+F5" and the like, ignoring case. Empty means no chord.
+    pub fn parse(text: &str) -> Result<Option<Chord>, String> {
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        let mut modifiers = 0;
+        let mut key = None;
+        for part in text.split('+').map(|p| p.trim().to_lowercase()) {
+            let bit = match part.as_str() {
+                "ctrl" | "control" => Some(CTRL),
+                "alt" | "option" => Some(ALT),
+                "shift" => Some(SHIFT),
+                "meta" | "super" | "win" | "cmd" | "command" => Some(META),
+                _ => None,
+            };
+            match (bit, key) {
+                (Some(bit), _) => modifiers |= bit,
+                (None, None) => key = Some(key_named(&part).ok_or_else(|| format!("Unknown key '{}' in '{}'", part, text))?),
+                (None, Some(_)) => return Err(format!("'{}' has more than one non-modifier key", text)),
+            }
+        }
+        let key = key.ok_or_else(|| format!("'{}' has no key besides modifiers", text))?;
+        if modifiers == 0 && !FUNCTION_KEYS.contains(&key) {
+            // A bare letter or Enter would fire during ordinary typing
+            return Err(format!("'{}' needs at least one modifier", text));
+        }
+        Ok(Some(Chord { modifiers, key }))
+    }
+}
+
+fn key_named(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return match c {
+            'a'..='z' => Some(LETTERS[(c as u8 - b'a') as usize]),
+            '0'..='9' => Some(DIGITS[(c as u8 - b'0') as usize]),
+            _ => None,
+        };
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return FUNCTION_KEYS.get(n.checked_sub(1)?).copied();
+    }
+    NAMED_KEYS.iter().find(|(n, _)| *n == name).map(|(_, key)| *key)
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (bit, name) in [(CTRL, "Ctrl"), (ALT, "Alt"), (SHIFT, "Shift"), (META, "Meta")] {
+            if self.modifiers & bit != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+/// What a chord does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotkeyAction {
+    ToggleRecording,
+    RunSkill(String), // Skill ID
+}
+
+/// The chords in effect in `state`.
+fn bindings(state: AppInputState) -> Vec<(Chord, HotkeyAction)> {
+    let settings = config::get().hotkeys;
+    let mut bindings = Vec::new();
+    match Chord::parse(&settings.record_toggle) {
+        Ok(chord) => bindings.extend(chord.map(|chord| (chord, HotkeyAction::ToggleRecording))),
+        Err(e) => warn!("Recording hotkey disabled: {}", e),
+    }
+    if state == AppInputState::Idle {
+        for binding in settings.skills {
+            match Chord::parse(&binding.chord) {
+                Ok(chord) => bindings.extend(chord.map(|chord| (chord, HotkeyAction::RunSkill(binding.skill_id)))),
+                Err(e) => warn!("Hotkey for skill {} disabled: {}", binding.skill_id, e),
+            }
+        }
+    }
+    bindings
+}
+
+pub enum Filtered {
+    Events(Vec<Event>), // Pass these on, in order
+    Chord(HotkeyAction),
+}
+
+/// Tracks held modifiers and keeps chord keys out of the event stream.
+#[derive(Default)]
+pub struct ChordFilter {
+    held: u8,
+    pending: Vec<Event>,  // Modifier presses that may begin a chord
+    swallowed: Vec<Key>, // Keys of a fired chord whose repeats and releases are dropped
+}
+
+impl ChordFilter {
+    fn release_pending(&mut self, event: Event) -> Filtered {
+        let mut events = std::mem::take(&mut self.pending);
+        events.push(event);
+        Filtered::Events(events)
+    }
+
+    /// Filters `event`, which arrived in `state`.
+    pub fn feed(&mut self, event: Event, state: AppInputState) -> Filtered {
+        match event.event_type {
+            EventType::KeyPress(key) if self.swallowed.contains(&key) => Filtered::Events(Vec::new()), // Auto-repeat
+            EventType::KeyRelease(key) if self.swallowed.contains(&key) => {
+                self.swallowed.retain(|k| *k != key);
+                if let Some(bit) = modifier_bit(key) {
+                    self.held &= !bit;
+                }
+                Filtered::Events(Vec::new())
+            }
+            EventType::KeyPress(key) => match modifier_bit(key) {
+                Some(bit) => {
+                    self.held |= bit;
+                    if bindings(state).iter().any(|(chord, _)| chord.modifiers & bit != 0) {
+                        self.pending.push(event);
+                        Filtered::Events(Vec::new())
+                    } else {
+                        self.release_pending(event)
+                    }
+                }
+                None => match bindings(state).into_iter().find(|(chord, _)| chord.key == key && chord.modifiers == self.held) {
+                    Some((_, action)) => {
+                        self.swallowed.push(key);
+                        for held in self.pending.drain(..) {
+                            if let EventType::KeyPress(modifier) = held.event_type {
+                                if !self.swallowed.contains(&modifier) {
+                                    self.swallowed.push(modifier);
+                                }
+                            }
+                        }
+                        Filtered::Chord(action)
+                    }
+                    None => self.release_pending(event),
+                },
+            },
+            EventType::KeyRelease(key) => {
+                if let Some(bit) = modifier_bit(key) {
+                    self.held &= !bit;
+                }
+                self.release_pending(event)
+            }
+            EventType::ButtonPress(_) | EventType::ButtonRelease(_) | EventType::Wheel { .. } => self.release_pending(event),
+            EventType::MouseMove { .. } => Filtered::Events(vec![event]),
+        }
+    }
+}
+
+fn toggle_recording(app_state: &SharedAppState, recording: &SharedRecordingState) {
+    let state = app_state.lock_or_recover().input_state();
+    let result = match state {
+        AppInputState::Idle => recorder::start(app_state, recording, None).and_then(|_| recorder::verify(app_state, recording)),
+        AppInputState::Recording => recorder::stop(app_state, recording),
+        other => {
+            info!("Recording hotkey ignored while {:?}", other);
+            return;
+        }
+    };
+    match result {
+        Ok(message) => info!("Recording hotkey: {}", message),
+        Err(e) => warn!("Recording hotkey failed: {}", e),
+    }
+}
+
+/// Carries out a chord's action off the listener thread.
+pub fn dispatch(action: HotkeyAction, app_state: &SharedAppState, recording: &SharedRecordingState, run_skill: RunSkill) {
+    let (app_state, recording) = (Arc::clone(app_state), Arc::clone(recording));
+    thread::spawn(move || match action {
+        HotkeyAction::ToggleRecording => toggle_recording(&app_state, &recording),
+        HotkeyAction::RunSkill(skill_id) => match run_skill(&app_state, &recording, skill_id.clone()) {
+            Ok(task_id) => info!("Skill hotkey: started skill {} as {}", skill_id, task_id),
+            Err(e) => warn!("Skill hotkey: skill {} failed to start: {}", skill_id, e),
+        },
+    });
+}
+
+/// Checks every chord in `settings` and that no two share a chord.
+fn validate(settings: &HotkeySettings) -> Result<(), String> {
+    let mut seen = Vec::new();
+    let chords = std::iter::once(settings.record_toggle.as_str()).chain(settings.skills.iter().map(|b| b.chord.as_str()));
+    for text in chords {
+        if let Some(chord) = Chord::parse(text)? {
+            if seen.contains(&chord) {
+                return Err(format!("{} is bound twice", chord));
+            }
+            seen.push(chord);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_hotkey_settings() -> Result<HotkeySettings, String> {
+    Ok(config::get().hotkeys)
+}
+
+#[tauri::command]
+pub fn update_hotkey_settings(settings: HotkeySettings) -> Result<HotkeySettings, String> {
+    validate(&settings)?;
+    let chord = Chord::parse(&settings.record_toggle)?;
+    info!("Recording hotkey: {}", chord.map_or("off".to_string(), |chord| chord.to_string()));
+    config::update(|s| s.hotkeys = settings).map(|s| s.hotkeys)
+}
+
+/// Runs the skill whenever `chord` is pressed while Metis is idle. A chord
+/// already bound to another skill is rebound.
+#[tauri::command]
+pub fn bind_skill_hotkey(skill_id: String, chord: String) -> Result<HotkeySettings, MetisError> {
+    let skill = skills::find(&skill_id)?;
+    let parsed = Chord::parse(&chord)
+        .map_err(MetisError::InvalidArgument)?
+        .ok_or_else(|| MetisError::InvalidArgument("A hotkey needs a chord".to_string()))?;
+    let mut hotkeys = config::get().hotkeys;
+    hotkeys.skills.retain(|b| Chord::parse(&b.chord).ok().flatten() != Some(parsed));
+    hotkeys.skills.push(SkillHotkey { chord: chord.trim().to_string(), skill_id });
+    validate(&hotkeys).map_err(MetisError::InvalidArgument)?;
+    info!("Hotkey {} runs skill '{}'", parsed, skill.name);
+    config::update(|s| s.hotkeys = hotkeys).map(|s| s.hotkeys).map_err(MetisError::State)
+}
+
+#[tauri::command]
+pub fn unbind_skill_hotkey(chord: String) -> Result<HotkeySettings, MetisError> {
+    let parsed = Chord::parse(&chord).map_err(MetisError::InvalidArgument)?;
+    let mut hotkeys = config::get().hotkeys;
+    let before = hotkeys.skills.len();
+    hotkeys.skills.retain(|b| Chord::parse(&b.chord).ok().flatten() != parsed);
+    if hotkeys.skills.len() == before {
+        return Err(MetisError::InvalidArgument(format!("No skill is bound to '{}'", chord)));
+    }
+    config::update(|s| s.hotkeys = hotkeys).map(|s| s.hotkeys).map_err(MetisError::State)
+}
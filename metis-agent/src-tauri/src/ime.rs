@@ -0,0 +1,101 @@
+// --- IME-Aware Text Entry ---
+// Synthesized key events can't compose CJK text: an active IME swallows or
+// re-composes them, and some platforms can't map ideographs to any key at all.
+// Text that needs an IME (or that the key path fails on) is pasted instead:
+// the clipboard is set, read back to verify nothing rewrote it, pasted with
+// the platform shortcut, and then restored to what the user had there. Text and
+// images are restored; other formats (copied files, the rich-text or HTML form
+// that often sits beside copied text) can't be read through arboard, so after a
+// paste the clipboard holds only the plain text, the image, or nothing.
+
+use std::thread;
+use std::time::Duration;
+
+use arboard::{Clipboard, ImageData};
+use enigo::{Direction, Key};
+use tracing::error;
+
+use crate::error::ActionError;
+use crate::input::InputBackend;
+
+// Pasting is asynchronous in the target app; restoring too early pastes the old contents
+const PASTE_SETTLE: Duration = Duration::from_millis(300);
+
+/// Whether `c` is normally entered through an IME rather than typed directly.
+fn needs_ime(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF     // Hangul Jamo
+        | 0x2E80..=0x2FDF   // CJK radicals, Kangxi
+        | 0x3000..=0x303F   // CJK symbols and punctuation
+        | 0x3040..=0x30FF   // Hiragana, Katakana
+        | 0x3100..=0x318F   // Bopomofo, Hangul compatibility Jamo
+        | 0x31A0..=0x31FF   // Bopomofo extended, Katakana extensions
+        | 0x3400..=0x4DBF   // CJK extension A
+        | 0x4E00..=0x9FFF   // CJK unified ideographs
+        | 0xA960..=0xA97F   // Hangul Jamo extended-A
+        | 0xAC00..=0xD7FF   // Hangul syllables, Jamo extended-B
+        | 0xF900..=0xFAFF   // CJK compatibility ideographs
+        | 0xFF00..=0xFFEF   // Half/fullwidth forms
+        | 0x20000..=0x3FFFF // Supplementary ideographic planes
+    )
+}
+
+/// Whether `text` should go through the clipboard rather than key events.
+pub fn needs_paste(text: &str) -> bool {
+    text.chars().any(needs_ime)
+}
+
+fn clipboard_error(message: impl std::fmt::Display) -> ActionError {
+    ActionError::TextEntry(format!("Clipboard: {}", message))
+}
+
+/// What the user had on the clipboard before a paste, as far as it can be read.
+enum Saved {
+    Text(String),
+    Image(ImageData<'static>),
+    Nothing, // Empty, or only formats that can't be read back (see the module comment)
+}
+
+fn save(clipboard: &mut Clipboard) -> Saved {
+    if let Ok(text) = clipboard.get_text() {
+        return Saved::Text(text);
+    }
+    match clipboard.get_image() {
+        Ok(image) => Saved::Image(image),
+        Err(_) => Saved::Nothing,
+    }
+}
+
+/// Enters `text` by pasting it through the clipboard, using `input` for the shortcut.
+pub fn paste(input: &mut dyn InputBackend, text: &str) -> Result<(), ActionError> {
+    // Kept alive until restored: on X11 the clipboard contents live in this process
+    let mut clipboard = Clipboard::new().map_err(clipboard_error)?;
+    let previous = save(&mut clipboard);
+
+    clipboard.set_text(text).map_err(clipboard_error)?;
+    // Clipboard managers and remote sessions can rewrite what was set
+    if clipboard.get_text().map_err(clipboard_error)? != text {
+        restore(&mut clipboard, previous);
+        return Err(clipboard_error("contents changed before they could be pasted"));
+    }
+
+    let modifier = if cfg!(target_os = "macos") { Key::Meta } else { Key::Control };
+    let result = input.key(modifier, Direction::Press).and_then(|_| {
+        let pasted = input.key(Key::Unicode('v'), Direction::Click);
+        input.key(modifier, Direction::Release).and(pasted)
+    });
+    thread::sleep(PASTE_SETTLE);
+    restore(&mut clipboard, previous);
+    result
+}
+
+fn restore(clipboard: &mut Clipboard, previous: Saved) {
+    let restored = match previous {
+        Saved::Text(text) => clipboard.set_text(text),
+        Saved::Image(image) => clipboard.set_image(image),
+        Saved::Nothing => clipboard.clear(),
+    };
+    if let Err(e) = restored {
+        error!("Failed to restore the clipboard after pasting: {}", e);
+    }
+}
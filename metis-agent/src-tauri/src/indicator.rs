@@ -0,0 +1,72 @@
+// --- On-Screen Activity Indicator ---
+// A small always-on-top window is shown whenever Metis is capturing (red,
+// "Recording") or driving the mouse and keyboard (amber, "Agent running"; grey
+// while the task is paused), and hidden when idle. It is created hidden during setup and only shown/hidden
+// afterwards, because building windows from synchronous commands can deadlock
+// on Windows. It is content-protected, so it never appears in our own captures.
+
+use once_cell::sync::OnceCell;
+use tauri::{AppHandle, LogicalPosition, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+use tracing::error;
+
+use crate::app_state::AppInputState;
+
+const INDICATOR_LABEL: &str = "indicator";
+const WIDTH: f64 = 150.0;
+const HEIGHT: f64 = 34.0;
+const MARGIN: f64 = 16.0;
+
+static INDICATOR: OnceCell<WebviewWindow> = OnceCell::new();
+
+/// Creates the (hidden) indicator window; called once from the Tauri setup hook.
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let window = WebviewWindowBuilder::new(app, INDICATOR_LABEL, WebviewUrl::App("indicator.html".into()))
+        .title("Metis")
+        .inner_size(WIDTH, HEIGHT)
+        .resizable(false)
+        .decorations(false)
+        .always_on_top(true)
+        .visible_on_all_workspaces(true)
+        .skip_taskbar(true)
+        .focused(false)
+        .content_protected(true)
+        .visible(false)
+        .build()?;
+
+    // Top-right corner of the primary monitor
+    if let Ok(Some(monitor)) = app.primary_monitor() {
+        let size = monitor.size().to_logical::<f64>(monitor.scale_factor());
+        let origin = monitor.position().to_logical::<f64>(monitor.scale_factor());
+        let _ = window.set_position(LogicalPosition::new(origin.x + size.width - WIDTH - MARGIN, origin.y + MARGIN));
+    }
+
+    let _ = INDICATOR.set(window);
+    Ok(())
+}
+
+/// Shows the indicator matching `state`, or hides it when idle.
+pub fn update(state: AppInputState) {
+    let Some(window) = INDICATOR.get() else {
+        return; // Not set up yet (or headless)
+    };
+    let mode = match state {
+        AppInputState::Idle => {
+            let _ = window.hide();
+            return;
+        }
+        AppInputState::Recording => "recording",
+        AppInputState::ExecutingAction => "executing",
+        AppInputState::Paused => "paused",
+    };
+    if let Err(e) = window.eval(&format!("window.setMode && window.setMode('{}')", mode)) {
+        error!("Failed to update indicator: {}", e);
+    }
+    if let Err(e) = window.show() {
+        error!("Failed to show indicator: {}", e);
+    }
+}
+
+/// Whether `label` is the indicator window (it should never count as "Metis in front").
+pub fn is_indicator(label: &str) -> bool {
+    label == INDICATOR_LABEL
+}
@@ -0,0 +1,303 @@
+// --- Input Injection Backends ---
+// do_action and execute_task_loop drive input through InputBackend rather than
+// enigo directly. EnigoBackend is what the app uses; MockInput records calls
+// instead of touching the real mouse/keyboard so the executor can run headless.
+// Other injectors (ydotool, an AutoHotkey bridge, ...) only need this trait.
+
+use std::thread;
+use std::time::Duration;
+
+use enigo::{Axis, Button, Coordinate, Direction, Enigo, Key, Keyboard, Mouse, Settings};
+use tracing::error;
+
+use crate::cdp::BrowserOp;
+use crate::error::ActionError;
+use crate::elements::{self, Activation};
+use crate::ime;
+use crate::launcher;
+use crate::window_control::{self, WindowOp};
+#[cfg(target_os = "windows")]
+use crate::touch;
+
+// Control+wheel notches per doubling of the zoom level in the pinch fallback
+const ZOOM_NOTCHES_PER_DOUBLING: f32 = 3.0;
+
+pub trait InputBackend {
+    fn name(&self) -> &'static str;
+    /// Moves the pointer to absolute screen coordinates.
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError>;
+    /// Presses, releases or clicks the left mouse button.
+    fn left_button(&mut self, direction: Direction) -> Result<(), ActionError>;
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError>;
+    fn text(&mut self, text: &str) -> Result<(), ActionError>;
+    /// Scrolls vertically; positive is down.
+    fn scroll(&mut self, units: i32) -> Result<(), ActionError>;
+    /// Scrolls horizontally; positive is right.
+    fn hscroll(&mut self, units: i32) -> Result<(), ActionError>;
+    /// Size of the main display in pixels.
+    fn main_display(&self) -> Result<(i32, i32), ActionError>;
+
+    /// Presses the named UI element in the foreground window via the accessibility API,
+    /// clicking its center when it can't be invoked directly.
+    fn activate_element(&mut self, name: &str, control_type: Option<&str>) -> Result<(), ActionError> {
+        match elements::activate(name, control_type)? {
+            Activation::Invoked => Ok(()),
+            Activation::Click(x, y) => {
+                self.move_mouse(x, y)?;
+                self.left_button(Direction::Click)
+            }
+        }
+    }
+
+    /// Focuses, minimizes or maximizes a top-level window by title (window_control.rs).
+    fn control_window(&mut self, title: &str, op: WindowOp) -> Result<(), ActionError> {
+        window_control::control(title, op)
+    }
+
+    /// Starts an allowlisted program, or opens a link or file with its default handler.
+    fn launch(&mut self, target: &str) -> Result<(), ActionError> {
+        launcher::launch(target)
+    }
+
+    /// Navigates, clicks or fills by selector in the browser; only the DevTools
+    /// bridge (cdp.rs) can.
+    fn browser(&mut self, _op: &BrowserOp) -> Result<(), ActionError> {
+        Err(ActionError::Browser("No DevTools-enabled browser is available; use coordinates instead".to_string()))
+    }
+
+    // Touch gestures. The defaults emulate them with the mouse for backends
+    // without touch injection; backends that can inject touches override them.
+
+    /// Touches and holds at a point for `hold`.
+    fn long_press(&mut self, x: i32, y: i32, hold: Duration) -> Result<(), ActionError> {
+        // Toolkits with touch support treat a held left button the same way
+        self.move_mouse(x, y)?;
+        self.left_button(Direction::Press)?;
+        thread::sleep(hold);
+        self.left_button(Direction::Release)
+    }
+
+    /// Two-finger pinch centered on a point; `scale` above 1 zooms in.
+    fn pinch(&mut self, x: i32, y: i32, scale: f32) -> Result<(), ActionError> {
+        // Control+wheel is the desktop equivalent; wheel up zooms in
+        let notches = (scale.log2() * ZOOM_NOTCHES_PER_DOUBLING).round() as i32;
+        self.move_mouse(x, y)?;
+        self.key(Key::Control, Direction::Press)?;
+        let zoomed = self.scroll(-notches);
+        self.key(Key::Control, Direction::Release).and(zoomed)
+    }
+
+    /// Two-finger scroll at a point; positive is down.
+    fn two_finger_scroll(&mut self, x: i32, y: i32, units: i32) -> Result<(), ActionError> {
+        self.move_mouse(x, y)?;
+        self.scroll(units)
+    }
+}
+
+pub struct EnigoBackend(Enigo);
+
+impl EnigoBackend {
+    pub fn new() -> Result<EnigoBackend, String> {
+        Enigo::new(&Settings::default())
+            .map(EnigoBackend)
+            .map_err(|e| format!("Failed to initialize Enigo: {}", e))
+    }
+}
+
+/// Types `text` one character at a time, so a failure partway through says how
+/// far it got: the byte offset of the first character not typed, and the error.
+fn type_chars<E>(text: &str, mut type_char: impl FnMut(&str) -> Result<(), E>) -> Result<(), (usize, E)> {
+    let mut buf = [0; 4];
+    for (at, c) in text.char_indices() {
+        type_char(c.encode_utf8(&mut buf)).map_err(|e| (at, e))?;
+    }
+    Ok(())
+}
+
+impl InputBackend for EnigoBackend {
+    fn name(&self) -> &'static str {
+        "enigo"
+    }
+
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError> {
+        Ok(self.0.move_mouse(x, y, Coordinate::Abs)?)
+    }
+
+    fn left_button(&mut self, direction: Direction) -> Result<(), ActionError> {
+        Ok(self.0.button(Button::Left, direction)?)
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError> {
+        Ok(self.0.key(key, direction)?)
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), ActionError> {
+        // IME languages are pasted; anything else is typed, pasting only what typing didn't get to
+        if ime::needs_paste(text) {
+            return ime::paste(self, text);
+        }
+        let enigo = &mut self.0;
+        if let Err((at, e)) = type_chars(text, |c| enigo.text(c)) {
+            error!("Typing failed after {} of {} characters ({}); pasting the rest.", text[..at].chars().count(), text.chars().count(), e);
+            return ime::paste(self, &text[at..]);
+        }
+        Ok(())
+    }
+
+    fn scroll(&mut self, units: i32) -> Result<(), ActionError> {
+        Ok(self.0.scroll(units, Axis::Vertical)?)
+    }
+
+    fn hscroll(&mut self, units: i32) -> Result<(), ActionError> {
+        Ok(self.0.scroll(units, Axis::Horizontal)?)
+    }
+
+    fn main_display(&self) -> Result<(i32, i32), ActionError> {
+        Ok(self.0.main_display()?)
+    }
+
+    // Windows can inject real touch contacts; elsewhere the mouse fallbacks apply
+
+    #[cfg(target_os = "windows")]
+    fn long_press(&mut self, x: i32, y: i32, hold: Duration) -> Result<(), ActionError> {
+        touch::long_press(x, y, hold)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn pinch(&mut self, x: i32, y: i32, scale: f32) -> Result<(), ActionError> {
+        touch::pinch(x, y, scale)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn two_finger_scroll(&mut self, x: i32, y: i32, units: i32) -> Result<(), ActionError> {
+        touch::two_finger_scroll(x, y, units)
+    }
+}
+
+/// One call made against a MockInput.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputCall {
+    MoveMouse(i32, i32),
+    LeftButton(Direction),
+    Key(Key, Direction),
+    Text(String),
+    Scroll(i32),
+    HScroll(i32),
+    ActivateElement(String, Option<String>),
+    ControlWindow(String, WindowOp),
+    Launch(String),
+    LongPress(i32, i32, Duration),
+    Pinch(i32, i32, f32),
+    TwoFingerScroll(i32, i32, i32),
+}
+
+/// Records every call instead of injecting it, for headless runs of the executor
+/// and for dry runs.
+pub struct MockInput {
+    pub display: (i32, i32),
+    pub calls: Vec<InputCall>,
+}
+
+impl MockInput {
+    pub fn new(width: i32, height: i32) -> Self {
+        MockInput { display: (width, height), calls: Vec::new() }
+    }
+}
+
+impl InputBackend for MockInput {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError> {
+        self.calls.push(InputCall::MoveMouse(x, y));
+        Ok(())
+    }
+
+    fn left_button(&mut self, direction: Direction) -> Result<(), ActionError> {
+        self.calls.push(InputCall::LeftButton(direction));
+        Ok(())
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError> {
+        self.calls.push(InputCall::Key(key, direction));
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), ActionError> {
+        self.calls.push(InputCall::Text(text.to_string()));
+        Ok(())
+    }
+
+    fn scroll(&mut self, units: i32) -> Result<(), ActionError> {
+        self.calls.push(InputCall::Scroll(units));
+        Ok(())
+    }
+
+    fn hscroll(&mut self, units: i32) -> Result<(), ActionError> {
+        self.calls.push(InputCall::HScroll(units));
+        Ok(())
+    }
+
+    fn main_display(&self) -> Result<(i32, i32), ActionError> {
+        Ok(self.display)
+    }
+
+    fn activate_element(&mut self, name: &str, control_type: Option<&str>) -> Result<(), ActionError> {
+        self.calls.push(InputCall::ActivateElement(name.to_string(), control_type.map(str::to_string)));
+        Ok(())
+    }
+
+    fn control_window(&mut self, title: &str, op: WindowOp) -> Result<(), ActionError> {
+        self.calls.push(InputCall::ControlWindow(title.to_string(), op));
+        Ok(())
+    }
+
+    fn launch(&mut self, target: &str) -> Result<(), ActionError> {
+        self.calls.push(InputCall::Launch(target.to_string()));
+        Ok(())
+    }
+
+    fn long_press(&mut self, x: i32, y: i32, hold: Duration) -> Result<(), ActionError> {
+        self.calls.push(InputCall::LongPress(x, y, hold));
+        Ok(())
+    }
+
+    fn pinch(&mut self, x: i32, y: i32, scale: f32) -> Result<(), ActionError> {
+        self.calls.push(InputCall::Pinch(x, y, scale));
+        Ok(())
+    }
+
+    fn two_finger_scroll(&mut self, x: i32, y: i32, units: i32) -> Result<(), ActionError> {
+        self.calls.push(InputCall::TwoFingerScroll(x, y, units));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typing_reports_where_it_stopped() {
+        let mut typed = String::new();
+        let result = type_chars("héllo", |c| {
+            if typed.chars().count() == 3 {
+                return Err("stuck");
+            }
+            typed.push_str(c);
+            Ok(())
+        });
+        assert_eq!(typed, "hél");
+        let (at, e) = result.unwrap_err();
+        assert_eq!((&"héllo"[at..], e), ("lo", "stuck"));
+
+        typed.clear();
+        assert!(type_chars("ok", |c| {
+            typed.push_str(c);
+            Ok::<_, ()>(())
+        })
+        .is_ok());
+        assert_eq!(typed, "ok");
+    }
+}
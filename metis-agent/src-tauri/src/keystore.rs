@@ -0,0 +1,932 @@
+// --- Encryption Key Storage ---
+// The encryption password crosses IPC once, in `set_encryption_password`. It is
+// turned into a key with Argon2id (salt, cost parameters and a check value live
+// in <base>/salt/kdf.json) and the key is kept in memory for the rest of the run.
+// Parameters saved with PBKDF2 before that are moved to Argon2id the next time
+// the password is entered.
+// If the user opts in with `remember`, the derived key (never the password) is
+// also stored in the OS keyring (Keychain on macOS, the Secret Service on Linux,
+// DPAPI on Windows) so later runs unlock without asking again.
+//
+// That master key never encrypts recordings directly. Each recording session
+// gets its own random data key, stored in its action folder wrapped (sealed) by
+// the master key as `<session id>.key`, and every file sealed with it starts with
+// that ID. (Folders recorded before keys were per session share a `session.key`,
+// and their files carry no ID.) Leaking one session's data key exposes only that
+// session, and rotating or changing the master key only re-wraps the small key
+// files instead of re-encrypting the whole archive. Processed CSVs are sealed
+// with the data key as `<name>.csv.enc`, bound to their name and key ID so sealed
+// files can't be swapped for one another; `decrypt_session` reads them back. Once
+// a password is set nothing is written in plaintext: without the key unlocked,
+// `sealing_key` refuses.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use tracing::{info, warn};
+
+use crate::crypto::{self, Kdf, Key, KEY_LEN, PBKDF2_ITERATIONS, SALT_LEN};
+use crate::error::CryptoError;
+use crate::recorder;
+use crate::sync::LockExt;
+
+const CHECK_PLAINTEXT: &[u8] = b"metis-key-check";
+/// Session data keys are stored as `<session id>.key` in their action folder.
+pub const KEY_SUFFIX: &str = ".key";
+/// ID of the one key shared by folders recorded before keys were per session
+/// (`session.key`). Their sealed files carry no key ID.
+const LEGACY_KEY_ID: &str = "session";
+/// Appended to the name of every file sealed with a session data key.
+pub const SEALED_SUFFIX: &str = ".enc";
+// Sealed files start with this, then the length and ID of the key that sealed them
+const SEALED_MAGIC: &[u8] = b"MTS1";
+// A key file's previous wrapping, kept while a rotation replaces it
+const OLD_SUFFIX: &str = ".old";
+// A key file's new wrapping, staged before a rotation swaps it in
+const REWRAP_SUFFIX: &str = ".rewrap";
+
+/// Everything needed to re-derive and verify the key except the password itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KdfParams {
+    salt: String, // hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<Kdf>,
+    // Parameters from before Argon2id have only this, the PBKDF2 round count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    iterations: Option<u32>,
+    check: String, // hex, CHECK_PLAINTEXT sealed under the derived key
+}
+
+impl KdfParams {
+    fn kdf(&self) -> Kdf {
+        self.kdf.unwrap_or(Kdf::Pbkdf2Sha256 { iterations: self.iterations.unwrap_or(PBKDF2_ITERATIONS) })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncryptionKeyStatus {
+    pub configured: bool, // A password has been set at some point
+    pub unlocked: bool,   // The key is available without asking (memory or keyring)
+}
+
+/// One recording session's data key.
+pub struct SessionKey {
+    id: String,
+    key: Key,
+}
+
+static SESSION_KEY: Lazy<Mutex<Option<Key>>> = Lazy::new(|| Mutex::new(None));
+
+fn kdf_params_path(base: &Path) -> PathBuf {
+    base.join("salt").join("kdf.json")
+}
+
+fn load_params(base: &Path) -> Result<Option<KdfParams>, CryptoError> {
+    let path = kdf_params_path(base);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| CryptoError::Crypto(format!("Invalid key parameters in {}: {}", path.display(), e)))
+}
+
+fn save_params(base: &Path, params: &KdfParams) -> Result<(), CryptoError> {
+    let path = kdf_params_path(base);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(params).expect("KDF params serialize");
+    write_atomically(&path, json.as_bytes())
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), CryptoError> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn new_params(password: &str, kdf: Kdf) -> Result<(KdfParams, Key), CryptoError> {
+    let salt = crypto::random_bytes::<SALT_LEN>()?;
+    let key = crypto::derive_key(password, &salt, kdf)?;
+    let check = crypto::seal(&key, CHECK_PLAINTEXT)?;
+    let params = KdfParams { salt: hex::encode(salt), kdf: Some(kdf), iterations: None, check: hex::encode(check) };
+    Ok((params, key))
+}
+
+fn key_matches(params: &KdfParams, key: &Key) -> bool {
+    hex::decode(&params.check)
+        .ok()
+        .and_then(|check| crypto::open(key, &check).ok())
+        .is_some_and(|plain| plain.as_slice() == CHECK_PLAINTEXT)
+}
+
+fn unlock(params: &KdfParams, password: &str) -> Result<Key, CryptoError> {
+    let salt = hex::decode(&params.salt).map_err(|e| CryptoError::Crypto(format!("Invalid salt: {}", e)))?;
+    let key = crypto::derive_key(password, &salt, params.kdf())?;
+    if key_matches(params, &key) {
+        Ok(key)
+    } else {
+        Err(CryptoError::WrongPassword)
+    }
+}
+
+fn key_from_bytes(bytes: &[u8]) -> Option<Key> {
+    Some(Key(bytes.try_into().ok()?))
+}
+
+fn key_from_hex(encoded: &str) -> Option<Key> {
+    key_from_bytes(&Zeroizing::new(hex::decode(encoded.trim()).ok()?))
+}
+
+fn key_to_hex(key: &Key) -> Zeroizing<String> {
+    Zeroizing::new(hex::encode(key.0))
+}
+
+/// Keeps `key` for the rest of the run, first finishing any rotation a crash interrupted.
+fn keep_unlocked(base: &Path, key: Key) {
+    recover_rotation(base, &key);
+    *SESSION_KEY.lock_or_recover() = Some(key);
+}
+
+/// The key for this run: from memory, else from the OS keyring if the user opted in.
+pub fn current_key() -> Result<Option<Key>, CryptoError> {
+    if let Some(key) = SESSION_KEY.lock_or_recover().clone() {
+        return Ok(Some(key));
+    }
+    let base = recorder::get_default_base_folder();
+    let Some(params) = load_params(&base)? else {
+        return Ok(None); // No password set yet
+    };
+    let Some(stored) = keyring::load()? else {
+        return Ok(None);
+    };
+    match key_from_hex(&stored).filter(|key| key_matches(&params, key)) {
+        Some(key) => {
+            keep_unlocked(&base, key.clone());
+            Ok(Some(key))
+        }
+        None => {
+            // Left over from before a password change on another install, or corrupted
+            warn!("Keyring entry does not match the current encryption key; ignoring it.");
+            Ok(None)
+        }
+    }
+}
+
+/// The master key new data is sealed under. `None` only while no password has
+/// ever been set: once one is, a locked key is an error rather than plaintext.
+pub fn sealing_key() -> Result<Option<Key>, CryptoError> {
+    match current_key()? {
+        Some(key) => Ok(Some(key)),
+        None if load_params(&recorder::get_default_base_folder())?.is_some() => Err(CryptoError::Locked),
+        None => Ok(None),
+    }
+}
+
+// Key IDs end up in file names and sealed file headers
+fn valid_key_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= u8::MAX as usize && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn key_path(session_dir: &Path, id: &str) -> PathBuf {
+    session_dir.join(format!("{}{}", id, KEY_SUFFIX))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Whether `name` is a wrapped session key, or one a rotation left beside it.
+pub fn is_key_file(name: &str) -> bool {
+    let name = name.strip_suffix(OLD_SUFFIX).or_else(|| name.strip_suffix(REWRAP_SUFFIX)).unwrap_or(name);
+    name.ends_with(KEY_SUFFIX)
+}
+
+fn unwrap_key(master: &Key, path: &Path) -> Result<Key, CryptoError> {
+    key_from_bytes(&crypto::open(master, &fs::read(path)?)?)
+        .ok_or_else(|| CryptoError::Crypto(format!("Invalid session key in {}", path.display())))
+}
+
+/// The data key of recording session `id` in `session_dir`, created (and
+/// wrapped by `master`) on first use.
+pub fn session_key(master: &Key, session_dir: &Path, id: &str) -> Result<SessionKey, CryptoError> {
+    if !valid_key_id(id) {
+        return Err(CryptoError::Crypto(format!("Invalid session ID '{}'", id)));
+    }
+    let path = key_path(session_dir, id);
+    let key = if path.exists() {
+        unwrap_key(master, &path)?
+    } else {
+        let key = Key(crypto::random_bytes::<KEY_LEN>()?);
+        fs::create_dir_all(session_dir)?;
+        write_atomically(&path, &crypto::seal(master, &key.0)?)?;
+        key
+    };
+    Ok(SessionKey { id: id.to_string(), key })
+}
+
+/// Splits a sealed file into the ID of the key that sealed it and the sealed
+/// blob. Files without the header belong to the folder's legacy key.
+fn sealed_parts(sealed: &[u8]) -> (&str, &[u8]) {
+    let header = sealed.strip_prefix(SEALED_MAGIC).and_then(|rest| {
+        let (&len, rest) = rest.split_first()?;
+        let (id, blob) = (rest.get(..len as usize)?, &rest[len as usize..]);
+        std::str::from_utf8(id).ok().filter(|id| valid_key_id(id)).map(|id| (id, blob))
+    });
+    header.unwrap_or((LEGACY_KEY_ID, sealed))
+}
+
+/// What a sealed file is bound to: the ID of its key and its (plaintext) name.
+fn sealed_aad(id: &str, name: &str) -> Vec<u8> {
+    format!("{}/{}", id, name).into_bytes()
+}
+
+fn file_name(path: &Path) -> Result<&str, CryptoError> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| CryptoError::Crypto(format!("{} has no UTF-8 file name", path.display())))
+}
+
+/// Seals `contents` under the session `key` into `<path>.enc`. Returns the path written.
+pub fn write_sealed(key: &SessionKey, path: &Path, contents: &[u8]) -> Result<PathBuf, CryptoError> {
+    let mut sealed = SEALED_MAGIC.to_vec();
+    sealed.push(key.id.len() as u8);
+    sealed.extend_from_slice(key.id.as_bytes());
+    sealed.extend_from_slice(&crypto::seal_with_aad(&key.key, contents, &sealed_aad(&key.id, file_name(path)?))?);
+    let sealed_path = with_suffix(path, SEALED_SUFFIX);
+    fs::write(&sealed_path, sealed)?;
+    Ok(sealed_path)
+}
+
+/// Opens a sealed file with the session key named in it, unwrapped by `master`.
+fn open_with(master: &Key, path: &Path) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    let session_dir = path.parent().ok_or_else(|| CryptoError::Crypto(format!("{} has no session folder", path.display())))?;
+    let sealed = fs::read(path)?;
+    let (id, blob) = sealed_parts(&sealed);
+    let key_file = key_path(session_dir, id);
+    if !key_file.is_file() {
+        return Err(CryptoError::Crypto(format!("No session key {} for {}", key_file.display(), path.display())));
+    }
+    let data_key = unwrap_key(master, &key_file)?;
+    if id == LEGACY_KEY_ID {
+        return crypto::open(&data_key, blob); // Sealed before files were bound to their names
+    }
+    let name = file_name(path)?;
+    let plain_name = name.strip_suffix(SEALED_SUFFIX).unwrap_or(name);
+    crypto::open_with_aad(&data_key, blob, &sealed_aad(id, plain_name))
+}
+
+/// Opens a file sealed with its session's data key, using the key unlocked for this run.
+pub fn open_sealed(path: &Path) -> Result<Vec<u8>, CryptoError> {
+    let master = current_key()?.ok_or(CryptoError::KeyNotSet)?;
+    Ok(open_with(&master, path)?.to_vec())
+}
+
+/// Like open_sealed, for text files.
+pub fn read_sealed(path: &Path) -> Result<String, CryptoError> {
+    String::from_utf8(open_sealed(path)?).map_err(|_| CryptoError::Crypto(format!("{} is not UTF-8 text", path.display())))
+}
+
+/// Replaces the plaintext file at `path` with `<path>.enc`, sealed under its
+/// session's data `key`. A file that doesn't exist is left alone.
+pub fn seal_file(path: &Path, key: &SessionKey) -> Result<(), CryptoError> {
+    if !path.exists() {
+        return Ok(());
+    }
+    write_sealed(key, path, &fs::read(path)?)?;
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DecryptedFile {
+    pub file: String, // Name without the sealed suffix
+    pub content: String,
+}
+
+/// Opens every sealed file in one recording session (an action folder under
+/// encrypted_csv) with the key unlocked by `password`.
+#[tauri::command]
+pub fn decrypt_session(password: String, session: String) -> Result<Vec<DecryptedFile>, String> {
+    let password = Zeroizing::new(password);
+    if session.is_empty() || session.contains(['/', '\\']) || session.starts_with('.') {
+        return Err(format!("Invalid session name '{}'", session));
+    }
+    let base = recorder::get_default_base_folder();
+    let params = load_params(&base).map_err(|e| e.to_string())?.ok_or_else(|| CryptoError::KeyNotSet.to_string())?;
+    let master = unlock(&params, &password).map_err(|e| e.to_string())?;
+    recover_rotation(&base, &master);
+
+    let session_dir = base.join("encrypted_csv").join(&session);
+    let mut names: Vec<String> = fs::read_dir(&session_dir)
+        .map_err(|e| format!("Failed to read {}: {}", session_dir.display(), e))?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.ends_with(SEALED_SUFFIX))
+        .collect();
+    if names.is_empty() {
+        return Err(format!("Session '{}' has no encrypted data", session));
+    }
+    names.sort();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let plain = open_with(&master, &session_dir.join(&name)).map_err(|e| format!("Failed to decrypt {}: {}", name, e))?;
+            let content = String::from_utf8(plain.to_vec()).map_err(|_| format!("{} is not UTF-8 text", name))?;
+            Ok(DecryptedFile { file: name.trim_end_matches(SEALED_SUFFIX).to_string(), content })
+        })
+        .collect()
+}
+
+/// Files in the recordings' action folders whose names end with `suffix`.
+fn key_files(base: &Path, suffix: &str) -> Vec<PathBuf> {
+    let Ok(folders) = fs::read_dir(base.join("encrypted_csv")) else {
+        return Vec::new();
+    };
+    folders
+        .filter_map(Result::ok)
+        .filter_map(|folder| fs::read_dir(folder.path()).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.ends_with(suffix)))
+        .collect()
+}
+
+/// Every wrapped session key under the recordings' encrypted_csv folder.
+fn session_key_files(base: &Path) -> Vec<PathBuf> {
+    key_files(base, KEY_SUFFIX)
+}
+
+/// Old wrappings a rotation left behind (`<id>.key.old`).
+fn old_wrappings(base: &Path) -> Vec<PathBuf> {
+    key_files(base, &format!("{}{}", KEY_SUFFIX, OLD_SUFFIX))
+}
+
+/// Finishes a rotation that stopped between re-wrapping the session keys and
+/// saving the new parameters, or before deleting the old wrappings after.
+/// `master` is the key the saved parameters open: an old wrapping it opens means
+/// the parameters were never replaced, so it goes back; one it doesn't is stale
+/// once the key file beside it opens. Returns the old wrappings left unresolved.
+fn recover_rotation(base: &Path, master: &Key) -> usize {
+    for staged in key_files(base, &format!("{}{}", KEY_SUFFIX, REWRAP_SUFFIX)) {
+        let _ = fs::remove_file(staged); // Never swapped in
+    }
+    let mut unresolved = 0;
+    for backup in old_wrappings(base) {
+        let path = backup.with_extension(""); // Drops ".old"
+        let recovered = if unwrap_key(master, &backup).is_ok() {
+            fs::rename(&backup, &path).map(|()| true)
+        } else if unwrap_key(master, &path).is_ok() {
+            fs::remove_file(&backup).map(|()| true)
+        } else {
+            Ok(false)
+        };
+        match recovered {
+            Ok(true) => info!("Recovered {} after an interrupted key rotation.", path.display()),
+            Ok(false) => {
+                warn!("Neither {} nor {} opens with the current key.", path.display(), backup.display());
+                unresolved += 1;
+            }
+            Err(e) => {
+                warn!("Failed to recover {} from {}: {}", path.display(), backup.display(), e);
+                unresolved += 1;
+            }
+        }
+    }
+    unresolved
+}
+
+/// Session keys re-wrapped under a new master key. Each old wrapping is kept
+/// beside its file as `<id>.key.old` until the new key's parameters are saved;
+/// after a crash in between, recover_rotation puts them back.
+struct Rewrapped {
+    replaced: Vec<(PathBuf, PathBuf)>, // Key file, its old wrapping
+}
+
+impl Rewrapped {
+    /// Puts the old wrappings back.
+    fn roll_back(self) {
+        for (path, backup) in self.replaced.iter().rev() {
+            if let Err(e) = fs::rename(backup, path) {
+                warn!("Failed to restore {} from {}: {}", path.display(), backup.display(), e);
+            }
+        }
+    }
+
+    /// Deletes the old wrappings once the new parameters are saved.
+    fn commit(self) -> usize {
+        for (_, backup) in &self.replaced {
+            if let Err(e) = fs::remove_file(backup) {
+                warn!("Failed to delete the old session key {}: {}", backup.display(), e);
+            }
+        }
+        self.replaced.len()
+    }
+}
+
+/// Re-wraps every session key from `old` to `new`. All keys are rewritten to temp
+/// files first, so one that fails to unwrap aborts before anything is replaced;
+/// a failure while swapping them in puts back the ones already swapped. Refused
+/// while an earlier rotation's old wrappings are still around, since they would
+/// be overwritten.
+fn rewrap_session_keys(base: &Path, old: &Key, new: &Key) -> Result<Rewrapped, CryptoError> {
+    if let Some(backup) = old_wrappings(base).first() {
+        return Err(CryptoError::Crypto(format!("An earlier key rotation did not finish ({} is still there)", backup.display())));
+    }
+    let files = session_key_files(base);
+    let mut staged = Vec::with_capacity(files.len());
+    let staging: Result<(), CryptoError> = files.iter().try_for_each(|path| {
+        let data_key = crypto::open(old, &fs::read(path)?)?;
+        let tmp = with_suffix(path, REWRAP_SUFFIX);
+        fs::write(&tmp, crypto::seal(new, &data_key)?)?;
+        staged.push((tmp, path.clone()));
+        Ok(())
+    });
+    if let Err(e) = staging {
+        for (tmp, _) in &staged {
+            let _ = fs::remove_file(tmp);
+        }
+        return Err(e);
+    }
+    let mut rewrapped = Rewrapped { replaced: Vec::with_capacity(staged.len()) };
+    for (i, (tmp, path)) in staged.iter().enumerate() {
+        let backup = with_suffix(path, OLD_SUFFIX);
+        let swapped = fs::rename(path, &backup).and_then(|()| {
+            fs::rename(tmp, path).inspect_err(|_| {
+                let _ = fs::rename(&backup, path);
+            })
+        });
+        if let Err(e) = swapped {
+            for (tmp, _) in &staged[i..] {
+                let _ = fs::remove_file(tmp);
+            }
+            rewrapped.roll_back();
+            return Err(e.into());
+        }
+        rewrapped.replaced.push((path.clone(), backup));
+    }
+    Ok(rewrapped)
+}
+
+/// Moves everything under `base` from the master key unlocked by `old_password`
+/// to a fresh one derived from `new_password` with `kdf` (and a new salt).
+/// Returns the new key and the number of session keys re-wrapped.
+fn replace_master_key(base: &Path, old_password: &str, new_password: &str, kdf: Kdf) -> Result<(Key, usize), CryptoError> {
+    let params = load_params(base)?.ok_or(CryptoError::KeyNotSet)?;
+    let old_key = unlock(&params, old_password)?;
+    recover_rotation(base, &old_key);
+    let (new_params, new_key) = new_params(new_password, kdf)?;
+
+    let rewrapped = rewrap_session_keys(base, &old_key, &new_key)?;
+    if let Err(e) = save_params(base, &new_params) {
+        // The old parameters are still on disk, so the old wrappings must be too
+        rewrapped.roll_back();
+        return Err(e);
+    }
+    Ok((new_key, rewrapped.commit()))
+}
+
+/// replace_master_key on the recordings folder, keeping the keyring and this run's key in step.
+fn switch_master_key(old_password: &str, new_password: &str) -> Result<(Key, usize), CryptoError> {
+    let (new_key, count) = replace_master_key(&recorder::get_default_base_folder(), old_password, new_password, crypto::ARGON2ID)?;
+    // Keep the keyring in step if the user had opted in
+    if keyring::load().ok().flatten().is_some() {
+        keyring::store(&key_to_hex(&new_key))?;
+    }
+    *SESSION_KEY.lock_or_recover() = Some(new_key.clone());
+    Ok((new_key, count))
+}
+
+#[tauri::command]
+pub fn set_encryption_password(password: String, remember: bool) -> Result<(), String> {
+    let password = Zeroizing::new(password);
+    if password.is_empty() {
+        return Err("Encryption password cannot be empty.".to_string());
+    }
+    let base = recorder::get_default_base_folder();
+    // First time: create the parameters. Afterwards: the password must match them.
+    let key = match load_params(&base).map_err(|e| e.to_string())? {
+        Some(params) if params.kdf.is_some() => unlock(&params, &password).map_err(|e| e.to_string())?,
+        Some(params) => {
+            // Saved with PBKDF2; move to Argon2id while the password is at hand
+            let key = unlock(&params, &password).map_err(|e| e.to_string())?;
+            match switch_master_key(&password, &password) {
+                Ok((new_key, count)) => {
+                    info!("Encryption key moved to Argon2id; re-wrapped {} session key(s).", count);
+                    new_key
+                }
+                Err(e) => {
+                    warn!("Failed to move the encryption key to Argon2id: {}", e);
+                    key
+                }
+            }
+        }
+        None => {
+            let (params, key) = new_params(&password, crypto::ARGON2ID).map_err(|e| e.to_string())?;
+            save_params(&base, &params).map_err(|e| e.to_string())?;
+            key
+        }
+    };
+    if remember {
+        keyring::store(&key_to_hex(&key)).map_err(|e| e.to_string())?;
+    }
+    keep_unlocked(&base, key);
+    info!("Encryption key unlocked (remembered in keyring: {}).", remember);
+    Ok(())
+}
+
+/// Changes the password. Session data keys are re-wrapped under the new master
+/// key; the recordings themselves are untouched. Returns the number re-wrapped.
+#[tauri::command]
+pub fn change_encryption_password(old_password: String, new_password: String) -> Result<usize, String> {
+    let (old_password, new_password) = (Zeroizing::new(old_password), Zeroizing::new(new_password));
+    if new_password.is_empty() {
+        return Err("Encryption password cannot be empty.".to_string());
+    }
+    let (_, count) = switch_master_key(&old_password, &new_password).map_err(|e| format!("Failed to change password: {}", e))?;
+    info!("Encryption password changed; re-wrapped {} session key(s).", count);
+    Ok(count)
+}
+
+/// Keeps the password but derives a new master key from a fresh salt, re-wrapping
+/// every session key. Returns the number re-wrapped.
+#[tauri::command]
+pub fn rotate_master_key(password: String) -> Result<usize, String> {
+    let password = Zeroizing::new(password);
+    let (_, count) = switch_master_key(&password, &password).map_err(|e| format!("Failed to rotate master key: {}", e))?;
+    info!("Master key rotated; re-wrapped {} session key(s).", count);
+    Ok(count)
+}
+
+/// Drops the key from memory and the keyring; the password is asked for again next time.
+#[tauri::command]
+pub fn forget_encryption_password() -> Result<(), String> {
+    *SESSION_KEY.lock_or_recover() = None;
+    keyring::delete().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_encryption_key_status() -> Result<EncryptionKeyStatus, String> {
+    let configured = load_params(&recorder::get_default_base_folder()).map_err(|e| e.to_string())?.is_some();
+    let unlocked = current_key().map_err(|e| e.to_string())?.is_some();
+    Ok(EncryptionKeyStatus { configured, unlocked })
+}
+
+// --- OS Keyring Backends ---
+// Each stores one hex-encoded secret under service "metis", account "encryption-key".
+
+mod keyring {
+    use zeroize::Zeroizing;
+
+    use crate::error::CryptoError;
+
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    const SERVICE: &str = "metis";
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    const ACCOUNT: &str = "encryption-key";
+
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    fn run(program: &str, args: &[&str], stdin: Option<&str>) -> Result<std::process::Output, CryptoError> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| CryptoError::Keyring(format!("Failed to run {}: {}", program, e)))?;
+        if let Some(input) = stdin {
+            if let Some(mut pipe) = child.stdin.take() {
+                pipe.write_all(input.as_bytes())?;
+            }
+        }
+        Ok(child.wait_with_output()?)
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn store(secret: &str) -> Result<(), CryptoError> {
+        // -U updates an existing item; -w with no value reads the secret from stdin
+        let output = run("security", &["add-generic-password", "-U", "-s", SERVICE, "-a", ACCOUNT, "-w"], Some(&Zeroizing::new(format!("{0}\n{0}\n", secret))))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(CryptoError::Keyring(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn load() -> Result<Option<Zeroizing<String>>, CryptoError> {
+        let output = run("security", &["find-generic-password", "-s", SERVICE, "-a", ACCOUNT, "-w"], None)?;
+        let stdout = Zeroizing::new(output.stdout);
+        Ok(output.status.success().then(|| Zeroizing::new(String::from_utf8_lossy(&stdout).trim().to_string())))
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn delete() -> Result<(), CryptoError> {
+        run("security", &["delete-generic-password", "-s", SERVICE, "-a", ACCOUNT], None)?; // Missing item is fine
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn store(secret: &str) -> Result<(), CryptoError> {
+        let output = run("secret-tool", &["store", "--label=Metis encryption key", "service", SERVICE, "account", ACCOUNT], Some(secret))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(CryptoError::Keyring(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn load() -> Result<Option<Zeroizing<String>>, CryptoError> {
+        let output = run("secret-tool", &["lookup", "service", SERVICE, "account", ACCOUNT], None)?;
+        let stdout = Zeroizing::new(output.stdout);
+        let secret = Zeroizing::new(String::from_utf8_lossy(&stdout).trim().to_string());
+        Ok((output.status.success() && !secret.is_empty()).then_some(secret))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn delete() -> Result<(), CryptoError> {
+        run("secret-tool", &["clear", "service", SERVICE, "account", ACCOUNT], None)?;
+        Ok(())
+    }
+
+    // Windows has no CLI for the credential store, so the secret is DPAPI-protected
+    // (bound to the user's login) and kept in the data directory.
+    #[cfg(target_os = "windows")]
+    fn dpapi_path() -> std::path::PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| std::path::PathBuf::from("."))
+            .join("metis")
+            .join("encryption-key.dpapi")
+    }
+
+    #[cfg(target_os = "windows")]
+    fn dpapi(data: &[u8], protect: bool) -> Result<Vec<u8>, CryptoError> {
+        use std::ptr;
+        use windows_sys::Win32::Foundation::LocalFree;
+        use windows_sys::Win32::Security::Cryptography::{
+            CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+        };
+
+        let input = CRYPT_INTEGER_BLOB { cbData: data.len() as u32, pbData: data.as_ptr() as *mut u8 };
+        let mut output = CRYPT_INTEGER_BLOB { cbData: 0, pbData: ptr::null_mut() };
+        // SAFETY: input points at `data` for the duration of the call; output is
+        // allocated by the system and freed with LocalFree below.
+        let ok = unsafe {
+            if protect {
+                CryptProtectData(&input, ptr::null(), ptr::null(), ptr::null(), ptr::null(), CRYPTPROTECT_UI_FORBIDDEN, &mut output)
+            } else {
+                CryptUnprotectData(&input, ptr::null_mut(), ptr::null(), ptr::null(), ptr::null(), CRYPTPROTECT_UI_FORBIDDEN, &mut output)
+            }
+        };
+        if ok == 0 {
+            return Err(CryptoError::Keyring(std::io::Error::last_os_error().to_string()));
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec() };
+        unsafe { LocalFree(output.pbData as _) };
+        Ok(bytes)
+    }
+
+    #[cfg(target_os = "windows")]
+    pub fn store(secret: &str) -> Result<(), CryptoError> {
+        let path = dpapi_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, dpapi(secret.as_bytes(), true)?)?;
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    pub fn load() -> Result<Option<Zeroizing<String>>, CryptoError> {
+        let Ok(protected) = std::fs::read(dpapi_path()) else {
+            return Ok(None);
+        };
+        let secret = Zeroizing::new(dpapi(&protected, false)?);
+        Ok(Some(Zeroizing::new(String::from_utf8_lossy(&secret).into_owned())))
+    }
+
+    #[cfg(target_os = "windows")]
+    pub fn delete() -> Result<(), CryptoError> {
+        match std::fs::remove_file(dpapi_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    pub fn store(_secret: &str) -> Result<(), CryptoError> {
+        Err(CryptoError::Keyring("No OS keyring is supported on this platform".to_string()))
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    pub fn load() -> Result<Option<Zeroizing<String>>, CryptoError> {
+        Ok(None)
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    pub fn delete() -> Result<(), CryptoError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_ARGON2ID: Kdf = Kdf::Argon2id { memory_kib: 64, passes: 1, lanes: 1 };
+
+    fn temp_base() -> PathBuf {
+        let base = std::env::temp_dir().join(format!("metis_keystore_{:08x}", rand::random::<u32>()));
+        fs::create_dir_all(base.join("encrypted_csv").join("default_1")).unwrap();
+        base
+    }
+
+    fn random_key() -> Key {
+        Key(crypto::random_bytes::<KEY_LEN>().unwrap())
+    }
+
+    fn folder(base: &Path) -> PathBuf {
+        base.join("encrypted_csv").join("default_1")
+    }
+
+    #[test]
+    fn each_session_seals_with_its_own_key() {
+        let base = temp_base();
+        let master = random_key();
+        let first = session_key(&master, &folder(&base), "rec_1_aa").unwrap();
+        let second = session_key(&master, &folder(&base), "rec_2_bb").unwrap();
+        assert_ne!(first.key.0, second.key.0);
+        let a = write_sealed(&first, &folder(&base).join("a.csv"), b"first").unwrap();
+        let b = write_sealed(&second, &folder(&base).join("b.csv"), b"second").unwrap();
+        assert_eq!(open_with(&master, &a).unwrap().as_slice(), b"first");
+        assert_eq!(open_with(&master, &b).unwrap().as_slice(), b"second");
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn sealed_files_cannot_be_swapped() {
+        let base = temp_base();
+        let master = random_key();
+        let key = session_key(&master, &folder(&base), "rec_1_aa").unwrap();
+        let a = write_sealed(&key, &folder(&base).join("a.csv"), b"first").unwrap();
+        let b = write_sealed(&key, &folder(&base).join("b.csv"), b"second").unwrap();
+        let (sealed_a, sealed_b) = (fs::read(&a).unwrap(), fs::read(&b).unwrap());
+        fs::write(&a, sealed_b).unwrap();
+        fs::write(&b, sealed_a).unwrap();
+        assert!(matches!(open_with(&master, &a), Err(CryptoError::Decrypt)));
+        assert!(matches!(open_with(&master, &b), Err(CryptoError::Decrypt)));
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn key_id_in_the_header_is_authenticated() {
+        let base = temp_base();
+        let master = random_key();
+        let first = session_key(&master, &folder(&base), "rec_1_aa").unwrap();
+        let second = session_key(&master, &folder(&base), "rec_2_aa").unwrap();
+        let path = write_sealed(&first, &folder(&base).join("a.csv"), b"first").unwrap();
+        // Point the header at the other session's key file
+        let mut sealed = fs::read(&path).unwrap();
+        let at = SEALED_MAGIC.len() + 1;
+        sealed[at..at + second.id.len()].copy_from_slice(second.id.as_bytes());
+        fs::write(&path, sealed).unwrap();
+        assert!(matches!(open_with(&master, &path), Err(CryptoError::Decrypt)));
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn params_saved_before_argon2id_use_pbkdf2() {
+        let params: KdfParams = serde_json::from_str(r#"{"salt": "00", "iterations": 1000, "check": "00"}"#).unwrap();
+        assert_eq!(params.kdf(), Kdf::Pbkdf2Sha256 { iterations: 1000 });
+        let (params, _) = new_params("hunter2", TEST_ARGON2ID).unwrap();
+        let saved: KdfParams = serde_json::from_str(&serde_json::to_string(&params).unwrap()).unwrap();
+        assert_eq!(saved.kdf(), TEST_ARGON2ID);
+    }
+
+    #[test]
+    fn files_without_a_key_id_open_with_the_legacy_key() {
+        let base = temp_base();
+        let master = random_key();
+        let legacy = session_key(&master, &folder(&base), LEGACY_KEY_ID).unwrap();
+        let path = folder(&base).join("old.csv.enc");
+        fs::write(&path, crypto::seal(&legacy.key, b"legacy").unwrap()).unwrap();
+        assert_eq!(open_with(&master, &path).unwrap().as_slice(), b"legacy");
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn unlock_rejects_a_wrong_password() {
+        let (params, key) = new_params("hunter2", TEST_ARGON2ID).unwrap();
+        assert_eq!(unlock(&params, "hunter2").unwrap().0, key.0);
+        assert!(matches!(unlock(&params, "hunter3"), Err(CryptoError::WrongPassword)));
+    }
+
+    #[test]
+    fn changing_the_password_rewraps_every_session_key() {
+        let base = temp_base();
+        let (params, old) = new_params("hunter2", TEST_ARGON2ID).unwrap();
+        save_params(&base, &params).unwrap();
+        let data = session_key(&old, &folder(&base), "rec_1_aa").unwrap();
+        let path = write_sealed(&data, &folder(&base).join("a.csv"), b"first").unwrap();
+
+        assert!(matches!(replace_master_key(&base, "wrong", "hunter3", TEST_ARGON2ID), Err(CryptoError::WrongPassword)));
+        let (new, count) = replace_master_key(&base, "hunter2", "hunter3", TEST_ARGON2ID).unwrap();
+        assert_eq!(count, 1);
+        assert!(old_wrappings(&base).is_empty());
+        let params = load_params(&base).unwrap().unwrap();
+        assert!(matches!(unlock(&params, "hunter2"), Err(CryptoError::WrongPassword)));
+        assert_eq!(unlock(&params, "hunter3").unwrap().0, new.0);
+        // The data key is the same, only its wrapping changed
+        assert_eq!(open_with(&new, &path).unwrap().as_slice(), b"first");
+        assert!(open_with(&old, &path).is_err());
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn crash_before_saving_params_restores_the_old_wrapping() {
+        let base = temp_base();
+        let (old, new) = (random_key(), random_key());
+        let data = session_key(&old, &folder(&base), "rec_1_aa").unwrap();
+        drop(rewrap_session_keys(&base, &old, &new).unwrap()); // Params never saved, nothing committed
+        assert_eq!(old_wrappings(&base).len(), 1);
+
+        assert_eq!(recover_rotation(&base, &old), 0);
+        assert!(old_wrappings(&base).is_empty());
+        assert_eq!(session_key(&old, &folder(&base), "rec_1_aa").unwrap().key.0, data.key.0);
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn crash_after_saving_params_drops_the_old_wrapping() {
+        let base = temp_base();
+        let (old, new) = (random_key(), random_key());
+        let data = session_key(&old, &folder(&base), "rec_1_aa").unwrap();
+        drop(rewrap_session_keys(&base, &old, &new).unwrap()); // Params saved, old wrappings not deleted
+
+        assert_eq!(recover_rotation(&base, &new), 0);
+        assert!(old_wrappings(&base).is_empty());
+        assert_eq!(session_key(&new, &folder(&base), "rec_1_aa").unwrap().key.0, data.key.0);
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn crash_while_staging_leaves_keys_untouched() {
+        let base = temp_base();
+        let old = random_key();
+        session_key(&old, &folder(&base), "rec_1_aa").unwrap();
+        let staged = with_suffix(&key_path(&folder(&base), "rec_1_aa"), REWRAP_SUFFIX);
+        fs::write(&staged, b"half written").unwrap();
+
+        assert_eq!(recover_rotation(&base, &old), 0);
+        assert!(!staged.exists());
+        assert!(session_key(&old, &folder(&base), "rec_1_aa").is_ok());
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn rotation_is_refused_while_an_old_wrapping_remains() {
+        let base = temp_base();
+        let (old, new) = (random_key(), random_key());
+        session_key(&old, &folder(&base), "rec_1_aa").unwrap();
+        let backup = with_suffix(&key_path(&folder(&base), "rec_1_aa"), OLD_SUFFIX);
+        fs::write(&backup, b"unrecoverable").unwrap();
+
+        // Neither wrapping opens with this key, so recovery has to leave both alone
+        assert_eq!(recover_rotation(&base, &random_key()), 1);
+        assert!(rewrap_session_keys(&base, &old, &new).is_err());
+        assert_eq!(fs::read(&backup).unwrap(), b"unrecoverable");
+        assert!(session_key(&old, &folder(&base), "rec_1_aa").is_ok());
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn roll_back_and_commit() {
+        let base = temp_base();
+        let (old, new) = (random_key(), random_key());
+        session_key(&old, &folder(&base), "rec_1_aa").unwrap();
+
+        rewrap_session_keys(&base, &old, &new).unwrap().roll_back();
+        assert!(old_wrappings(&base).is_empty());
+        assert!(session_key(&old, &folder(&base), "rec_1_aa").is_ok());
+
+        assert_eq!(rewrap_session_keys(&base, &old, &new).unwrap().commit(), 1);
+        assert!(old_wrappings(&base).is_empty());
+        assert!(session_key(&new, &folder(&base), "rec_1_aa").is_ok());
+        fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+// --- Session Language Hint ---
+// The parser's OCR reads English unless told otherwise, and the LLM does better
+// with non-Latin screens when it knows what it is looking at. The user sets a
+// BCP 47 hint ("ja", "zh-TW", "ar") for the current session; it goes to the
+// parser with every image (selecting its OCR language pack) and into the
+// executor prompt. Unset means English/auto, as before.
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tracing::info;
+
+use crate::sync::LockExt;
+
+static SESSION_LANGUAGE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+// Primary subtag plus optional region/script subtags; also keeps the hint JSON-safe
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z]{2,3}(-[A-Za-z0-9]{2,8})*$").expect("valid language tag regex"));
+
+// Scripts written right to left
+const RTL_LANGUAGES: &[&str] = &["ar", "fa", "he", "ur", "yi", "ps", "sd", "ug"];
+
+/// The current session's language hint, if one is set.
+pub fn hint() -> Option<String> {
+    SESSION_LANGUAGE.lock_or_recover().clone()
+}
+
+/// Whether `tag`'s primary language is written right to left.
+pub fn is_rtl(tag: &str) -> bool {
+    let primary = tag.split('-').next().unwrap_or_default().to_ascii_lowercase();
+    RTL_LANGUAGES.contains(&primary.as_str())
+}
+
+/// Prompt section telling the executor which language the screen is in.
+pub fn prompt_note(tag: &str) -> String {
+    let mut note = format!(
+        "--- Screen Language ---\nOn-screen text is mostly in '{}'. Quote it exactly as it appears, without translating or transliterating, in type: and click_element: actions.\n",
+        tag
+    );
+    if is_rtl(tag) {
+        note.push_str("This language is written right to left: content is given in reading order, and layouts are usually mirrored (navigation and back controls on the right).\n");
+    }
+    note.push('\n');
+    note
+}
+
+/// Sets (or with `None`/"" clears) the language hint for this session.
+#[tauri::command]
+pub fn set_session_language(language: Option<String>) -> Result<Option<String>, String> {
+    let language = language.map(|l| l.trim().replace('_', "-")).filter(|l| !l.is_empty());
+    if let Some(tag) = &language {
+        if !TAG.is_match(tag) {
+            return Err(format!("'{}' is not a language tag (expected e.g. \"ja\" or \"zh-TW\")", tag));
+        }
+    }
+    info!("Session language hint: {}", language.as_deref().unwrap_or("none"));
+    *SESSION_LANGUAGE.lock_or_recover() = language.clone();
+    Ok(language)
+}
+
+#[tauri::command]
+pub fn get_session_language() -> Result<Option<String>, String> {
+    Ok(hint())
+}
@@ -0,0 +1,136 @@
+// --- Application Launcher ---
+// Backs the `launch:` action: starts a program, or opens a web link, file or
+// folder with the desktop's default handler, so a task doesn't hinge on finding
+// an icon to click. Nothing starts unless settings.launch allows it: programs
+// must be listed by name in `allowed_apps`, links need `allow_urls` (and are
+// still subject to local-only mode), files and folders need `allow_files`.
+// The admin policy's disable_shell_actions refuses every launch.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use tracing::{info, warn};
+
+use crate::config::{self, LaunchSettings};
+use crate::error::ActionError;
+use crate::net;
+use crate::policy;
+
+#[derive(Debug)]
+enum Target<'a> {
+    Url(&'a str),
+    Path(&'a Path),
+    App(&'a str),
+}
+
+fn classify(target: &str) -> Target<'_> {
+    let lower = target.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        Target::Url(target)
+    } else if Path::new(target).exists() {
+        Target::Path(Path::new(target))
+    } else {
+        Target::App(target)
+    }
+}
+
+/// Whether `app` is on the allowlist, by exact entry or by program name
+/// ("firefox" allows "/usr/bin/firefox" and "Firefox.exe").
+fn app_allowed(app: &str, settings: &LaunchSettings) -> bool {
+    let stem = Path::new(app).file_stem().and_then(|s| s.to_str()).unwrap_or(app);
+    settings
+        .allowed_apps
+        .iter()
+        .map(|entry| entry.trim())
+        .any(|entry| entry.eq_ignore_ascii_case(app) || entry.eq_ignore_ascii_case(stem))
+}
+
+/// The platform's "open with the default handler" command for a link or path.
+fn open_command(target: &str) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut c = Command::new("open");
+        c.arg(target);
+        c
+    } else if cfg!(target_os = "windows") {
+        // Unlike `cmd /C start`, this needs no quoting for links containing '&'
+        let mut c = Command::new("rundll32");
+        c.args(["url.dll,FileProtocolHandler", target]);
+        c
+    } else {
+        let mut c = Command::new("xdg-open");
+        c.arg(target);
+        c
+    }
+}
+
+/// Command starting an allowlisted program by name or path.
+fn app_command(app: &str) -> Command {
+    if cfg!(target_os = "macos") && !app.contains('/') {
+        let mut c = Command::new("open"); // Finds .app bundles by name, which PATH does not
+        c.args(["-a", app]);
+        c
+    } else if cfg!(target_os = "windows") {
+        // `start` also finds programs registered under App Paths, not just those on PATH
+        let mut c = Command::new("cmd");
+        c.args(["/C", "start", "", app]);
+        c
+    } else {
+        Command::new(app)
+    }
+}
+
+fn spawn(mut command: Command, target: &str) -> Result<(), ActionError> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| ActionError::Launch(format!("Failed to start {:?}: {}", target, e)))?;
+    thread::spawn(move || child.wait()); // Reap it whenever it exits
+    Ok(())
+}
+
+/// Starts `target` if the launch settings allow it. Returns without waiting for it.
+pub fn launch(target: &str) -> Result<(), ActionError> {
+    let target = target.trim();
+    if policy::get().disable_shell_actions {
+        warn!("Admin policy: blocked launching {:?}", target);
+        return Err(ActionError::LaunchBlocked(format!("starting programs is disabled by the admin policy ({})", target)));
+    }
+    let settings = config::get().launch;
+    match classify(target) {
+        Target::Url(url) => {
+            if !settings.allow_urls {
+                return Err(ActionError::LaunchBlocked(format!("opening links is disabled ({})", url)));
+            }
+            net::ensure_allowed(url).map_err(|e| ActionError::LaunchBlocked(e.to_string()))?;
+            spawn(open_command(url), url)?;
+        }
+        // An allowlisted program given by path runs rather than opening in its handler
+        Target::Path(_) if app_allowed(target, &settings) => spawn(app_command(target), target)?,
+        Target::Path(path) => {
+            if !settings.allow_files {
+                return Err(ActionError::LaunchBlocked(format!("opening files is disabled ({})", path.display())));
+            }
+            spawn(open_command(target), target)?;
+        }
+        Target::App(app) => {
+            if !app_allowed(app, &settings) {
+                return Err(ActionError::LaunchBlocked(format!("{:?} is not in the allowed apps list", app)));
+            }
+            spawn(app_command(app), app)?;
+        }
+    }
+    info!("Launched {:?}", target);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_launch_settings() -> Result<LaunchSettings, String> {
+    Ok(config::get().launch)
+}
+
+#[tauri::command]
+pub fn update_launch_settings(settings: LaunchSettings) -> Result<LaunchSettings, String> {
+    config::update(|s| s.launch = settings).map(|s| s.launch)
+}
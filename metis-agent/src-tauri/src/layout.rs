@@ -0,0 +1,72 @@
+// --- Keyboard Layout Detection ---
+// Key positions are not characters: the key labelled "A" on a US board types
+// "q" on AZERTY. Recording labels use the character the OS produced through the
+// active layout, and single-character taps are injected as that character (the
+// input backend looks up which key makes it), so neither side assumes US. This
+// reports which layout was active, so frames and runs can say what they were
+// made with. Windows gives the foreground window's HKL, macOS the input source
+// ID, Linux what setxkbmap reports for the X session.
+
+/// Identifier of the active keyboard layout, if the OS reports one.
+#[cfg(target_os = "windows")]
+pub fn active() -> Option<String> {
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::GetKeyboardLayout;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+    // Layouts are per thread; the one that matters belongs to the window being typed into
+    let layout = unsafe { GetKeyboardLayout(GetWindowThreadProcessId(GetForegroundWindow(), std::ptr::null_mut())) };
+    (layout != 0).then(|| format!("{:08x}", layout as usize as u32))
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn TISCopyCurrentKeyboardLayoutInputSource() -> *const std::ffi::c_void;
+    fn TISGetInputSourceProperty(
+        source: *const std::ffi::c_void,
+        key: core_foundation::string::CFStringRef,
+    ) -> *const std::ffi::c_void;
+    static kTISPropertyInputSourceID: core_foundation::string::CFStringRef;
+}
+
+/// Identifier of the active keyboard layout, if the OS reports one.
+#[cfg(target_os = "macos")]
+pub fn active() -> Option<String> {
+    use core_foundation::base::{CFRelease, TCFType};
+    use core_foundation::string::{CFString, CFStringRef};
+
+    unsafe {
+        let source = TISCopyCurrentKeyboardLayoutInputSource();
+        if source.is_null() {
+            return None;
+        }
+        // Owned by the source: read it before releasing
+        let id = TISGetInputSourceProperty(source, kTISPropertyInputSourceID) as CFStringRef;
+        let name = (!id.is_null()).then(|| CFString::wrap_under_get_rule(id).to_string()); // e.g. "com.apple.keylayout.French"
+        CFRelease(source);
+        name
+    }
+}
+
+/// Identifier of the active keyboard layout, if the OS reports one.
+#[cfg(target_os = "linux")]
+pub fn active() -> Option<String> {
+    let output = std::process::Command::new("setxkbmap").arg("-query").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let field = |name: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(|value| value.trim_start_matches(':').trim().to_string())
+    };
+    // "fr" or "de(nodeadkeys)"
+    match (field("layout"), field("variant")) {
+        (Some(layout), Some(variant)) if !variant.is_empty() => Some(format!("{}({})", layout, variant)),
+        (layout, _) => layout,
+    }
+}
+
+/// Identifier of the active keyboard layout, if the OS reports one.
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+pub fn active() -> Option<String> {
+    None
+}
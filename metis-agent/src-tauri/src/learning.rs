@@ -0,0 +1,276 @@
+// --- Learning From Videos ---
+// Turns a screen-capture video (a tutorial, or a recording made with another
+// tool) into a skill. ffmpeg decodes one keyframe per second into a temporary
+// folder; the keyframes are split into scenes wherever the perceptual hash
+// (dedup.rs) jumps, and the last, settled frame of each scene is parsed with the
+// same engine as recorded frames. Each parse becomes a CSV in a new action
+// folder, indexed in the session database like a recording, and a new skill
+// points at that folder. Progress goes out as learn://progress events and is
+// kept for get_learning_progress.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::{error, info, info_span, warn};
+
+use crate::dedup;
+use crate::error::{MetisError, ParserError};
+use crate::events;
+use crate::frames::FrameMeta;
+use crate::keystore;
+use crate::net;
+use crate::provenance;
+use crate::recorder;
+use crate::skills;
+use crate::storage;
+use crate::sync::LockExt;
+use crate::video;
+
+pub const LEARNING_PROGRESS_EVENT: &str = "learn://progress";
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "mov", "avi", "mkv"];
+const SAMPLE_FPS: u32 = 1;
+const KEPT_PROGRESS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LearningStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LearningProgress {
+    pub skill_id: String,
+    pub progress: u8, // 0-100
+    pub status: LearningStatus,
+    pub message: Option<String>, // What is happening, or why it failed
+    pub last_updated: u64,
+}
+
+static PROGRESS: Lazy<Mutex<Vec<LearningProgress>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn report(skill_id: &str, progress: u8, status: LearningStatus, message: impl Into<String>) {
+    let update = LearningProgress {
+        skill_id: skill_id.to_string(),
+        progress,
+        status,
+        message: Some(message.into()),
+        last_updated: now_ms(),
+    };
+    {
+        let mut all = PROGRESS.lock_or_recover();
+        all.retain(|p| p.skill_id != skill_id);
+        if all.len() >= KEPT_PROGRESS {
+            if let Some(i) = all.iter().position(|p| p.status != LearningStatus::InProgress) {
+                all.remove(i);
+            }
+        }
+        all.push(update.clone());
+    }
+    events::emit(LEARNING_PROGRESS_EVENT, update);
+}
+
+/// Decodes SAMPLE_FPS keyframes per second of `video` into `dir`, oldest first.
+fn extract_keyframes(video: &Path, dir: &Path) -> Result<Vec<PathBuf>, MetisError> {
+    fs::create_dir_all(dir)?;
+    let output = Command::new(video::ffmpeg_binary())
+        .args(["-hide_banner", "-loglevel", "error", "-nostdin", "-i"])
+        .arg(video)
+        .args(["-vf", &format!("fps={}", SAMPLE_FPS)])
+        .arg(dir.join("frame_%06d.png"))
+        .output()
+        .map_err(|e| MetisError::State(format!("Failed to start {} (set METIS_FFMPEG to its path): {}", video::ffmpeg_binary(), e)))?;
+    if !output.status.success() {
+        return Err(MetisError::InvalidArgument(format!(
+            "ffmpeg could not decode {}: {}",
+            video.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let mut keyframes: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    keyframes.sort(); // frame_%06d sorts by time
+    Ok(keyframes)
+}
+
+/// The last keyframe of each scene, with its offset into the video in ms.
+fn scene_keyframes(keyframes: &[PathBuf]) -> Vec<(u64, PathBuf)> {
+    let mut scenes = Vec::new();
+    let mut scene_start: Option<String> = None;
+    let mut last: Option<(u64, PathBuf)> = None;
+    for (i, path) in keyframes.iter().enumerate() {
+        let hash = match image::open(path) {
+            Ok(image) => dedup::hash(&image),
+            Err(e) => {
+                warn!("Skipping unreadable keyframe {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if scene_start.as_ref().map_or(true, |start| dedup::scene_changed(start, &hash)) {
+            scenes.extend(last.take());
+            scene_start = Some(hash);
+        }
+        last = Some((i as u64 * 1000 / u64::from(SAMPLE_FPS), path.clone()));
+    }
+    scenes.extend(last);
+    scenes
+}
+
+/// Parses each scene into `location` and indexes it. Returns how many scenes were saved.
+fn learn_scenes(skill_id: &str, video: &Path, location: &str, keyframes: &[PathBuf]) -> Result<usize, MetisError> {
+    let base = recorder::get_default_base_folder();
+    let action_folder = base.join("encrypted_csv").join(location);
+    let session_key = keystore::sealing_key()?
+        .map(|master| keystore::session_key(&master, &action_folder, &provenance::new_session_id()))
+        .transpose()?;
+    let client = net::blocking_client(Duration::from_secs(120)).map_err(ParserError::from)?;
+    let db = storage::open(&base)?;
+
+    let scenes = scene_keyframes(keyframes);
+    info!("{} keyframes, {} scenes", keyframes.len(), scenes.len());
+    let started_ms = now_ms();
+    let mut saved = 0;
+    for (action_number, (offset_ms, path)) in scenes.iter().enumerate() {
+        let progress = 30 + (65 * action_number / scenes.len().max(1)) as u8;
+        report(skill_id, progress, LearningStatus::InProgress, format!("Parsing scene {} of {}", action_number + 1, scenes.len()));
+        let parsed = match recorder::parse_frame(&client, path) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("{}", e);
+                continue;
+            }
+        };
+        let action = format!("Scene_{}s", offset_ms / 1000);
+        let csv = recorder::annotate_csv(parsed.as_deref(), &action, None, action_number as u32, None);
+        let timestamp_ms = started_ms + offset_ms; // Keeps the scenes' spacing in the video
+        let csv_name = format!("parsed_content_{}_{}.csv", timestamp_ms, started_ms / 1000);
+        let csv_file = match &session_key {
+            Some(key) => {
+                let name = format!("{}{}", csv_name, keystore::SEALED_SUFFIX);
+                keystore::write_sealed(key, &action_folder.join(&csv_name), csv.as_bytes())?;
+                name
+            }
+            None => {
+                fs::write(action_folder.join(&csv_name), &csv)?;
+                csv_name
+            }
+        };
+        let meta = FrameMeta {
+            file: format!("{}#{}", video.file_name().unwrap_or_default().to_string_lossy(), offset_ms),
+            timestamp_ms,
+            action,
+            action_folder: location.to_string(),
+            mouse: None,
+            display_generation: 0,
+            display: None,
+            sensitive: false,
+            element: None,
+            device: None,
+            layout: None,
+            session: None,
+            window: None,
+            hash: None,
+            duplicate: false,
+        };
+        if let Err(e) = storage::record_action(&db, location, action_number as u32, &meta, &csv_file) {
+            warn!("Failed to index {} in the session database: {}", csv_file, e);
+        }
+        saved += 1;
+    }
+    Ok(saved)
+}
+
+fn learn(skill_id: &str, name: &str, video: &Path) -> Result<String, MetisError> {
+    let scratch = std::env::temp_dir().join(format!("metis_learn_{}", skill_id));
+    report(skill_id, 5, LearningStatus::InProgress, "Decoding video");
+    let keyframes = extract_keyframes(video, &scratch);
+    let result = keyframes.and_then(|keyframes| {
+        if keyframes.is_empty() {
+            return Err(MetisError::InvalidArgument(format!("{} has no frames", video.display())));
+        }
+        report(skill_id, 30, LearningStatus::InProgress, format!("Decoded {} keyframes", keyframes.len()));
+
+        let encrypted_dir = recorder::get_default_base_folder().join("encrypted_csv");
+        let location = recorder::create_action_folder(&encrypted_dir)?;
+        let db = storage::open(&recorder::get_default_base_folder())?;
+        storage::create_session(&db, &location, "")?;
+        storage::rename_session(&db, &location, name)?;
+        drop(db);
+        match learn_scenes(skill_id, video, &location, &keyframes) {
+            Ok(0) => Err(MetisError::State("No scene could be parsed; is the parser running?".to_string())),
+            Ok(_) => Ok(location.clone()),
+            Err(e) => Err(e),
+        }
+        .inspect_err(|_| {
+            // Nothing usable was learned; don't leave an empty session behind
+            let _ = fs::remove_dir_all(encrypted_dir.join(&location));
+            if let Ok(db) = storage::open(&recorder::get_default_base_folder()) {
+                let _ = storage::delete_session(&db, &location);
+            }
+        })
+    });
+    if let Err(e) = fs::remove_dir_all(&scratch) {
+        warn!("Failed to remove {}: {}", scratch.display(), e);
+    }
+    let location = result?;
+    skills::attach(skill_id, &location)?;
+    Ok(location)
+}
+
+/// Starts learning a skill from the video at `file_path` and returns the new
+/// skill's ID. Poll get_learning_progress or listen for learn://progress.
+#[tauri::command]
+pub fn process_learning_video(file_path: String) -> Result<String, MetisError> {
+    let video = PathBuf::from(&file_path);
+    if !video.is_file() {
+        return Err(MetisError::InvalidArgument(format!("{} is not a file", file_path)));
+    }
+    let extension = video.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if !VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(MetisError::InvalidArgument(format!("Unsupported video type '.{}'", extension)));
+    }
+    let name = video.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let description = format!("Learned from {}", video.file_name().unwrap_or_default().to_string_lossy());
+    let skill = skills::create_skill(name.clone(), Some(description), Some(vec!["video".to_string()]))?;
+    let skill_id = skill.id.clone();
+    report(&skill_id, 0, LearningStatus::InProgress, "Queued");
+
+    thread::spawn(move || {
+        let _learning = info_span!("learning", skill = %skill_id).entered();
+        match learn(&skill_id, &name, &video) {
+            Ok(location) => {
+                info!("Learned skill {} from {} into {}", skill_id, video.display(), location);
+                report(&skill_id, 100, LearningStatus::Completed, "Done");
+            }
+            Err(e) => {
+                error!("Learning from {} failed: {}", video.display(), e);
+                if let Err(e) = skills::remove(&skill_id) {
+                    warn!("Failed to remove skill {}: {}", skill_id, e);
+                }
+                report(&skill_id, 100, LearningStatus::Failed, e.to_string());
+            }
+        }
+    });
+    Ok(skill.id)
+}
+
+#[tauri::command]
+pub fn get_learning_progress() -> Result<Vec<LearningProgress>, String> {
+    Ok(PROGRESS.lock_or_recover().clone())
+}
@@ -1,32 +1,826 @@
-use gemini_rs::{Client, Chat};
-use tokio; // Make sure to add these dependencies in your Cargo.toml
+// --- LLM Providers ---
+// The action loop talks to an `LlmProvider`, picked from the `llm` settings:
+// Gemini (GEMINI_API_KEY), OpenAI (OPENAI_API_KEY), Anthropic (ANTHROPIC_API_KEY)
+// or a local OpenAI-compatible server (optional LOCAL_LLM_API_KEY). Every call is
+// checked against local-only mode and the admin policy first, so a local server
+// keeps working where cloud models are blocked.
+//
+// Replies stream where the API allows it (OpenAI-style and Anthropic server-sent
+// events), so the UI can show the agent's reasoning as it is written. gemini_rs
+// has no streaming call; Gemini replies arrive as a single chunk.
+//
+// In vision mode the query carries a PNG screenshot as well, in each API's own
+// image form. The configured model has to accept images for that to work.
+//
+// Every call's token counts, as the API reports them, are added up per task and
+// for the session, with a cost estimated from the model's price (the settings'
+// prices, else a built-in table; local models are free). Where an API reports
+// nothing, tokens are estimated from the text at four characters each. A task
+// stops before its next call once it has used up settings.task_budget.
+//
+// With settings.llm.tool_use the action loop offers the actions as tools, in
+// each API's native form (OpenAI tools, Anthropic tools, Gemini function
+// declarations), and the LLM answers with a typed call instead of a line of the
+// action grammar. gemini_rs can't send tools or read function calls, so those
+// Gemini requests go to its REST API directly.
 
-pub async fn get_llm(context: String, query: String, client: &Client) -> Result<String, gemini_rs::Error> {
-    // Initialize the client with API key from environment
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::sync::Mutex;
+use std::time::Duration;
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use gemini_rs::types::{Content, InlineData, Part, Role};
+use gemini_rs::Client;
+use once_cell::sync::Lazy;
+use reqwest::blocking::{RequestBuilder, Response};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::runtime::Runtime;
+use tracing::{info, warn};
 
-    // Create a new chat instance with the desired model
-    let mut chat = client.chat("gemini-2.0-flash");
+use crate::config::{self, LlmProviderKind, LlmSettings, ModelConfig, TaskBudget};
+use crate::error::LlmError;
+use crate::net;
+use crate::sync::LockExt;
 
-    // Set the system instruction with the context
-    chat = chat.system_instruction(&context);
+const GEMINI_API_URL: &str = "https://generativelanguage.googleapis.com";
+const OPENAI_API_URL: &str = "https://api.openai.com/v1";
+const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1";
+const LOCAL_API_URL: &str = "http://localhost:11434/v1"; // Ollama's default
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MAX_TOKENS: u32 = 1024; // One thought and one action, unless configured
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+const CHARS_PER_TOKEN: u64 = 4; // For estimates where the API reports no usage
 
-    // Send the query message and get the response
-    let response = chat.send_message(&query).await?;
+// USD per million (input, output) tokens, by model name prefix; the first match wins
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gemini-2.0-flash-lite", 0.075, 0.30),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-3-opus", 15.00, 75.00),
+];
 
-    // Return the response as a String
-    Ok(response.to_string())
+static USAGE: Lazy<Mutex<UsageTotals>> = Lazy::new(|| Mutex::new(UsageTotals::default()));
+
+pub trait LlmProvider {
+    fn name(&self) -> &'static str;
+
+    fn model(&self) -> &str;
+
+    /// Where requests go, for the local-only and policy checks.
+    fn endpoint(&self) -> &str;
+
+    /// Sends `query`, and the PNG `image` if given, with `system` as the system
+    /// instruction and returns the reply text.
+    fn complete(&self, system: &str, query: &str, image: Option<&[u8]>) -> Result<String, LlmError>;
+
+    /// Like `complete`, but hands each piece of the reply to `on_chunk` as it
+    /// arrives. Providers that can't stream deliver the whole reply as one chunk.
+    fn complete_streaming(
+        &self,
+        system: &str,
+        query: &str,
+        image: Option<&[u8]>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, LlmError> {
+        let reply = self.complete(system, query, image)?;
+        on_chunk(&reply);
+        Ok(reply)
+    }
+
+    /// Like `complete_streaming`, offering the LLM `tools`; the reply carries
+    /// the first tool it called. Without tools this is a plain completion.
+    fn complete_with_tools(
+        &self,
+        system: &str,
+        query: &str,
+        image: Option<&[u8]>,
+        tools: &[ToolSpec],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<LlmReply, LlmError>;
 }
 
-// Example usage (you would call this from an async context):
-/*
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let context = "You are a helpful AI assistant.".to_string();
-    let query = "What is the capital of France?".to_string();
+fn api_key(var: &'static str) -> Result<SecretString, LlmError> {
+    // Moved straight into a SecretString, which wipes it on drop
+    std::env::var(var).map(SecretString::from).map_err(|_| LlmError::MissingApiKey(var))
+}
+
+fn http_client() -> Result<reqwest::blocking::Client, LlmError> {
+    Ok(net::blocking_client(REQUEST_TIMEOUT)?)
+}
+
+/// Sends a JSON request, turning API errors into `LlmError::Api`.
+fn send(request: RequestBuilder, body: &Value) -> Result<Response, LlmError> {
+    let response = request.json(body).send()?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().unwrap_or_default();
+        return Err(LlmError::Api(format!("{}: {}", status, text)));
+    }
+    Ok(response)
+}
+
+fn send_json(request: RequestBuilder, body: &Value) -> Result<Value, LlmError> {
+    Ok(send(request, body)?.json()?)
+}
 
-    let result = get_llm(context, query).await?;
-    println!("Response: {}", result);
+/// Reads a server-sent event stream, passing each JSON `data:` payload to
+/// `on_event` until `[DONE]` or the end of the stream.
+fn read_events(response: Response, mut on_event: impl FnMut(&Value) -> Result<(), LlmError>) -> Result<(), LlmError> {
+    for line in BufReader::new(response).lines() {
+        let line = line.map_err(|e| LlmError::Api(format!("Stream interrupted: {}", e)))?;
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            continue; // Blank separators, `event:` names, comments
+        };
+        if data == "[DONE]" {
+            break;
+        }
+        match serde_json::from_str::<Value>(data) {
+            Ok(event) => on_event(&event)?,
+            Err(e) => warn!("Skipping unreadable stream event ({}): {}", e, data),
+        }
+    }
     Ok(())
 }
-*/
\ No newline at end of file
+
+// --- Tool Use ---
+
+/// A tool offered to the LLM, with a JSON Schema of its arguments.
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: Value,
+}
+
+/// A tool the LLM called. Arguments that weren't valid JSON are kept as the
+/// string the LLM sent, for the caller to reject.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub name: String,
+    pub arguments: Value,
+}
+
+impl ToolCall {
+    fn new(name: String, arguments: &str) -> Self {
+        let arguments = if arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string()))
+        };
+        ToolCall { name, arguments }
+    }
+}
+
+/// The call as shown in logs and in the prompt's previous actions.
+impl fmt::Display for ToolCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.arguments {
+            Value::Object(arguments) if arguments.is_empty() => write!(f, "{}", self.name),
+            arguments => write!(f, "{} {}", self.name, arguments),
+        }
+    }
+}
+
+/// What the LLM answered: its text, and the tool it called, if it was offered any.
+#[derive(Debug, Clone, Default)]
+pub struct LlmReply {
+    pub text: String,
+    pub call: Option<ToolCall>,
+}
+
+impl LlmReply {
+    fn text(text: String) -> Self {
+        LlmReply { text, call: None }
+    }
+
+    /// The reply as counted for usage estimates.
+    fn measured(&self) -> String {
+        match &self.call {
+            Some(call) => format!("{}{}", self.text, call),
+            None => self.text.clone(),
+        }
+    }
+}
+
+// --- Usage ---
+
+/// LLM calls, their tokens and estimated cost.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Usage {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub response_tokens: u64,
+    pub cost_usd: f64,
+    pub estimated: bool, // Some counts were guessed from text length
+}
+
+impl Usage {
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.response_tokens
+    }
+
+    fn add(&mut self, call: &Usage) {
+        self.calls += call.calls;
+        self.prompt_tokens += call.prompt_tokens;
+        self.response_tokens += call.response_tokens;
+        self.cost_usd += call.cost_usd;
+        self.estimated |= call.estimated;
+    }
+}
+
+#[derive(Default)]
+struct UsageTotals {
+    session: Usage,
+    task: Usage, // Since the last begin_task
+}
+
+/// What get_llm_usage reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct LlmUsage {
+    pub session: Usage,
+    pub task: Usage,
+    pub budget: TaskBudget,
+}
+
+/// USD per million (input, output) tokens for `model`.
+fn price(provider: &str, model: &str) -> (f64, f64) {
+    let settings = config::get().llm;
+    let known = if provider == "local" {
+        Some((0.0, 0.0))
+    } else {
+        PRICES.iter().find(|(prefix, _, _)| model.starts_with(prefix)).map(|(_, input, output)| (*input, *output))
+    };
+    let (input, output) = known.unwrap_or((0.0, 0.0));
+    (settings.input_usd_per_mtok.unwrap_or(input), settings.output_usd_per_mtok.unwrap_or(output))
+}
+
+/// Records one call. `counts` are the (prompt, response) tokens the API
+/// reported; without them the text is measured instead. Estimates leave out
+/// any attached image.
+fn record_usage(provider: &str, model: &str, counts: Option<(u64, u64)>, system: &str, query: &str, reply: &str) {
+    let (prompt_tokens, response_tokens) = counts.unwrap_or_else(|| {
+        let estimate = |chars: usize| (chars as u64).div_ceil(CHARS_PER_TOKEN);
+        (estimate(system.chars().count() + query.chars().count()), estimate(reply.chars().count()))
+    });
+    let (input, output) = price(provider, model);
+    let call = Usage {
+        calls: 1,
+        prompt_tokens,
+        response_tokens,
+        cost_usd: (prompt_tokens as f64 * input + response_tokens as f64 * output) / 1_000_000.0,
+        estimated: counts.is_none(),
+    };
+    let mut totals = USAGE.lock_or_recover();
+    totals.session.add(&call);
+    totals.task.add(&call);
+}
+
+/// Starts counting a new task's usage against the budget.
+pub fn begin_task() {
+    USAGE.lock_or_recover().task = Usage::default();
+}
+
+/// Why the current task may not call the LLM again, if its budget is spent.
+pub fn budget_exceeded() -> Option<String> {
+    let budget = config::get().task_budget;
+    let task = USAGE.lock_or_recover().task;
+    if let Some(max) = budget.max_tokens.filter(|max| task.tokens() >= *max) {
+        return Some(format!("the task used {} LLM tokens, its budget is {}", task.tokens(), max));
+    }
+    if let Some(max) = budget.max_usd.filter(|max| task.cost_usd >= *max) {
+        return Some(format!("the task spent ${:.4} on the LLM, its budget is ${:.2}", task.cost_usd, max));
+    }
+    None
+}
+
+/// Sampling parameters sent with every request; unset ones are left to the API.
+#[derive(Debug, Clone, Copy)]
+struct Sampling {
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: u32,
+}
+
+impl Sampling {
+    /// Adds the parameters to an OpenAI- or Anthropic-style request body.
+    fn apply(&self, body: &mut Value) {
+        body["max_tokens"] = json!(self.max_tokens);
+        if let Some(temperature) = self.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = self.top_p {
+            body["top_p"] = json!(top_p);
+        }
+    }
+}
+
+/// Builds the provider selected in the settings, with `overrides` (a task's
+/// own model config) taking precedence over the settings' model and sampling.
+pub fn provider_from_config(overrides: &ModelConfig) -> Result<Box<dyn LlmProvider>, LlmError> {
+    let settings = config::get().llm;
+    let ModelConfig { model, temperature, top_p, max_tokens } = overrides.clone().or(settings.model_config());
+    let sampling = Sampling { temperature, top_p, max_tokens: max_tokens.unwrap_or(MAX_TOKENS) };
+    let LlmSettings { provider, base_url, .. } = settings;
+    let provider: Box<dyn LlmProvider> = match provider {
+        LlmProviderKind::Gemini => Box::new(GeminiProvider::new(model, sampling)?),
+        LlmProviderKind::OpenAi => Box::new(OpenAiCompatible {
+            name: "openai",
+            base_url: base_url.unwrap_or_else(|| OPENAI_API_URL.to_string()),
+            api_key: Some(api_key("OPENAI_API_KEY")?),
+            model: model.unwrap_or_else(|| "gpt-4o-mini".to_string()),
+            sampling,
+            client: http_client()?,
+        }),
+        LlmProviderKind::Anthropic => Box::new(AnthropicProvider {
+            base_url: base_url.unwrap_or_else(|| ANTHROPIC_API_URL.to_string()),
+            api_key: api_key("ANTHROPIC_API_KEY")?,
+            model: model.unwrap_or_else(|| "claude-3-5-sonnet-latest".to_string()),
+            sampling,
+            client: http_client()?,
+        }),
+        LlmProviderKind::Local => Box::new(OpenAiCompatible {
+            name: "local",
+            base_url: base_url.unwrap_or_else(|| LOCAL_API_URL.to_string()),
+            api_key: api_key("LOCAL_LLM_API_KEY").ok(),
+            model: model.unwrap_or_else(|| "llama3.1".to_string()),
+            sampling,
+            client: http_client()?,
+        }),
+    };
+    // Fail before the task starts rather than on its first step
+    net::ensure_llm_allowed(provider.endpoint())?;
+    Ok(provider)
+}
+
+/// Asks the LLM, with an optional PNG screenshot attached, streaming the reply
+/// into `on_chunk`; returns the whole reply.
+pub fn get_llm(
+    context: String,
+    query: String,
+    image: Option<&[u8]>,
+    provider: &dyn LlmProvider,
+    on_chunk: &mut dyn FnMut(&str),
+) -> Result<String, LlmError> {
+    // Re-checked per call: local-only mode may have been switched on mid-task
+    net::ensure_llm_allowed(provider.endpoint())?;
+    provider.complete_streaming(&context, &query, image, on_chunk)
+}
+
+/// Like `get_llm`, offering the LLM `tools` to call instead of answering in text.
+pub fn get_llm_with_tools(
+    context: String,
+    query: String,
+    image: Option<&[u8]>,
+    provider: &dyn LlmProvider,
+    tools: &[ToolSpec],
+    on_chunk: &mut dyn FnMut(&str),
+) -> Result<LlmReply, LlmError> {
+    net::ensure_llm_allowed(provider.endpoint())?;
+    provider.complete_with_tools(&context, &query, image, tools, on_chunk)
+}
+
+// --- Gemini ---
+
+pub struct GeminiProvider {
+    client: Client,
+    api_key: SecretString, // For the REST requests that carry tools
+    http: reqwest::blocking::Client,
+    model: String,
+    sampling: Sampling,
+    runtime: Runtime, // gemini_rs is async; the action loop is not
+}
+
+impl GeminiProvider {
+    fn new(model: Option<String>, sampling: Sampling) -> Result<Self, LlmError> {
+        Ok(GeminiProvider {
+            client: Client::new(api_key("GEMINI_API_KEY")?),
+            api_key: api_key("GEMINI_API_KEY")?,
+            http: http_client()?,
+            model: model.unwrap_or_else(|| "gemini-2.0-flash".to_string()),
+            sampling,
+            runtime: Runtime::new().map_err(|e| LlmError::Runtime(e.to_string()))?,
+        })
+    }
+}
+
+impl LlmProvider for GeminiProvider {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn endpoint(&self) -> &str {
+        GEMINI_API_URL
+    }
+
+    fn complete(&self, system: &str, query: &str, image: Option<&[u8]>) -> Result<String, LlmError> {
+        let mut chat = self.client.chat(&self.model).system_instruction(system);
+        let config = chat.config_mut();
+        config.temperature = self.sampling.temperature;
+        config.top_p = self.sampling.top_p;
+        config.max_output_tokens = Some(self.sampling.max_tokens.min(i32::MAX as u32) as i32);
+        let mut parts = vec![Part::text(query)];
+        if let Some(png) = image {
+            parts.push(Part {
+                inline_data: Some(InlineData { mime_type: "image/png".to_string(), data: BASE64.encode(png) }),
+                ..Default::default()
+            });
+        }
+        chat.history_mut().push(Content { role: Role::User, parts });
+        let response = self.runtime.block_on(chat.generate_content())?;
+        let reply = response.to_string();
+        let counts = response.usage_metadata.as_ref().map(|u| (u.prompt_token_count, u.candidates_token_count));
+        record_usage(self.name(), &self.model, counts, system, query, &reply);
+        Ok(reply)
+    }
+
+    fn complete_with_tools(
+        &self,
+        system: &str,
+        query: &str,
+        image: Option<&[u8]>,
+        tools: &[ToolSpec],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<LlmReply, LlmError> {
+        if tools.is_empty() {
+            return self.complete_streaming(system, query, image, on_chunk).map(LlmReply::text);
+        }
+        let mut parts = vec![json!({ "text": query })];
+        if let Some(png) = image {
+            parts.push(json!({ "inline_data": { "mime_type": "image/png", "data": BASE64.encode(png) } }));
+        }
+        let declarations: Vec<Value> = tools
+            .iter()
+            .map(|tool| {
+                let mut declaration = json!({ "name": tool.name, "description": tool.description });
+                // Gemini rejects an object schema without properties
+                if tool.parameters["properties"].as_object().is_some_and(|properties| !properties.is_empty()) {
+                    declaration["parameters"] = tool.parameters.clone();
+                }
+                declaration
+            })
+            .collect();
+        let mut generation_config = json!({ "maxOutputTokens": self.sampling.max_tokens });
+        if let Some(temperature) = self.sampling.temperature {
+            generation_config["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = self.sampling.top_p {
+            generation_config["topP"] = json!(top_p);
+        }
+        let body = json!({
+            "system_instruction": { "parts": [{ "text": system }] },
+            "contents": [{ "role": "user", "parts": parts }],
+            "tools": [{ "function_declarations": declarations }],
+            "generationConfig": generation_config,
+        });
+        let url = format!("{}/v1beta/models/{}:generateContent", GEMINI_API_URL, self.model);
+        let request = self.http.post(url).header("x-goog-api-key", self.api_key.expose_secret());
+        let response = send_json(request, &body)?;
+        let mut reply = LlmReply::default();
+        for part in response["candidates"][0]["content"]["parts"].as_array().into_iter().flatten() {
+            if let Some(text) = part["text"].as_str() {
+                reply.text.push_str(text);
+            }
+            if let (None, Some(name)) = (&reply.call, part["functionCall"]["name"].as_str()) {
+                let arguments = part["functionCall"].get("args").cloned().unwrap_or_else(|| json!({}));
+                reply.call = Some(ToolCall { name: name.to_string(), arguments });
+            }
+        }
+        if reply.text.is_empty() && reply.call.is_none() {
+            return Err(LlmError::Api(format!("No content in response: {}", response)));
+        }
+        on_chunk(&reply.text);
+        let usage = &response["usageMetadata"];
+        let counts = usage["promptTokenCount"].as_u64().zip(usage["candidatesTokenCount"].as_u64());
+        record_usage(self.name(), &self.model, counts, system, query, &reply.measured());
+        Ok(reply)
+    }
+}
+
+// --- OpenAI and compatible servers ---
+
+pub struct OpenAiCompatible {
+    name: &'static str,
+    base_url: String,
+    api_key: Option<SecretString>, // Local servers usually don't want one
+    model: String,
+    sampling: Sampling,
+    client: reqwest::blocking::Client,
+}
+
+impl LlmProvider for OpenAiCompatible {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+
+    fn complete(&self, system: &str, query: &str, image: Option<&[u8]>) -> Result<String, LlmError> {
+        let response = send_json(self.request(), &self.body(system, query, image, &[], false))?;
+        let reply = response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| LlmError::Api(format!("No message content in response: {}", response)))?;
+        record_usage(self.name, &self.model, openai_usage(&response), system, query, &reply);
+        Ok(reply)
+    }
+
+    fn complete_streaming(
+        &self,
+        system: &str,
+        query: &str,
+        image: Option<&[u8]>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, LlmError> {
+        self.complete_with_tools(system, query, image, &[], on_chunk).map(|reply| reply.text)
+    }
+
+    fn complete_with_tools(
+        &self,
+        system: &str,
+        query: &str,
+        image: Option<&[u8]>,
+        tools: &[ToolSpec],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<LlmReply, LlmError> {
+        let response = send(self.request(), &self.body(system, query, image, tools, true))?;
+        let mut text = String::new();
+        let (mut tool_name, mut tool_arguments) = (String::new(), String::new());
+        let mut counts = None;
+        read_events(response, |event| {
+            let delta = &event["choices"][0]["delta"];
+            if let Some(content) = delta["content"].as_str() {
+                text.push_str(content);
+                on_chunk(content);
+            }
+            // A call's name and arguments arrive in pieces; only the first call is kept
+            for call in delta["tool_calls"].as_array().into_iter().flatten().filter(|call| call["index"].as_u64().unwrap_or(0) == 0) {
+                tool_name.push_str(call["function"]["name"].as_str().unwrap_or_default());
+                tool_arguments.push_str(call["function"]["arguments"].as_str().unwrap_or_default());
+            }
+            counts = openai_usage(event).or(counts); // In the last event, asked for with include_usage
+            Ok(())
+        })?;
+        let call = (!tool_name.is_empty()).then(|| ToolCall::new(tool_name, &tool_arguments));
+        let reply = LlmReply { text, call };
+        record_usage(self.name, &self.model, counts, system, query, &reply.measured());
+        Ok(reply)
+    }
+}
+
+fn openai_usage(response: &Value) -> Option<(u64, u64)> {
+    let usage = &response["usage"];
+    Some((usage["prompt_tokens"].as_u64()?, usage["completion_tokens"].as_u64()?))
+}
+
+impl OpenAiCompatible {
+    fn request(&self) -> RequestBuilder {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let request = self.client.post(url);
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key.expose_secret()),
+            None => request,
+        }
+    }
+
+    fn body(&self, system: &str, query: &str, image: Option<&[u8]>, tools: &[ToolSpec], stream: bool) -> Value {
+        let content = match image {
+            Some(png) => json!([
+                { "type": "text", "text": query },
+                { "type": "image_url", "image_url": { "url": format!("data:image/png;base64,{}", BASE64.encode(png)) } },
+            ]),
+            None => json!(query),
+        };
+        let mut body = json!({
+            "model": self.model,
+            "stream": stream,
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": content },
+            ],
+        });
+        self.sampling.apply(&mut body);
+        if stream {
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+        if !tools.is_empty() {
+            let tools: Vec<Value> = tools
+                .iter()
+                .map(|tool| json!({
+                    "type": "function",
+                    "function": { "name": tool.name, "description": tool.description, "parameters": tool.parameters },
+                }))
+                .collect();
+            body["tools"] = json!(tools);
+        }
+        body
+    }
+}
+
+// --- Anthropic ---
+
+pub struct AnthropicProvider {
+    base_url: String,
+    api_key: SecretString,
+    model: String,
+    sampling: Sampling,
+    client: reqwest::blocking::Client,
+}
+
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+
+    fn complete(&self, system: &str, query: &str, image: Option<&[u8]>) -> Result<String, LlmError> {
+        let response = send_json(self.request(), &self.body(system, query, image, &[], false))?;
+        let text: String = response["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect();
+        if text.is_empty() {
+            return Err(LlmError::Api(format!("No text content in response: {}", response)));
+        }
+        let usage = &response["usage"];
+        let counts = usage["input_tokens"].as_u64().zip(usage["output_tokens"].as_u64());
+        record_usage(self.name(), &self.model, counts, system, query, &text);
+        Ok(text)
+    }
+
+    fn complete_streaming(
+        &self,
+        system: &str,
+        query: &str,
+        image: Option<&[u8]>,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, LlmError> {
+        self.complete_with_tools(system, query, image, &[], on_chunk).map(|reply| reply.text)
+    }
+
+    fn complete_with_tools(
+        &self,
+        system: &str,
+        query: &str,
+        image: Option<&[u8]>,
+        tools: &[ToolSpec],
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<LlmReply, LlmError> {
+        let response = send(self.request(), &self.body(system, query, image, tools, true))?;
+        let mut text = String::new();
+        // The first tool_use block: its index, name and the input JSON so far
+        let mut tool: Option<(u64, String, String)> = None;
+        let (mut input_tokens, mut output_tokens) = (None, None);
+        read_events(response, |event| match event["type"].as_str() {
+            Some("content_block_start") => {
+                let block = &event["content_block"];
+                if let (None, "tool_use", Some(name)) = (&tool, block["type"].as_str().unwrap_or_default(), block["name"].as_str()) {
+                    tool = Some((event["index"].as_u64().unwrap_or(0), name.to_string(), String::new()));
+                }
+                Ok(())
+            }
+            Some("content_block_delta") => {
+                if let Some(delta) = event["delta"]["text"].as_str() {
+                    text.push_str(delta);
+                    on_chunk(delta);
+                }
+                if let (Some((index, _, input)), Some(partial)) = (&mut tool, event["delta"]["partial_json"].as_str()) {
+                    if event["index"].as_u64() == Some(*index) {
+                        input.push_str(partial);
+                    }
+                }
+                Ok(())
+            }
+            Some("message_start") => {
+                input_tokens = event["message"]["usage"]["input_tokens"].as_u64();
+                Ok(())
+            }
+            Some("message_delta") => {
+                output_tokens = event["usage"]["output_tokens"].as_u64().or(output_tokens);
+                Ok(())
+            }
+            // Overload and similar errors arrive mid-stream, after a 200
+            Some("error") => Err(LlmError::Api(event["error"]["message"].as_str().unwrap_or("stream error").to_string())),
+            _ => Ok(()),
+        })?;
+        let call = tool.map(|(_, name, input)| ToolCall::new(name, &input));
+        let reply = LlmReply { text, call };
+        record_usage(self.name(), &self.model, input_tokens.zip(output_tokens), system, query, &reply.measured());
+        Ok(reply)
+    }
+}
+
+impl AnthropicProvider {
+    fn request(&self) -> RequestBuilder {
+        let url = format!("{}/messages", self.base_url.trim_end_matches('/'));
+        self.client
+            .post(url)
+            .header("x-api-key", self.api_key.expose_secret())
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    fn body(&self, system: &str, query: &str, image: Option<&[u8]>, tools: &[ToolSpec], stream: bool) -> Value {
+        let mut content = vec![json!({ "type": "text", "text": query })];
+        if let Some(png) = image {
+            content.push(json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": "image/png", "data": BASE64.encode(png) },
+            }));
+        }
+        let mut body = json!({
+            "model": self.model,
+            "stream": stream,
+            "system": system,
+            "messages": [{ "role": "user", "content": content }],
+        });
+        self.sampling.apply(&mut body);
+        if !tools.is_empty() {
+            let tools: Vec<Value> = tools
+                .iter()
+                .map(|tool| json!({ "name": tool.name, "description": tool.description, "input_schema": tool.parameters }))
+                .collect();
+            body["tools"] = json!(tools);
+        }
+        body
+    }
+}
+
+#[tauri::command]
+pub fn get_llm_settings() -> Result<LlmSettings, String> {
+    Ok(config::get().llm)
+}
+
+#[tauri::command]
+pub fn update_llm_settings(settings: LlmSettings) -> Result<LlmSettings, String> {
+    settings.model_config().validate()?;
+    config::update(|s| s.llm = settings).map(|s| s.llm)
+}
+
+/// Sets the model and sampling parameters tasks use unless they bring their
+/// own; unset fields go back to the provider's defaults.
+#[tauri::command]
+pub fn set_model_config(model: Option<String>, temperature: Option<f32>, top_p: Option<f32>, max_tokens: Option<u32>) -> Result<ModelConfig, String> {
+    let model_config = ModelConfig { model: model.filter(|m| !m.trim().is_empty()), temperature, top_p, max_tokens };
+    model_config.validate()?;
+    let settings = config::update(|s| {
+        s.llm.model = model_config.model.clone();
+        s.llm.temperature = model_config.temperature;
+        s.llm.top_p = model_config.top_p;
+        s.llm.max_tokens = model_config.max_tokens;
+    })?;
+    Ok(settings.llm.model_config())
+}
+
+/// Tokens and estimated cost of the current (or last) task and of the session.
+#[tauri::command]
+pub fn get_llm_usage() -> Result<LlmUsage, String> {
+    let totals = USAGE.lock_or_recover();
+    Ok(LlmUsage { session: totals.session, task: totals.task, budget: config::get().task_budget })
+}
+
+/// Caps what each task may spend on the LLM; leave both unset for no limit.
+#[tauri::command]
+pub fn set_task_budget(max_tokens: Option<u64>, max_usd: Option<f64>) -> Result<TaskBudget, String> {
+    if max_tokens == Some(0) {
+        return Err("The token budget must be above zero".to_string());
+    }
+    if max_usd.is_some_and(|usd| !usd.is_finite() || usd <= 0.0) {
+        return Err("The cost budget must be a positive amount".to_string());
+    }
+    let budget = config::update(|s| s.task_budget = TaskBudget { max_tokens, max_usd })?.task_budget;
+    info!("Task budget set to {:?} tokens, {:?} USD", budget.max_tokens, budget.max_usd);
+    Ok(budget)
+}
@@ -0,0 +1,106 @@
+// --- Logging ---
+// Everything logs through `tracing`. Events go to stdout as before and to a
+// daily-rolling file under <data dir>/metis/logs, of which the last few days are
+// kept, so a user can send a log after the fact and the in-app viewer can show
+// it (get_recent_logs). Recording, post-processing and every task-loop
+// iteration run inside spans, so their lines carry the session or iteration.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use once_cell::sync::OnceCell;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
+
+const LOG_FILE_PREFIX: &str = "metis";
+const LOG_FILE_SUFFIX: &str = "log";
+const KEPT_LOG_FILES: usize = 7;
+const DEFAULT_LINES: usize = 200;
+const MAX_LINES: usize = 5000;
+
+// Flushes buffered lines when the process exits
+static FILE_GUARD: OnceCell<WorkerGuard> = OnceCell::new();
+
+pub fn log_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("metis")
+        .join("logs")
+}
+
+fn file_appender() -> Result<RollingFileAppender, String> {
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(KEPT_LOG_FILES)
+        .build(log_dir())
+        .map_err(|e| e.to_string())
+}
+
+/// Installs the global subscriber. Call once, before anything logs.
+pub fn init() {
+    install(BoxMakeWriter::new(io::stdout));
+}
+
+/// Like `init`, but the console lines go to stderr: in `--mcp` mode stdout
+/// carries the protocol (mcp.rs).
+pub fn init_stderr() {
+    install(BoxMakeWriter::new(io::stderr));
+}
+
+fn install(console: BoxMakeWriter) {
+    let file_layer = match file_appender() {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let _ = FILE_GUARD.set(guard);
+            Some(fmt::layer().with_writer(writer).with_ansi(false))
+        }
+        Err(e) => {
+            eprintln!("File logging unavailable ({}): {}", log_dir().display(), e);
+            None
+        }
+    };
+    let registry = tracing_subscriber::registry().with(fmt::layer().with_writer(console)).with(file_layer);
+    if let Err(e) = registry.try_init() {
+        eprintln!("Failed to install the log subscriber: {}", e);
+    }
+}
+
+/// Log files, oldest first (their names end in the date they cover).
+fn log_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(log_dir())
+        .map(|entries| entries.filter_map(Result::ok).map(|e| e.path()).collect())
+        .unwrap_or_default();
+    files.retain(|path| {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(LOG_FILE_PREFIX) && n.ends_with(LOG_FILE_SUFFIX))
+    });
+    files.sort();
+    files
+}
+
+/// The last `lines` lines logged (default 200), oldest first, reaching back
+/// into earlier days' files when today's is short.
+#[tauri::command]
+pub fn get_recent_logs(lines: Option<usize>) -> Result<Vec<String>, String> {
+    let wanted = lines.unwrap_or(DEFAULT_LINES).min(MAX_LINES);
+    let mut recent: Vec<String> = Vec::new();
+    for path in log_files().iter().rev() {
+        if recent.len() >= wanted {
+            break;
+        }
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let older: Vec<String> = content.lines().map(str::to_string).collect();
+        let take = (wanted - recent.len()).min(older.len());
+        let mut chunk = older[older.len() - take..].to_vec();
+        chunk.append(&mut recent);
+        recent = chunk;
+    }
+    Ok(recent)
+}
@@ -0,0 +1,311 @@
+// --- Skill Marketplace ---
+// Skill bundles are listed in a JSON catalog (settings.marketplace.catalog_url,
+// an http(s) URL or a local path): an array of SkillBundle. Each bundle ships
+// one or more .metisskill files (skill_file.rs), given relative to the catalog,
+// and may require other bundles, a minimum Metis version or a particular OS.
+//
+// install_skill_bundle resolves those requirements first. Bundles it depends on
+// are installed before it, transitively, unless a recent enough version is
+// already installed. If anything can't be satisfied, nothing is installed and
+// the error lists every unmet requirement.
+//
+// The listing can be sorted by this machine's own signals: the runs, success
+// rate and ratings of skills already installed from each bundle, falling back
+// to the catalog's rating and download count for bundles never installed.
+//
+// The admin policy's disable_marketplace turns off both the listing and installs.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config;
+use crate::error::{DependencyError, MetisError, UnmetRequirement};
+use crate::net;
+use crate::policy;
+use crate::skill_file;
+use crate::skills::{self, RankSignals, Skill, SkillSort};
+
+const METIS_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Something a bundle needs before it can be installed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SkillRef {
+    Skill {
+        id: String, // The bundle that provides the skill
+        #[serde(default)]
+        min_version: Option<String>,
+    },
+    Metis {
+        min_version: String,
+    },
+    Os {
+        os: Vec<String>, // Any of "windows", "macos", "linux"
+    },
+}
+
+impl SkillRef {
+    fn describe(&self) -> String {
+        match self {
+            SkillRef::Skill { id, min_version: Some(min) } => format!("skill {} >= {}", id, min),
+            SkillRef::Skill { id, min_version: None } => format!("skill {}", id),
+            SkillRef::Metis { min_version } => format!("Metis >= {}", min_version),
+            SkillRef::Os { os } => format!("OS {}", os.join(" or ")),
+        }
+    }
+}
+
+/// A skill listed in a bundle, for display before it is installed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledSkill {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillBundle {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub skills: Vec<BundledSkill>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub author: String,
+    pub version: String,
+    #[serde(default)]
+    pub thumbnail_url: Option<String>,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub updated_at: u64,
+    #[serde(default)]
+    pub downloads: u64,
+    #[serde(default)]
+    pub rating: f64,
+    pub files: Vec<String>, // .metisskill files, relative to the catalog
+    #[serde(default)]
+    pub requires: Vec<SkillRef>,
+}
+
+/// Compares dotted version numbers ("1.10" > "1.9"); missing parts count as 0.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |v: &str| -> Vec<u64> {
+        v.trim()
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.chars().take_while(char::is_ascii_digit).collect::<String>().parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Reads `location`, resolved against `base` (the catalog's own URL or path).
+fn fetch(base: &str, location: &str) -> Result<Vec<u8>, MetisError> {
+    if let Some(base_url) = Url::parse(base).ok().filter(|url| matches!(url.scheme(), "http" | "https")) {
+        let url = base_url
+            .join(location)
+            .map_err(|e| MetisError::InvalidArgument(format!("Invalid location '{}': {}", location, e)))?;
+        net::ensure_allowed(url.as_str()).map_err(|e| MetisError::State(e.to_string()))?;
+        let fetched = net::blocking_client(Duration::from_secs(120))
+            .and_then(|client| client.get(url.clone()).send())
+            .and_then(|resp| resp.error_for_status())
+            .and_then(|resp| resp.bytes());
+        return fetched.map(|bytes| bytes.to_vec()).map_err(|e| MetisError::State(format!("Failed to download {}: {}", url, e)));
+    }
+    // A local catalog; an absolute `location` replaces the catalog's folder
+    Ok(fs::read(Path::new(base).parent().unwrap_or(Path::new("")).join(location))?)
+}
+
+/// Refuses the marketplace when the admin policy disables it.
+fn ensure_allowed() -> Result<(), MetisError> {
+    if policy::get().disable_marketplace {
+        warn!("Admin policy: blocked the skill marketplace");
+        return Err(MetisError::State("The skill marketplace is disabled by the admin policy".to_string()));
+    }
+    Ok(())
+}
+
+fn catalog_url() -> Result<String, MetisError> {
+    config::get()
+        .marketplace
+        .catalog_url
+        .filter(|url| !url.trim().is_empty())
+        .ok_or_else(|| MetisError::State("No skill marketplace is configured".to_string()))
+}
+
+fn load_catalog(url: &str) -> Result<Vec<SkillBundle>, MetisError> {
+    // Joining a URL or path with itself yields it unchanged
+    serde_json::from_slice(&fetch(url, url)?)
+        .map_err(|e| MetisError::InvalidArgument(format!("Invalid skill catalog {}: {}", url, e)))
+}
+
+/// Walks a bundle's requirements depth first, collecting bundles to install
+/// (dependencies before the bundles that need them) and what can't be met.
+struct Resolver<'a> {
+    catalog: &'a [SkillBundle],
+    installed: &'a [Skill],
+    order: Vec<&'a SkillBundle>,
+    visiting: Vec<String>,
+    unmet: Vec<UnmetRequirement>,
+}
+
+impl<'a> Resolver<'a> {
+    fn unmet(&mut self, required_by: &str, requirement: &SkillRef, reason: impl Into<String>) {
+        self.unmet.push(UnmetRequirement {
+            required_by: required_by.to_string(),
+            requirement: requirement.describe(),
+            reason: reason.into(),
+        });
+    }
+
+    fn already_installed(&self, id: &str, min_version: Option<&str>) -> bool {
+        self.installed.iter().any(|skill| {
+            skill.bundle_id.as_deref() == Some(id)
+                && min_version.map_or(true, |min| compare_versions(&skill.version, min).is_ge())
+        })
+    }
+
+    fn visit(&mut self, bundle: &'a SkillBundle) {
+        if self.order.iter().any(|b| b.id == bundle.id) {
+            return;
+        }
+        self.visiting.push(bundle.id.clone());
+        for requirement in &bundle.requires {
+            match requirement {
+                SkillRef::Skill { id, min_version } => {
+                    if self.already_installed(id, min_version.as_deref()) {
+                        continue;
+                    }
+                    if self.visiting.contains(id) {
+                        self.unmet(&bundle.id, requirement, "circular dependency");
+                        continue;
+                    }
+                    match self.catalog.iter().find(|b| &b.id == id) {
+                        None => self.unmet(&bundle.id, requirement, "not in the marketplace"),
+                        Some(dependency) if min_version.as_deref().is_some_and(|min| compare_versions(&dependency.version, min).is_lt()) => {
+                            let reason = format!("the marketplace only has {}", dependency.version);
+                            self.unmet(&bundle.id, requirement, reason);
+                        }
+                        Some(dependency) => self.visit(dependency),
+                    }
+                }
+                SkillRef::Metis { min_version } => {
+                    if compare_versions(METIS_VERSION, min_version).is_lt() {
+                        self.unmet(&bundle.id, requirement, format!("this is Metis {}", METIS_VERSION));
+                    }
+                }
+                SkillRef::Os { os } => {
+                    if !os.iter().any(|os| os.eq_ignore_ascii_case(std::env::consts::OS)) {
+                        self.unmet(&bundle.id, requirement, format!("this is {}", std::env::consts::OS));
+                    }
+                }
+            }
+        }
+        self.visiting.pop();
+        self.order.push(bundle);
+    }
+}
+
+/// The bundles to install for `bundle_id`, dependencies first.
+fn resolve<'a>(catalog: &'a [SkillBundle], installed: &'a [Skill], bundle_id: &str) -> Result<Vec<&'a SkillBundle>, MetisError> {
+    let bundle = catalog
+        .iter()
+        .find(|b| b.id == bundle_id)
+        .ok_or_else(|| MetisError::InvalidArgument(format!("No skill bundle '{}' in the marketplace", bundle_id)))?;
+    let mut resolver = Resolver { catalog, installed, order: Vec::new(), visiting: Vec::new(), unmet: Vec::new() };
+    resolver.visit(bundle);
+    if !resolver.unmet.is_empty() {
+        return Err(DependencyError { bundle: bundle_id.to_string(), unmet: resolver.unmet }.into());
+    }
+    Ok(resolver.order)
+}
+
+/// A bundle's signals: the catalog's, plus those of its installed skills combined.
+fn bundle_signals(bundle: &SkillBundle, installed: &HashMap<&str, Vec<&Skill>>) -> RankSignals {
+    let mut signals = RankSignals { name: bundle.name.to_lowercase(), rating: bundle.rating, ..Default::default() };
+    let Some(skills) = installed.get(bundle.id.as_str()) else {
+        return signals;
+    };
+    let mut stars = Vec::new();
+    for skill in skills {
+        signals.usage.runs += skill.usage.runs;
+        signals.usage.successes += skill.usage.successes;
+        signals.usage.last_run_ms = signals.usage.last_run_ms.max(skill.usage.last_run_ms);
+        stars.extend(skill.local_rating.as_ref().map(|r| f64::from(r.stars)));
+    }
+    if !stars.is_empty() {
+        signals.stars = Some(stars.iter().sum::<f64>() / stars.len() as f64);
+    }
+    signals
+}
+
+#[tauri::command]
+pub fn get_marketplace_skill_bundles(page: Option<usize>, limit: Option<usize>, sort: Option<SkillSort>) -> Result<Vec<SkillBundle>, MetisError> {
+    ensure_allowed()?;
+    let (page, limit) = (page.unwrap_or(1).max(1), limit.unwrap_or(10).max(1));
+    let mut catalog = load_catalog(&catalog_url()?)?;
+    if let Some(sort) = sort {
+        let skills = skills::all()?;
+        let mut installed: HashMap<&str, Vec<&Skill>> = HashMap::new();
+        for skill in &skills {
+            if let Some(bundle_id) = &skill.bundle_id {
+                installed.entry(bundle_id.as_str()).or_default().push(skill);
+            }
+        }
+        // Sorted before paging, so pages follow one order
+        let mut ranked: Vec<(RankSignals, SkillBundle)> =
+            catalog.into_iter().map(|bundle| (bundle_signals(&bundle, &installed), bundle)).collect();
+        ranked.sort_by(|(a, a_bundle), (b, b_bundle)| {
+            a.compare(b, sort).then_with(|| match sort {
+                // Bundles with no local history rank by popularity
+                SkillSort::Usage | SkillSort::Recent | SkillSort::SuccessRate => b_bundle.downloads.cmp(&a_bundle.downloads),
+                SkillSort::Name | SkillSort::Rating => Ordering::Equal,
+            })
+        });
+        catalog = ranked.into_iter().map(|(_, bundle)| bundle).collect();
+    }
+    Ok(catalog.into_iter().skip((page - 1) * limit).take(limit).collect())
+}
+
+/// Installs a bundle from the marketplace along with every bundle it requires.
+/// Returns the skills installed.
+#[tauri::command]
+pub fn install_skill_bundle(bundle_id: String) -> Result<Vec<Skill>, MetisError> {
+    ensure_allowed()?;
+    let url = catalog_url()?;
+    let catalog = load_catalog(&url)?;
+    let installed = skills::all()?;
+    let order = resolve(&catalog, &installed, &bundle_id)?;
+
+    let mut skills = Vec::new();
+    for bundle in order {
+        info!("Installing skill bundle {} {}", bundle.id, bundle.version);
+        for file in &bundle.files {
+            let skill = skill_file::import_bytes(&fetch(&url, file)?, file)?;
+            skills.push(skills::modify(&skill.id, |skill| {
+                skill.bundle_id = Some(bundle.id.clone());
+                skill.version = bundle.version.clone();
+                Ok(())
+            })?);
+        }
+    }
+    Ok(skills)
+}
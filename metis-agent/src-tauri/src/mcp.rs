@@ -0,0 +1,318 @@
+// --- MCP Server ---
+// Lets external LLM clients (Claude Desktop, IDE assistants) drive the same
+// automation engine as the task loop, over the Model Context Protocol. Started
+// as `metis --mcp`, Metis opens no window and speaks JSON-RPC 2.0 on stdin and
+// stdout, one message per line, as MCP's stdio transport expects; logs go to
+// stderr and the log file. A client is configured with the Metis executable
+// and the `--mcp` argument.
+//
+// Tools:
+//   screenshot   the primary display as a PNG image
+//   read_screen  the parsed screen (the CSV the task loop's prompt gets)
+//   click, type_text, press_keys, scroll
+//                single actions, validated against the screen like the LLM's
+//   action       any one action in the task loop's grammar
+//   run_task     a whole task, run to completion by Metis's own loop
+//
+// Coordinates are in the pixels of the client's last screenshot or read_screen
+// and are mapped to the screen like the task loop's (coords.rs). Element ids
+// (click_element:id, type_into) refer to the last read_screen's CSV.
+//
+// The server is its own process, so it doesn't see a task or recording the
+// Metis window has running. Screenshots and read_screen are refused under an
+// admin policy that bans cloud LLMs, since the client may forward what's on
+// screen to one.
+
+use std::io::{self, BufRead, Write};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::DynamicImage;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::action;
+use crate::app_state::SharedAppState;
+use crate::capture;
+use crate::clock;
+use crate::error::MetisError;
+use crate::coords::CoordinateMap;
+use crate::input::{EnigoBackend, InputBackend};
+use crate::policy;
+use crate::recorder::SharedRecordingState;
+use crate::scheduler::RunTask;
+
+/// The command-line argument that starts the server instead of the app.
+pub const STDIO_FLAG: &str = "--mcp";
+
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"]; // Newest first
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+fn tool_definitions() -> Value {
+    let point = json!({
+        "type": "object",
+        "properties": {
+            "x": { "type": "integer", "description": "Pixels from the left edge, as in the screenshot" },
+            "y": { "type": "integer", "description": "Pixels from the top edge, as in the screenshot" }
+        },
+        "required": ["x", "y"]
+    });
+    json!([
+        {
+            "name": "screenshot",
+            "description": "Capture the primary display as a PNG image.",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "read_screen",
+            "description": "Capture the primary display and return its UI elements as CSV: text, type and bounding box of each, in pixels.",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "click",
+            "description": "Left-click at a point on the primary display.",
+            "inputSchema": point
+        },
+        {
+            "name": "type_text",
+            "description": "Type text into the focused control, exactly as given.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "text": { "type": "string" } },
+                "required": ["text"]
+            }
+        },
+        {
+            "name": "press_keys",
+            "description": "Press a key or shortcut, e.g. \"Enter\", \"ctrl+c\", \"ctrl+shift+t\".",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "keys": { "type": "string" } },
+                "required": ["keys"]
+            }
+        },
+        {
+            "name": "scroll",
+            "description": "Scroll vertically; positive amounts scroll down, negative up.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "amount": { "type": "integer" } },
+                "required": ["amount"]
+            }
+        },
+        {
+            "name": "action",
+            "description": format!("Perform one action written in Metis's action grammar, e.g. \"drag:(400,300)\".\n{}", action::ACTION_GRAMMAR),
+            "inputSchema": {
+                "type": "object",
+                "properties": { "action": { "type": "string" } },
+                "required": ["action"]
+            }
+        },
+        {
+            "name": "run_task",
+            "description": "Have Metis carry out a whole task on this computer with its own perception and planning loop, e.g. \"archive every email from last week\". Returns when the task ends.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "command": { "type": "string" } },
+                "required": ["command"]
+            }
+        }
+    ])
+}
+
+struct Server {
+    app_state: SharedAppState,
+    recording: SharedRecordingState,
+    run: RunTask,
+    input: Option<EnigoBackend>, // Created on first use
+    map: Option<CoordinateMap>,  // From the client's last screenshot or read_screen
+    screen_csv: Option<String>,  // From the last read_screen, if no screenshot since
+}
+
+fn text_result(text: impl Into<String>, is_error: bool) -> Value {
+    json!({ "content": [{ "type": "text", "text": text.into() }], "isError": is_error })
+}
+
+fn string_arg<'a>(arguments: &'a Value, name: &str) -> Result<&'a str, String> {
+    arguments.get(name).and_then(Value::as_str).ok_or_else(|| format!("Missing string argument '{}'", name))
+}
+
+fn int_arg(arguments: &Value, name: &str) -> Result<i64, String> {
+    arguments.get(name).and_then(Value::as_i64).ok_or_else(|| format!("Missing integer argument '{}'", name))
+}
+
+impl Server {
+    fn input(&mut self) -> Result<&mut EnigoBackend, String> {
+        if self.input.is_none() {
+            self.input = Some(EnigoBackend::new().map_err(|e| format!("Input is unavailable: {}", e))?);
+        }
+        self.input.as_mut().ok_or_else(|| "Input is unavailable".to_string())
+    }
+
+    /// Captures the screen, recording the map its pixel coordinates need.
+    fn capture(&mut self) -> Result<DynamicImage, MetisError> {
+        let image = capture::capture_screen()?;
+        let screen = match self.input() {
+            Ok(input) => input.main_display()?,
+            Err(_) => (image.width() as i32, image.height() as i32),
+        };
+        self.map = Some(CoordinateMap::for_local((image.width(), image.height()), screen));
+        self.screen_csv = None;
+        Ok(image)
+    }
+
+    /// Performs one grammar action and reports what was done.
+    fn perform(&mut self, action_str: &str) -> Value {
+        let (map, screen_csv, app_state) = (self.map.clone(), self.screen_csv.clone(), self.app_state.clone());
+        let input = match self.input() {
+            Ok(input) => input,
+            Err(e) => return text_result(e, true),
+        };
+        // Before any screenshot, the client can only mean screen coordinates
+        let map = match map {
+            Some(map) => map,
+            None => match input.main_display() {
+                Ok(screen) => CoordinateMap::identity(screen),
+                Err(e) => return text_result(e.to_string(), true),
+            },
+        };
+        let clock = clock::system();
+        match action::perform(action_str, &map, screen_csv.as_deref(), input, clock.as_ref(), &app_state) {
+            Ok(description) => text_result(description, false),
+            Err(e) => text_result(format!("{} ({})", e, e.kind()), true),
+        }
+    }
+
+    fn call_tool(&mut self, name: &str, arguments: &Value) -> Result<Value, String> {
+        let result = match name {
+            "screenshot" => {
+                if policy::get().disable_cloud_llm {
+                    return Ok(text_result("Screenshots are disabled by the admin policy", true));
+                }
+                match self.capture().and_then(|image| Ok((action::encode_png(&image)?, image))) {
+                    Ok((png, image)) => json!({
+                        "content": [
+                            { "type": "image", "data": STANDARD.encode(png), "mimeType": "image/png" },
+                            { "type": "text", "text": format!("{}x{} pixels", image.width(), image.height()) }
+                        ],
+                        "isError": false
+                    }),
+                    Err(e) => text_result(e.to_string(), true),
+                }
+            }
+            "read_screen" => {
+                if policy::get().disable_cloud_llm {
+                    return Ok(text_result("Reading the screen is disabled by the admin policy", true));
+                }
+                match self.capture().and_then(|image| Ok(action::parse_screenshot(&image)?)) {
+                    Ok(csv) => {
+                        self.screen_csv = Some(csv.clone());
+                        text_result(csv, false)
+                    }
+                    Err(e) => text_result(e.to_string(), true),
+                }
+            }
+            "click" => self.perform(&format!("click:({},{})", int_arg(arguments, "x")?, int_arg(arguments, "y")?)),
+            "type_text" => self.perform(&format!("type:'{}'", string_arg(arguments, "text")?)),
+            "press_keys" => {
+                let keys = string_arg(arguments, "keys")?.trim();
+                let verb = if keys.len() > 1 && keys.contains('+') { "keys" } else { "tap" };
+                self.perform(&format!("{}:'{}'", verb, keys))
+            }
+            "scroll" => self.perform(&format!("scroll:{}", int_arg(arguments, "amount")?)),
+            "action" => self.perform(string_arg(arguments, "action")?),
+            "run_task" => {
+                let command = string_arg(arguments, "command")?.trim().to_string();
+                if command.is_empty() {
+                    return Err("The command is empty".to_string());
+                }
+                info!("MCP: running task '{}'", command);
+                match (self.run)(&self.app_state, &self.recording, command) {
+                    Ok(message) => text_result(message, false),
+                    Err(e) => text_result(format!("Task failed: {}", e), true),
+                }
+            }
+            other => return Err(format!("Unknown tool '{}'", other)),
+        };
+        Ok(result)
+    }
+
+    /// The result of a request, or a JSON-RPC error as (code, message).
+    fn dispatch(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => {
+                let requested = params.get("protocolVersion").and_then(Value::as_str).unwrap_or_default();
+                let version = PROTOCOL_VERSIONS.iter().find(|v| **v == requested).unwrap_or(&PROTOCOL_VERSIONS[0]);
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": { "name": "metis", "version": env!("CARGO_PKG_VERSION") }
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tool_definitions() })),
+            "tools/call" => {
+                let name = params.get("name").and_then(Value::as_str).ok_or((INVALID_PARAMS, "Missing tool name".to_string()))?;
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                self.call_tool(name, &arguments).map_err(|e| (INVALID_PARAMS, e))
+            }
+            other => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", other))),
+        }
+    }
+
+    /// Handles one incoming line; returns the response, if the message wants one.
+    fn handle(&mut self, line: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => return Some(json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": e.to_string() } })),
+        };
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            if message.get("id").is_some() && message.get("result").is_none() && message.get("error").is_none() {
+                let error = json!({ "code": INVALID_REQUEST, "message": "Missing method" });
+                return Some(json!({ "jsonrpc": "2.0", "id": message["id"], "error": error }));
+            }
+            return None; // A response to something we never sent
+        };
+        let id = message.get("id")?; // Notifications (initialized, cancelled) need no answer
+        let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+        Some(match self.dispatch(method, &params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, error)) => {
+                warn!("MCP: {} failed: {}", method, error);
+                json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": error } })
+            }
+        })
+    }
+}
+
+/// Serves MCP on stdin/stdout until stdin closes, running tasks through `run`.
+pub fn serve_stdio(app_state: SharedAppState, recording: SharedRecordingState, run: RunTask) {
+    info!("MCP server started on stdio");
+    let mut server = Server { app_state, recording, run, input: None, map: None, screen_csv: None };
+    let stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("MCP: failed to read stdin: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle(&line) {
+            let mut out = stdout.lock();
+            if writeln!(out, "{}", response).and_then(|_| out.flush()).is_err() {
+                break; // The client went away
+            }
+        }
+    }
+    info!("MCP server stopped");
+}
@@ -0,0 +1,170 @@
+// --- Task Memory ---
+// The LLM's thoughts and actions used to be thrown away when a task ended, so a
+// command run every morning was worked out from scratch every morning. Each
+// run's transcript (the thought, action and any error of every step, and how
+// the run ended) is now saved in the session database's task_runs table.
+//
+// When a task starts, the saved runs whose commands are most like its own are
+// summarized for the prompt: the actions of a run that succeeded, or how far a
+// failed run got and why it stopped. Commands are compared by their words, so
+// "export the sales report" recalls "export the weekly sales report".
+//
+// Transcripts are redacted before they are saved, dry runs aren't remembered,
+// and settings.memory can turn the whole thing off.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use tracing::{info, warn};
+
+use crate::config;
+use crate::error::{ActionError, MetisError};
+use crate::recorder;
+use crate::redact;
+use crate::storage::{self, TaskRun, TaskStep};
+
+// Share of their words two commands need in common to count as similar
+const MIN_SIMILARITY: f64 = 0.5;
+// Steps of one run shown in the prompt; longer runs show their last steps
+const MAX_STEPS_SHOWN: usize = 25;
+
+fn words(command: &str) -> HashSet<String> {
+    command
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Jaccard similarity of the two commands' word sets.
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+fn summarize(run: &TaskRun) -> String {
+    let mut summary = if run.succeeded {
+        format!("Earlier run of \"{}\" succeeded ({}) with these actions:\n", run.command, run.outcome)
+    } else {
+        format!("Earlier run of \"{}\" did not finish ({}). What it tried:\n", run.command, run.outcome)
+    };
+    // A successful run's rejected and failed steps are dead ends, not the way there
+    let steps: Vec<&TaskStep> = run.steps.iter().filter(|step| !run.succeeded || step.error.is_none()).collect();
+    let skipped = steps.len().saturating_sub(MAX_STEPS_SHOWN);
+    if skipped > 0 {
+        summary.push_str(&format!("  ({} earlier steps left out)\n", skipped));
+    }
+    for (i, step) in steps.iter().enumerate().skip(skipped) {
+        summary.push_str(&format!("  {}. {}", i + 1, step.action));
+        if let Some(error) = &step.error {
+            summary.push_str(&format!(" - failed: {}", error));
+        }
+        summary.push('\n');
+    }
+    summary
+}
+
+/// The prompt section recalling past runs of commands like `command`, if any.
+pub fn recall(base: &Path, command: &str) -> Option<String> {
+    let settings = config::get().memory;
+    if !settings.enabled || settings.recalled_runs == 0 {
+        return None;
+    }
+    let runs = match storage::open(base).and_then(|db| storage::list_task_runs(&db)) {
+        Ok(runs) => runs,
+        Err(e) => {
+            warn!("Failed to read task memory: {}", e);
+            return None;
+        }
+    };
+    let wanted = words(command);
+    let mut similar: Vec<(f64, TaskRun)> = runs
+        .into_iter()
+        .map(|run| (similarity(&wanted, &words(&run.command)), run))
+        .filter(|(score, _)| *score >= MIN_SIMILARITY)
+        .collect();
+    // Successes first, then the closest commands; the list is newest first, which the stable sort keeps for ties
+    similar.sort_by(|(a_score, a), (b_score, b)| b.succeeded.cmp(&a.succeeded).then(b_score.total_cmp(a_score)));
+    if similar.is_empty() {
+        return None;
+    }
+    info!("Recalled {} earlier run(s) similar to '{}'", similar.len().min(settings.recalled_runs), command);
+    let summaries: Vec<String> = similar.iter().take(settings.recalled_runs).map(|(_, run)| summarize(run)).collect();
+    Some(summaries.join("\n"))
+}
+
+/// Collects a task run's steps and saves them when dropped, so every exit path
+/// of the action loop is remembered.
+pub struct TaskTranscript {
+    base: PathBuf,
+    command: String,
+    steps: Vec<TaskStep>,
+    outcome: Option<Result<String, String>>, // How the run ended, if the loop said
+    enabled: bool,
+}
+
+impl TaskTranscript {
+    pub fn new(base: &Path, command: &str, enabled: bool) -> Self {
+        TaskTranscript {
+            base: base.to_path_buf(),
+            command: command.to_string(),
+            steps: Vec::new(),
+            outcome: None,
+            enabled: enabled && config::get().memory.enabled,
+        }
+    }
+
+    pub fn step(&mut self, thought: &str, action: &str, error: Option<&ActionError>) {
+        self.steps.push(TaskStep {
+            thought: redact::redact(thought).into_owned(),
+            action: redact::redact(action).into_owned(),
+            error: error.map(ToString::to_string),
+        });
+    }
+
+    /// The task finished with `done`.
+    pub fn finish(&mut self, message: &str) {
+        self.outcome = Some(Ok(redact::redact(message).into_owned()));
+    }
+
+    /// The task gave up or was stopped, for `reason`.
+    pub fn stop(&mut self, reason: impl Into<String>) {
+        self.outcome = Some(Err(reason.into()));
+    }
+}
+
+impl Drop for TaskTranscript {
+    fn drop(&mut self) {
+        if !self.enabled || (self.steps.is_empty() && self.outcome.is_none()) {
+            return;
+        }
+        let (succeeded, outcome) = match self.outcome.take() {
+            Some(Ok(message)) => (true, message),
+            Some(Err(reason)) => (false, reason),
+            None => (false, "stopped by an error".to_string()),
+        };
+        let kept = config::get().memory.kept_runs.max(1);
+        let saved = storage::open(&self.base)
+            .and_then(|db| storage::record_task_run(&db, &self.command, succeeded, &outcome, &self.steps, kept));
+        if let Err(e) = saved {
+            warn!("Failed to save the task to memory: {}", e);
+        }
+    }
+}
+
+/// Every remembered task run, newest first.
+#[tauri::command]
+pub fn list_task_memory() -> Result<Vec<TaskRun>, MetisError> {
+    Ok(storage::list_task_runs(&storage::open(&recorder::get_default_base_folder())?)?)
+}
+
+/// Forgets every remembered task run.
+#[tauri::command]
+pub fn clear_task_memory() -> Result<(), MetisError> {
+    storage::clear_task_runs(&storage::open(&recorder::get_default_base_folder())?)?;
+    info!("Cleared task memory");
+    Ok(())
+}
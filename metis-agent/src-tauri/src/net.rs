@@ -0,0 +1,77 @@
+// --- Outbound Network Policy ---
+// Every HTTP/LLM request goes through this layer. In strict local-only mode
+// (settings.local_only) anything that isn't loopback is refused here, so the
+// parser and model must be local and nothing leaves the machine: no remote LLM,
+// no uploads. Clients built here also ignore system proxies in that mode, so
+// "local" traffic can't be routed off the box either.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use reqwest::Url;
+use tracing::{info, warn};
+
+use crate::config;
+use crate::error::BlockedRequest;
+use crate::policy;
+
+pub fn local_only() -> bool {
+    config::get().local_only
+}
+
+/// Whether `url` points at this machine (localhost or a loopback address).
+pub fn is_local_url(url: &str) -> bool {
+    let Ok(parsed) = Url::parse(url) else {
+        return false;
+    };
+    match parsed.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
+/// Refuses non-local requests while local-only mode is on.
+pub fn ensure_allowed(url: &str) -> Result<(), BlockedRequest> {
+    if local_only() && !is_local_url(url) {
+        warn!("Local-only mode: blocked outbound request to {}", url);
+        return Err(BlockedRequest { url: url.to_string(), reason: "Local-only mode" });
+    }
+    Ok(())
+}
+
+/// Like `ensure_allowed`, but also honours the admin policy's cloud-LLM ban.
+pub fn ensure_llm_allowed(url: &str) -> Result<(), BlockedRequest> {
+    if policy::get().disable_cloud_llm && !is_local_url(url) {
+        warn!("Admin policy: blocked cloud LLM request to {}", url);
+        return Err(BlockedRequest { url: url.to_string(), reason: "Admin policy" });
+    }
+    ensure_allowed(url)
+}
+
+/// Builds the blocking HTTP client used for all outbound requests.
+pub fn blocking_client(timeout: Duration) -> reqwest::Result<Client> {
+    let builder = Client::builder().timeout(timeout);
+    if local_only() {
+        builder.no_proxy().build()
+    } else {
+        builder.build()
+    }
+}
+
+#[tauri::command]
+pub fn set_local_only_mode(enabled: bool) -> Result<bool, String> {
+    info!("Local-only mode: {}", enabled);
+    config::update(|s| s.local_only = enabled).map(|s| s.local_only)
+}
+
+#[tauri::command]
+pub fn get_local_only_mode() -> Result<bool, String> {
+    Ok(local_only())
+}
@@ -0,0 +1,285 @@
+// --- Native Screen Parser ---
+// An in-process alternative to the Python processImage service, picked with the
+// `parser.engine` setting. Text comes from tesseract (METIS_TESSERACT, or
+// `tesseract` on the PATH) as one box per line; widgets are found by tracing
+// luma edges in the screenshot and boxing each connected outline. The CSV has
+// the columns the action prompt describes: id, class (Text or Compo),
+// column_min, row_min, column_max, row_max, width, height, content. A widget's
+// content is the text inside it, so a button carries its label.
+//
+// Without tesseract the widgets are still reported, with empty content.
+
+use std::collections::BTreeMap;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use image::{DynamicImage, GrayImage, ImageOutputFormat};
+use tracing::warn;
+
+use crate::config::{self, ParserSettings};
+use crate::error::ParserError;
+use crate::perf::{self, Stage};
+
+const CSV_HEADER: [&str; 9] = ["id", "class", "column_min", "row_min", "column_max", "row_max", "width", "height", "content"];
+const EDGE_THRESHOLD: u8 = 24; // Luma step between neighbours that counts as an edge
+const MIN_COMPONENT_SIDE: u32 = 8; // Smaller outlines are glyph fragments or noise
+const MAX_COMPONENT_SHARE: f32 = 0.9; // Wider and taller than this is the window itself
+const MAX_COMPONENTS: usize = 400;
+const MIN_OCR_CONFIDENCE: f32 = 30.0;
+
+/// Pixel box, edges inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bbox {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+impl Bbox {
+    fn width(&self) -> u32 {
+        self.right - self.left + 1
+    }
+
+    fn height(&self) -> u32 {
+        self.bottom - self.top + 1
+    }
+
+    fn union(&self, other: &Bbox) -> Bbox {
+        Bbox {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn contains(&self, other: &Bbox) -> bool {
+        self.left <= other.left && self.top <= other.top && self.right >= other.right && self.bottom >= other.bottom
+    }
+
+    fn contains_center_of(&self, other: &Bbox) -> bool {
+        let (x, y) = ((other.left + other.right) / 2, (other.top + other.bottom) / 2);
+        self.left <= x && x <= self.right && self.top <= y && y <= self.bottom
+    }
+
+    /// Grown by `margin` on every side (not clamped to the image).
+    fn grown(&self, margin: u32) -> Bbox {
+        Bbox {
+            left: self.left.saturating_sub(margin),
+            top: self.top.saturating_sub(margin),
+            right: self.right + margin,
+            bottom: self.bottom + margin,
+        }
+    }
+}
+
+struct Element {
+    class: &'static str,
+    bbox: Bbox,
+    content: String,
+}
+
+fn tesseract_binary() -> String {
+    std::env::var("METIS_TESSERACT").unwrap_or_else(|_| "tesseract".to_string())
+}
+
+/// Runs tesseract over the image and returns one Text element per recognised line.
+fn recognize_text(image: &DynamicImage, languages: &str) -> Result<Vec<Element>, ParserError> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|e| ParserError::Encode(e.to_string()))?;
+
+    // Sparse-text mode (psm 11) suits UIs better than the default page layout
+    let mut child = Command::new(tesseract_binary())
+        .args(["stdin", "stdout", "-l", languages, "--psm", "11", "tsv"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ParserError::Ocr(format!("Failed to start {} (set METIS_TESSERACT to its path): {}", tesseract_binary(), e)))?;
+    let mut stdin = child.stdin.take().ok_or_else(|| ParserError::Ocr("tesseract stdin unavailable".to_string()))?;
+    // Written from another thread so a full stdout pipe can't deadlock us
+    let writer = thread::spawn(move || stdin.write_all(&png));
+    let output = child.wait_with_output().map_err(|e| ParserError::Ocr(e.to_string()))?;
+    let _ = writer.join();
+    if !output.status.success() {
+        return Err(ParserError::Ocr(format!("tesseract exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(lines_from_tsv(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Groups tesseract's word rows (level 5) into lines.
+fn lines_from_tsv(tsv: &str) -> Vec<Element> {
+    // level page block par line word left top width height conf text
+    let mut lines: BTreeMap<(u32, u32, u32, u32), Element> = BTreeMap::new();
+    for row in tsv.lines().skip(1) {
+        let fields: Vec<&str> = row.splitn(12, '\t').collect();
+        if fields.len() < 12 || fields[0] != "5" {
+            continue;
+        }
+        let numbers: Option<Vec<u32>> = fields[1..10].iter().map(|f| f.parse().ok()).collect();
+        let confidence: f32 = fields[10].parse().unwrap_or(-1.0);
+        let text = fields[11].trim();
+        let Some(n) = numbers else { continue };
+        if text.is_empty() || confidence < MIN_OCR_CONFIDENCE || n[7] == 0 || n[8] == 0 {
+            continue;
+        }
+        let bbox = Bbox { left: n[5], top: n[6], right: n[5] + n[7] - 1, bottom: n[6] + n[8] - 1 };
+        lines
+            .entry((n[0], n[1], n[2], n[3]))
+            .and_modify(|line| {
+                line.bbox = line.bbox.union(&bbox);
+                line.content.push(' ');
+                line.content.push_str(text);
+            })
+            .or_insert_with(|| Element { class: "Text", bbox, content: text.to_string() });
+    }
+    lines.into_values().collect()
+}
+
+/// Marks pixels that differ enough from their right or lower neighbour.
+fn edge_mask(gray: &GrayImage) -> Vec<bool> {
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    let pixels = gray.as_raw();
+    let mut mask = vec![false; pixels.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let here = pixels[i];
+            let right = if x + 1 < width { pixels[i + 1] } else { here };
+            let below = if y + 1 < height { pixels[i + width] } else { here };
+            mask[i] = here.abs_diff(right).max(here.abs_diff(below)) >= EDGE_THRESHOLD;
+        }
+    }
+    mask
+}
+
+/// Boxes of the 8-connected edge regions, consuming the mask.
+fn connected_boxes(mut mask: Vec<bool>, width: usize, height: usize) -> Vec<Bbox> {
+    let mut boxes = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..mask.len() {
+        if !mask[start] {
+            continue;
+        }
+        mask[start] = false;
+        stack.push(start);
+        let (x, y) = ((start % width) as u32, (start / width) as u32);
+        let mut bbox = Bbox { left: x, top: y, right: x, bottom: y };
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            bbox = bbox.union(&Bbox { left: x as u32, top: y as u32, right: x as u32, bottom: y as u32 });
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let n = ny * width + nx;
+                    if mask[n] {
+                        mask[n] = false;
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+        boxes.push(bbox);
+    }
+    boxes
+}
+
+/// Widget outlines: edge regions that aren't noise, the whole window, or a piece of text.
+fn detect_components(gray: &GrayImage, texts: &[Element]) -> Vec<Element> {
+    let (width, height) = gray.dimensions();
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let max_width = (width as f32 * MAX_COMPONENT_SHARE) as u32;
+    let max_height = (height as f32 * MAX_COMPONENT_SHARE) as u32;
+    let text_areas: Vec<Bbox> = texts.iter().map(|t| t.bbox.grown(2)).collect();
+
+    let mut boxes: Vec<Bbox> = connected_boxes(edge_mask(gray), width as usize, height as usize)
+        .into_iter()
+        .filter(|b| b.width() >= MIN_COMPONENT_SIDE && b.height() >= MIN_COMPONENT_SIDE)
+        .filter(|b| b.width() <= max_width || b.height() <= max_height)
+        .filter(|b| !text_areas.iter().any(|area| area.contains(b)))
+        .collect();
+    // Keep the biggest when there are too many; those are the panels and buttons
+    boxes.sort_by_key(|b| std::cmp::Reverse(b.width() * b.height()));
+    boxes.dedup();
+    boxes.truncate(MAX_COMPONENTS);
+
+    boxes
+        .into_iter()
+        .map(|bbox| {
+            let content: Vec<&str> = texts
+                .iter()
+                .filter(|t| bbox.contains_center_of(&t.bbox))
+                .map(|t| t.content.as_str())
+                .collect();
+            Element { class: "Compo", bbox, content: content.join(" ") }
+        })
+        .collect()
+}
+
+fn to_csv(elements: &[Element]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_HEADER).expect("writing CSV to memory");
+    for (id, element) in elements.iter().enumerate() {
+        let b = &element.bbox;
+        writer
+            .write_record([
+                id.to_string(),
+                element.class.to_string(),
+                b.left.to_string(),
+                b.top.to_string(),
+                b.right.to_string(),
+                b.bottom.to_string(),
+                b.width().to_string(),
+                b.height().to_string(),
+                element.content.clone(),
+            ])
+            .expect("writing CSV to memory");
+    }
+    String::from_utf8(writer.into_inner().expect("writing CSV to memory")).unwrap_or_default()
+}
+
+/// The center of the element with this id in screen CSV from either engine,
+/// rounded to a pixel. Columns are found by header name; the Python backend may
+/// write the bbox as floats.
+pub fn element_center(csv: &str, id: &str) -> Option<(i32, i32)> {
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers = reader.headers().ok()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let (id_col, left, top, right, bottom) = (column("id")?, column("column_min")?, column("row_min")?, column("column_max")?, column("row_max")?);
+    let record = reader.records().filter_map(Result::ok).find(|r| r.get(id_col).is_some_and(|v| v.trim() == id))?;
+    let value = |col: usize| record.get(col)?.trim().parse::<f64>().ok();
+    let x = (value(left)? + value(right)?) / 2.0;
+    let y = (value(top)? + value(bottom)?) / 2.0;
+    Some((x.round() as i32, y.round() as i32))
+}
+
+/// Parses a screenshot into element CSV without the Python backend.
+pub fn parse(image: &DynamicImage) -> Result<String, ParserError> {
+    let settings = config::get().parser;
+    perf::time(Stage::Backend, || {
+        let texts = recognize_text(image, &settings.ocr_languages).unwrap_or_else(|e| {
+            warn!("Text recognition unavailable, reporting widgets only: {}", e);
+            Vec::new()
+        });
+        let components = detect_components(&image.to_luma8(), &texts);
+        let mut elements = texts;
+        elements.extend(components);
+        elements.sort_by_key(|e| (e.bbox.top, e.bbox.left));
+        Ok(to_csv(&elements))
+    })
+}
+
+#[tauri::command]
+pub fn get_parser_settings() -> Result<ParserSettings, String> {
+    Ok(config::get().parser)
+}
+
+#[tauri::command]
+pub fn update_parser_settings(settings: ParserSettings) -> Result<ParserSettings, String> {
+    config::update(|s| s.parser = settings).map(|s| s.parser)
+}
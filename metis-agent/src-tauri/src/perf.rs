@@ -0,0 +1,154 @@
+// --- Performance Counters ---
+// Tracks how long each stage of the capture -> parse -> LLM pipeline takes so
+// users can see where time goes on their machine and tune settings accordingly.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::info;
+
+use crate::sync::LockExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Capture,   // Grabbing pixels from the monitor
+    Encode,    // PNG + base64 encoding
+    Backend,   // Round trip to the image-processing backend
+    Llm,       // Round trip to the LLM
+    Iteration, // One full pass of the action loop
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StageStats {
+    pub count: u64,
+    pub total_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub last_ms: f64,
+    pub avg_ms: f64,
+}
+
+impl StageStats {
+    fn add(&mut self, ms: f64) {
+        if self.count == 0 || ms < self.min_ms {
+            self.min_ms = ms;
+        }
+        if ms > self.max_ms {
+            self.max_ms = ms;
+        }
+        self.count += 1;
+        self.total_ms += ms;
+        self.last_ms = ms;
+        self.avg_ms = self.total_ms / self.count as f64;
+    }
+}
+
+static PERF_STATS: Lazy<Mutex<BTreeMap<Stage, StageStats>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Records a single measurement for a pipeline stage.
+pub fn record(stage: Stage, elapsed: Duration) {
+    let mut stats = PERF_STATS.lock_or_recover();
+    stats.entry(stage).or_default().add(to_ms(elapsed));
+}
+
+/// Runs `f`, recording how long it took under `stage`.
+pub fn time<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(stage, start.elapsed());
+    result
+}
+
+fn to_ms(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkReport {
+    pub iterations: u32,
+    pub width: u32,
+    pub height: u32,
+    pub png_bytes: usize,
+    pub capture: StageStats,
+    pub encode: StageStats,
+    pub backend: Option<StageStats>, // Full get_screen_csv round trip, only when requested
+    pub total: StageStats,
+}
+
+/// Measures capture, encode, and (optionally) the backend round trip on this machine.
+fn run_benchmark_internal(iterations: u32, include_backend: bool) -> Result<BenchmarkReport, String> {
+    let mut capture = StageStats::default();
+    let mut encode = StageStats::default();
+    let mut backend = StageStats::default();
+    let mut total = StageStats::default();
+    let (mut width, mut height, mut png_bytes) = (0, 0, 0);
+
+    for i in 0..iterations {
+        info!("[Benchmark] Iteration {}/{}", i + 1, iterations);
+        let iteration_start = Instant::now();
+
+        let start = Instant::now();
+        let screenshot = crate::capture::capture_screen().map_err(|e| format!("Screen capture failed: {}", e))?;
+        capture.add(to_ms(start.elapsed()));
+        width = screenshot.width();
+        height = screenshot.height();
+
+        let start = Instant::now();
+        let mut buffer = Cursor::new(Vec::new());
+        screenshot.write_to(&mut buffer, image::ImageOutputFormat::Png)
+            .map_err(|e| format!("Failed to write PNG to buffer: {}", e))?;
+        encode.add(to_ms(start.elapsed()));
+        png_bytes = buffer.get_ref().len();
+
+        if include_backend {
+            let start = Instant::now();
+            crate::action::get_screen_csv(&crate::capture::Desktop).map_err(|e| e.to_string())?;
+            backend.add(to_ms(start.elapsed()));
+        }
+
+        total.add(to_ms(iteration_start.elapsed()));
+    }
+
+    Ok(BenchmarkReport {
+        iterations,
+        width,
+        height,
+        png_bytes,
+        capture,
+        encode,
+        backend: if include_backend { Some(backend) } else { None },
+        total,
+    })
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_perf_stats() -> Result<BTreeMap<Stage, StageStats>, String> {
+    Ok(PERF_STATS.lock_or_recover().clone())
+}
+
+#[tauri::command]
+pub fn reset_perf_stats() -> Result<(), String> {
+    PERF_STATS.lock_or_recover().clear();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn run_benchmark(iterations: Option<u32>, include_backend: Option<bool>) -> Result<BenchmarkReport, String> {
+    let iterations = iterations.unwrap_or(5).clamp(1, 100);
+    let include_backend = include_backend.unwrap_or(false);
+    info!("Run benchmark command received ({} iterations, backend: {}).", iterations, include_backend);
+
+    // Captures, encodes and backend round trips block, so they run on the blocking pool
+    match tauri::async_runtime::spawn_blocking(move || run_benchmark_internal(iterations, include_backend)).await {
+        Ok(result) => result,
+        Err(_) => Err("Benchmark thread panicked".to_string()),
+    }
+}
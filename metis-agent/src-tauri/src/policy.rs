@@ -0,0 +1,102 @@
+// --- Machine-Level Admin Policy ---
+// IT can drop a policy.json in a machine-wide location that users can't write:
+//   Windows: %ProgramData%\Metis\policy.json
+//   macOS:   /Library/Application Support/Metis/policy.json
+//   Linux:   /etc/metis/policy.json
+// It is read once at startup and always wins over user settings: config::get()
+// applies it on top of whatever the user saved, and the features it disables
+// check it directly.
+
+use std::fs;
+use std::path::PathBuf;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::config::Settings;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    pub disable_shell_actions: bool,   // No launch: actions (launcher.rs)
+    pub disable_marketplace: bool,     // No skill catalog listing or installs
+    pub disable_cloud_llm: bool,       // Only loopback model endpoints may be used
+    pub force_local_only: bool,        // Strict local-only mode, not user-switchable
+    pub force_redaction: bool,         // PII redaction can't be turned off
+    pub parser_endpoint: Option<String>, // Pinned image-processing endpoint
+    pub storage_dir: Option<String>,   // Where recordings must be stored
+    pub retention_days: Option<u32>,   // Recordings older than this are deleted
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyStatus {
+    pub path: String,
+    pub loaded: bool,
+    pub policy: Policy,
+}
+
+static POLICY: Lazy<(bool, Policy)> = Lazy::new(load);
+
+pub fn policy_path() -> PathBuf {
+    if cfg!(target_os = "windows") {
+        std::env::var_os("ProgramData")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("C:\\ProgramData"))
+            .join("Metis")
+            .join("policy.json")
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from("/Library/Application Support/Metis/policy.json")
+    } else {
+        PathBuf::from("/etc/metis/policy.json")
+    }
+}
+
+fn load() -> (bool, Policy) {
+    let path = policy_path();
+    let Ok(content) = fs::read_to_string(&path) else {
+        return (false, Policy::default()); // Unmanaged machine
+    };
+    match serde_json::from_str(&content) {
+        Ok(policy) => {
+            info!("Loaded admin policy from {}", path.display());
+            (true, policy)
+        }
+        Err(e) => {
+            // A broken managed policy must not silently unlock everything
+            error!("Invalid admin policy {}: {}. Applying the most restrictive policy.", path.display(), e);
+            (true, Policy {
+                disable_shell_actions: true,
+                disable_marketplace: true,
+                disable_cloud_llm: true,
+                force_local_only: true,
+                force_redaction: true,
+                ..Policy::default()
+            })
+        }
+    }
+}
+
+pub fn get() -> &'static Policy {
+    &POLICY.1
+}
+
+/// Overrides whatever the user configured with the policy's requirements.
+pub fn apply(settings: &mut Settings) {
+    let policy = get();
+    if policy.force_local_only {
+        settings.local_only = true;
+    }
+    if policy.force_redaction {
+        settings.redaction.enabled = true;
+    }
+}
+
+#[tauri::command]
+pub fn get_policy() -> Result<PolicyStatus, String> {
+    Ok(PolicyStatus {
+        path: policy_path().display().to_string(),
+        loaded: POLICY.0,
+        policy: get().clone(),
+    })
+}
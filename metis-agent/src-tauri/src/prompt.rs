@@ -0,0 +1,140 @@
+// --- Prompt Template ---
+// The instructions the action loop sends the LLM each step used to be a format
+// string compiled into the app. They are now a template with {{placeholders}},
+// the same syntax skills use for their arguments:
+//
+//   {{command}}           the task the user gave
+//   {{previous_actions}}  the LLM's earlier replies in this task
+//   {{context}}           the screen state, windows, memory and recordings
+//   {{grammar}}           the action grammar (action.rs)
+//
+// The default lives here. A user's own version is saved as prompt_template.txt
+// in the storage root and read at the start of each task, so edits apply to the
+// next task without a restart. Saving an empty template restores the default.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::error::MetisError;
+use crate::recorder;
+use crate::skills;
+
+const TEMPLATE_FILE: &str = "prompt_template.txt";
+const PLACEHOLDERS: &[&str] = &["command", "previous_actions", "context", "grammar"];
+// Without these the LLM can't see the screen or know how to answer
+const REQUIRED: &[&str] = &["context", "grammar"];
+
+pub const DEFAULT_TEMPLATE: &str = "\
+The command given to you was: {{command}}
+
+Previous actions: {{previous_actions}}
+
+Below is the Current Screen State (as CSV data with columns including id, class, column_min, row_min, column_max, row_max, width, height, content) and may include Relevant Historical Actions:
+
+{{context}}
+
+Based on this information, perform the following steps:
+1. First, provide a brief explanation (1-3 sentences) of your reasoning and the intended action, enclosed within <think></think> tags. Refer to element details (like id, class, content, or coordinates) from the CSV context in your reasoning.
+2. Immediately following the closing </think> tag, provide the single next action command using the exact format specified below.
+
+{{grammar}}
+Examples of the required output format:
+<think>User wants to log in. I see a button component (id: 5, class: Compo, row_min: 250, col_min: 100, row_max: 280, col_max: 150, content: 'Login'). I will click its approximate center.</think>click:(125,265)
+<think>The input field (id: 3, class: Compo, row_min: 100, col_min: 80, row_max: 120, col_max: 280) seems to be for the username based on nearby text. I will type 'testuser'.</think>type:'testuser'
+<think>The required information is below the current view. I need to scroll down the page significantly.</think>scroll:15
+<think>I see the text 'Welcome, testuser!' (id: 12, class: Text). The login was successful, fulfilling the command.</think>done:'Login successful.'
+
+Your Response:";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTemplate {
+    pub template: String,
+    pub is_default: bool,
+    pub placeholders: Vec<String>, // What the template may use
+}
+
+fn template_path(base: &Path) -> PathBuf {
+    base.join(TEMPLATE_FILE)
+}
+
+/// The user's template, or the default if there is none or it can't be read.
+pub fn load() -> String {
+    let path = template_path(&recorder::get_default_base_folder());
+    match fs::read_to_string(&path) {
+        Ok(template) if !template.trim().is_empty() => template,
+        Ok(_) => DEFAULT_TEMPLATE.to_string(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => DEFAULT_TEMPLATE.to_string(),
+        Err(e) => {
+            warn!("Cannot read {}, using the default prompt: {}", path.display(), e);
+            DEFAULT_TEMPLATE.to_string()
+        }
+    }
+}
+
+/// Fills the template's placeholders. Values are inserted as they are, so text
+/// on screen that looks like a placeholder stays as it is.
+pub fn render(template: &str, command: &str, previous_actions: &str, context: &str, grammar: &str) -> String {
+    let values: HashMap<String, String> = [
+        ("command", command),
+        ("previous_actions", previous_actions),
+        ("context", context),
+        ("grammar", grammar),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    skills::substitute(template, &values).into_owned()
+}
+
+fn validate(template: &str) -> Result<(), MetisError> {
+    let used = skills::placeholders(template);
+    if let Some(unknown) = used.iter().find(|name| !PLACEHOLDERS.contains(&name.as_str())) {
+        return Err(MetisError::InvalidArgument(format!(
+            "Unknown placeholder {{{{{}}}}}; the template may use {}",
+            unknown,
+            PLACEHOLDERS.join(", ")
+        )));
+    }
+    if let Some(missing) = REQUIRED.iter().find(|name| !used.iter().any(|used| used == *name)) {
+        return Err(MetisError::InvalidArgument(format!("The template needs {{{{{}}}}}", missing)));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_prompt_template() -> Result<PromptTemplate, MetisError> {
+    let template = load();
+    Ok(PromptTemplate {
+        is_default: template == DEFAULT_TEMPLATE,
+        template,
+        placeholders: PLACEHOLDERS.iter().map(|name| name.to_string()).collect(),
+    })
+}
+
+/// Saves the template the next tasks use; an empty one restores the default.
+#[tauri::command]
+pub fn set_prompt_template(template: String) -> Result<PromptTemplate, MetisError> {
+    let path = template_path(&recorder::get_default_base_folder());
+    if template.trim().is_empty() || template == DEFAULT_TEMPLATE {
+        match fs::remove_file(&path) {
+            Ok(()) => info!("Restored the default prompt template"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        validate(&template)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &template)?;
+        fs::rename(&tmp, &path)?;
+        info!("Saved a custom prompt template to {}", path.display());
+    }
+    get_prompt_template()
+}
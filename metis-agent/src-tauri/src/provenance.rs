@@ -0,0 +1,326 @@
+// --- Artifact Provenance ---
+// Recordings end up as training data far from the machine that made them, so
+// every artifact we write out says where it came from: the recording session
+// (or the audit log), the agent version, and the SHA-256 of its content. Screenshots
+// carry it inside the PNG as a tEXt chunk (hashed without that chunk), skill
+// archives in the zip comment (hashed with the comment empty); other files
+// (parsed CSVs, exported reports) get a `<file>.provenance.json` sidecar.
+// `verify_artifact` recomputes the hash, so edited or re-encoded files show up.
+//
+// Each record is signed with this install's Ed25519 key (created on first use in
+// <data dir>/metis/provenance.key) and carries the public half, so a consumer
+// who pins that public key can tell our artifacts from ones stamped elsewhere.
+// Without a usable key the record goes out unsigned and verifies as such.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use ring::rand::SystemRandom;
+use ring::signature::{self, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use crate::archive;
+
+const AGENT: &str = "metis-agent";
+const PNG_KEYWORD: &[u8] = b"Metis-Provenance";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const SIDECAR_SUFFIX: &str = ".provenance.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub session_id: String, // Recording session, "audit-log" for audit exports, "skill:<id>" for skills
+    pub agent: String,
+    pub agent_version: String,
+    pub created_ms: u64, // Unix milliseconds
+    pub sha256: String,  // Of the content, excluding any embedded provenance
+    #[serde(default)]
+    pub public_key: String, // hex, Ed25519 key of the install that stamped it
+    #[serde(default)]
+    pub signature: String, // hex, over this record with the signature empty
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtifactVerification {
+    pub provenance: Provenance,
+    pub embedded: bool,     // In the file itself rather than a sidecar
+    pub intact: bool,       // Content still hashes to what was recorded
+    pub signed: bool,       // The record's signature checks out against its public key
+    pub this_install: bool, // ...and that key is this install's
+}
+
+static SIGNING_KEY: Lazy<Option<Ed25519KeyPair>> = Lazy::new(load_signing_key);
+
+fn signing_key_path() -> PathBuf {
+    dirs::data_dir().unwrap_or_else(|| PathBuf::from(".")).join("metis").join("provenance.key")
+}
+
+/// This install's signing key, created on first use.
+fn load_signing_key() -> Option<Ed25519KeyPair> {
+    let path = signing_key_path();
+    if let Ok(pkcs8) = fs::read(&path) {
+        match Ed25519KeyPair::from_pkcs8(&pkcs8) {
+            Ok(key) => return Some(key),
+            Err(e) => {
+                error!("Provenance key {} is unreadable ({}); stamps will be unsigned", path.display(), e);
+                return None;
+            }
+        }
+    }
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).ok()?;
+    let saved = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, pkcs8.as_ref()));
+    if let Err(e) = saved {
+        error!("Failed to save the provenance key to {}: {}; stamps will be unsigned", path.display(), e);
+        return None;
+    }
+    Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).ok()
+}
+
+/// The bytes a record's signature covers.
+fn signed_bytes(provenance: &Provenance) -> Vec<u8> {
+    let unsigned = Provenance { signature: String::new(), ..provenance.clone() };
+    serde_json::to_vec(&unsigned).expect("provenance serializes")
+}
+
+fn sign(mut provenance: Provenance, key: Option<&Ed25519KeyPair>) -> Provenance {
+    if let Some(key) = key {
+        provenance.public_key = hex::encode(key.public_key().as_ref());
+        provenance.signature = hex::encode(key.sign(&signed_bytes(&provenance)).as_ref());
+    }
+    provenance
+}
+
+fn signature_valid(provenance: &Provenance) -> bool {
+    let (Ok(public_key), Ok(sig)) = (hex::decode(&provenance.public_key), hex::decode(&provenance.signature)) else {
+        return false;
+    };
+    UnparsedPublicKey::new(&signature::ED25519, public_key).verify(&signed_bytes(provenance), &sig).is_ok()
+}
+
+/// The verdict on `content` against the record stamped on it.
+fn check(provenance: Provenance, content: &[u8], embedded: bool, key: Option<&Ed25519KeyPair>) -> ArtifactVerification {
+    let intact = hex::encode(Sha256::digest(content)) == provenance.sha256;
+    let signed = signature_valid(&provenance);
+    let this_install = signed && key.is_some_and(|key| hex::encode(key.public_key().as_ref()) == provenance.public_key);
+    ArtifactVerification { provenance, embedded, intact, signed, this_install }
+}
+
+/// A fresh ID for one recording session.
+pub fn new_session_id() -> String {
+    let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format!("rec_{}_{:08x}", ts, rand::random::<u32>())
+}
+
+fn unsigned_provenance(content: &[u8], session_id: &str) -> Provenance {
+    Provenance {
+        session_id: session_id.to_string(),
+        agent: AGENT.to_string(),
+        agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        sha256: hex::encode(Sha256::digest(content)),
+        public_key: String::new(),
+        signature: String::new(),
+    }
+}
+
+fn provenance_for(content: &[u8], session_id: &str) -> Provenance {
+    sign(unsigned_provenance(content, session_id), SIGNING_KEY.as_ref())
+}
+
+// --- PNG chunks ---
+
+/// Splits a PNG into (chunk type, whole chunk bytes) after the signature.
+fn png_chunks(png: &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+    let mut chunks = Vec::new();
+    let mut rest = png.strip_prefix(PNG_SIGNATURE)?;
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let total = length.checked_add(12)?; // Length, type, data, CRC
+        let chunk = rest.get(..total)?;
+        chunks.push((&chunk[4..8], chunk));
+        rest = &rest[total..];
+    }
+    Some(chunks)
+}
+
+fn text_chunk(keyword: &[u8], text: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(4 + keyword.len() + 1 + text.len());
+    body.extend_from_slice(b"tEXt");
+    body.extend_from_slice(keyword);
+    body.push(0);
+    body.extend_from_slice(text);
+
+    let mut chunk = Vec::with_capacity(body.len() + 8);
+    chunk.extend_from_slice(&((body.len() - 4) as u32).to_be_bytes());
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&crc32fast::hash(&body).to_be_bytes()); // Covers type and data
+    chunk
+}
+
+/// Our provenance chunk's JSON, if `chunk` is one.
+fn provenance_text<'a>(kind: &[u8], chunk: &'a [u8]) -> Option<&'a [u8]> {
+    if kind != b"tEXt" {
+        return None;
+    }
+    let data = &chunk[8..chunk.len() - 4];
+    data.strip_prefix(PNG_KEYWORD)?.strip_prefix(b"\0")
+}
+
+/// Returns `png` with a provenance chunk right after IHDR. Anything that isn't
+/// a well-formed PNG is returned unchanged.
+pub fn stamp_png(png: &[u8], session_id: &str) -> Vec<u8> {
+    let Some(chunks) = png_chunks(png) else {
+        return png.to_vec();
+    };
+    let provenance = provenance_for(png, session_id);
+    let json = serde_json::to_vec(&provenance).expect("provenance serializes");
+
+    let mut stamped = Vec::with_capacity(png.len() + json.len() + 32);
+    stamped.extend_from_slice(PNG_SIGNATURE);
+    for (i, (_, chunk)) in chunks.iter().enumerate() {
+        stamped.extend_from_slice(chunk);
+        if i == 0 {
+            stamped.extend_from_slice(&text_chunk(PNG_KEYWORD, &json)); // IHDR must stay first
+        }
+    }
+    stamped
+}
+
+fn verify_png(png: &[u8]) -> Option<ArtifactVerification> {
+    let chunks = png_chunks(png)?;
+    let mut provenance = None;
+    let mut unstamped = PNG_SIGNATURE.to_vec();
+    for (kind, chunk) in chunks {
+        match provenance_text(kind, chunk) {
+            Some(json) if provenance.is_none() => provenance = serde_json::from_slice::<Provenance>(json).ok(),
+            _ => unstamped.extend_from_slice(chunk),
+        }
+    }
+    Some(check(provenance?, &unstamped, true, SIGNING_KEY.as_ref()))
+}
+
+// --- Zip archives ---
+
+/// Returns the zip archive `zip` (which must have no comment) with provenance
+/// in its comment.
+pub fn stamp_zip(zip: Vec<u8>, session_id: &str) -> std::io::Result<Vec<u8>> {
+    let json = serde_json::to_vec(&provenance_for(&zip, session_id)).expect("provenance serializes");
+    archive::with_comment(zip, &json)
+}
+
+fn verify_zip(zip: &[u8]) -> Option<ArtifactVerification> {
+    let (bare, comment) = archive::split_comment(zip)?;
+    let provenance = serde_json::from_slice::<Provenance>(comment).ok()?;
+    Some(check(provenance, &bare, true, SIGNING_KEY.as_ref()))
+}
+
+// --- Sidecars ---
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Writes the provenance sidecar for the file at `path`.
+pub fn stamp_file(path: &Path, session_id: &str) -> Result<(), String> {
+    let content = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let json = serde_json::to_string_pretty(&provenance_for(&content, session_id)).map_err(|e| e.to_string())?;
+    let sidecar = sidecar_path(path);
+    fs::write(&sidecar, json).map_err(|e| format!("Failed to write {}: {}", sidecar.display(), e))
+}
+
+/// Checks an artifact against its embedded or sidecar provenance.
+pub fn verify(path: &Path) -> Result<ArtifactVerification, String> {
+    let content = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if let Some(verification) = verify_png(&content).or_else(|| verify_zip(&content)) {
+        return Ok(verification);
+    }
+    let sidecar = sidecar_path(path);
+    let json = fs::read_to_string(&sidecar)
+        .map_err(|_| format!("{} has no provenance (no embedded record or {})", path.display(), sidecar.display()))?;
+    let provenance: Provenance =
+        serde_json::from_str(&json).map_err(|e| format!("Unreadable provenance in {}: {}", sidecar.display(), e))?;
+    Ok(check(provenance, &content, false, SIGNING_KEY.as_ref()))
+}
+
+#[tauri::command]
+pub fn verify_artifact(path: String) -> Result<ArtifactVerification, String> {
+    let verification = verify(Path::new(&path))?;
+    info!(
+        "Verified {} (session {}, intact: {}, signed: {}, this install: {})",
+        path, verification.provenance.session_id, verification.intact, verification.signed, verification.this_install
+    );
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn record(content: &[u8], key: &Ed25519KeyPair) -> Provenance {
+        sign(unsigned_provenance(content, "rec_1_aa"), Some(key))
+    }
+
+    #[test]
+    fn signed_records_verify_against_their_install() {
+        let (ours, theirs) = (test_key(), test_key());
+        let verification = check(record(b"content", &ours), b"content", false, Some(&ours));
+        assert!(verification.intact && verification.signed && verification.this_install);
+
+        let elsewhere = check(record(b"content", &theirs), b"content", false, Some(&ours));
+        assert!(elsewhere.signed && !elsewhere.this_install);
+    }
+
+    #[test]
+    fn edited_records_and_content_fail() {
+        let key = test_key();
+        assert!(!check(record(b"content", &key), b"edited", false, Some(&key)).intact);
+
+        let mut relabeled = record(b"content", &key);
+        relabeled.session_id = "rec_2_bb".to_string();
+        let verification = check(relabeled, b"content", false, Some(&key));
+        assert!(verification.intact && !verification.signed && !verification.this_install);
+
+        // Re-hashing edited content doesn't help without the key
+        let mut rehashed = record(b"content", &key);
+        rehashed.sha256 = hex::encode(Sha256::digest(b"edited"));
+        assert!(!check(rehashed, b"edited", false, Some(&key)).signed);
+    }
+
+    #[test]
+    fn unsigned_records_still_check_integrity() {
+        let unsigned = sign(unsigned_provenance(b"content", "rec_1_aa"), None);
+        let verification = check(unsigned, b"content", false, Some(&test_key()));
+        assert!(verification.intact && !verification.signed && !verification.this_install);
+    }
+
+    #[test]
+    fn zip_stamps_cover_the_archive() {
+        let key = test_key();
+        let mut zip = archive::ZipWriter::new();
+        zip.add("skill.json", b"{}").unwrap();
+        let zip = zip.finish().unwrap();
+        let json = serde_json::to_vec(&sign(unsigned_provenance(&zip, "skill:demo"), Some(&key))).unwrap();
+        let stamped = archive::with_comment(zip, &json).unwrap();
+        assert_eq!(archive::read_all(&stamped, u64::MAX).unwrap().len(), 1);
+
+        let (bare, comment) = archive::split_comment(&stamped).unwrap();
+        let verification = check(serde_json::from_slice(comment).unwrap(), &bare, true, Some(&key));
+        assert!(verification.intact && verification.signed && verification.this_install);
+        assert_eq!(verification.provenance.session_id, "skill:demo");
+
+        let mut edited = stamped;
+        edited[40] ^= 1;
+        let (bare, comment) = archive::split_comment(&edited).unwrap();
+        assert!(!check(serde_json::from_slice(comment).unwrap(), &bare, true, Some(&key)).intact);
+    }
+}
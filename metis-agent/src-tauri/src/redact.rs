@@ -0,0 +1,145 @@
+// --- PII Redaction ---
+// Parsed screen content is scrubbed before it is written to disk or put into an
+// LLM prompt. Matches are replaced with typed placeholders ([EMAIL], [CARD],
+// [SSN], [API_KEY]) so the model still knows a value was there; the user's own
+// patterns from the settings become [REDACTED]. Which kinds are redacted comes
+// from the settings; a per-session override can switch the pass off (or force
+// it on) until the current recording has been processed.
+
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use tracing::{info, warn};
+
+use crate::config::{self, RedactionSettings};
+use crate::policy;
+use crate::sync::LockExt;
+
+static EMAIL_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b").expect("valid email regex"));
+// 13-19 digits, optionally grouped with spaces or dashes; confirmed with a Luhn check
+static CARD_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").expect("valid card regex"));
+static SSN_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").expect("valid ssn regex"));
+// Tokens with a recognizable prefix: AWS access key IDs, GitHub, Slack, OpenAI-style
+// and Google API keys, and JWTs. Generic high-entropy strings are left alone.
+static API_KEY_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+        r"|\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+        r"|\bgithub_pat_[A-Za-z0-9_]{22,}\b",
+        r"|\bxox[abposr]-[A-Za-z0-9-]{10,}",
+        r"|\bsk-(?:proj-|ant-)?[A-Za-z0-9_-]{20,}",
+        r"|\bAIza[0-9A-Za-z_-]{35}\b",
+        r"|\beyJ[A-Za-z0-9_-]{8,}\.eyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}",
+    ))
+    .expect("valid api key regex")
+});
+
+// settings.redaction.custom_patterns, compiled once per change of the list
+static CUSTOM_RES: Lazy<Mutex<(Vec<String>, Vec<Regex>)>> = Lazy::new(|| Mutex::new((Vec::new(), Vec::new())));
+
+// None = follow settings; Some(x) = redaction forced on/off for this session
+static SESSION_OVERRIDE: Lazy<Mutex<Option<bool>>> = Lazy::new(|| Mutex::new(None));
+
+fn luhn_valid(digits: &str) -> bool {
+    let mut sum = 0;
+    for (i, d) in digits.chars().rev().filter_map(|c| c.to_digit(10)).enumerate() {
+        sum += if i % 2 == 1 {
+            let doubled = d * 2;
+            if doubled > 9 { doubled - 9 } else { doubled }
+        } else {
+            d
+        };
+    }
+    sum % 10 == 0
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    patterns
+        .iter()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| Regex::new(p).map_err(|e| format!("Invalid redaction pattern '{}': {}", p, e)))
+        .collect()
+}
+
+fn custom_patterns(patterns: &[String]) -> Vec<Regex> {
+    let mut cache = CUSTOM_RES.lock_or_recover();
+    if cache.0 != patterns {
+        // A bad pattern hand-edited into settings.toml is skipped, not fatal
+        let compiled = patterns
+            .iter()
+            .filter(|p| !p.trim().is_empty())
+            .filter_map(|p| Regex::new(p).inspect_err(|e| warn!("Skipping invalid redaction pattern '{}': {}", p, e)).ok())
+            .collect();
+        *cache = (patterns.to_vec(), compiled);
+    }
+    cache.1.clone()
+}
+
+fn is_enabled(settings: &RedactionSettings) -> bool {
+    if policy::get().force_redaction {
+        return true; // Not even a session override can switch it off
+    }
+    SESSION_OVERRIDE.lock_or_recover().unwrap_or(settings.enabled)
+}
+
+/// Redacts PII in `text` according to the current settings and session override.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let settings = config::get().redaction;
+    if !is_enabled(&settings) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut out = Cow::Borrowed(text);
+    if settings.emails && EMAIL_RE.is_match(&out) {
+        out = Cow::Owned(EMAIL_RE.replace_all(&out, "[EMAIL]").into_owned());
+    }
+    // SSNs before cards, so a dashed SSN isn't considered as a card candidate
+    if settings.ssns && SSN_RE.is_match(&out) {
+        out = Cow::Owned(SSN_RE.replace_all(&out, "[SSN]").into_owned());
+    }
+    if settings.credit_cards && CARD_RE.is_match(&out) {
+        let replaced = CARD_RE.replace_all(&out, |caps: &Captures| {
+            let digits: String = caps[0].chars().filter(char::is_ascii_digit).collect();
+            if luhn_valid(&digits) { "[CARD]".to_string() } else { caps[0].to_string() }
+        });
+        out = Cow::Owned(replaced.into_owned());
+    }
+    if settings.api_keys && API_KEY_RE.is_match(&out) {
+        out = Cow::Owned(API_KEY_RE.replace_all(&out, "[API_KEY]").into_owned());
+    }
+    for re in custom_patterns(&settings.custom_patterns) {
+        if re.is_match(&out) {
+            out = Cow::Owned(re.replace_all(&out, "[REDACTED]").into_owned());
+        }
+    }
+    out
+}
+
+/// Clears the session override; called once a recording has been processed.
+pub fn reset_session_override() {
+    *SESSION_OVERRIDE.lock_or_recover() = None;
+}
+
+/// Forces redaction on or off for the current session; `None` goes back to the settings.
+#[tauri::command]
+pub fn set_redaction_override(enabled: Option<bool>) -> Result<(), String> {
+    info!("Redaction override for this session: {:?}", enabled);
+    *SESSION_OVERRIDE.lock_or_recover() = enabled;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_redaction_settings() -> Result<RedactionSettings, String> {
+    Ok(config::get().redaction)
+}
+
+#[tauri::command]
+pub fn update_redaction_settings(settings: RedactionSettings) -> Result<RedactionSettings, String> {
+    compile(&settings.custom_patterns)?;
+    config::update(|s| s.redaction = settings).map(|s| s.redaction)
+}
@@ -0,0 +1,375 @@
+// --- Macro Replay ---
+// Plays a recorded action folder back through enigo without the LLM: each
+// processed CSV carries the frame's action label, mouse position and
+// action_number, and the session database the frame's capture time, which
+// paces the replay.
+// Mouse presses and releases replay at their recorded positions (so drags come
+// back as drags) and single key presses as the same key. A recording only
+// knows that a typing burst or a scroll happened, not what was typed or how
+// far, so those steps, and password entry, are skipped and reported.
+// Replay runs in the background like a task (tasks.rs) and holds
+// ExecutingAction, so ESC and cancel_task stop it, and its input goes into the
+// audit log. A button still held when replay ends, however it ends (the
+// recording stops mid-drag, ESC, a failed step), is released.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use enigo::{Direction, Key};
+use tauri::State;
+use tracing::{info, warn};
+
+use crate::action::{self, ParsedKey};
+use crate::app_state::{ExecutionGuard, SharedAppState};
+use crate::audit::{self, AuditedInput};
+use crate::clock::{self, Clock};
+use crate::error::MetisError;
+use crate::input::{EnigoBackend, InputBackend};
+use crate::keystore;
+use crate::recorder::{self, SharedRecordingState};
+use crate::storage;
+use crate::sync::LockExt;
+use crate::tasks;
+
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 10.0;
+
+#[derive(Debug)]
+struct Step {
+    number: u32,
+    timestamp_ms: u64,
+    action: String,
+    mouse: (i32, i32),
+}
+
+enum Replayed {
+    Press(Direction),
+    Key(Key),
+    Skipped(&'static str),
+}
+
+/// What a recorded action label replays as.
+fn interpret(label: &str) -> Replayed {
+    match label {
+        "MousePress" => Replayed::Press(Direction::Press),
+        "MouseRelease" => Replayed::Press(Direction::Release),
+        "MouseScroll" => Replayed::Skipped("scroll amounts are not recorded"),
+        "Typing" => Replayed::Skipped("typed text is not recorded"),
+        recorder::SECURE_INPUT_LABEL => Replayed::Skipped("password entry is never recorded"),
+        _ => match label.strip_prefix("KeyPress_").and_then(recorded_key) {
+            Some(key) => Replayed::Key(key),
+            None => Replayed::Skipped("unknown action"),
+        },
+    }
+}
+
+/// The key in a `KeyPress_` label: a produced character in quotes, or an rdev key name.
+fn recorded_key(name: &str) -> Option<Key> {
+    if let Some(c) = name.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')) {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(Key::Unicode(c)),
+            _ => None,
+        };
+    }
+    let name = match name {
+        "ShiftLeft" | "ShiftRight" => "Shift",
+        "ControlLeft" | "ControlRight" => "Control",
+        "MetaLeft" | "MetaRight" => "Meta",
+        "AltGr" => "Alt",
+        // Letter and digit keys that produced no character (e.g. under a modifier)
+        other => other.strip_prefix("Key").or_else(|| other.strip_prefix("Num")).filter(|rest| rest.len() == 1).unwrap_or(other),
+    };
+    match action::parse_key_name(name).ok()? {
+        ParsedKey::Key(key) => Some(key),
+        ParsedKey::Char(c) => Some(Key::Unicode(c.to_ascii_lowercase())),
+    }
+}
+
+/// The replay step stored in one processed CSV (every row carries the same action).
+fn read_step(content: &str, timestamp_ms: u64) -> Result<Step, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers: Vec<String> = reader.headers().map_err(|e| e.to_string())?.iter().map(str::to_string).collect();
+    // The recorder appends its columns after the parser's, so take the last match
+    let column = |name: &str| headers.iter().rposition(|h| h == name).ok_or_else(|| format!("no {} column", name));
+    let (action, x, y, number) = (column("action")?, column("mouse_x")?, column("mouse_y")?, column("action_number")?);
+    let row = reader.records().next().ok_or("no rows")?.map_err(|e| e.to_string())?;
+    let field = |i: usize| row.get(i).unwrap_or_default().trim();
+    Ok(Step {
+        number: field(number).parse().map_err(|_| format!("bad action_number {:?}", field(number)))?,
+        timestamp_ms,
+        action: field(action).to_string(),
+        mouse: (field(x).parse().unwrap_or(0), field(y).parse().unwrap_or(0)),
+    })
+}
+
+/// Every recorded step of an action folder, in action_number order. The
+/// session database lists the folder's CSVs with their frames' capture times.
+fn load_steps(base_folder: &Path, action_folder: &str) -> Result<Vec<Step>, String> {
+    let folder = base_folder.join("encrypted_csv").join(action_folder);
+    let actions = storage::open(base_folder)
+        .and_then(|db| storage::list_actions(&db, action_folder))
+        .map_err(|e| format!("Failed to read the recorded actions of '{}': {}", action_folder, e))?;
+    let mut steps = Vec::new();
+    for recorded in actions {
+        let path = folder.join(&recorded.csv_file);
+        let content = if recorded.csv_file.ends_with(keystore::SEALED_SUFFIX) {
+            keystore::read_sealed(&path).map_err(|e| e.to_string())
+        } else {
+            fs::read_to_string(&path).map_err(|e| e.to_string())
+        };
+        match content.and_then(|content| read_step(&content, recorded.frame.timestamp_ms)) {
+            Ok(step) => steps.push(step),
+            Err(e) => warn!("Skipping {} in replay: {}", path.display(), e),
+        }
+    }
+    steps.sort_by_key(|step| (step.number, step.timestamp_ms));
+    Ok(steps)
+}
+
+fn replay(app_state: &SharedAppState, steps: &[Step], speed: f64, input: &mut dyn InputBackend, clock: &dyn Clock) -> Result<(usize, usize), String> {
+    let mut button_held = false;
+    let result = replay_steps(app_state, steps, speed, input, clock, &mut button_held);
+    if button_held {
+        if let Err(e) = input.left_button(Direction::Release) {
+            warn!("Failed to release the mouse button after replay: {}", e);
+        }
+    }
+    result
+}
+
+/// Plays `steps`, keeping `button_held` up to date for `replay` to clean up after.
+fn replay_steps(
+    app_state: &SharedAppState,
+    steps: &[Step],
+    speed: f64,
+    input: &mut dyn InputBackend,
+    clock: &dyn Clock,
+    button_held: &mut bool,
+) -> Result<(usize, usize), String> {
+    let (mut replayed, mut skipped) = (0, 0);
+    let mut previous_ms = steps.first().map(|step| step.timestamp_ms).unwrap_or_default();
+    for step in steps {
+        let gap = Duration::from_millis(step.timestamp_ms.saturating_sub(previous_ms)).div_f64(speed);
+        previous_ms = step.timestamp_ms;
+        action::wait(app_state, clock, gap);
+        if app_state.lock_or_recover().action_interrupted {
+            return Err(format!("Replay interrupted by user after {} step(s).", replayed));
+        }
+        let result = match interpret(&step.action) {
+            Replayed::Press(direction) => input.move_mouse(step.mouse.0, step.mouse.1).and_then(|_| {
+                // Counted as held from the attempt on: a failed press may still have gone down
+                *button_held = direction == Direction::Press;
+                input.left_button(direction)
+            }),
+            Replayed::Key(key) => input.key(key, Direction::Click),
+            Replayed::Skipped(reason) => {
+                info!("Replay step {} ({}) skipped: {}", step.number, step.action, reason);
+                skipped += 1;
+                continue;
+            }
+        };
+        result.map_err(|e| format!("Replay step {} ({}) failed: {}", step.number, step.action, e))?;
+        info!("Replayed step {}: {} at {:?}", step.number, step.action, step.mouse);
+        replayed += 1;
+    }
+    Ok((replayed, skipped))
+}
+
+/// Replays a recorded action folder (under encrypted_csv) with its original
+/// timing, `speed` times as fast (default 1). Returns a task ID at once; poll
+/// get_task_status for the outcome.
+#[tauri::command]
+pub async fn replay_action(
+    app_state: State<'_, SharedAppState>,
+    recording: State<'_, SharedRecordingState>,
+    action_folder: String,
+    speed: Option<f64>,
+) -> Result<String, String> {
+    if action_folder.is_empty() || action_folder.contains(['/', '\\']) || action_folder.starts_with('.') {
+        return Err(format!("Invalid action folder '{}'", action_folder));
+    }
+    let speed = speed.unwrap_or(1.0);
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        return Err(format!("Replay speed must be between {} and {}", MIN_SPEED, MAX_SPEED));
+    }
+    let base_folder = recording
+        .lock_or_recover()
+        .base_folder
+        .clone()
+        .map(Into::into)
+        .unwrap_or_else(recorder::get_default_base_folder);
+    let steps = load_steps(&base_folder, &action_folder)?;
+    if steps.is_empty() {
+        return Err(format!("No processed actions found in '{}'", action_folder));
+    }
+    info!("Replaying {} step(s) of {} at {}x speed.", steps.len(), action_folder, speed);
+
+    let app_state = Arc::clone(&app_state);
+    Ok(tasks::spawn(format!("Replay {}", action_folder), move || {
+        let _execution = ExecutionGuard::acquire(&app_state).map_err(|e| MetisError::State(format!("Cannot start replay: {}", e)))?;
+        let clock = clock::system();
+        let mut enigo = EnigoBackend::new().map_err(MetisError::State)?;
+        let mut input = AuditedInput::new(&mut enigo, audit::new_run_id());
+        let (replayed, skipped) = replay(&app_state, &steps, speed, &mut input, clock.as_ref()).map_err(MetisError::State)?;
+        Ok(format!("Replay completed: {} step(s) replayed, {} skipped.", replayed, skipped))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state;
+    use crate::clock::ManualClock;
+    use crate::error::ActionError;
+    use crate::input::{InputCall, MockInput};
+
+    fn step(number: u32, action: &str) -> Step {
+        Step { number, timestamp_ms: u64::from(number) * 100, action: action.to_string(), mouse: (10 * number as i32, 20) }
+    }
+
+    /// MockInput that interrupts replay on the first press, or fails every key.
+    struct Disrupted {
+        mock: MockInput,
+        app_state: SharedAppState,
+        interrupt_on_press: bool,
+    }
+
+    impl InputBackend for Disrupted {
+        fn name(&self) -> &'static str {
+            "disrupted"
+        }
+
+        fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError> {
+            self.mock.move_mouse(x, y)
+        }
+
+        fn left_button(&mut self, direction: Direction) -> Result<(), ActionError> {
+            if self.interrupt_on_press && direction == Direction::Press {
+                self.app_state.lock_or_recover().action_interrupted = true;
+            }
+            self.mock.left_button(direction)
+        }
+
+        fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError> {
+            self.mock.key(key, direction)?;
+            Err(ActionError::Device("key injection failed".to_string()))
+        }
+
+        fn text(&mut self, text: &str) -> Result<(), ActionError> {
+            self.mock.text(text)
+        }
+
+        fn scroll(&mut self, units: i32) -> Result<(), ActionError> {
+            self.mock.scroll(units)
+        }
+
+        fn hscroll(&mut self, units: i32) -> Result<(), ActionError> {
+            self.mock.hscroll(units)
+        }
+
+        fn main_display(&self) -> Result<(i32, i32), ActionError> {
+            self.mock.main_display()
+        }
+    }
+
+    fn replay_disrupted(steps: &[Step], interrupt_on_press: bool) -> (Result<(usize, usize), String>, Vec<InputCall>) {
+        let app_state = app_state::new_shared();
+        let mut input = Disrupted { mock: MockInput::new(1920, 1080), app_state: Arc::clone(&app_state), interrupt_on_press };
+        let result = replay(&app_state, steps, 1.0, &mut input, &ManualClock::new());
+        (result, input.mock.calls)
+    }
+
+    #[test]
+    fn drags_replay_at_their_positions() {
+        let steps = [step(1, "MousePress"), step(2, "Typing"), step(3, "MouseRelease"), step(4, "KeyPress_Return")];
+        let mut input = MockInput::new(1920, 1080);
+        let result = replay(&app_state::new_shared(), &steps, 2.0, &mut input, &ManualClock::new());
+        assert_eq!(result, Ok((3, 1)));
+        assert_eq!(input.calls, vec![
+            InputCall::MoveMouse(10, 20),
+            InputCall::LeftButton(Direction::Press),
+            InputCall::MoveMouse(30, 20),
+            InputCall::LeftButton(Direction::Release),
+            InputCall::Key(Key::Return, Direction::Click),
+        ]);
+    }
+
+    #[test]
+    fn a_recording_that_ends_mid_drag_releases_the_button() {
+        let mut input = MockInput::new(1920, 1080);
+        replay(&app_state::new_shared(), &[step(1, "MousePress")], 1.0, &mut input, &ManualClock::new()).unwrap();
+        assert_eq!(input.calls.last(), Some(&InputCall::LeftButton(Direction::Release)));
+    }
+
+    #[test]
+    fn interrupting_mid_drag_releases_the_button() {
+        let (result, calls) = replay_disrupted(&[step(1, "MousePress"), step(2, "MouseRelease")], true);
+        assert!(result.unwrap_err().contains("interrupted"));
+        assert_eq!(calls, vec![
+            InputCall::MoveMouse(10, 20),
+            InputCall::LeftButton(Direction::Press),
+            InputCall::LeftButton(Direction::Release),
+        ]);
+    }
+
+    #[test]
+    fn a_failed_step_mid_drag_releases_the_button() {
+        let (result, calls) = replay_disrupted(&[step(1, "MousePress"), step(2, "KeyPress_Tab")], false);
+        assert!(result.unwrap_err().contains("Replay step 2"));
+        assert_eq!(calls.last(), Some(&InputCall::LeftButton(Direction::Release)));
+    }
+
+    #[test]
+    fn nothing_is_released_that_was_not_held() {
+        let (result, calls) = replay_disrupted(&[step(1, "MousePress"), step(2, "MouseRelease"), step(3, "KeyPress_Tab")], false);
+        assert!(result.is_err());
+        assert_eq!(calls.iter().filter(|call| **call == InputCall::LeftButton(Direction::Release)).count(), 1);
+    }
+
+    #[test]
+    fn read_step_takes_the_recorder_columns() {
+        // The parser's own action/mouse columns come first; the recorder's are appended
+        let csv = "id,action,mouse_x,mouse_y,text,action,mouse_x,mouse_y,action_number\n\
+                   1,parsed,1,2,OK,MousePress,640,360,7\n\
+                   2,parsed,3,4,Cancel,MousePress,640,360,7\n";
+        let step = read_step(csv, 1234).unwrap();
+        assert_eq!((step.number, step.timestamp_ms, step.action.as_str(), step.mouse), (7, 1234, "MousePress", (640, 360)));
+    }
+
+    #[test]
+    fn read_step_rejects_incomplete_csvs() {
+        assert!(read_step("action,mouse_x,mouse_y\nMousePress,1,2\n", 0).unwrap_err().contains("action_number"));
+        assert!(read_step("action,mouse_x,mouse_y,action_number\n", 0).unwrap_err().contains("no rows"));
+        assert!(read_step("action,mouse_x,mouse_y,action_number\nMousePress,1,2,x\n", 0).unwrap_err().contains("bad action_number"));
+        // Unparsable positions fall back to the origin rather than dropping the step
+        assert_eq!(read_step("action,mouse_x,mouse_y,action_number\nTyping,,?,3\n", 0).unwrap().mouse, (0, 0));
+    }
+
+    #[test]
+    fn recorded_keys_map_to_enigo_keys() {
+        assert_eq!(recorded_key("'a'"), Some(Key::Unicode('a')));
+        assert_eq!(recorded_key("'é'"), Some(Key::Unicode('é')));
+        assert_eq!(recorded_key("'ab'"), None);
+        assert_eq!(recorded_key("''"), None);
+        assert_eq!(recorded_key("ShiftRight"), Some(Key::Shift));
+        assert_eq!(recorded_key("ControlLeft"), Some(Key::Control));
+        assert_eq!(recorded_key("MetaLeft"), Some(Key::Meta));
+        assert_eq!(recorded_key("AltGr"), Some(Key::Alt));
+        assert_eq!(recorded_key("KeyA"), Some(Key::Unicode('a')));
+        assert_eq!(recorded_key("Num7"), Some(Key::Unicode('7')));
+        assert_eq!(recorded_key("Return"), Some(Key::Return));
+        assert_eq!(recorded_key("NoSuchKey"), None);
+    }
+
+    #[test]
+    fn labels_replay_as_presses_keys_or_skips() {
+        assert!(matches!(interpret("MousePress"), Replayed::Press(Direction::Press)));
+        assert!(matches!(interpret("KeyPress_Tab"), Replayed::Key(Key::Tab)));
+        assert!(matches!(interpret("Typing"), Replayed::Skipped(_)));
+        assert!(matches!(interpret(recorder::SECURE_INPUT_LABEL), Replayed::Skipped(_)));
+        assert!(matches!(interpret("KeyPress_NoSuchKey"), Replayed::Skipped("unknown action")));
+    }
+}
@@ -0,0 +1,236 @@
+// --- Retention ---
+// Recordings pile up: every session keeps its parsed CSVs, unprocessed frames
+// stay in images/ until processed, and session videos are large. A sweep, run
+// once per launch off the startup path and again when the policy changes,
+// deletes the oldest of them (action folders, raw frames, videos) that are older
+// than settings.retention.days (or the admin policy's retention_days, whichever
+// is stricter), then keeps deleting oldest-first while Metis uses more than
+// settings.retention.max_gb. Deleted sessions are dropped from the database too.
+// get_storage_usage reports the space each kind of data takes.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use tauri::State;
+use tracing::{error, info, warn};
+
+use crate::app_state::{AppInputState, SharedAppState};
+use crate::config::{self, RetentionSettings};
+use crate::frames;
+use crate::policy;
+use crate::recorder;
+use crate::storage;
+use crate::storage_root::MANAGED_ENTRIES;
+use crate::sync::LockExt;
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsage {
+    pub path: String,
+    pub total_bytes: u64,
+    pub sessions_bytes: u64, // Processed action folders
+    pub session_count: usize,
+    pub raw_frames_bytes: u64, // Screenshots not yet processed
+    pub raw_frame_count: usize,
+    pub video_bytes: u64,
+    pub database_bytes: u64,
+    pub limit_bytes: Option<u64>, // From settings.retention.max_gb
+}
+
+enum Item {
+    Session(PathBuf),
+    Frame(String), // File name under images/
+    Video(Vec<PathBuf>), // The video and its .json sidecar
+}
+
+struct Candidate {
+    item: Item,
+    modified: SystemTime,
+    bytes: u64,
+}
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path).and_then(|m| m.modified()).unwrap_or_else(|_| SystemTime::now())
+}
+
+/// Bytes taken by a file, or by everything under a directory.
+pub fn size_of(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.filter_map(Result::ok).map(|e| size_of(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(Result::ok).map(|e| e.path()).collect())
+        .unwrap_or_default()
+}
+
+fn action_folders(base: &Path) -> Vec<PathBuf> {
+    entries(&base.join("encrypted_csv")).into_iter().filter(|path| path.is_dir()).collect()
+}
+
+/// Unprocessed frame files named in the index, each once.
+fn raw_frames(images_dir: &Path) -> io::Result<Vec<String>> {
+    let mut files: Vec<String> = frames::load(images_dir)?.into_iter().map(|meta| meta.file).collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Everything the sweep may delete, oldest first.
+fn candidates(base: &Path) -> io::Result<Vec<Candidate>> {
+    let mut candidates: Vec<Candidate> = action_folders(base)
+        .into_iter()
+        .map(|path| Candidate { modified: modified(&path), bytes: size_of(&path), item: Item::Session(path) })
+        .collect();
+
+    let images_dir = base.join("images");
+    for file in raw_frames(&images_dir)? {
+        let path = images_dir.join(&file);
+        if path.is_file() {
+            candidates.push(Candidate { modified: modified(&path), bytes: size_of(&path), item: Item::Frame(file) });
+        }
+    }
+
+    for path in entries(&base.join("video")) {
+        if path.extension().is_some_and(|ext| ext == "json") {
+            continue; // Goes with its video
+        }
+        let sidecar = path.with_extension("json");
+        let bytes = size_of(&path) + size_of(&sidecar);
+        candidates.push(Candidate { modified: modified(&path), bytes, item: Item::Video(vec![path, sidecar]) });
+    }
+
+    candidates.sort_by_key(|c| c.modified);
+    Ok(candidates)
+}
+
+fn total_bytes(base: &Path) -> u64 {
+    MANAGED_ENTRIES.iter().map(|name| size_of(&base.join(name))).sum()
+}
+
+fn limit_bytes(settings: &RetentionSettings) -> Option<u64> {
+    settings.max_gb.filter(|gb| *gb > 0.0).map(|gb| (gb * BYTES_PER_GB) as u64)
+}
+
+/// Applies the retention settings and policy. Returns how many items were deleted.
+pub fn sweep() -> io::Result<usize> {
+    let settings = config::get().retention;
+    let days = match (settings.days, policy::get().retention_days) {
+        (Some(mine), Some(admin)) => Some(mine.min(admin)),
+        (mine, admin) => mine.or(admin),
+    };
+    let limit = limit_bytes(&settings);
+    let base = recorder::get_default_base_folder();
+    if (days.is_none() && limit.is_none()) || !base.exists() {
+        return Ok(0);
+    }
+    let cutoff = days.map(|days| SystemTime::now() - Duration::from_secs(u64::from(days) * SECS_PER_DAY));
+
+    let mut usage = total_bytes(&base);
+    let mut removed = 0;
+    let mut removed_sessions = Vec::new();
+    for candidate in candidates(&base)? {
+        let expired = cutoff.is_some_and(|cutoff| candidate.modified < cutoff);
+        let over = limit.is_some_and(|limit| usage > limit);
+        if !expired && !over {
+            break; // Everything after this is newer, and we're under the limit
+        }
+        match &candidate.item {
+            Item::Session(path) => {
+                fs::remove_dir_all(path)?;
+                removed_sessions.push(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+            }
+            Item::Frame(file) => fs::remove_file(base.join("images").join(file))?,
+            Item::Video(paths) => {
+                for path in paths.iter().filter(|path| path.exists()) {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        usage = usage.saturating_sub(candidate.bytes);
+        removed += 1;
+    }
+
+    // Keep the frame index and the database in step with what is left
+    let images_dir = base.join("images");
+    let kept: Vec<_> = frames::load(&images_dir)?.into_iter().filter(|meta| images_dir.join(&meta.file).exists()).collect();
+    frames::rewrite(&images_dir, &kept)?;
+    if !removed_sessions.is_empty() {
+        match storage::open(&base) {
+            Ok(db) => {
+                for location in &removed_sessions {
+                    if let Err(e) = storage::delete_session(&db, location) {
+                        warn!("Failed to drop {} from the session database: {}", location, e);
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to open the session database: {}", e),
+        }
+    }
+    Ok(removed)
+}
+
+/// Runs a sweep on its own thread, logging the outcome.
+pub fn sweep_in_background() {
+    thread::spawn(|| match sweep() {
+        Ok(0) => {}
+        Ok(removed) => info!("Retention: removed {} old item(s).", removed),
+        Err(e) => error!("Retention cleanup failed: {}", e),
+    });
+}
+
+#[tauri::command]
+pub fn get_retention_policy() -> Result<RetentionSettings, String> {
+    Ok(config::get().retention)
+}
+
+/// Keeps recordings for `days` and within `max_gb` (either may be unset), and
+/// applies the new policy right away unless a recording or task is running.
+#[tauri::command]
+pub fn set_retention_policy(app_state: State<'_, SharedAppState>, days: Option<u32>, max_gb: Option<f64>) -> Result<RetentionSettings, String> {
+    if days == Some(0) {
+        return Err("Retention must be at least one day".to_string());
+    }
+    if max_gb.is_some_and(|gb| !gb.is_finite() || gb <= 0.0) {
+        return Err(format!("Storage limit must be a positive number of GB, got {}", max_gb.unwrap_or_default()));
+    }
+    let settings = config::update(|s| s.retention = RetentionSettings { days, max_gb })?.retention;
+    if app_state.lock_or_recover().input_state() == AppInputState::Idle {
+        sweep_in_background();
+    }
+    Ok(settings)
+}
+
+#[tauri::command]
+pub fn get_storage_usage() -> Result<StorageUsage, String> {
+    let base = recorder::get_default_base_folder();
+    let sessions = action_folders(&base);
+    let images_dir = base.join("images");
+    let frames = raw_frames(&images_dir).map_err(|e| format!("Failed to read the frame index: {}", e))?;
+    let database_bytes = ["metis.db", "metis.db-wal", "metis.db-shm"].iter().map(|name| size_of(&base.join(name))).sum();
+    Ok(StorageUsage {
+        path: base.to_string_lossy().into_owned(),
+        total_bytes: total_bytes(&base),
+        sessions_bytes: sessions.iter().map(|path| size_of(path)).sum(),
+        session_count: sessions.len(),
+        raw_frames_bytes: frames.iter().map(|file| size_of(&images_dir.join(file))).sum(),
+        raw_frame_count: frames.len(),
+        video_bytes: size_of(&base.join("video")),
+        database_bytes,
+        limit_bytes: limit_bytes(&config::get().retention),
+    })
+}
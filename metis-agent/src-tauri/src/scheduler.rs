@@ -0,0 +1,395 @@
+// --- Task Scheduler ---
+// Runs task commands unattended at times given by a cron expression: the five
+// classic fields (minute hour day-of-month month day-of-week) with *, lists,
+// ranges and /steps, or one of @hourly, @daily, @weekly, @monthly, @yearly.
+// Times are wall-clock minutes at the schedule's fixed UTC offset (the UI sends
+// the user's current one), so a daylight saving change shifts them by an hour.
+//
+// Schedules live in schedules.json in the storage root, each with its next run
+// time, so they survive restarts. A background thread checks them every few
+// seconds. A due task only starts while the app is Idle; while recording or
+// running another task it waits, and a run that can't start within
+// MISSED_GRACE (including runs missed while Metis was closed) is skipped.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::app_state::{AppInputState, SharedAppState};
+use crate::error::MetisError;
+use crate::recorder::{self, SharedRecordingState};
+use crate::sync::LockExt;
+use crate::tasks;
+
+const SCHEDULES_FILE: &str = "schedules.json";
+const TICK: Duration = Duration::from_secs(15);
+const MISSED_GRACE_MS: u64 = 10 * 60 * 1000;
+const SEARCH_DAYS: i64 = 4 * 366 + 1; // Every day/month/weekday combination recurs within four years
+
+static STORE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Runs a scheduled command to completion, like start_act on this desktop.
+pub type RunTask = fn(&SharedAppState, &SharedRecordingState, String) -> Result<String, MetisError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub command: String,
+    pub cron: String,
+    #[serde(default)]
+    pub utc_offset_minutes: i32, // Offset of the wall clock the cron fields refer to
+    pub created_ms: u64,
+    pub next_run_ms: Option<u64>, // None once the expression can no longer fire
+    #[serde(default)]
+    pub last_run_ms: Option<u64>,
+    #[serde(default)]
+    pub last_task_id: Option<String>, // For get_task_status
+}
+
+/// A parsed cron expression; each field is a bit set of the values it allows.
+#[derive(Debug, Clone, Copy)]
+struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,     // 1-31
+    months: u64,   // 1-12
+    weekdays: u64, // 0-6, Sunday first
+    any_day: bool, // Day-of-month is *
+    any_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(|| format!("Invalid step in '{}'", part))?),
+            None => (part, 1),
+        };
+        let value = |v: &str| v.parse::<u32>().ok().filter(|v| (min..=max).contains(v)).ok_or_else(|| format!("'{}' is not in {}-{}", v, min, max));
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if part.contains('/') => (value(range)?, max), // "5/15": from 5, every 15
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("Empty range '{}'", range));
+        }
+        bits |= (start..=end).step_by(step as usize).fold(0, |bits, v| bits | 1 << v);
+    }
+    Ok(bits)
+}
+
+impl CronExpr {
+    fn parse(expr: &str) -> Result<CronExpr, String> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("Expected 5 fields (minute hour day month weekday), got {}", fields.len()));
+        };
+        let weekdays = parse_field(weekday, 0, 7)?;
+        Ok(CronExpr {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays: (weekdays | weekdays >> 7) & 0x7f, // 7 is Sunday too
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Classic cron: when both day fields are restricted, either one matching is enough.
+    fn day_matches(&self, day: u32, weekday: u32) -> bool {
+        let (day_ok, weekday_ok) = (self.days & 1 << day != 0, self.weekdays & 1 << weekday != 0);
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday_ok,
+            (false, true) => day_ok,
+            (false, false) => day_ok || weekday_ok,
+        }
+    }
+
+    /// The first matching minute after `after_ms`, as Unix ms.
+    fn next_after(&self, after_ms: u64, utc_offset_minutes: i32) -> Option<u64> {
+        let first = (after_ms / 60_000) as i64 + i64::from(utc_offset_minutes) + 1; // Local minutes since the epoch
+        let first_day = first.div_euclid(1440);
+        for day in first_day..first_day + SEARCH_DAYS {
+            let (month, day_of_month) = month_and_day(day);
+            let weekday = (day + 4).rem_euclid(7) as u32; // 1970-01-01 was a Thursday
+            if self.months & 1 << month == 0 || !self.day_matches(day_of_month, weekday) {
+                continue;
+            }
+            let from = if day == first_day { first.rem_euclid(1440) } else { 0 };
+            let minute_of_day = (from..1440).find(|m| self.hours & 1 << (m / 60) != 0 && self.minutes & 1 << (m % 60) != 0);
+            if let Some(minute_of_day) = minute_of_day {
+                let utc_minutes = day * 1440 + minute_of_day - i64::from(utc_offset_minutes);
+                return u64::try_from(utc_minutes * 60_000).ok();
+            }
+        }
+        None
+    }
+}
+
+/// The month (1-12) and day of month of a day counted from 1970-01-01.
+fn month_and_day(days: i64) -> (u32, u32) {
+    // Howard Hinnant's civil_from_days, in a March-based year
+    let doe = (days + 719_468).rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (month, day)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn store_path(base: &Path) -> PathBuf {
+    base.join(SCHEDULES_FILE)
+}
+
+fn load(base: &Path) -> io::Result<Vec<Schedule>> {
+    match fs::read(store_path(base)) {
+        Ok(content) => serde_json::from_slice(&content).map_err(io::Error::from),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn save(base: &Path, schedules: &[Schedule]) -> io::Result<()> {
+    let path = store_path(base);
+    let tmp = path.with_extension("tmp");
+    fs::create_dir_all(base)?;
+    fs::write(&tmp, serde_json::to_vec_pretty(schedules)?)?;
+    fs::rename(&tmp, &path)
+}
+
+fn next_run(schedule: &Schedule, after_ms: u64) -> Option<u64> {
+    CronExpr::parse(&schedule.cron).ok()?.next_after(after_ms, schedule.utc_offset_minutes)
+}
+
+/// Starts due schedules, at most one per check since a task holds the app busy.
+fn tick(app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) -> io::Result<()> {
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut schedules = load(&base)?;
+    let now = now_ms();
+    let mut changed = false;
+    let mut started = false;
+    for schedule in schedules.iter_mut() {
+        let Some(due) = schedule.next_run_ms.filter(|due| *due <= now) else {
+            continue;
+        };
+        if now - due > MISSED_GRACE_MS {
+            warn!("Schedule {}: skipped the run due at {} ms for '{}', the app was busy or closed", schedule.id, due, schedule.command);
+        } else if started || app_state.lock_or_recover().input_state() != AppInputState::Idle {
+            continue; // Try again on the next check
+        } else {
+            info!("Schedule {}: starting '{}'", schedule.id, schedule.command);
+            let (app_state, recording, command) = (app_state.clone(), recording.clone(), schedule.command.clone());
+            schedule.last_task_id = Some(tasks::spawn(command.clone(), move || run(&app_state, &recording, command)));
+            schedule.last_run_ms = Some(now);
+            started = true;
+        }
+        schedule.next_run_ms = next_run(schedule, now);
+        changed = true;
+    }
+    if changed {
+        save(&base, &schedules)?;
+    }
+    Ok(())
+}
+
+/// Checks the schedules every TICK for the rest of the process, running due
+/// ones through `run`.
+pub fn start(app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) {
+    let (app_state, recording) = (app_state.clone(), recording.clone());
+    thread::spawn(move || loop {
+        if let Err(e) = tick(&app_state, &recording, run) {
+            warn!("Failed to check task schedules: {}", e);
+        }
+        thread::sleep(TICK);
+    });
+}
+
+/// Runs `command` whenever `cron_expr` matches. `utc_offset_minutes` is the
+/// offset of the wall clock the expression refers to (UTC if omitted).
+#[tauri::command]
+pub fn schedule_task(command: String, cron_expr: String, utc_offset_minutes: Option<i32>) -> Result<Schedule, MetisError> {
+    let command = command.trim().to_string();
+    if command.is_empty() {
+        return Err(MetisError::InvalidArgument("A scheduled task needs a command".to_string()));
+    }
+    let utc_offset_minutes = utc_offset_minutes.unwrap_or(0);
+    if utc_offset_minutes.abs() > 14 * 60 {
+        return Err(MetisError::InvalidArgument(format!("UTC offset {} minutes is out of range", utc_offset_minutes)));
+    }
+    let cron = cron_expr.trim().to_string();
+    let expr = CronExpr::parse(&cron).map_err(|e| MetisError::InvalidArgument(format!("Invalid cron expression '{}': {}", cron, e)))?;
+    let now = now_ms();
+    let next_run_ms = expr.next_after(now, utc_offset_minutes);
+    if next_run_ms.is_none() {
+        return Err(MetisError::InvalidArgument(format!("'{}' never matches a real date", cron)));
+    }
+    let schedule = Schedule {
+        id: format!("schedule_{}_{:08x}", now, rand::random::<u32>()),
+        command,
+        cron,
+        utc_offset_minutes,
+        created_ms: now,
+        next_run_ms,
+        last_run_ms: None,
+        last_task_id: None,
+    };
+
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut schedules = load(&base)?;
+    schedules.push(schedule.clone());
+    save(&base, &schedules)?;
+    info!("Scheduled '{}' at '{}', next run at {:?} ms", schedule.command, schedule.cron, schedule.next_run_ms);
+    Ok(schedule)
+}
+
+#[tauri::command]
+pub fn list_schedules() -> Result<Vec<Schedule>, MetisError> {
+    let _guard = STORE_LOCK.lock_or_recover();
+    Ok(load(&recorder::get_default_base_folder())?)
+}
+
+/// Removes a schedule; a task it already started keeps running.
+#[tauri::command]
+pub fn delete_schedule(id: String) -> Result<(), MetisError> {
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut schedules = load(&base)?;
+    let before = schedules.len();
+    schedules.retain(|s| s.id != id);
+    if schedules.len() == before {
+        return Err(MetisError::InvalidArgument(format!("No schedule '{}'", id)));
+    }
+    save(&base, &schedules)?;
+    info!("Deleted schedule {}", id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unix ms of a UTC wall-clock time.
+    fn utc(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> u64 {
+        // Howard Hinnant's days_from_civil, the inverse of month_and_day
+        let y = if month <= 2 { year - 1 } else { year };
+        let (era, yoe) = (y.div_euclid(400), y.rem_euclid(400));
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+        ((days * 1440 + hour * 60 + minute) * 60_000) as u64
+    }
+
+    fn next(expr: &str, after_ms: u64) -> Option<u64> {
+        CronExpr::parse(expr).unwrap().next_after(after_ms, 0)
+    }
+
+    fn bits(values: &[u32]) -> u64 {
+        values.iter().fold(0, |bits, v| bits | 1 << v)
+    }
+
+    #[test]
+    fn fields_parse_lists_ranges_and_steps() {
+        assert_eq!(parse_field("*", 0, 59), Ok((1u64 << 60) - 1));
+        assert_eq!(parse_field("7", 0, 59), Ok(bits(&[7])));
+        assert_eq!(parse_field("1,3,5", 0, 59), Ok(bits(&[1, 3, 5])));
+        assert_eq!(parse_field("9-12", 0, 23), Ok(bits(&[9, 10, 11, 12])));
+        assert_eq!(parse_field("*/15", 0, 59), Ok(bits(&[0, 15, 30, 45])));
+        assert_eq!(parse_field("5/20", 0, 59), Ok(bits(&[5, 25, 45])));
+        assert_eq!(parse_field("10-20/5,59", 0, 59), Ok(bits(&[10, 15, 20, 59])));
+        assert_eq!(parse_field("*/5", 1, 12), Ok(bits(&[1, 6, 11])));
+
+        for bad in ["60", "5-1", "*/0", "*/x", "a", "", "1,", "-1"] {
+            assert!(parse_field(bad, 0, 59).is_err(), "{}", bad);
+        }
+        assert!(parse_field("0", 1, 31).is_err());
+    }
+
+    #[test]
+    fn expressions_need_five_fields_or_an_alias() {
+        assert!(CronExpr::parse("* * * *").is_err());
+        assert!(CronExpr::parse("* * * * * *").is_err());
+        assert!(CronExpr::parse("@often").is_err());
+        assert!(CronExpr::parse("0 24 * * *").is_err());
+
+        let sunday = CronExpr::parse("0 0 * * 7").unwrap();
+        assert_eq!(sunday.weekdays, CronExpr::parse("0 0 * * 0").unwrap().weekdays);
+        assert_eq!(CronExpr::parse("0 0 * * 5-7").unwrap().weekdays, bits(&[0, 5, 6]));
+
+        for (alias, expr) in [("@hourly", "0 * * * *"), ("@daily", "0 0 * * *"), ("@midnight", "0 0 * * *"), ("@weekly", "0 0 * * 0"), ("@monthly", "0 0 1 * *"), ("@yearly", "0 0 1 1 *"), ("@annually", "0 0 1 1 *")] {
+            let after = utc(2024, 3, 15, 10, 30);
+            assert_eq!(next(alias, after), next(expr, after), "{}", alias);
+        }
+    }
+
+    #[test]
+    fn month_and_day_follow_the_calendar() {
+        assert_eq!(utc(2024, 1, 1, 0, 0), 1_704_067_200_000);
+        assert_eq!(month_and_day(0), (1, 1));
+        assert_eq!(month_and_day(-1), (12, 31));
+        let day = |ms: u64| (ms / 86_400_000) as i64;
+        assert_eq!(month_and_day(day(utc(2024, 2, 29, 0, 0))), (2, 29));
+        assert_eq!(month_and_day(day(utc(2024, 3, 1, 0, 0))), (3, 1));
+        assert_eq!(month_and_day(day(utc(2100, 3, 1, 0, 0)) - 1), (2, 28)); // Not a leap year
+    }
+
+    #[test]
+    fn next_run_is_the_first_matching_minute_after() {
+        let start = utc(2024, 1, 1, 0, 7); // A Monday
+        assert_eq!(next("*/15 * * * *", start), Some(utc(2024, 1, 1, 0, 15)));
+        assert_eq!(next("*/15 * * * *", utc(2024, 1, 1, 0, 15)), Some(utc(2024, 1, 1, 0, 30))); // Strictly after
+        assert_eq!(next("*/15 * * * *", utc(2024, 1, 1, 0, 15) - 1), Some(utc(2024, 1, 1, 0, 15)));
+        assert_eq!(next("@hourly", start), Some(utc(2024, 1, 1, 1, 0)));
+        assert_eq!(next("30 9 * * 1-5", utc(2024, 1, 5, 10, 0)), Some(utc(2024, 1, 8, 9, 30))); // Friday to Monday
+        assert_eq!(next("@weekly", start), Some(utc(2024, 1, 7, 0, 0)));
+        assert_eq!(next("@monthly", utc(2024, 1, 31, 12, 0)), Some(utc(2024, 2, 1, 0, 0)));
+        assert_eq!(next("0 0 31 * *", utc(2024, 4, 1, 0, 0)), Some(utc(2024, 5, 31, 0, 0)));
+        assert_eq!(next("59 23 31 12 *", start), Some(utc(2024, 12, 31, 23, 59)));
+        assert_eq!(next("0 12 29 2 *", utc(2024, 3, 1, 0, 0)), Some(utc(2028, 2, 29, 12, 0)));
+        assert_eq!(next("0 0 31 2 *", start), None);
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // The 13th, or any Friday: Friday 2024-01-05 comes first
+        assert_eq!(next("0 0 13 * 5", utc(2024, 1, 1, 0, 0)), Some(utc(2024, 1, 5, 0, 0)));
+        assert_eq!(next("0 0 13 * 5", utc(2024, 1, 12, 0, 0)), Some(utc(2024, 1, 13, 0, 0)));
+    }
+
+    #[test]
+    fn fields_are_read_at_the_schedules_offset() {
+        let daily_nine = CronExpr::parse("0 9 * * *").unwrap();
+        let after = utc(2024, 1, 1, 0, 0);
+        assert_eq!(daily_nine.next_after(after, 120), Some(utc(2024, 1, 1, 7, 0))); // UTC+2
+        assert_eq!(daily_nine.next_after(after, -300), Some(utc(2024, 1, 1, 14, 0))); // UTC-5
+        // 23:30 UTC is already Tuesday 08:30 at UTC+9, so Monday-only waits a week
+        let monday = CronExpr::parse("0 9 * * 1").unwrap();
+        assert_eq!(monday.next_after(utc(2024, 1, 1, 23, 30), 540), Some(utc(2024, 1, 8, 0, 0)));
+    }
+}
@@ -0,0 +1,87 @@
+// --- Screenshot Encoding ---
+// Full-size PNGs of a high-DPI screen add up quickly over a long recording, so
+// the recorder encodes frames as settings.screenshots says: PNG, JPEG at a chosen
+// quality, or lossless WebP, optionally downscaled to a maximum width first. The
+// same bytes are saved to disk and served to get_frame; the live preview gets a
+// smaller JPEG (`preview`) over binary IPC instead. Only PNGs can carry embedded
+// provenance; the recorder gives other formats a sidecar instead.
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageError, ImageOutputFormat};
+
+use crate::config::{self, ScreenshotFormat, ScreenshotSettings};
+
+const PREVIEW_QUALITY: u8 = 75;
+
+impl ScreenshotFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Jpeg => "jpg",
+            ScreenshotFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "image/png",
+            ScreenshotFormat::Jpeg => "image/jpeg",
+            ScreenshotFormat::Webp => "image/webp",
+        }
+    }
+}
+
+fn downscale(image: &DynamicImage, max_width: Option<u32>) -> Option<DynamicImage> {
+    let max_width = max_width.filter(|&w| w > 0 && w < image.width())?;
+    let height = ((image.height() as u64 * max_width as u64) / image.width() as u64).max(1) as u32;
+    Some(image.resize_exact(max_width, height, FilterType::Triangle))
+}
+
+/// Encodes `image` per `settings`, downscaling it first if it is wider than allowed.
+pub fn encode(image: &DynamicImage, settings: &ScreenshotSettings) -> Result<Vec<u8>, ImageError> {
+    let scaled = downscale(image, settings.max_width);
+    let image = scaled.as_ref().unwrap_or(image);
+    let mut buffer = Cursor::new(Vec::new());
+    match settings.format {
+        ScreenshotFormat::Png => image.write_to(&mut buffer, ImageOutputFormat::Png)?,
+        ScreenshotFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let rgb = image.to_rgb8();
+            JpegEncoder::new_with_quality(&mut buffer, settings.quality.clamp(1, 100)).encode_image(&rgb)?
+        }
+        ScreenshotFormat::Webp => {
+            let rgba = image.to_rgba8();
+            WebPEncoder::new_lossless(&mut buffer).encode(&rgba, rgba.width(), rgba.height(), image::ColorType::Rgba8)?
+        }
+    }
+    Ok(buffer.into_inner())
+}
+
+/// Encodes a small JPEG of `image` for the live preview, at most `max_width` wide.
+pub fn preview(image: &DynamicImage, max_width: u32) -> Result<Vec<u8>, ImageError> {
+    let scaled = downscale(image, Some(max_width));
+    let rgb = scaled.as_ref().unwrap_or(image).to_rgb8();
+    let mut buffer = Cursor::new(Vec::new());
+    JpegEncoder::new_with_quality(&mut buffer, PREVIEW_QUALITY).encode_image(&rgb)?;
+    Ok(buffer.into_inner())
+}
+
+#[tauri::command]
+pub fn get_screenshot_settings() -> Result<ScreenshotSettings, String> {
+    Ok(config::get().screenshots)
+}
+
+#[tauri::command]
+pub fn update_screenshot_settings(settings: ScreenshotSettings) -> Result<ScreenshotSettings, String> {
+    if !(1..=100).contains(&settings.quality) {
+        return Err(format!("Quality must be between 1 and 100, got {}", settings.quality));
+    }
+    if settings.max_width == Some(0) {
+        return Err("Maximum width must be greater than zero".to_string());
+    }
+    config::update(|s| s.screenshots = settings).map(|s| s.screenshots)
+}
@@ -0,0 +1,47 @@
+// --- Secure Text Entry Detection ---
+// While the user is typing into a password field, key names must never reach
+// frame labels or the typing metrics. The OS is asked whether secure input is
+// active: macOS reports it directly (IsSecureEventInputEnabled); on Windows the
+// focused control is checked for the ES_PASSWORD style. Elsewhere there is no
+// reliable signal and this always reports false.
+
+#[cfg(target_os = "macos")]
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn IsSecureEventInputEnabled() -> bool;
+}
+
+/// Whether the OS reports secure (password) text entry right now.
+#[cfg(target_os = "macos")]
+pub fn active() -> bool {
+    unsafe { IsSecureEventInputEnabled() }
+}
+
+/// Whether the OS reports secure (password) text entry right now.
+#[cfg(target_os = "windows")]
+pub fn active() -> bool {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetGUIThreadInfo, GetWindowLongW, GetWindowThreadProcessId, ES_PASSWORD, GUITHREADINFO,
+        GWL_STYLE,
+    };
+
+    unsafe {
+        let foreground = GetForegroundWindow();
+        if foreground == 0 {
+            return false;
+        }
+        let thread_id = GetWindowThreadProcessId(foreground, std::ptr::null_mut());
+        let mut info: GUITHREADINFO = std::mem::zeroed();
+        info.cbSize = std::mem::size_of::<GUITHREADINFO>() as u32;
+        if GetGUIThreadInfo(thread_id, &mut info) == 0 || info.hwndFocus == 0 {
+            return false;
+        }
+        GetWindowLongW(info.hwndFocus, GWL_STYLE) & ES_PASSWORD != 0
+    }
+}
+
+/// Whether the OS reports secure (password) text entry right now.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub fn active() -> bool {
+    false
+}
@@ -0,0 +1,134 @@
+// --- Session Lock / Sleep Watcher ---
+// When the machine locks or sleeps, captures come back black (or fail) and any
+// delayed capture or action timer fires against the wrong screen. A background
+// thread polls the session state; the recorder drops events and the action loop
+// waits while the session is paused, and both pick up again after unlock/wake.
+//
+// Lock detection: OpenInputDesktop on Windows, logind's LockedHint on Linux.
+// Sleep detection works everywhere: a wall-clock jump far larger than the poll
+// interval means the process was suspended.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::info;
+
+use crate::clock::Clock;
+use crate::events;
+use crate::sync::LockExt;
+
+pub const SESSION_STATE_EVENT: &str = "app://session-state";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// A poll gap this much longer than POLL_INTERVAL is treated as a suspend
+const SLEEP_GAP: Duration = Duration::from_secs(5);
+// After waking, stay paused briefly so displays and the compositor come back
+const WAKE_SETTLE: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SessionState {
+    Active,
+    Locked,
+    Waking, // Just resumed from sleep, waiting for WAKE_SETTLE
+}
+
+static SESSION_STATE: Lazy<Mutex<SessionState>> = Lazy::new(|| Mutex::new(SessionState::Active));
+
+pub fn state() -> SessionState {
+    *SESSION_STATE.lock_or_recover()
+}
+
+/// True while recording and execution should hold off.
+pub fn is_paused() -> bool {
+    state() != SessionState::Active
+}
+
+/// Blocks until the session is active again. Returns false if `timeout` passes
+/// first or `should_abort` asks to stop waiting.
+pub fn wait_until_active(clock: &dyn Clock, timeout: Duration, should_abort: impl Fn() -> bool) -> bool {
+    let start = clock.now();
+    while is_paused() {
+        if should_abort() || clock.now().duration_since(start) >= timeout {
+            return false;
+        }
+        clock.sleep(Duration::from_millis(500));
+    }
+    true
+}
+
+fn set_state(next: SessionState) {
+    let mut current = SESSION_STATE.lock_or_recover();
+    if *current != next {
+        info!("[Session] {:?} -> {:?}", *current, next);
+        *current = next;
+        events::emit(SESSION_STATE_EVENT, next);
+    }
+}
+
+/// Starts the watcher thread; called once from main.
+pub fn start_watcher() {
+    thread::spawn(|| {
+        let mut last_wall = SystemTime::now();
+        let mut settle_until: Option<Instant> = None;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let now_wall = SystemTime::now();
+            let gap = now_wall.duration_since(last_wall).unwrap_or_default();
+            last_wall = now_wall;
+            if gap > POLL_INTERVAL + SLEEP_GAP {
+                info!("[Session] Resumed after ~{}s suspended", gap.as_secs());
+                settle_until = Some(Instant::now() + WAKE_SETTLE);
+            }
+
+            let next = if session_locked() {
+                SessionState::Locked
+            } else if settle_until.is_some_and(|until| Instant::now() < until) {
+                SessionState::Waking
+            } else {
+                settle_until = None;
+                SessionState::Active
+            };
+            set_state(next);
+        }
+    });
+}
+
+#[cfg(target_os = "windows")]
+fn session_locked() -> bool {
+    use windows_sys::Win32::System::StationsAndDesktops::{CloseDesktop, OpenInputDesktop, DESKTOP_SWITCHDESKTOP};
+    // The input desktop can't be opened while the secure (lock/UAC) desktop is active
+    unsafe {
+        let desktop = OpenInputDesktop(0, 0, DESKTOP_SWITCHDESKTOP);
+        if desktop == 0 {
+            return true;
+        }
+        CloseDesktop(desktop);
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn session_locked() -> bool {
+    let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "self".to_string());
+    match std::process::Command::new("loginctl")
+        .args(["show-session", &session, "-p", "LockedHint", "--value"])
+        .output()
+    {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim() == "yes",
+        _ => false, // No logind: fall back to sleep detection only
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn session_locked() -> bool {
+    false
+}
+
+#[tauri::command]
+pub fn get_session_state() -> Result<SessionState, String> {
+    Ok(state())
+}
@@ -0,0 +1,158 @@
+// --- Session Import ---
+// Brings a recorded workflow from another machine into this one. An archive is
+// a folder holding the session's parsed files (CSVs, events.jsonl,
+// trajectory.jsonl) in plain text plus a manifest.json describing them:
+//
+//   {"format": "metis-session", "version": 1, "query": "open the invoice",
+//    "recording_id": "...", "files": [{"name": "parsed_1.csv", "sha256": "..."}]}
+//
+// import_session checks the manifest and every file's checksum, copies the files
+// into a fresh action_N folder and adds a session row named after the query, so
+// execute_task_loop picks the workflow up as historical context straight away.
+// Sealed files can't be opened with this install's key and are refused; the
+// imported files are sealed with a new session key when encryption is set up.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::error::MetisError;
+use crate::keystore;
+use crate::provenance;
+use crate::recorder;
+use crate::storage;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const ARCHIVE_FORMAT: &str = "metis-session";
+const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+struct ManifestFile {
+    name: String,
+    sha256: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    format: String,
+    version: u32,
+    query: String, // The task the session demonstrates
+    #[serde(default)]
+    recording_id: Option<String>,
+    files: Vec<ManifestFile>,
+}
+
+/// A session's files as (name, contents).
+pub type SessionFiles = Vec<(String, Vec<u8>)>;
+
+fn invalid(message: String) -> MetisError {
+    MetisError::InvalidArgument(message)
+}
+
+/// Checks that `name` can be stored in an action folder: a plain, unencrypted file.
+pub fn check_file_name(name: &str) -> Result<(), MetisError> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(invalid(format!("Invalid file name '{}'", name)));
+    }
+    if keystore::is_key_file(name) || name.ends_with(keystore::SEALED_SUFFIX) {
+        return Err(invalid(format!("'{}' is encrypted; export the session unencrypted", name)));
+    }
+    Ok(())
+}
+
+/// The manifest and the contents of the files it lists, checksums verified.
+fn read_archive(archive: &Path) -> Result<(Manifest, SessionFiles), MetisError> {
+    let path = archive.join(MANIFEST_FILE);
+    let manifest: Manifest = serde_json::from_slice(&fs::read(&path)?)
+        .map_err(|e| invalid(format!("Invalid {}: {}", path.display(), e)))?;
+    if manifest.format != ARCHIVE_FORMAT || manifest.version != ARCHIVE_VERSION {
+        return Err(invalid(format!(
+            "Unsupported archive format {} version {}",
+            manifest.format, manifest.version
+        )));
+    }
+    if manifest.query.trim().is_empty() {
+        return Err(invalid("The archive has no query".to_string()));
+    }
+    if manifest.files.is_empty() {
+        return Err(invalid("The archive lists no files".to_string()));
+    }
+
+    let mut seen = HashSet::new();
+    let mut files = Vec::with_capacity(manifest.files.len());
+    for file in &manifest.files {
+        let name = file.name.as_str();
+        check_file_name(name)?;
+        if name == MANIFEST_FILE {
+            return Err(invalid(format!("Invalid file name '{}' in the manifest", name)));
+        }
+        if !seen.insert(name) {
+            return Err(invalid(format!("'{}' is listed twice", name)));
+        }
+        let content = fs::read(archive.join(name))
+            .map_err(|e| invalid(format!("Missing '{}' in the archive: {}", name, e)))?;
+        if !hex::encode(Sha256::digest(&content)).eq_ignore_ascii_case(file.sha256.trim()) {
+            return Err(invalid(format!("Checksum mismatch for '{}'", name)));
+        }
+        files.push((file.name.clone(), content));
+    }
+    Ok((manifest, files))
+}
+
+/// Writes `files` into `folder`, sealed if encryption is set up.
+fn fill_session(folder: &Path, files: &SessionFiles) -> Result<(), MetisError> {
+    let key = keystore::sealing_key()?
+        .map(|master| keystore::session_key(&master, folder, &provenance::new_session_id()))
+        .transpose()?;
+    for (name, content) in files {
+        match &key {
+            Some(key) => {
+                keystore::write_sealed(key, &folder.join(name), content)?;
+            }
+            None => fs::write(folder.join(name), content)?,
+        }
+    }
+    Ok(())
+}
+
+/// Stores `files` (names checked with check_file_name) as a new session named
+/// `query`. Returns the session's ID (its action folder name).
+pub fn add_session(query: &str, recording_id: &str, files: &SessionFiles) -> Result<String, MetisError> {
+    let base = recorder::get_default_base_folder();
+    let encrypted_dir = base.join("encrypted_csv");
+    let location = recorder::create_action_folder(&encrypted_dir)?;
+    let folder = encrypted_dir.join(&location);
+
+    let imported = fill_session(&folder, files).and_then(|_| {
+        let db = storage::open(&base)?;
+        storage::create_session(&db, &location, recording_id)?;
+        storage::rename_session(&db, &location, query)?;
+        Ok(())
+    });
+    if let Err(e) = imported {
+        // Don't leave a half-imported session behind for the task loop to read
+        if let Err(cleanup) = fs::remove_dir_all(&folder) {
+            warn!("Failed to remove {}: {}", folder.display(), cleanup);
+        }
+        return Err(e);
+    }
+    Ok(location)
+}
+
+/// Imports the session archive folder at `path`. Returns the new session's ID
+/// (its action folder name).
+#[tauri::command]
+pub fn import_session(path: String) -> Result<String, MetisError> {
+    let archive = Path::new(&path);
+    if !archive.is_dir() {
+        return Err(invalid(format!("{} is not a session archive folder", path)));
+    }
+    let (manifest, files) = read_archive(archive)?;
+    let location = add_session(manifest.query.trim(), manifest.recording_id.as_deref().unwrap_or_default(), &files)?;
+    info!("Imported {} file(s) from {} as session {}", files.len(), archive.display(), location);
+    Ok(location)
+}
@@ -0,0 +1,215 @@
+// --- Skill Files ---
+// A .metisskill file carries one skill and everything it learned from, so it
+// can be passed around over chat or email. It is a zip archive (archive.rs):
+//
+//   skill.json                 SkillFile: the skill and a list of its recordings
+//   recordings/<n>/<file>      The n-th recording's files (parsed CSVs, any
+//                              thumbnails), decrypted
+//
+// The raw input and trajectory logs (events.jsonl, trajectory.jsonl) hold every
+// keystroke and pointer move of the recording, so they are left out unless the
+// exporting user asks for them with `include_raw_logs`.
+//
+// import_skill stores each recording as a new session, named as it was on the
+// exporting machine (sealed again under this install's key if one is set up),
+// and installs the skill under a new ID pointing at those sessions. Exports are
+// stamped with signed provenance in the zip comment (provenance.rs).
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::archive::{self, ZipWriter};
+use crate::error::MetisError;
+use crate::event_log::EVENTS_FILE;
+use crate::keystore;
+use crate::provenance;
+use crate::recorder;
+use crate::session_import::{self, SessionFiles};
+use crate::skills::{self, Skill};
+use crate::storage;
+use crate::trajectory::TRAJECTORY_FILE;
+
+pub const SKILL_FILE_EXTENSION: &str = "metisskill";
+
+const SKILL_ENTRY: &str = "skill.json";
+const FILE_FORMAT: &str = "metis-skill";
+const FILE_VERSION: u32 = 1;
+const MAX_UNPACKED_BYTES: u64 = 512 * 1024 * 1024; // Guards against zip bombs
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordingEntry {
+    query: String, // The session's name, which the task loop matches commands against
+    files: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SkillFile {
+    format: String,
+    version: u32,
+    skill: Skill, // action_folders empty; the recordings below replace them
+    recordings: Vec<RecordingEntry>,
+}
+
+fn invalid(message: String) -> MetisError {
+    MetisError::InvalidArgument(message)
+}
+
+/// The files of one action folder, sealed ones opened, sorted by name. The raw
+/// input and trajectory logs only with `include_raw_logs`.
+fn recording_files(folder: &Path, include_raw_logs: bool) -> Result<SessionFiles, MetisError> {
+    let mut files = Vec::new();
+    for path in fs::read_dir(folder)?.filter_map(Result::ok).map(|e| e.path()).filter(|p| p.is_file()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if keystore::is_key_file(&name) || name.starts_with('.') {
+            continue;
+        }
+        let plain_name = name.strip_suffix(keystore::SEALED_SUFFIX).unwrap_or(&name);
+        if !include_raw_logs && [EVENTS_FILE, TRAJECTORY_FILE].contains(&plain_name) {
+            continue;
+        }
+        match name.strip_suffix(keystore::SEALED_SUFFIX) {
+            Some(plain_name) => files.push((plain_name.to_string(), keystore::open_sealed(&path)?)),
+            None => files.push((name, fs::read(&path)?)),
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+/// Writes the skill `skill_id` and its recordings to a .metisskill file at
+/// `path` (the extension is added if missing). Returns the path written.
+#[tauri::command]
+pub fn export_skill(skill_id: String, path: String, include_raw_logs: Option<bool>) -> Result<String, MetisError> {
+    let include_raw_logs = include_raw_logs.unwrap_or(false);
+    let mut skill = skills::find(&skill_id)?;
+    let mut path = PathBuf::from(path);
+    if path.extension().is_none() {
+        path.set_extension(SKILL_FILE_EXTENSION);
+    }
+
+    let base = recorder::get_default_base_folder();
+    let names: HashMap<String, String> = storage::list_sessions(&storage::open(&base)?)?
+        .into_iter()
+        .map(|record| (record.location, record.query))
+        .collect();
+    let mut zip = ZipWriter::new();
+    let mut recordings = Vec::new();
+    for location in &skill.action_folders {
+        let folder = base.join("encrypted_csv").join(location);
+        if !folder.is_dir() {
+            warn!("Skill '{}': recording {} no longer exists, not exporting it", skill.name, location);
+            continue;
+        }
+        let files = recording_files(&folder, include_raw_logs)?;
+        for (name, content) in &files {
+            zip.add(&format!("recordings/{}/{}", recordings.len(), name), content)?;
+        }
+        recordings.push(RecordingEntry {
+            query: names.get(location).cloned().unwrap_or_else(|| skill.name.clone()),
+            files: files.into_iter().map(|(name, _)| name).collect(),
+        });
+    }
+    skill.action_folders.clear();
+    let manifest = SkillFile { format: FILE_FORMAT.to_string(), version: FILE_VERSION, skill, recordings };
+    zip.add(SKILL_ENTRY, &serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::from)?)?;
+    fs::write(&path, provenance::stamp_zip(zip.finish()?, &format!("skill:{}", skill_id))?)?;
+    info!("Exported skill {} with {} recording(s) to {}", skill_id, manifest.recordings.len(), path.display());
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Unpacks a .metisskill file (read from `source`) and checks it against its
+/// skill.json. Returns the manifest and each recording's files.
+fn read_skill_file(bytes: &[u8], source: &str) -> Result<(SkillFile, Vec<SessionFiles>), MetisError> {
+    let entries = archive::read_all(bytes, MAX_UNPACKED_BYTES)
+        .map_err(|e| invalid(format!("{} is not a valid skill file: {}", source, e)))?;
+    let mut entries: HashMap<String, Vec<u8>> = entries.into_iter().collect();
+    let manifest = entries.remove(SKILL_ENTRY).ok_or_else(|| invalid(format!("{} has no {}", source, SKILL_ENTRY)))?;
+    let manifest: SkillFile =
+        serde_json::from_slice(&manifest).map_err(|e| invalid(format!("Invalid {}: {}", SKILL_ENTRY, e)))?;
+    if manifest.format != FILE_FORMAT || manifest.version != FILE_VERSION {
+        return Err(invalid(format!("Unsupported skill file format {} version {}", manifest.format, manifest.version)));
+    }
+    if manifest.skill.name.trim().is_empty() {
+        return Err(invalid("The skill has no name".to_string()));
+    }
+
+    let mut recordings = Vec::with_capacity(manifest.recordings.len());
+    for (i, recording) in manifest.recordings.iter().enumerate() {
+        let mut files = Vec::with_capacity(recording.files.len());
+        for name in &recording.files {
+            session_import::check_file_name(name)?;
+            let content = entries
+                .remove(&format!("recordings/{}/{}", i, name))
+                .ok_or_else(|| invalid(format!("Recording {} is missing '{}'", i, name)))?;
+            files.push((name.clone(), content));
+        }
+        recordings.push(files);
+    }
+    if let Some(extra) = entries.keys().find(|name| !name.ends_with('/')) {
+        return Err(invalid(format!("Unexpected entry '{}' in the skill file", extra)));
+    }
+    Ok((manifest, recordings))
+}
+
+/// Installs the skill in the .metisskill file at `path` under a new ID, with its
+/// recordings stored as new sessions.
+#[tauri::command]
+pub fn import_skill(path: String) -> Result<Skill, MetisError> {
+    import_bytes(&fs::read(&path)?, &path)
+}
+
+/// Installs the skill in a .metisskill file's contents; `source` names it in messages.
+pub fn import_bytes(bytes: &[u8], source: &str) -> Result<Skill, MetisError> {
+    let (manifest, recordings) = read_skill_file(bytes, source)?;
+
+    let mut locations = Vec::with_capacity(recordings.len());
+    let imported = manifest.recordings.iter().zip(&recordings).try_for_each(|(recording, files)| {
+        let query = Some(recording.query.trim()).filter(|q| !q.is_empty()).unwrap_or(&manifest.skill.name);
+        locations.push(session_import::add_session(query, "", files)?);
+        Ok(())
+    });
+    let installed = imported.and_then(|_| skills::install(Skill { action_folders: locations.clone(), ..manifest.skill }));
+    match installed {
+        Ok(skill) => {
+            info!("Imported skill {} ({}) with {} recording(s) from {}", skill.id, skill.name, locations.len(), source);
+            Ok(skill)
+        }
+        Err(e) => {
+            // Sessions stored before the failure would otherwise linger without their skill
+            let base = recorder::get_default_base_folder();
+            for location in &locations {
+                if let Err(e) = fs::remove_dir_all(base.join("encrypted_csv").join(location)) {
+                    warn!("Failed to remove {}: {}", location, e);
+                }
+                if let Err(e) = storage::open(&base).and_then(|db| storage::delete_session(&db, location)) {
+                    warn!("Failed to drop {} from the session database: {}", location, e);
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_logs_are_only_exported_on_request() {
+        let folder = std::env::temp_dir().join(format!("metis_skill_{:08x}", rand::random::<u32>()));
+        fs::create_dir_all(&folder).unwrap();
+        for name in ["parsed.csv", EVENTS_FILE, TRAJECTORY_FILE, "rec_1_aa.key", ".hidden"] {
+            fs::write(folder.join(name), name).unwrap();
+        }
+        let names = |include_raw_logs| -> Vec<String> {
+            recording_files(&folder, include_raw_logs).unwrap().into_iter().map(|(name, _)| name).collect()
+        };
+        assert_eq!(names(false), ["parsed.csv"]);
+        assert_eq!(names(true), [EVENTS_FILE, "parsed.csv", TRAJECTORY_FILE]);
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,426 @@
+// --- Skills ---
+// A skill is a named task Metis has been shown how to do. It keeps the action
+// folders of the recordings that taught it, and execute_skill runs the task
+// loop with those sessions' parsed screens placed ahead of whatever the command
+// text happens to match in the session database. Skills are stored in
+// skills.json in the storage root, next to the action folders they point at.
+//
+// A skill can declare parameters. Its description and the parsed screens of its
+// recordings may then contain {{name}} placeholders, which execute_skill fills
+// from the arguments it is given (or the parameter's default) before anything
+// reaches the LLM prompt.
+//
+// Each skill also keeps local signals: how often execute_skill ran it and how
+// often that succeeded, and the user's own star rating. Installed skills and
+// marketplace bundles can be sorted by them, so the ones that work here rise.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use tracing::{info, warn};
+
+use crate::error::MetisError;
+use crate::recorder::{self, SharedRecordingState};
+use crate::sync::LockExt;
+
+const SKILLS_FILE: &str = "skills.json";
+
+// Held across every read-modify-write of skills.json
+static STORE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+static PLACEHOLDER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillParameter {
+    pub name: String, // Written as {{name}}; letters, digits and underscores
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub required: bool, // execute_skill fails without it (unless there is a default)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Skill {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub version: String,
+    pub created_at: u64, // Unix ms
+    pub updated_at: u64,
+    #[serde(default)]
+    pub downloads: u64,
+    #[serde(default)]
+    pub rating: f64,
+    #[serde(default)]
+    pub action_folders: Vec<String>, // Recordings that demonstrate the skill, oldest first
+    #[serde(default)]
+    pub parameters: Vec<SkillParameter>,
+    #[serde(default)]
+    pub bundle_id: Option<String>, // Marketplace bundle it was installed from
+    #[serde(default)]
+    pub usage: SkillUsage,
+    #[serde(default)]
+    pub local_rating: Option<LocalRating>,
+}
+
+/// How a skill has fared on this machine.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillUsage {
+    pub runs: u64,
+    pub successes: u64,
+    pub last_run_ms: Option<u64>,
+}
+
+impl SkillUsage {
+    /// Success rate with one success and one failure assumed up front, so a single
+    /// lucky run doesn't outrank a skill that worked nine times out of ten.
+    pub fn success_score(&self) -> f64 {
+        (self.successes as f64 + 1.0) / (self.runs as f64 + 2.0)
+    }
+}
+
+/// The user's own rating, kept apart from the marketplace's `rating`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalRating {
+    pub stars: u8, // 1-5
+    pub comment: String,
+    pub rated_at: u64,
+}
+
+/// Orders for get_installed_skills and get_marketplace_skill_bundles. Ties keep
+/// the stored (or catalog) order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkillSort {
+    Name,
+    Recent,      // Most recently run first
+    Rating,      // Own stars first, then the marketplace rating
+    Usage,       // Most runs first
+    SuccessRate, // Most reliable first
+}
+
+/// What the task loop needs from a skill being executed.
+#[derive(Debug, Clone)]
+pub struct SkillRun {
+    pub folders: Vec<String>,               // Action folders to read first
+    pub arguments: HashMap<String, String>, // Parameter values, defaults filled in
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn store_path(base: &Path) -> PathBuf {
+    base.join(SKILLS_FILE)
+}
+
+fn load(base: &Path) -> io::Result<Vec<Skill>> {
+    match fs::read(store_path(base)) {
+        Ok(content) => serde_json::from_slice(&content).map_err(io::Error::from),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn save(base: &Path, skills: &[Skill]) -> io::Result<()> {
+    let path = store_path(base);
+    let tmp = path.with_extension("tmp");
+    fs::create_dir_all(base)?;
+    fs::write(&tmp, serde_json::to_vec_pretty(skills)?)?;
+    fs::rename(&tmp, &path)
+}
+
+/// Applies `change` to the skill `id` and saves the store. Returns the updated skill.
+pub fn modify(id: &str, change: impl FnOnce(&mut Skill) -> Result<(), MetisError>) -> Result<Skill, MetisError> {
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut skills = load(&base)?;
+    let skill = skills
+        .iter_mut()
+        .find(|s| s.id == id)
+        .ok_or_else(|| MetisError::InvalidArgument(format!("No skill '{}'", id)))?;
+    change(skill)?;
+    skill.updated_at = now_ms();
+    let updated = skill.clone();
+    save(&base, &skills)?;
+    Ok(updated)
+}
+
+/// The skill `id`, if it is installed.
+pub fn find(id: &str) -> Result<Skill, MetisError> {
+    let _guard = STORE_LOCK.lock_or_recover();
+    load(&recorder::get_default_base_folder())?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| MetisError::InvalidArgument(format!("No skill '{}'", id)))
+}
+
+/// Adds `skill` to the store under a new ID, stamped with the current time and
+/// with no local history.
+pub fn install(mut skill: Skill) -> Result<Skill, MetisError> {
+    let now = now_ms();
+    skill.usage = SkillUsage::default();
+    skill.local_rating = None;
+    skill.id = format!("skill_{}_{:08x}", now, rand::random::<u32>());
+    skill.created_at = now;
+    skill.updated_at = now;
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut skills = load(&base)?;
+    skills.push(skill.clone());
+    save(&base, &skills)?;
+    Ok(skill)
+}
+
+/// Adds the action folder `location` to the skill's recordings.
+pub fn attach(skill_id: &str, location: &str) -> Result<Skill, MetisError> {
+    let skill = modify(skill_id, |skill| {
+        if !skill.action_folders.iter().any(|folder| folder == location) {
+            skill.action_folders.push(location.to_string());
+        }
+        Ok(())
+    })?;
+    info!("Attached session {} to skill {}", location, skill.id);
+    Ok(skill)
+}
+
+/// Drops the skill `id` from the store. The recordings it points at are kept.
+pub fn remove(id: &str) -> Result<(), MetisError> {
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut skills = load(&base)?;
+    skills.retain(|s| s.id != id);
+    save(&base, &skills)?;
+    Ok(())
+}
+
+/// Replaces each {{name}} in `text` that has a value in `arguments`; others are left as they are.
+pub fn substitute<'a>(text: &'a str, arguments: &HashMap<String, String>) -> Cow<'a, str> {
+    PLACEHOLDER_RE.replace_all(text, |caps: &Captures| {
+        arguments.get(&caps[1]).cloned().unwrap_or_else(|| caps[0].to_string())
+    })
+}
+
+/// The names of the {{placeholders}} in `text`, each once, in order of appearance.
+pub fn placeholders(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for caps in PLACEHOLDER_RE.captures_iter(text) {
+        if !names.iter().any(|name| name == &caps[1]) {
+            names.push(caps[1].to_string());
+        }
+    }
+    names
+}
+
+/// Checks `args` against the skill's parameters and fills in defaults.
+fn resolve_arguments(skill: &Skill, mut args: HashMap<String, String>) -> Result<HashMap<String, String>, MetisError> {
+    if let Some(unknown) = args.keys().find(|name| !skill.parameters.iter().any(|p| &p.name == *name)) {
+        return Err(MetisError::InvalidArgument(format!("Skill '{}' has no parameter '{}'", skill.name, unknown)));
+    }
+    for parameter in &skill.parameters {
+        if args.contains_key(&parameter.name) {
+            continue;
+        }
+        match &parameter.default {
+            Some(default) => {
+                args.insert(parameter.name.clone(), default.clone());
+            }
+            None if parameter.required => {
+                return Err(MetisError::InvalidArgument(format!(
+                    "Skill '{}' needs a value for '{}'",
+                    skill.name, parameter.name
+                )));
+            }
+            None => {}
+        }
+    }
+    Ok(args)
+}
+
+/// The command and recordings execute_skill hands to the task loop, with `args`
+/// substituted into the command. Recordings deleted since they were attached are skipped.
+pub fn task_for(skill: &Skill, args: HashMap<String, String>) -> Result<(String, SkillRun), MetisError> {
+    let arguments = resolve_arguments(skill, args)?;
+    let mut command = skill.name.clone();
+    if !skill.description.trim().is_empty() {
+        command.push_str(": ");
+        command.push_str(&substitute(skill.description.trim(), &arguments));
+    }
+    let encrypted_dir = recorder::get_default_base_folder().join("encrypted_csv");
+    let folders = skill
+        .action_folders
+        .iter()
+        .filter(|folder| {
+            let present = encrypted_dir.join(folder).is_dir();
+            if !present {
+                warn!("Skill '{}': recording {} no longer exists", skill.name, folder);
+            }
+            present
+        })
+        .cloned()
+        .collect();
+    Ok((command, SkillRun { folders, arguments }))
+}
+
+/// Every installed skill, in stored order.
+pub fn all() -> Result<Vec<Skill>, MetisError> {
+    let _guard = STORE_LOCK.lock_or_recover();
+    Ok(load(&recorder::get_default_base_folder())?)
+}
+
+/// Counts one execute_skill run and whether it succeeded.
+pub fn record_run(id: &str, succeeded: bool) -> Result<(), MetisError> {
+    modify(id, |skill| {
+        skill.usage.runs += 1;
+        skill.usage.successes += u64::from(succeeded);
+        skill.usage.last_run_ms = Some(now_ms());
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// What get_installed_skills and get_marketplace_skill_bundles sort by.
+#[derive(Debug, Clone, Default)]
+pub struct RankSignals {
+    pub name: String,
+    pub usage: SkillUsage,
+    pub stars: Option<f64>, // The user's own rating
+    pub rating: f64,        // The marketplace's rating
+}
+
+impl RankSignals {
+    pub fn of(skill: &Skill) -> Self {
+        Self {
+            name: skill.name.to_lowercase(),
+            usage: skill.usage.clone(),
+            stars: skill.local_rating.as_ref().map(|r| f64::from(r.stars)),
+            rating: skill.rating,
+        }
+    }
+
+    /// Orders `self` before `other` when it ranks higher under `sort`.
+    pub fn compare(&self, other: &Self, sort: SkillSort) -> Ordering {
+        match sort {
+            SkillSort::Name => self.name.cmp(&other.name),
+            SkillSort::Recent => other.usage.last_run_ms.cmp(&self.usage.last_run_ms),
+            SkillSort::Rating => other
+                .stars
+                .unwrap_or(0.0)
+                .total_cmp(&self.stars.unwrap_or(0.0))
+                .then(other.rating.total_cmp(&self.rating)),
+            SkillSort::Usage => other.usage.runs.cmp(&self.usage.runs),
+            SkillSort::SuccessRate => other.usage.success_score().total_cmp(&self.usage.success_score()),
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_installed_skills(sort: Option<SkillSort>) -> Result<Vec<Skill>, MetisError> {
+    let mut skills = all()?;
+    if let Some(sort) = sort {
+        skills.sort_by(|a, b| RankSignals::of(a).compare(&RankSignals::of(b), sort));
+    }
+    Ok(skills)
+}
+
+/// Rates a skill 1-5 stars for this machine's rankings; rating again replaces it.
+#[tauri::command]
+pub fn rate_skill(skill_id: String, stars: u8, comment: Option<String>) -> Result<Skill, MetisError> {
+    if !(1..=5).contains(&stars) {
+        return Err(MetisError::InvalidArgument(format!("Ratings are 1 to 5 stars, got {}", stars)));
+    }
+    modify(&skill_id, |skill| {
+        skill.local_rating = Some(LocalRating { stars, comment: comment.unwrap_or_default().trim().to_string(), rated_at: now_ms() });
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn create_skill(name: String, description: Option<String>, tags: Option<Vec<String>>) -> Result<Skill, MetisError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(MetisError::InvalidArgument("A skill needs a name".to_string()));
+    }
+    let skill = Skill {
+        id: String::new(),
+        name,
+        description: description.unwrap_or_default().trim().to_string(),
+        tags: tags.unwrap_or_default(),
+        author: String::new(),
+        version: "1.0.0".to_string(),
+        created_at: 0,
+        updated_at: 0,
+        downloads: 0,
+        rating: 0.0,
+        action_folders: Vec::new(),
+        parameters: Vec::new(),
+        bundle_id: None,
+        usage: SkillUsage::default(),
+        local_rating: None,
+    };
+    let skill = install(skill)?;
+    info!("Created skill {} ({})", skill.id, skill.name);
+    Ok(skill)
+}
+
+/// Links a finished recording session (its action folder, as listed by
+/// list_sessions) to a skill as a demonstration of it.
+#[tauri::command]
+pub fn attach_recording_to_skill(
+    recording: State<'_, SharedRecordingState>,
+    skill_id: String,
+    session_id: String,
+) -> Result<Skill, MetisError> {
+    if session_id.is_empty() || session_id.contains(['/', '\\']) || session_id.starts_with('.') {
+        return Err(MetisError::InvalidArgument(format!("Invalid session ID '{}'", session_id)));
+    }
+    if !recorder::get_default_base_folder().join("encrypted_csv").join(&session_id).is_dir() {
+        return Err(MetisError::InvalidArgument(format!("No session '{}'", session_id)));
+    }
+    if recorder::active_action_folder(&recording).as_deref() == Some(session_id.as_str()) {
+        return Err(MetisError::State(format!("Session '{}' is still being recorded", session_id)));
+    }
+    attach(&skill_id, &session_id)
+}
+
+/// Replaces a skill's parameter list.
+#[tauri::command]
+pub fn update_skill_parameters(skill_id: String, parameters: Vec<SkillParameter>) -> Result<Skill, MetisError> {
+    let mut seen = HashSet::new();
+    for parameter in &parameters {
+        let name = parameter.name.as_str();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(MetisError::InvalidArgument(format!(
+                "Invalid parameter name '{}': use letters, digits and underscores",
+                name
+            )));
+        }
+        if !seen.insert(name) {
+            return Err(MetisError::InvalidArgument(format!("Parameter '{}' is declared twice", name)));
+        }
+    }
+    modify(&skill_id, |skill| {
+        skill.parameters = parameters;
+        Ok(())
+    })
+}
@@ -0,0 +1,320 @@
+// --- Session Database ---
+// The recording index used to be main.csv (query, location), rewritten wholesale
+// whenever a recording was renamed. It now lives in <base>/metis.db (SQLite):
+//   sessions - one row per action folder, with the query that names it
+//   frames   - capture metadata of every processed frame
+//   actions  - the numbered actions written from those frames, and their CSV
+//   task_runs - transcripts of past task runs, for memory.rs
+// An existing main.csv is imported the first time the database is opened and
+// kept as main.csv.migrated. Schema changes bump PRAGMA user_version.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::StorageError;
+use crate::frames::FrameMeta;
+
+const DB_FILE: &str = "metis.db";
+const LEGACY_INDEX: &str = "main.csv";
+const SCHEMA_VERSION: i32 = 2;
+const DEFAULT_QUERY_PREFIX: &str = "default_";
+
+/// Opens (creating and migrating as needed) the database in `base_folder`.
+pub fn open(base_folder: &Path) -> Result<Connection, StorageError> {
+    fs::create_dir_all(base_folder)?;
+    let conn = Connection::open(base_folder.join(DB_FILE))?;
+    // Post-processing runs on its own thread while commands may touch the same file
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    migrate(&conn)?;
+    import_main_csv(&conn, base_folder)?;
+    Ok(conn)
+}
+
+fn migrate(conn: &Connection) -> Result<(), StorageError> {
+    let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version < 1 {
+        conn.execute_batch(
+            "BEGIN;
+            CREATE TABLE IF NOT EXISTS sessions (
+                location     TEXT PRIMARY KEY, -- Action folder under encrypted_csv
+                query        TEXT NOT NULL,
+                recording_id TEXT,             -- Provenance session ID, if recorded since
+                created_ms   INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS frames (
+                id           INTEGER PRIMARY KEY,
+                location     TEXT NOT NULL REFERENCES sessions(location),
+                file         TEXT NOT NULL,
+                timestamp_ms INTEGER NOT NULL,
+                action       TEXT NOT NULL,
+                mouse_x      INTEGER,
+                mouse_y      INTEGER,
+                meta         TEXT NOT NULL     -- The whole FrameMeta as JSON
+            );
+            CREATE TABLE IF NOT EXISTS actions (
+                id            INTEGER PRIMARY KEY,
+                location      TEXT NOT NULL REFERENCES sessions(location),
+                action_number INTEGER NOT NULL,
+                frame_id      INTEGER NOT NULL REFERENCES frames(id),
+                csv_file      TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS frames_by_location ON frames(location, timestamp_ms);
+            CREATE INDEX IF NOT EXISTS actions_by_location ON actions(location, action_number);
+            COMMIT;",
+        )?;
+    }
+    if version < 2 {
+        conn.execute_batch(
+            "BEGIN;
+            CREATE TABLE IF NOT EXISTS task_runs (
+                id         INTEGER PRIMARY KEY,
+                command    TEXT NOT NULL,
+                succeeded  INTEGER NOT NULL,
+                outcome    TEXT NOT NULL,     -- The done message, or why the run stopped
+                steps      TEXT NOT NULL,     -- The TaskStep list as JSON
+                created_ms INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS task_runs_by_time ON task_runs(created_ms);
+            COMMIT;",
+        )?;
+    }
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct LegacyIndexRow {
+    query: String,
+    location: String,
+}
+
+/// Moves main.csv's rows into `sessions`, once.
+fn import_main_csv(conn: &Connection, base_folder: &Path) -> Result<(), StorageError> {
+    let path = base_folder.join(LEGACY_INDEX);
+    if !path.is_file() {
+        return Ok(());
+    }
+    let mut reader = csv::Reader::from_path(&path).map_err(|e| StorageError::Import(e.to_string()))?;
+    let rows: Vec<LegacyIndexRow> = reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(|e| StorageError::Import(e.to_string()))?;
+
+    let tx = conn.unchecked_transaction()?;
+    for row in &rows {
+        tx.execute(
+            "INSERT OR IGNORE INTO sessions (location, query) VALUES (?1, ?2)",
+            params![row.location, row.query],
+        )?;
+    }
+    tx.commit()?;
+    // Kept rather than deleted, in case anything outside Metis still reads it
+    fs::rename(&path, base_folder.join(format!("{}.migrated", LEGACY_INDEX)))?;
+    info!("Imported {} recording(s) from {} into {}", rows.len(), LEGACY_INDEX, DB_FILE);
+    Ok(())
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+/// Adds a session for `location`, named `default_N` until the user renames it.
+/// Returns the name it was given.
+pub fn create_session(conn: &Connection, location: &str, recording_id: &str) -> Result<String, StorageError> {
+    let mut stmt = conn.prepare("SELECT query FROM sessions WHERE query LIKE 'default\\_%' ESCAPE '\\'")?;
+    let next_index = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter_map(Result::ok)
+        .filter_map(|query| query.strip_prefix(DEFAULT_QUERY_PREFIX).and_then(|n| n.parse::<u64>().ok()))
+        .max()
+        .map_or(0, |highest| highest + 1);
+    let query = format!("{}{}", DEFAULT_QUERY_PREFIX, next_index);
+    conn.execute(
+        "INSERT INTO sessions (location, query, recording_id, created_ms) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(location) DO UPDATE SET query = excluded.query, recording_id = excluded.recording_id",
+        params![location, query, recording_id, now_ms()],
+    )?;
+    Ok(query)
+}
+
+/// Renames the session recorded in `location`. Returns false if there is none.
+pub fn rename_session(conn: &Connection, location: &str, name: &str) -> Result<bool, StorageError> {
+    Ok(conn.execute("UPDATE sessions SET query = ?1 WHERE location = ?2", params![name, location])? > 0)
+}
+
+/// Locations of sessions whose name shares at least one word with `command`.
+pub fn find_locations(conn: &Connection, command: &str) -> Result<Vec<String>, StorageError> {
+    let words: Vec<String> = command.split_whitespace().map(str::to_lowercase).collect();
+    let mut stmt = conn.prepare("SELECT query, location FROM sessions ORDER BY created_ms")?;
+    let sessions = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+    let mut locations = Vec::new();
+    for session in sessions {
+        let (query, location) = session?;
+        let query = query.to_lowercase();
+        if words.iter().any(|word| query.contains(word.as_str())) {
+            locations.push(location);
+        }
+    }
+    Ok(locations)
+}
+
+/// A session row with a summary of its processed frames.
+#[derive(Debug, Clone)]
+pub struct SessionRecord {
+    pub location: String,
+    pub query: String,
+    pub recording_id: Option<String>,
+    pub created_ms: i64,
+    pub frame_count: usize,
+    pub first_frame_ms: Option<i64>,
+    pub last_frame_ms: Option<i64>,
+}
+
+/// Every session, newest first.
+pub fn list_sessions(conn: &Connection) -> Result<Vec<SessionRecord>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT s.location, s.query, s.recording_id, s.created_ms, COUNT(f.id), MIN(f.timestamp_ms), MAX(f.timestamp_ms)
+         FROM sessions s LEFT JOIN frames f ON f.location = s.location
+         GROUP BY s.location ORDER BY s.created_ms DESC, s.location DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(SessionRecord {
+            location: row.get(0)?,
+            query: row.get(1)?,
+            recording_id: row.get(2)?,
+            created_ms: row.get(3)?,
+            frame_count: row.get::<_, i64>(4)? as usize,
+            first_frame_ms: row.get(5)?,
+            last_frame_ms: row.get(6)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Forgets the session recorded in `location` along with its frames and actions.
+pub fn delete_session(conn: &Connection, location: &str) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM actions WHERE location = ?1", params![location])?;
+    tx.execute("DELETE FROM frames WHERE location = ?1", params![location])?;
+    tx.execute("DELETE FROM sessions WHERE location = ?1", params![location])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Records one processed frame and the action written from it.
+pub fn record_action(
+    conn: &Connection,
+    location: &str,
+    action_number: u32,
+    meta: &FrameMeta,
+    csv_file: &str,
+) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    // Frames processed without a known folder ("action_unknown") still need a parent row
+    tx.execute("INSERT OR IGNORE INTO sessions (location, query) VALUES (?1, ?1)", params![location])?;
+    let (mouse_x, mouse_y) = meta.mouse.unzip();
+    let meta_json = serde_json::to_string(meta).expect("frame metadata serializes");
+    tx.execute(
+        "INSERT INTO frames (location, file, timestamp_ms, action, mouse_x, mouse_y, meta)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![location, meta.file, meta.timestamp_ms as i64, meta.action, mouse_x, mouse_y, meta_json],
+    )?;
+    let frame_id = tx.last_insert_rowid();
+    tx.execute(
+        "INSERT INTO actions (location, action_number, frame_id, csv_file) VALUES (?1, ?2, ?3, ?4)",
+        params![location, action_number, frame_id, csv_file],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// A processed action: the CSV written for it and the frame it came from.
+#[derive(Debug, Clone)]
+pub struct RecordedAction {
+    pub csv_file: String,
+    pub frame: FrameMeta,
+}
+
+/// The actions recorded in `location`, in action_number order.
+pub fn list_actions(conn: &Connection, location: &str) -> Result<Vec<RecordedAction>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT a.action_number, a.csv_file, f.meta
+         FROM actions a JOIN frames f ON f.id = a.frame_id
+         WHERE a.location = ?1 ORDER BY a.action_number, f.timestamp_ms",
+    )?;
+    let rows = stmt.query_map(params![location], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?;
+    let mut actions = Vec::new();
+    for row in rows {
+        let (action_number, csv_file, meta) = row?;
+        match serde_json::from_str(&meta) {
+            Ok(frame) => actions.push(RecordedAction { csv_file, frame }),
+            Err(e) => warn!("Skipping action {} of {} with unreadable frame metadata: {}", action_number, location, e),
+        }
+    }
+    Ok(actions)
+}
+
+/// One step of a task run: the action the LLM chose and what came of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskStep {
+    pub thought: String,
+    pub action: String,
+    pub error: Option<String>, // Set if the action was rejected or failed
+}
+
+/// A past task run, as memory.rs recalls it.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRun {
+    pub id: i64,
+    pub command: String,
+    pub succeeded: bool,
+    pub outcome: String,
+    pub steps: Vec<TaskStep>,
+    pub created_ms: i64,
+}
+
+/// Saves a task run, keeping only the newest `keep` runs.
+pub fn record_task_run(conn: &Connection, command: &str, succeeded: bool, outcome: &str, steps: &[TaskStep], keep: usize) -> Result<(), StorageError> {
+    let steps_json = serde_json::to_string(steps).expect("task steps serialize");
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO task_runs (command, succeeded, outcome, steps, created_ms) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![command, succeeded, outcome, steps_json, now_ms()],
+    )?;
+    tx.execute(
+        "DELETE FROM task_runs WHERE id NOT IN (SELECT id FROM task_runs ORDER BY created_ms DESC, id DESC LIMIT ?1)",
+        params![keep as i64],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Every saved task run, newest first.
+pub fn list_task_runs(conn: &Connection) -> Result<Vec<TaskRun>, StorageError> {
+    let mut stmt = conn.prepare("SELECT id, command, succeeded, outcome, steps, created_ms FROM task_runs ORDER BY created_ms DESC, id DESC")?;
+    let rows = stmt.query_map([], |row| {
+        let steps: String = row.get(4)?;
+        Ok(TaskRun {
+            id: row.get(0)?,
+            command: row.get(1)?,
+            succeeded: row.get(2)?,
+            outcome: row.get(3)?,
+            steps: serde_json::from_str(&steps).unwrap_or_default(), // Unreadable steps just aren't recalled
+            created_ms: row.get(5)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Forgets every saved task run.
+pub fn clear_task_runs(conn: &Connection) -> Result<(), StorageError> {
+    conn.execute("DELETE FROM task_runs", [])?;
+    Ok(())
+}
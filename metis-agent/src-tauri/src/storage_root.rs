@@ -0,0 +1,166 @@
+// --- Recording Storage Location ---
+// Recordings live in the admin policy's storage_dir if it sets one, else in
+// settings.storage.base_folder, else in Downloads/screenshots. set_storage_root
+// changes the setting after checking the new folder is writable, and moves the
+// existing sessions along so they stay searchable and replayable. Only what
+// Metis itself wrote is moved, so pointing the old root at a shared folder
+// never drags unrelated files with it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::State;
+use tracing::info;
+
+use crate::app_state::{AppInputState, SharedAppState};
+use crate::config;
+use crate::policy;
+use crate::recorder::{self, SharedRecordingState};
+use crate::sync::LockExt;
+
+// Everything the recorder, session database, key store and video writer keep in the root
+pub const MANAGED_ENTRIES: &[&str] = &[
+    "metis.db",
+    "metis.db-wal",
+    "metis.db-shm",
+    "main.csv",
+    "main.csv.migrated",
+    "images",
+    "encrypted_csv",
+    "salt",
+    "video",
+    "skills.json",
+    "schedules.json",
+    "triggers.json",
+    "browser-profile",
+    "prompt_template.txt",
+];
+
+const WRITE_PROBE: &str = ".metis-write-test";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageRoot {
+    pub path: String,
+    pub custom: bool,           // Set in settings rather than the default
+    pub pinned_by_policy: bool, // The admin policy decides; set_storage_root is refused
+}
+
+fn current() -> StorageRoot {
+    StorageRoot {
+        path: recorder::get_default_base_folder().to_string_lossy().into_owned(),
+        custom: config::get().storage.base_folder.is_some(),
+        pinned_by_policy: policy::get().storage_dir.is_some(),
+    }
+}
+
+fn ensure_writable(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let probe = dir.join(WRITE_PROBE);
+    fs::write(&probe, b"metis").map_err(|e| format!("{} is not writable: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Renames `from` to `to`, falling back to copy-then-delete across filesystems.
+fn move_entry(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_recursive(from, to)?;
+    if from.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+/// Moves Metis's files from the `from` root into `to`. Returns how many entries moved.
+fn migrate(from: &Path, to: &Path) -> Result<usize, String> {
+    let present: Vec<&str> = MANAGED_ENTRIES.iter().copied().filter(|name| from.join(name).exists()).collect();
+    let conflicts: Vec<&str> = present.iter().copied().filter(|name| to.join(name).exists()).collect();
+    if !conflicts.is_empty() {
+        return Err(format!(
+            "{} already holds Metis data ({}); move or remove it first",
+            to.display(),
+            conflicts.join(", ")
+        ));
+    }
+    if let Some(name) = present.iter().find(|name| to.starts_with(from.join(name))) {
+        return Err(format!("{} is inside the Metis folder {} it would be moved from", to.display(), name));
+    }
+    for (i, name) in present.iter().enumerate() {
+        if let Err(e) = move_entry(&from.join(name), &to.join(name)) {
+            // Put back what already moved so the old root stays complete
+            for name in present[..i].iter().rev() {
+                let _ = move_entry(&to.join(name), &from.join(name));
+            }
+            return Err(format!("Failed to move {} to {}: {}", from.join(name).display(), to.display(), e));
+        }
+    }
+    Ok(present.len())
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[tauri::command]
+pub fn get_storage_root() -> Result<StorageRoot, String> {
+    Ok(current())
+}
+
+/// Stores recordings under `path` from now on ("" restores the default), moving
+/// the existing sessions there. Refused while recording or running a task.
+#[tauri::command]
+pub fn set_storage_root(
+    app_state: State<'_, SharedAppState>,
+    recording: State<'_, SharedRecordingState>,
+    path: String,
+) -> Result<StorageRoot, String> {
+    if policy::get().storage_dir.is_some() {
+        return Err("The storage location is set by the administrator policy".to_string());
+    }
+    let state = app_state.lock_or_recover().input_state();
+    if state != AppInputState::Idle {
+        return Err(format!("Cannot move recordings while busy (state: {:?})", state));
+    }
+
+    let path = path.trim();
+    let setting = Some(path.to_string()).filter(|p| !p.is_empty());
+    if setting.as_deref().is_some_and(|p| !Path::new(p).is_absolute()) {
+        return Err(format!("Storage location must be an absolute path, got '{}'", path));
+    }
+    let old_root = recorder::get_default_base_folder();
+    let new_root = match &setting {
+        Some(p) => PathBuf::from(p),
+        None => recorder::default_storage_root(),
+    };
+    ensure_writable(&new_root)?;
+
+    if !same_dir(&old_root, &new_root) && old_root.exists() {
+        let moved = migrate(&old_root, &new_root)?;
+        info!("Moved {} item(s) from {} to {}", moved, old_root.display(), new_root.display());
+    }
+    config::update(|s| s.storage.base_folder = setting)?;
+    // The task loop caches the root it last used; the next run must pick up the new one
+    recording.lock_or_recover().base_folder = None;
+    Ok(current())
+}
@@ -0,0 +1,57 @@
+// --- Poison-Tolerant State Access ---
+// A panic while holding one of the global mutexes poisons it, and every later
+// `.lock().unwrap()` would then panic too, taking down every command for the rest
+// of the session. `lock_or_recover` takes the guard anyway, clears the poison,
+// and records the recovery so it shows up in `get_state_health`.
+
+use std::panic::Location;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::warn;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StateHealth {
+    pub poison_recoveries: u64,
+    pub last_recovery_location: Option<String>, // file:line of the lock call that recovered
+    pub last_recovery_time: Option<u64>,        // Unix seconds
+}
+
+// Plain Mutex on purpose: it is only held for a few instructions and never while
+// another lock is taken, so it can't be poisoned by the code it is tracking.
+static STATE_HEALTH: Lazy<Mutex<StateHealth>> = Lazy::new(|| Mutex::new(StateHealth::default()));
+
+pub trait LockExt<T> {
+    /// Locks the mutex, recovering the guard if a previous holder panicked.
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockExt<T> for Mutex<T> {
+    #[track_caller]
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        match self.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                let location = Location::caller();
+                warn!("Recovered poisoned mutex at {}:{}", location.file(), location.line());
+                record_recovery(location);
+                self.clear_poison();
+                poisoned.into_inner()
+            }
+        }
+    }
+}
+
+fn record_recovery(location: &Location<'_>) {
+    let mut health = STATE_HEALTH.lock().unwrap_or_else(|p| p.into_inner());
+    health.poison_recoveries += 1;
+    health.last_recovery_location = Some(format!("{}:{}", location.file(), location.line()));
+    health.last_recovery_time = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+}
+
+#[tauri::command]
+pub fn get_state_health() -> Result<StateHealth, String> {
+    Ok(STATE_HEALTH.lock().unwrap_or_else(|p| p.into_inner()).clone())
+}
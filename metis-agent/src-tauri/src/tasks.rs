@@ -0,0 +1,108 @@
+// --- Background Tasks ---
+// start_act returns a task ID as soon as the loop is spawned instead of holding
+// the IPC call open for the whole run. The loop blocks (sleeps, blocking HTTP),
+// so it runs as a blocking task on Tauri's tokio runtime, and its outcome is
+// recorded here for get_task_status. The last few finished tasks are kept so a
+// late poll still sees how they ended.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{error, info};
+
+use crate::error::MetisError;
+use crate::sync::LockExt;
+
+const MAX_KEPT_TASKS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub id: String,
+    pub command: String,
+    pub state: TaskState,
+    pub message: Option<String>, // The result or error once finished
+    pub error: Option<Value>, // A failure as { category, kind, message } (MetisError)
+    pub started_ms: u64,
+    pub finished_ms: Option<u64>,
+}
+
+static TASKS: Lazy<Mutex<VecDeque<TaskStatus>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Starts `run` in the background and returns its task ID.
+pub fn spawn(command: String, run: impl FnOnce() -> Result<String, MetisError> + Send + 'static) -> String {
+    let started_ms = now_ms();
+    let id = format!("task_{}_{:08x}", started_ms, rand::random::<u32>());
+    {
+        let mut tasks = TASKS.lock_or_recover();
+        // Drop the oldest finished task; running ones are never forgotten
+        if tasks.len() >= MAX_KEPT_TASKS {
+            if let Some(i) = tasks.iter().position(|t| t.state != TaskState::Running) {
+                tasks.remove(i);
+            }
+        }
+        tasks.push_back(TaskStatus { id: id.clone(), command, state: TaskState::Running, message: None, error: None, started_ms, finished_ms: None });
+    }
+    let task_id = id.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|panic_info| {
+            let payload = panic_info
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic_info.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic payload".to_string());
+            error!("Task {} panicked: {}", task_id, payload);
+            Err(MetisError::State(format!("Action execution thread panicked: {}", payload)))
+        });
+        finish(&task_id, result);
+    });
+    id
+}
+
+fn finish(id: &str, result: Result<String, MetisError>) {
+    let mut tasks = TASKS.lock_or_recover();
+    if let Some(task) = tasks.iter_mut().find(|t| t.id == id) {
+        let (state, message) = match result {
+            Ok(message) => (TaskState::Completed, message),
+            Err(e) => {
+                task.error = serde_json::to_value(&e).ok();
+                (TaskState::Failed, e.to_string())
+            }
+        };
+        info!("Task {} {:?}: {}", id, state, message);
+        task.state = state;
+        task.message = Some(message);
+        task.finished_ms = Some(now_ms());
+    }
+}
+
+/// Whether the task `id` is still running.
+pub fn is_running(id: &str) -> bool {
+    TASKS.lock_or_recover().iter().any(|t| t.id == id && t.state == TaskState::Running)
+}
+
+/// The task `id`, while it is running or still among the kept finished ones.
+pub fn status(id: &str) -> Option<TaskStatus> {
+    TASKS.lock_or_recover().iter().find(|t| t.id == id).cloned()
+}
+
+#[tauri::command]
+pub fn get_task_status(id: String) -> Result<TaskStatus, String> {
+    status(&id).ok_or_else(|| format!("Unknown task '{}'", id))
+}
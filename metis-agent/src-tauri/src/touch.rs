@@ -0,0 +1,105 @@
+// --- Touch Injection (Windows) ---
+// Real touch contacts through InjectTouchInput, so touch-first apps and
+// touchscreen laptops see a long press, pinch or two-finger scroll rather than
+// the mouse emulation other backends fall back to. A gesture is a set of
+// contacts moving in straight lines: all go down, move together in small steps
+// (Windows cancels contacts that stop being updated), then lift.
+
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+
+use windows_sys::Win32::Foundation::{GetLastError, POINT, RECT};
+use windows_sys::Win32::UI::Input::Pointer::{
+    InitializeTouchInjection, InjectTouchInput, POINTER_FLAGS, POINTER_FLAG_DOWN, POINTER_FLAG_INCONTACT,
+    POINTER_FLAG_INRANGE, POINTER_FLAG_UP, POINTER_FLAG_UPDATE, POINTER_TOUCH_INFO, TOUCH_FEEDBACK_DEFAULT,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{PT_TOUCH, TOUCH_MASK_NONE};
+
+use crate::error::ActionError;
+
+const MAX_CONTACTS: u32 = 2;
+const FRAME: Duration = Duration::from_millis(16); // Contact update interval
+const GESTURE_FRAMES: u32 = 20;
+const PINCH_START_SPREAD: i32 = 100; // Pixels from the center to each finger
+const FINGER_GAP: i32 = 60;          // Between the two fingers of a scroll
+const SCROLL_STEP: i32 = 100;        // Pixels of travel per scroll unit
+
+static INIT: Once = Once::new();
+
+fn touch_error(what: &str) -> ActionError {
+    ActionError::Gesture(format!("{} failed (error {})", what, unsafe { GetLastError() }))
+}
+
+fn contact(id: u32, (x, y): (i32, i32), flags: POINTER_FLAGS) -> POINTER_TOUCH_INFO {
+    let mut info: POINTER_TOUCH_INFO = unsafe { std::mem::zeroed() };
+    info.pointerInfo.pointerType = PT_TOUCH;
+    info.pointerInfo.pointerId = id;
+    info.pointerInfo.pointerFlags = flags;
+    info.pointerInfo.ptPixelLocation = POINT { x, y };
+    info.touchMask = TOUCH_MASK_NONE;
+    info.rcContact = RECT { left: x - 2, top: y - 2, right: x + 2, bottom: y + 2 };
+    info
+}
+
+fn inject(points: &[(i32, i32)], flags: POINTER_FLAGS) -> Result<(), ActionError> {
+    let contacts: Vec<POINTER_TOUCH_INFO> =
+        points.iter().enumerate().map(|(id, &point)| contact(id as u32, point, flags)).collect();
+    if unsafe { InjectTouchInput(contacts.len() as u32, contacts.as_ptr()) } == 0 {
+        return Err(touch_error("InjectTouchInput"));
+    }
+    Ok(())
+}
+
+/// Puts one finger per path down at its start, moves all of them to their ends
+/// over `duration`, and lifts them.
+fn gesture(paths: &[((i32, i32), (i32, i32))], duration: Duration) -> Result<(), ActionError> {
+    let mut init_result = Ok(());
+    INIT.call_once(|| {
+        if unsafe { InitializeTouchInjection(MAX_CONTACTS, TOUCH_FEEDBACK_DEFAULT) } == 0 {
+            init_result = Err(touch_error("InitializeTouchInjection"));
+        }
+    });
+    init_result?;
+
+    let at = |t: f32| -> Vec<(i32, i32)> {
+        paths
+            .iter()
+            .map(|&((x0, y0), (x1, y1))| {
+                (x0 + ((x1 - x0) as f32 * t) as i32, y0 + ((y1 - y0) as f32 * t) as i32)
+            })
+            .collect()
+    };
+    let frames = (duration.as_millis() / FRAME.as_millis()).max(1) as u32;
+
+    inject(&at(0.0), POINTER_FLAG_DOWN | POINTER_FLAG_INRANGE | POINTER_FLAG_INCONTACT)?;
+    let moved = (1..=frames).try_for_each(|frame| {
+        thread::sleep(FRAME);
+        inject(&at(frame as f32 / frames as f32), POINTER_FLAG_UPDATE | POINTER_FLAG_INRANGE | POINTER_FLAG_INCONTACT)
+    });
+    // Always lift, or the contacts stay down until Windows times them out
+    let lifted = inject(&at(1.0), POINTER_FLAG_UP);
+    moved.and(lifted)
+}
+
+pub fn long_press(x: i32, y: i32, hold: Duration) -> Result<(), ActionError> {
+    gesture(&[((x, y), (x, y))], hold)
+}
+
+pub fn pinch(x: i32, y: i32, scale: f32) -> Result<(), ActionError> {
+    let end_spread = (PINCH_START_SPREAD as f32 * scale).round() as i32;
+    gesture(
+        &[
+            ((x - PINCH_START_SPREAD, y), (x - end_spread, y)),
+            ((x + PINCH_START_SPREAD, y), (x + end_spread, y)),
+        ],
+        FRAME * GESTURE_FRAMES,
+    )
+}
+
+pub fn two_finger_scroll(x: i32, y: i32, units: i32) -> Result<(), ActionError> {
+    // Fingers move up to scroll the content down
+    let travel = units * SCROLL_STEP;
+    let (left, right) = (x - FINGER_GAP / 2, x + FINGER_GAP / 2);
+    gesture(&[((left, y), (left, y - travel)), ((right, y), (right, y - travel))], FRAME * GESTURE_FRAMES)
+}
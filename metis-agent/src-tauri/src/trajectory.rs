@@ -0,0 +1,112 @@
+// --- Mouse Trajectories ---
+// A click's screenshots show where the pointer started and ended, not how it got
+// there, so drags and hover paths can't be reconstructed from them. The mouse
+// tracker samples the pointer every 50 ms; samples where it moved are collected
+// into a segment, and each button press or release closes the current segment
+// into trajectory.jsonl in the action folder, one TrajectorySegment per line.
+// A segment closed by a release was a drag; one closed by a press was a hover path.
+//
+// Points are stored as an encoded polyline (the Google Maps algorithm) of
+// (ms since the previous point, dx, dy) triples, the first one relative to
+// (start_ms, 0, 0). Decoding: read signed varints of 5-bit groups, each char
+// minus 63, bit 0x20 meaning "more follows", zigzag-decoded, then sum the deltas.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::warn;
+
+pub const TRAJECTORY_FILE: &str = "trajectory.jsonl";
+
+#[derive(Debug, Serialize)]
+struct TrajectorySegment<'a> {
+    start_ms: u64,
+    end_ms: u64,
+    until: &'a str, // What closed the segment: "ButtonPress", "ButtonRelease" or "Stop"
+    drag: bool,     // A button was held throughout
+    points: usize,
+    polyline: String,
+}
+
+fn push_value(out: &mut String, value: i64) {
+    let mut v = if value < 0 { !(value << 1) } else { value << 1 } as u64;
+    while v >= 0x20 {
+        out.push(char::from((0x20 | (v & 0x1f)) as u8 + 63));
+        v >>= 5;
+    }
+    out.push(char::from(v as u8 + 63));
+}
+
+fn encode(start_ms: u64, points: &[(u64, i32, i32)]) -> String {
+    let mut out = String::new();
+    let mut previous = (start_ms, 0, 0);
+    for &(t, x, y) in points {
+        push_value(&mut out, t as i64 - previous.0 as i64);
+        push_value(&mut out, i64::from(x - previous.1));
+        push_value(&mut out, i64::from(y - previous.2));
+        previous = (t, x, y);
+    }
+    out
+}
+
+/// An open trajectory.jsonl and the segment being collected.
+pub struct TrajectoryLog {
+    writer: BufWriter<File>,
+    points: Vec<(u64, i32, i32)>, // (Unix ms, x, y) since the last button event
+    button_down: bool,
+}
+
+impl TrajectoryLog {
+    pub fn open(action_folder: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(action_folder.join(TRAJECTORY_FILE))?;
+        Ok(TrajectoryLog { writer: BufWriter::new(file), points: Vec::new(), button_down: false })
+    }
+
+    /// Adds a pointer sample, unless the pointer hasn't moved since the last one.
+    pub fn sample(&mut self, timestamp_ms: u64, x: i32, y: i32) {
+        if self.points.last().is_some_and(|&(_, px, py)| (px, py) == (x, y)) {
+            return;
+        }
+        self.points.push((timestamp_ms, x, y));
+    }
+
+    /// Closes the current segment at a button event (or "Stop"). The pointer's last
+    /// position carries over as the first point of the next segment.
+    pub fn split(&mut self, until: &str, timestamp_ms: u64) {
+        let Some(&(_, x, y)) = self.points.last() else {
+            self.button_down = until == "ButtonPress";
+            return;
+        };
+        self.points.push((timestamp_ms, x, y));
+        // Just the carried-over point and this one: the pointer never moved
+        if self.points.len() > 2 {
+            let start_ms = self.points[0].0;
+            let segment = TrajectorySegment {
+                start_ms,
+                end_ms: timestamp_ms,
+                until,
+                drag: self.button_down,
+                points: self.points.len(),
+                polyline: encode(start_ms, &self.points),
+            };
+            let written = serde_json::to_writer(&mut self.writer, &segment)
+                .map_err(io::Error::from)
+                .and_then(|_| self.writer.write_all(b"\n"));
+            if let Err(e) = written {
+                warn!("Failed to log mouse trajectory: {}", e);
+            }
+        }
+        self.points = vec![(timestamp_ms, x, y)];
+        self.button_down = until == "ButtonPress";
+    }
+
+    /// Writes the last segment and flushes; call when the recording stops.
+    pub fn finish(mut self, timestamp_ms: u64) {
+        self.split("Stop", timestamp_ms);
+        if let Err(e) = self.writer.flush() {
+            warn!("Failed to flush the mouse trajectory log: {}", e);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+// --- Folder-Watch Triggers ---
+// A watch trigger runs a task command whenever a new file matching a glob lands
+// in a folder, e.g. "import {{file}} into the CRM" for *.csv in Downloads. In
+// the command, {{file}} is replaced with the file's full path and {{name}} with
+// its file name.
+//
+// Triggers live in triggers.json in the storage root. A background thread polls
+// their folders (not subfolders) every POLL_INTERVAL. Files already present when
+// a trigger is added, or when Metis starts, are left alone, and a new file only
+// counts once its size holds still between two polls, so a half-copied file
+// doesn't start a task. Tasks start one at a time while the app is Idle; files
+// that land while it is recording or busy wait their turn in trigger_queue.json
+// next to the triggers, so a restart doesn't lose them.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use glob::Pattern;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::app_state::{AppInputState, SharedAppState};
+use crate::error::MetisError;
+use crate::recorder::{self, SharedRecordingState};
+use crate::scheduler::RunTask;
+use crate::sync::LockExt;
+use crate::tasks;
+
+const TRIGGERS_FILE: &str = "triggers.json";
+const QUEUE_FILE: &str = "trigger_queue.json";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_QUEUED: usize = 100;
+
+static STORE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchTrigger {
+    pub id: String,
+    pub path: String,             // The folder watched
+    pub glob: String,             // Matched against file names, e.g. "*.csv"
+    pub command_template: String, // With {{file}} and {{name}} placeholders
+    pub created_ms: u64,
+    #[serde(default)]
+    pub last_file: Option<String>,
+    #[serde(default)]
+    pub last_run_ms: Option<u64>,
+    #[serde(default)]
+    pub last_task_id: Option<String>, // For get_task_status
+}
+
+impl WatchTrigger {
+    fn command_for(&self, file: &Path) -> String {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        self.command_template.replace("{{file}}", &file.to_string_lossy()).replace("{{name}}", &name)
+    }
+}
+
+/// A file that landed and is waiting for its trigger's task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Queued {
+    trigger_id: String,
+    file: PathBuf,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn store_path(base: &Path) -> PathBuf {
+    base.join(TRIGGERS_FILE)
+}
+
+fn load(base: &Path) -> io::Result<Vec<WatchTrigger>> {
+    match fs::read(store_path(base)) {
+        Ok(content) => serde_json::from_slice(&content).map_err(io::Error::from),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn save(base: &Path, triggers: &[WatchTrigger]) -> io::Result<()> {
+    let path = store_path(base);
+    let tmp = path.with_extension("tmp");
+    fs::create_dir_all(base)?;
+    fs::write(&tmp, serde_json::to_vec_pretty(triggers)?)?;
+    fs::rename(&tmp, &path)
+}
+
+/// The files still waiting from the last run, minus any removed since.
+fn load_queue(base: &Path) -> VecDeque<Queued> {
+    let queue: VecDeque<Queued> = match fs::read(base.join(QUEUE_FILE)) {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+            warn!("Invalid {}, dropping the waiting files: {}", QUEUE_FILE, e);
+            VecDeque::new()
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+        Err(e) => {
+            warn!("Failed to read {}: {}", QUEUE_FILE, e);
+            VecDeque::new()
+        }
+    };
+    queue.into_iter().filter(|queued| queued.file.is_file()).collect()
+}
+
+fn save_queue(base: &Path, queue: &VecDeque<Queued>) -> io::Result<()> {
+    let path = base.join(QUEUE_FILE);
+    let tmp = path.with_extension("tmp");
+    fs::create_dir_all(base)?;
+    fs::write(&tmp, serde_json::to_vec_pretty(queue)?)?;
+    fs::rename(&tmp, &path)
+}
+
+/// Matching files in the trigger's folder with their sizes.
+fn matching_files(trigger: &WatchTrigger) -> io::Result<HashMap<PathBuf, u64>> {
+    let pattern = Pattern::new(&trigger.glob).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut files = HashMap::new();
+    for entry in fs::read_dir(&trigger.path)?.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Hidden and partial-download files are never the finished file
+        if name.starts_with('.') || name.ends_with(".part") || name.ends_with(".crdownload") || !pattern.matches(&name) {
+            continue;
+        }
+        if let Some(meta) = entry.metadata().ok().filter(|meta| meta.is_file()) {
+            files.insert(entry.path(), meta.len());
+        }
+    }
+    Ok(files)
+}
+
+/// What the watcher knows about one trigger's folder.
+#[derive(Default)]
+struct Folder {
+    known: HashMap<PathBuf, u64>,    // Files already handled (or there from the start)
+    settling: HashMap<PathBuf, u64>, // New files and their size at the last poll
+}
+
+impl Folder {
+    /// Returns the files that landed and settled since the last poll.
+    fn poll(&mut self, files: HashMap<PathBuf, u64>) -> Vec<PathBuf> {
+        self.known.retain(|path, _| files.contains_key(path)); // A file removed and added again counts anew
+        let mut landed = Vec::new();
+        let mut settling = HashMap::new();
+        for (path, size) in files {
+            if self.known.contains_key(&path) {
+                continue;
+            }
+            if self.settling.get(&path) == Some(&size) {
+                self.known.insert(path.clone(), size);
+                landed.push(path);
+            } else {
+                settling.insert(path, size);
+            }
+        }
+        self.settling = settling;
+        landed.sort();
+        landed
+    }
+}
+
+struct Watcher {
+    folders: HashMap<String, Folder>, // By trigger ID
+    queue: VecDeque<Queued>,
+    running: Option<String>, // The last task started, which may not have left Idle yet
+}
+
+impl Watcher {
+    fn poll(&mut self, triggers: &[WatchTrigger]) {
+        self.folders.retain(|id, _| triggers.iter().any(|t| &t.id == id));
+        self.queue.retain(|queued| triggers.iter().any(|t| t.id == queued.trigger_id));
+        for trigger in triggers {
+            let files = match matching_files(trigger) {
+                Ok(files) => files,
+                Err(e) => {
+                    warn!("Trigger {}: cannot read {}: {}", trigger.id, trigger.path, e);
+                    continue;
+                }
+            };
+            match self.folders.get_mut(&trigger.id) {
+                Some(folder) => {
+                    for path in folder.poll(files) {
+                        if self.queue.len() >= MAX_QUEUED {
+                            warn!("Trigger {}: too many files waiting, ignoring {}", trigger.id, path.display());
+                            continue;
+                        }
+                        info!("Trigger {}: {} landed", trigger.id, path.display());
+                        self.queue.push_back(Queued { trigger_id: trigger.id.clone(), file: path });
+                    }
+                }
+                // New trigger, or just started: what is there now is the baseline
+                None => {
+                    self.folders.insert(trigger.id.clone(), Folder { known: files, settling: HashMap::new() });
+                }
+            }
+        }
+    }
+}
+
+/// Starts the next queued file's task if the app is Idle and the previous one finished.
+fn start_next(watcher: &mut Watcher, app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) -> io::Result<()> {
+    if watcher.queue.is_empty()
+        || watcher.running.as_deref().is_some_and(tasks::is_running)
+        || app_state.lock_or_recover().input_state() != AppInputState::Idle
+    {
+        return Ok(());
+    }
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut triggers = load(&base)?;
+    let Some(Queued { trigger_id, file: path }) = watcher.queue.pop_front() else {
+        return Ok(());
+    };
+    let Some(trigger) = triggers.iter_mut().find(|t| t.id == trigger_id) else {
+        return Ok(()); // Deleted meanwhile
+    };
+    let command = trigger.command_for(&path);
+    info!("Trigger {}: starting '{}'", trigger.id, command);
+    let (app_state, recording) = (app_state.clone(), recording.clone());
+    let task_command = command.clone();
+    let task_id = tasks::spawn(command, move || run(&app_state, &recording, task_command));
+    watcher.running = Some(task_id.clone());
+    trigger.last_task_id = Some(task_id);
+    trigger.last_file = Some(path.to_string_lossy().into_owned());
+    trigger.last_run_ms = Some(now_ms());
+    save(&base, &triggers)
+}
+
+/// Watches the triggers' folders for the rest of the process, running tasks
+/// through `run`.
+pub fn start(app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) {
+    let (app_state, recording) = (app_state.clone(), recording.clone());
+    thread::spawn(move || {
+        let queue = load_queue(&recorder::get_default_base_folder());
+        if !queue.is_empty() {
+            info!("{} triggered file(s) still waiting from the last run", queue.len());
+        }
+        let mut watcher = Watcher { folders: HashMap::new(), queue, running: None };
+        loop {
+            let base = recorder::get_default_base_folder();
+            let waiting = watcher.queue.clone();
+            let triggers = {
+                let _guard = STORE_LOCK.lock_or_recover();
+                load(&base)
+            };
+            match triggers {
+                Ok(triggers) => watcher.poll(&triggers),
+                Err(e) => warn!("Failed to load watch triggers: {}", e),
+            }
+            if let Err(e) = start_next(&mut watcher, &app_state, &recording, run) {
+                warn!("Failed to start a triggered task: {}", e);
+            }
+            if watcher.queue != waiting {
+                let _guard = STORE_LOCK.lock_or_recover();
+                if let Err(e) = save_queue(&base, &watcher.queue) {
+                    warn!("Failed to save the triggered files waiting: {}", e);
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+/// Runs `command_template` for each new file matching `glob` in the folder
+/// `path`; see the top of this file for the placeholders.
+#[tauri::command]
+pub fn add_watch_trigger(path: String, glob: String, command_template: String) -> Result<WatchTrigger, MetisError> {
+    let folder = PathBuf::from(path.trim());
+    if !folder.is_dir() {
+        return Err(MetisError::InvalidArgument(format!("{} is not a folder", folder.display())));
+    }
+    let glob = Some(glob.trim()).filter(|g| !g.is_empty()).unwrap_or("*").to_string();
+    Pattern::new(&glob).map_err(|e| MetisError::InvalidArgument(format!("Invalid pattern '{}': {}", glob, e)))?;
+    let command_template = command_template.trim().to_string();
+    if command_template.is_empty() {
+        return Err(MetisError::InvalidArgument("A trigger needs a command".to_string()));
+    }
+    let now = now_ms();
+    let trigger = WatchTrigger {
+        id: format!("trigger_{}_{:08x}", now, rand::random::<u32>()),
+        path: folder.to_string_lossy().into_owned(),
+        glob,
+        command_template,
+        created_ms: now,
+        last_file: None,
+        last_run_ms: None,
+        last_task_id: None,
+    };
+
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut triggers = load(&base)?;
+    triggers.push(trigger.clone());
+    save(&base, &triggers)?;
+    info!("Watching {} for {}: '{}'", trigger.path, trigger.glob, trigger.command_template);
+    Ok(trigger)
+}
+
+#[tauri::command]
+pub fn list_watch_triggers() -> Result<Vec<WatchTrigger>, MetisError> {
+    let _guard = STORE_LOCK.lock_or_recover();
+    Ok(load(&recorder::get_default_base_folder())?)
+}
+
+/// Stops watching; files already waiting for this trigger are dropped.
+#[tauri::command]
+pub fn delete_watch_trigger(id: String) -> Result<(), MetisError> {
+    let base = recorder::get_default_base_folder();
+    let _guard = STORE_LOCK.lock_or_recover();
+    let mut triggers = load(&base)?;
+    let before = triggers.len();
+    triggers.retain(|t| t.id != id);
+    if triggers.len() == before {
+        return Err(MetisError::InvalidArgument(format!("No watch trigger '{}'", id)));
+    }
+    save(&base, &triggers)?;
+    info!("Deleted watch trigger {}", id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("metis_triggers_test_{:08x}", rand::random::<u32>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn trigger(dir: &Path) -> WatchTrigger {
+        WatchTrigger {
+            id: "trigger_1".to_string(),
+            path: dir.to_string_lossy().into_owned(),
+            glob: "*.csv".to_string(),
+            command_template: "import {{name}}".to_string(),
+            created_ms: 0,
+            last_file: None,
+            last_run_ms: None,
+            last_task_id: None,
+        }
+    }
+
+    #[test]
+    fn settled_files_are_queued_once() {
+        let dir = temp_dir();
+        fs::write(dir.join("old.csv"), "a").unwrap();
+        let triggers = [trigger(&dir)];
+        let mut watcher = Watcher { folders: HashMap::new(), queue: VecDeque::new(), running: None };
+        watcher.poll(&triggers); // Baseline
+        fs::write(dir.join("new.csv"), "b").unwrap();
+        fs::write(dir.join("new.txt"), "c").unwrap();
+        watcher.poll(&triggers); // Settling
+        assert!(watcher.queue.is_empty());
+        watcher.poll(&triggers);
+        watcher.poll(&triggers);
+        assert_eq!(watcher.queue, [Queued { trigger_id: "trigger_1".to_string(), file: dir.join("new.csv") }]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_queue_survives_a_restart() {
+        let dir = temp_dir();
+        assert!(load_queue(&dir).is_empty());
+        fs::write(dir.join("a.csv"), "a").unwrap();
+        let queue: VecDeque<Queued> = ["a.csv", "gone.csv"]
+            .iter()
+            .map(|name| Queued { trigger_id: "trigger_1".to_string(), file: dir.join(name) })
+            .collect();
+        save_queue(&dir, &queue).unwrap();
+        // Files removed while Metis wasn't running are dropped
+        assert_eq!(load_queue(&dir), [queue[0].clone()]);
+
+        fs::write(dir.join(QUEUE_FILE), "not json").unwrap();
+        assert!(load_queue(&dir).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+// --- UI Automation Element Provider (Windows) ---
+// Walks the foreground window's UI Automation tree. Elements that support the
+// Invoke pattern are pressed through it without moving the mouse at all; the
+// rest are reported by their bounding rectangle for the caller to click.
+// `element_at` uses ElementFromPoint and climbs the control view to a named
+// element instead of walking the tree.
+
+use windows::core::Error as WinError;
+use windows::Win32::Foundation::POINT;
+use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED};
+use windows::Win32::UI::Accessibility::{
+    CUIAutomation, IUIAutomation, IUIAutomationElement, IUIAutomationInvokePattern, TreeScope_Descendants,
+    UIA_InvokePatternId,
+};
+use windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow;
+
+use crate::elements::{self, Activation, ElementInfo, ElementProvider};
+use crate::error::ActionError;
+
+// Unnamed wrappers rarely nest deeper than this above a named control
+const MAX_PARENTS: usize = 25;
+
+pub struct UiaProvider;
+
+fn uia_error(e: WinError) -> ActionError {
+    ActionError::ElementTargeting(e.to_string())
+}
+
+fn automation() -> Result<IUIAutomation, ActionError> {
+    // SAFETY: plain COM calls; every interface is reference-counted by the windows crate
+    unsafe {
+        // Already-initialized apartments (S_FALSE / RPC_E_CHANGED_MODE) are fine
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER).map_err(uia_error)
+    }
+}
+
+/// All descendants of the foreground window.
+fn foreground_elements() -> Result<Vec<IUIAutomationElement>, ActionError> {
+    let automation = automation()?;
+    // SAFETY: see automation
+    unsafe {
+        let root = automation.ElementFromHandle(GetForegroundWindow()).map_err(uia_error)?;
+        let condition = automation.CreateTrueCondition().map_err(uia_error)?;
+        let found = root.FindAll(TreeScope_Descendants, &condition).map_err(uia_error)?;
+        Ok((0..found.Length().map_err(uia_error)?).filter_map(|i| found.GetElement(i).ok()).collect())
+    }
+}
+
+/// The innermost named element at a screen point, in any window.
+fn hit_test(x: i32, y: i32) -> Result<Option<ElementInfo>, ActionError> {
+    let automation = automation()?;
+    // SAFETY: see automation
+    unsafe {
+        let walker = automation.ControlViewWalker().map_err(uia_error)?;
+        let mut element = automation.ElementFromPoint(POINT { x, y }).map_err(uia_error)?;
+        for _ in 0..MAX_PARENTS {
+            if let Some(info) = describe(&element) {
+                return Ok(Some(info));
+            }
+            let Ok(parent) = walker.GetParentElement(&element) else { break };
+            element = parent;
+        }
+        Ok(None)
+    }
+}
+
+/// Name, role and bounds of a visible, named element.
+fn describe(element: &IUIAutomationElement) -> Option<ElementInfo> {
+    unsafe {
+        if element.CurrentIsOffscreen().map(|b| b.as_bool()).unwrap_or(true) {
+            return None;
+        }
+        let name = element.CurrentName().ok()?.to_string();
+        if name.trim().is_empty() {
+            return None;
+        }
+        let role = element.CurrentLocalizedControlType().map(|t| t.to_string()).unwrap_or_default();
+        let rect = element.CurrentBoundingRectangle().ok()?;
+        Some(ElementInfo { name, role, x: rect.left, y: rect.top, width: rect.right - rect.left, height: rect.bottom - rect.top })
+    }
+}
+
+impl ElementProvider for UiaProvider {
+    fn name(&self) -> &'static str {
+        "uia"
+    }
+
+    fn snapshot(&self, limit: usize) -> Result<Vec<ElementInfo>, ActionError> {
+        Ok(foreground_elements()?.iter().filter_map(describe).take(limit).collect())
+    }
+
+    fn activate(&self, name: &str, role: Option<&str>) -> Result<Activation, ActionError> {
+        for element in foreground_elements()? {
+            let Some(info) = describe(&element) else { continue };
+            if !elements::matches(&info.name, &info.role, name, role) {
+                continue;
+            }
+            // SAFETY: see foreground_elements
+            if let Ok(pattern) = unsafe { element.GetCurrentPatternAs::<IUIAutomationInvokePattern>(UIA_InvokePatternId) } {
+                unsafe { pattern.Invoke() }.map_err(uia_error)?;
+                return Ok(Activation::Invoked);
+            }
+            let (x, y) = info.center();
+            return Ok(Activation::Click(x, y));
+        }
+        Err(elements::not_found(name, role))
+    }
+
+    fn element_at(&self, x: i32, y: i32) -> Result<Option<ElementInfo>, ActionError> {
+        hit_test(x, y)
+    }
+}
@@ -0,0 +1,178 @@
+// --- Continuous Session Video ---
+// Event screenshots only show the moments something was clicked or typed; the
+// optional session video fills in what happened between them. A thread grabs
+// the screen at a low fixed rate and pipes raw frames into ffmpeg (METIS_FFMPEG,
+// or `ffmpeg` on the PATH), writing video/session_<start ms>.mp4 or .webm.
+//
+// The video is constant rate: when a capture runs late the previous frame is
+// repeated, so video time is always wall time since `start_ms`. A frame whose
+// index timestamp is T sits at (T - start_ms) ms into the video; the start time
+// and rate are in the .json sidecar next to it. While the session is paused,
+// Metis or an app excluded from recording is in front, or a password is being
+// typed, the last frame is held.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::app_filter;
+use crate::capture::capture_screen;
+use crate::config::{self, VideoFormat, VideoSettings};
+use crate::focus;
+use crate::recorder::{self, SharedRecordingState};
+use crate::secure_input;
+use crate::session;
+
+const MAX_FPS: u32 = 30;
+
+#[derive(Serialize)]
+struct VideoSidecar<'a> {
+    file: &'a str,
+    session_id: &'a str,
+    start_ms: u64, // Wall-clock time of frame 0, same clock as the frame index
+    fps: u32,
+    width: u32,
+    height: u32,
+}
+
+pub fn ffmpeg_binary() -> String {
+    std::env::var("METIS_FFMPEG").unwrap_or_else(|_| "ffmpeg".to_string())
+}
+
+/// Whether the screen must not be captured right now.
+fn hold() -> bool {
+    session::is_paused() || focus::metis_focused() || secure_input::active() || app_filter::blocked()
+}
+
+/// The frame to write now, or `None` to hold the previous one.
+fn grab() -> Option<RgbaImage> {
+    if hold() {
+        return None;
+    }
+    match capture_screen() {
+        Ok(image) => Some(image.to_rgba8()),
+        Err(e) => {
+            error!("Video frame capture failed: {}", e);
+            None
+        }
+    }
+}
+
+fn spawn_encoder(path: &Path, settings: &VideoSettings, width: u32, height: u32) -> std::io::Result<Child> {
+    let codec: &[&str] = match settings.format {
+        VideoFormat::Mp4 => &["-c:v", "libx264", "-preset", "veryfast"],
+        VideoFormat::Webm => &["-c:v", "libvpx-vp9", "-deadline", "realtime"],
+    };
+    Command::new(ffmpeg_binary())
+        .args(["-loglevel", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{}x{}", width, height), "-r", &settings.fps.to_string(), "-i", "-"])
+        .args(codec)
+        // yuv420p needs even dimensions
+        .args(["-vf", "crop=trunc(iw/2)*2:trunc(ih/2)*2", "-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()
+}
+
+/// Starts recording video of the desktop into `base_folder`/video, until the
+/// recording stops. Failures are logged; the event recording carries on without it.
+pub fn start(recording: &SharedRecordingState, base_folder: &str, session_id: &str) {
+    let settings = config::get().video;
+    let video_dir = PathBuf::from(base_folder).join("video");
+    let session_id = session_id.to_string();
+    let recording = Arc::clone(recording);
+    thread::spawn(move || {
+        if let Err(e) = record(&recording, &video_dir, &session_id, settings) {
+            warn!("Session video stopped: {}", e);
+        }
+    });
+}
+
+fn record(recording: &SharedRecordingState, video_dir: &Path, session_id: &str, mut settings: VideoSettings) -> Result<(), String> {
+    settings.fps = settings.fps.clamp(1, MAX_FPS);
+    fs::create_dir_all(video_dir).map_err(|e| format!("Failed to create {}: {}", video_dir.display(), e))?;
+
+    // The first frame fixes the size; later frames are scaled to it if the display changes
+    let started = Instant::now();
+    let start_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    let mut first = capture_screen().map_err(|e| e.to_string())?.to_rgba8();
+    recorder::black_out(&mut first, &recorder::redaction_regions(recording));
+    let (width, height) = first.dimensions();
+    // Usually Metis itself is in front when recording starts: begin on black instead
+    let first = if hold() { RgbaImage::new(width, height) } else { first };
+
+    let extension = match settings.format {
+        VideoFormat::Mp4 => "mp4",
+        VideoFormat::Webm => "webm",
+    };
+    let file = format!("session_{}.{}", start_ms, extension);
+    let path = video_dir.join(&file);
+    let sidecar = VideoSidecar { file: &file, session_id, start_ms, fps: settings.fps, width, height };
+    let json = serde_json::to_string_pretty(&sidecar).map_err(|e| e.to_string())?;
+    fs::write(video_dir.join(format!("session_{}.json", start_ms)), json)
+        .map_err(|e| format!("Failed to write video sidecar: {}", e))?;
+
+    let mut encoder = spawn_encoder(&path, &settings, width, height)
+        .map_err(|e| format!("Failed to start {} (set METIS_FFMPEG to its path): {}", ffmpeg_binary(), e))?;
+    let stdin = encoder.stdin.take().ok_or("ffmpeg stdin unavailable")?;
+    info!("Recording session video to {} at {} fps", path.display(), settings.fps);
+
+    let result = write_frames(recording, stdin, first, started, settings.fps, session_id);
+    // stdin is closed by now, so ffmpeg finishes the file and exits
+    match encoder.wait() {
+        Ok(status) if status.success() => info!("Session video saved: {}", path.display()),
+        Ok(status) => warn!("ffmpeg exited with {} for {}", status, path.display()),
+        Err(e) => error!("Failed to wait for ffmpeg: {}", e),
+    }
+    result
+}
+
+fn write_frames(recording: &SharedRecordingState, mut stdin: ChildStdin, first: RgbaImage, started: Instant, fps: u32, session_id: &str) -> Result<(), String> {
+    let interval = Duration::from_secs(1) / fps;
+    let (width, height) = first.dimensions();
+    let mut frame = first;
+    let mut written: u32 = 0;
+
+    // Compared by ID so a quick stop-and-restart doesn't keep this video going
+    while recorder::active_session_id(recording).as_deref() == Some(session_id) {
+        // Every frame due by now; after a slow capture this repeats the latest one
+        let due = (started.elapsed().as_millis() * fps as u128 / 1000) as u32 + 1;
+        while written < due {
+            stdin.write_all(frame.as_raw()).map_err(|e| format!("Failed to write to ffmpeg: {}", e))?;
+            written += 1;
+        }
+
+        let next = started + interval * written;
+        thread::sleep(next.saturating_duration_since(Instant::now()));
+        if let Some(mut image) = grab() {
+            recorder::black_out(&mut image, &recorder::redaction_regions(recording));
+            frame = if image.dimensions() == (width, height) {
+                image
+            } else {
+                imageops::resize(&image, width, height, FilterType::Triangle)
+            };
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_video_settings() -> Result<VideoSettings, String> {
+    Ok(config::get().video)
+}
+
+#[tauri::command]
+pub fn update_video_settings(settings: VideoSettings) -> Result<VideoSettings, String> {
+    config::update(|s| s.video = settings).map(|s| s.video)
+}
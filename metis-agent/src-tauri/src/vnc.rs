@@ -0,0 +1,472 @@
+// --- Remote Desktop Target (VNC) ---
+// Lets the executor operate a VM or another machine while this one stays
+// usable: frames come from the remote framebuffer and input goes back over the
+// same RFB connection, so nothing touches the local mouse or keyboard.
+//
+// This is a minimal RFB 3.3-3.8 client: "None" and "VNC Authentication"
+// security, raw encoding, plus DesktopSize so resolution changes follow along.
+// RDP isn't spoken natively; Windows hosts are reached through a VNC server or
+// an RDP-to-VNC gateway. The password, if the server wants one, is read from
+// METIS_VNC_PASSWORD.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use des::cipher::{BlockEncrypt, KeyInit};
+use des::Des;
+use enigo::{Direction, Key};
+use image::{DynamicImage, ImageBuffer, Rgba};
+use zeroize::Zeroizing;
+use tracing::info;
+
+use crate::capture::CaptureBackend;
+use crate::error::{ActionError, CaptureError};
+use crate::input::InputBackend;
+use crate::net;
+use crate::sync::LockExt;
+use crate::window_control::WindowOp;
+
+const DEFAULT_PORT: u16 = 5900;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Client -> server message types
+const SET_PIXEL_FORMAT: u8 = 0;
+const SET_ENCODINGS: u8 = 2;
+const FRAMEBUFFER_UPDATE_REQUEST: u8 = 3;
+const KEY_EVENT: u8 = 4;
+const POINTER_EVENT: u8 = 5;
+
+// Server -> client message types
+const FRAMEBUFFER_UPDATE: u8 = 0;
+const SET_COLOUR_MAP_ENTRIES: u8 = 1;
+const BELL: u8 = 2;
+const SERVER_CUT_TEXT: u8 = 3;
+
+const ENCODING_RAW: i32 = 0;
+const ENCODING_DESKTOP_SIZE: i32 = -223;
+
+// Pointer button mask bits
+const BUTTON_LEFT: u8 = 1;
+const WHEEL_UP: u8 = 1 << 3;
+const WHEEL_DOWN: u8 = 1 << 4;
+const WHEEL_LEFT: u8 = 1 << 5; // Buttons 6 and 7, the usual X11 horizontal wheel
+const WHEEL_RIGHT: u8 = 1 << 6;
+
+fn vnc_error(message: impl ToString) -> ActionError {
+    ActionError::Device(format!("VNC: {}", message.to_string()))
+}
+
+/// One RFB connection and the framebuffer it keeps up to date.
+struct Connection {
+    stream: TcpStream,
+    width: u16,
+    height: u16,
+    pixels: Vec<u8>, // RGBA, width * height * 4
+}
+
+impl Connection {
+    fn read_exact<const N: usize>(&mut self) -> std::io::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.stream.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_u8(&mut self) -> std::io::Result<u8> {
+        Ok(self.read_exact::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> std::io::Result<u16> {
+        Ok(u16::from_be_bytes(self.read_exact()?))
+    }
+
+    fn read_u32(&mut self) -> std::io::Result<u32> {
+        Ok(u32::from_be_bytes(self.read_exact()?))
+    }
+
+    fn read_vec(&mut self, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.stream.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Length-prefixed failure reason sent before the server closes.
+    fn read_reason(&mut self) -> String {
+        self.read_u32()
+            .and_then(|len| self.read_vec(len.min(4096) as usize))
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_else(|_| "no reason given".to_string())
+    }
+
+    fn connect(host: &str, port: u16, password: Option<&str>) -> Result<Connection, ActionError> {
+        net::ensure_allowed(&format!("vnc://{}:{}", host, port)).map_err(vnc_error)?;
+        let addr = (host, port)
+            .to_socket_addrs()
+            .map_err(vnc_error)?
+            .next()
+            .ok_or_else(|| vnc_error(format!("Cannot resolve {}", host)))?;
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(vnc_error)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(vnc_error)?;
+        stream.set_nodelay(true).map_err(vnc_error)?;
+        let mut conn = Connection { stream, width: 0, height: 0, pixels: Vec::new() };
+        conn.handshake(password).map_err(vnc_error)?;
+        info!("Connected to VNC server {}:{} ({}x{})", host, port, conn.width, conn.height);
+        Ok(conn)
+    }
+
+    fn handshake(&mut self, password: Option<&str>) -> Result<(), String> {
+        let io = |e: std::io::Error| e.to_string();
+
+        // --- Protocol version: "RFB 003.00x\n" ---
+        let version = self.read_exact::<12>().map_err(io)?;
+        let minor = match &version[..] {
+            b"RFB 003.003\n" => 3,
+            b"RFB 003.007\n" => 7,
+            v if v.starts_with(b"RFB 003.") => 8, // 3.8 and anything newer speaks 3.8
+            _ => return Err(format!("Not an RFB server: {:?}", String::from_utf8_lossy(&version))),
+        };
+        self.stream.write_all(format!("RFB 003.{:03}\n", minor).as_bytes()).map_err(io)?;
+
+        // --- Security type ---
+        let security = if minor == 3 {
+            self.read_u32().map_err(io)? // Server decides
+        } else {
+            let count = self.read_u8().map_err(io)?;
+            if count == 0 {
+                return Err(format!("Server refused the connection: {}", self.read_reason()));
+            }
+            let offered = self.read_vec(count as usize).map_err(io)?;
+            let chosen = [1u8, 2].into_iter().find(|t| offered.contains(t)).ok_or_else(|| {
+                format!("No supported security type (server offers {:?})", offered)
+            })?;
+            self.stream.write_all(&[chosen]).map_err(io)?;
+            chosen as u32
+        };
+        match security {
+            0 => return Err(format!("Server refused the connection: {}", self.read_reason())),
+            1 => {}
+            2 => {
+                let password = password.ok_or("Server requires a password; set METIS_VNC_PASSWORD")?;
+                let challenge = self.read_exact::<16>().map_err(io)?;
+                self.stream.write_all(&vnc_auth_response(password, challenge)).map_err(io)?;
+            }
+            other => return Err(format!("Unsupported security type {}", other)),
+        }
+        // 3.8 always sends a result; older versions only after VNC authentication
+        if (security == 2 || minor == 8) && self.read_u32().map_err(io)? != 0 {
+            let reason = if minor == 8 { self.read_reason() } else { "wrong password".to_string() };
+            return Err(format!("Authentication failed: {}", reason));
+        }
+
+        // --- Init: shared session, so a person can watch along ---
+        self.stream.write_all(&[1]).map_err(io)?;
+        self.width = self.read_u16().map_err(io)?;
+        self.height = self.read_u16().map_err(io)?;
+        self.read_exact::<16>().map_err(io)?; // Server pixel format; we set our own below
+        let name_len = self.read_u32().map_err(io)?;
+        self.read_vec(name_len as usize).map_err(io)?;
+        self.resize(self.width, self.height);
+
+        // 32bpp little-endian true colour: bytes arrive as B, G, R, X
+        let mut format = vec![SET_PIXEL_FORMAT, 0, 0, 0, 32, 24, 0, 1];
+        format.extend_from_slice(&255u16.to_be_bytes());
+        format.extend_from_slice(&255u16.to_be_bytes());
+        format.extend_from_slice(&255u16.to_be_bytes());
+        format.extend_from_slice(&[16, 8, 0, 0, 0, 0]);
+        self.stream.write_all(&format).map_err(io)?;
+
+        let mut encodings = vec![SET_ENCODINGS, 0];
+        encodings.extend_from_slice(&2u16.to_be_bytes());
+        encodings.extend_from_slice(&ENCODING_RAW.to_be_bytes());
+        encodings.extend_from_slice(&ENCODING_DESKTOP_SIZE.to_be_bytes());
+        self.stream.write_all(&encodings).map_err(io)
+    }
+
+    fn resize(&mut self, width: u16, height: u16) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width as usize * height as usize * 4];
+    }
+
+    /// Requests the whole screen and reads messages until the update arrives.
+    fn refresh(&mut self) -> std::io::Result<()> {
+        let mut request = vec![FRAMEBUFFER_UPDATE_REQUEST, 0, 0, 0, 0, 0];
+        request.extend_from_slice(&self.width.to_be_bytes());
+        request.extend_from_slice(&self.height.to_be_bytes());
+        self.stream.write_all(&request)?;
+
+        loop {
+            match self.read_u8()? {
+                FRAMEBUFFER_UPDATE => {
+                    self.read_u8()?; // Padding
+                    let rects = self.read_u16()?;
+                    for _ in 0..rects {
+                        self.read_rect()?;
+                    }
+                    return Ok(());
+                }
+                SET_COLOUR_MAP_ENTRIES => {
+                    self.read_exact::<3>()?; // Padding, first colour
+                    let count = self.read_u16()?;
+                    self.read_vec(count as usize * 6)?; // Unused in true-colour mode
+                }
+                BELL => {}
+                SERVER_CUT_TEXT => {
+                    self.read_exact::<3>()?;
+                    let len = self.read_u32()?;
+                    self.read_vec(len as usize)?; // The remote clipboard stays remote
+                }
+                other => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Unexpected server message type {}", other),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn read_rect(&mut self) -> std::io::Result<()> {
+        let (x, y) = (self.read_u16()? as usize, self.read_u16()? as usize);
+        let (w, h) = (self.read_u16()? as usize, self.read_u16()? as usize);
+        match i32::from_be_bytes(self.read_exact()?) {
+            ENCODING_RAW => {
+                let data = self.read_vec(w * h * 4)?;
+                let stride = self.width as usize * 4;
+                for row in 0..h {
+                    let src = &data[row * w * 4..(row + 1) * w * 4];
+                    let start = (y + row) * stride + x * 4;
+                    let Some(dst) = self.pixels.get_mut(start..start + w * 4) else { break };
+                    for (out, bgrx) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+                        out.copy_from_slice(&[bgrx[2], bgrx[1], bgrx[0], 255]);
+                    }
+                }
+                Ok(())
+            }
+            ENCODING_DESKTOP_SIZE => {
+                self.resize(w as u16, h as u16);
+                Ok(())
+            }
+            other => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Server sent unrequested encoding {}", other),
+            )),
+        }
+    }
+
+    fn pointer(&mut self, buttons: u8, (x, y): (i32, i32)) -> std::io::Result<()> {
+        let clamp = |v: i32, max: u16| v.clamp(0, max.saturating_sub(1) as i32) as u16;
+        let mut event = vec![POINTER_EVENT, buttons];
+        event.extend_from_slice(&clamp(x, self.width).to_be_bytes());
+        event.extend_from_slice(&clamp(y, self.height).to_be_bytes());
+        self.stream.write_all(&event)
+    }
+
+    fn key(&mut self, keysym: u32, down: bool) -> std::io::Result<()> {
+        let mut event = vec![KEY_EVENT, down as u8, 0, 0];
+        event.extend_from_slice(&keysym.to_be_bytes());
+        self.stream.write_all(&event)
+    }
+}
+
+/// The DES response to a VNC Authentication challenge. VNC uses the first eight
+/// bytes of the password as the key, with each byte's bits reversed.
+fn vnc_auth_response(password: &str, challenge: [u8; 16]) -> [u8; 16] {
+    let mut key = Zeroizing::new([0u8; 8]);
+    for (k, b) in key.iter_mut().zip(password.bytes()) {
+        *k = b.reverse_bits();
+    }
+    let cipher = Des::new_from_slice(key.as_ref()).expect("DES key is 8 bytes");
+    let mut response = challenge;
+    for block in response.chunks_exact_mut(8) {
+        cipher.encrypt_block(block.into());
+    }
+    response
+}
+
+/// X11 keysym for a key, as RFB expects.
+fn keysym(key: Key) -> Option<u32> {
+    Some(match key {
+        Key::Unicode(c) => char_keysym(c),
+        Key::Return => 0xff0d,
+        Key::Tab => 0xff09,
+        Key::Backspace => 0xff08,
+        Key::Escape => 0xff1b,
+        Key::Delete => 0xffff,
+        Key::Space => 0x20,
+        Key::Home => 0xff50,
+        Key::LeftArrow => 0xff51,
+        Key::UpArrow => 0xff52,
+        Key::RightArrow => 0xff53,
+        Key::DownArrow => 0xff54,
+        Key::PageUp => 0xff55,
+        Key::PageDown => 0xff56,
+        Key::End => 0xff57,
+        Key::Shift => 0xffe1,
+        Key::Control => 0xffe3,
+        Key::CapsLock => 0xffe5,
+        Key::Alt | Key::Option => 0xffe9,
+        Key::Meta => 0xffeb, // Super_L: the Windows/Command key on the remote side
+        Key::F1 => 0xffbe,
+        Key::F2 => 0xffbf,
+        Key::F3 => 0xffc0,
+        Key::F4 => 0xffc1,
+        Key::F5 => 0xffc2,
+        Key::F6 => 0xffc3,
+        Key::F7 => 0xffc4,
+        Key::F8 => 0xffc5,
+        Key::F9 => 0xffc6,
+        Key::F10 => 0xffc7,
+        Key::F11 => 0xffc8,
+        Key::F12 => 0xffc9,
+        _ => return None,
+    })
+}
+
+fn char_keysym(c: char) -> u32 {
+    match c {
+        '\n' | '\r' => 0xff0d,
+        '\t' => 0xff09,
+        c if (c as u32) < 0x100 => c as u32, // Latin-1 keysyms equal their code points
+        c => 0x0100_0000 | c as u32,         // Unicode keysym range
+    }
+}
+
+/// A connected remote desktop. Capture and input share one connection.
+#[derive(Clone)]
+pub struct VncSession(Arc<Mutex<Connection>>);
+
+impl VncSession {
+    /// Connects to `target` ("host" or "host:port"; ports below 100 are display numbers).
+    pub fn connect(target: &str) -> Result<VncSession, String> {
+        let (host, port) = match target.rsplit_once(':') {
+            Some((host, port)) => {
+                let port: u16 = port.parse().map_err(|_| format!("Invalid VNC port in '{}'", target))?;
+                (host, if port < 100 { DEFAULT_PORT + port } else { port })
+            }
+            None => (target, DEFAULT_PORT),
+        };
+        let password = std::env::var("METIS_VNC_PASSWORD").ok().map(Zeroizing::new);
+        Connection::connect(host, port, password.as_deref().map(String::as_str))
+            .map(|conn| VncSession(Arc::new(Mutex::new(conn))))
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn input(&self) -> VncInput {
+        VncInput { session: self.clone(), pointer: (0, 0), buttons: 0 }
+    }
+}
+
+impl CaptureBackend for VncSession {
+    fn name(&self) -> &'static str {
+        "vnc"
+    }
+
+    fn capture(&self) -> Result<Option<DynamicImage>, CaptureError> {
+        let mut conn = self.0.lock_or_recover();
+        conn.refresh().map_err(|e| CaptureError::Capture(format!("VNC: {}", e)))?;
+        let (width, height) = (conn.width as u32, conn.height as u32);
+        ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(width, height, conn.pixels.clone())
+            .map(|buffer| Some(DynamicImage::ImageRgba8(buffer)))
+            .ok_or_else(|| CaptureError::Capture("VNC framebuffer size mismatch".to_string()))
+    }
+
+    fn local_display(&self) -> bool {
+        false
+    }
+}
+
+/// Mouse and keyboard on the remote desktop.
+pub struct VncInput {
+    session: VncSession,
+    pointer: (i32, i32),
+    buttons: u8, // Currently held button mask
+}
+
+impl VncInput {
+    fn send_pointer(&mut self, buttons: u8) -> Result<(), ActionError> {
+        self.session.0.lock_or_recover().pointer(buttons, self.pointer).map_err(vnc_error)
+    }
+}
+
+impl InputBackend for VncInput {
+    fn name(&self) -> &'static str {
+        "vnc"
+    }
+
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<(), ActionError> {
+        self.pointer = (x, y);
+        self.send_pointer(self.buttons)
+    }
+
+    fn left_button(&mut self, direction: Direction) -> Result<(), ActionError> {
+        match direction {
+            Direction::Press => self.buttons |= BUTTON_LEFT,
+            Direction::Release => self.buttons &= !BUTTON_LEFT,
+            Direction::Click => {
+                self.send_pointer(self.buttons | BUTTON_LEFT)?;
+                self.buttons &= !BUTTON_LEFT;
+            }
+        }
+        self.send_pointer(self.buttons)
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), ActionError> {
+        let keysym = keysym(key).ok_or_else(|| ActionError::UnknownKey(format!("{:?} (no VNC keysym)", key)))?;
+        let mut conn = self.session.0.lock_or_recover();
+        if direction != Direction::Release {
+            conn.key(keysym, true).map_err(vnc_error)?;
+        }
+        if direction != Direction::Press {
+            conn.key(keysym, false).map_err(vnc_error)?;
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), ActionError> {
+        let mut conn = self.session.0.lock_or_recover();
+        for c in text.chars() {
+            let keysym = char_keysym(c);
+            conn.key(keysym, true).and_then(|_| conn.key(keysym, false)).map_err(vnc_error)?;
+        }
+        Ok(())
+    }
+
+    fn scroll(&mut self, units: i32) -> Result<(), ActionError> {
+        // Each wheel notch is a press and release of button 4 (up) or 5 (down)
+        let wheel = if units > 0 { WHEEL_DOWN } else { WHEEL_UP };
+        for _ in 0..units.unsigned_abs() {
+            self.send_pointer(self.buttons | wheel)?;
+            self.send_pointer(self.buttons)?;
+        }
+        Ok(())
+    }
+
+    fn hscroll(&mut self, units: i32) -> Result<(), ActionError> {
+        let wheel = if units > 0 { WHEEL_RIGHT } else { WHEEL_LEFT };
+        for _ in 0..units.unsigned_abs() {
+            self.send_pointer(self.buttons | wheel)?;
+            self.send_pointer(self.buttons)?;
+        }
+        Ok(())
+    }
+
+    fn main_display(&self) -> Result<(i32, i32), ActionError> {
+        let conn = self.session.0.lock_or_recover();
+        Ok((conn.width as i32, conn.height as i32))
+    }
+
+    fn activate_element(&mut self, _name: &str, _control_type: Option<&str>) -> Result<(), ActionError> {
+        Err(ActionError::ElementTargeting("Element targeting is not available on remote desktops".to_string()))
+    }
+
+    fn control_window(&mut self, _title: &str, _op: WindowOp) -> Result<(), ActionError> {
+        Err(ActionError::WindowControl("Window control is not available on remote desktops".to_string()))
+    }
+
+    fn launch(&mut self, _target: &str) -> Result<(), ActionError> {
+        Err(ActionError::Launch("Launching apps is not available on remote desktops".to_string()))
+    }
+}
@@ -0,0 +1,441 @@
+// --- Local HTTP Trigger Server ---
+// Lets scripts and home-automation tools start tasks: an opt-in HTTP/1.1
+// listener on 127.0.0.1 (settings.webhook) with two routes, both requiring
+// "Authorization: Bearer <token>":
+//
+//   POST /tasks               {"command": "..."} -> 202 {"id": "task_..."}
+//   GET  /tasks/{id}/status   -> 200 with the task's get_task_status record
+//
+// Tasks go through the same machinery as start_act. A task is refused with 409
+// while Metis is recording, running another task, or still starting the last
+// one posted here. Requests whose Host isn't localhost are refused, so a web
+// page can't reach the server through DNS rebinding. Only as much of HTTP as
+// these routes need is spoken: one request per connection, Content-Length
+// bodies, no chunked encoding. At most MAX_CONNECTIONS requests are served at
+// once; further connections get 503 until one finishes.
+//
+// The server follows the settings: enabling, disabling or moving it takes
+// effect within a second, without a restart.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app_state::{AppInputState, SharedAppState};
+use crate::config::{self, WebhookSettings};
+use crate::recorder::SharedRecordingState;
+use crate::scheduler::RunTask;
+use crate::sync::LockExt;
+use crate::tasks;
+
+const SETTINGS_CHECK: Duration = Duration::from_secs(1);
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+const MAX_CONNECTIONS: usize = 8;
+
+static LAST_TASK: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+static OPEN_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// A slot among the MAX_CONNECTIONS being served, given back when dropped.
+struct ConnectionSlot;
+
+impl ConnectionSlot {
+    fn take() -> Option<ConnectionSlot> {
+        OPEN_CONNECTIONS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| Some(open + 1).filter(|&n| n <= MAX_CONNECTIONS))
+            .ok()
+            .map(|_| ConnectionSlot)
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        OPEN_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>, // Names lowercased
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+struct TaskRequest {
+    command: String,
+}
+
+fn read_request(stream: &mut impl Read) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(at) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break at;
+        }
+        if buf.len() > MAX_HEADER_BYTES {
+            return Err(invalid("Request headers too large"));
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(invalid("Connection closed mid-request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.split('?').next().unwrap_or_default().to_string()),
+        _ => return Err(invalid("Malformed request line")),
+    };
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+    let length = match headers.iter().find(|(n, _)| n == "content-length") {
+        Some((_, value)) => value.parse::<usize>().map_err(|_| invalid("Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(invalid("Request body too large"));
+    }
+    let mut body = buf.split_off(header_end + 4);
+    if body.len() < length {
+        let already = body.len();
+        body.resize(length, 0);
+        stream.read_exact(&mut body[already..])?;
+    }
+    body.truncate(length);
+    Ok(Request { method, path, headers, body })
+}
+
+fn respond(stream: &mut TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    let body = body.to_string();
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        reason,
+        body.len()
+    );
+    if status == 401 {
+        response.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    stream.write_all(response.as_bytes())
+}
+
+fn error(message: impl Into<String>) -> Value {
+    json!({ "error": message.into() })
+}
+
+/// Compares without stopping at the first difference, so timing doesn't leak the token.
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn is_local_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    matches!(name, "127.0.0.1" | "localhost" | "::1")
+}
+
+fn start_task(request: &Request, app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) -> (u16, Value) {
+    let command = match serde_json::from_slice::<TaskRequest>(&request.body) {
+        Ok(task) if !task.command.trim().is_empty() => task.command.trim().to_string(),
+        Ok(_) => return (400, error("The command is empty")),
+        Err(e) => return (400, error(format!("Expected {{\"command\": \"...\"}}: {}", e))),
+    };
+    let mut last = LAST_TASK.lock_or_recover();
+    if last.as_deref().is_some_and(tasks::is_running) {
+        return (409, error("A task started here is still running"));
+    }
+    let state = app_state.lock_or_recover().input_state();
+    if state != AppInputState::Idle {
+        return (409, error(format!("Metis is busy ({:?})", state)));
+    }
+    info!("Webhook: starting '{}'", command);
+    let (app_state, recording) = (app_state.clone(), recording.clone());
+    let task_command = command.clone();
+    let id = tasks::spawn(command, move || run(&app_state, &recording, task_command));
+    *last = Some(id.clone());
+    (202, json!({ "id": id }))
+}
+
+fn route(request: &Request, app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) -> (u16, Value) {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["tasks"]) => start_task(request, app_state, recording, run),
+        ("GET", ["tasks", id, "status"]) => match tasks::status(id) {
+            Some(status) => (200, serde_json::to_value(status).unwrap_or_default()),
+            None => (404, error(format!("Unknown task '{}'", id))),
+        },
+        (_, ["tasks"]) | (_, ["tasks", _, "status"]) => (405, error("Method not allowed")),
+        _ => (404, error("Not found")),
+    }
+}
+
+/// Checks the Host and bearer token, then routes the request.
+fn answer(request: &Request, token: &str, app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) -> (u16, Value) {
+    if !request.header("host").is_some_and(is_local_host) {
+        (403, error("Only localhost may call this server"))
+    } else if !request.header("authorization").and_then(|v| v.strip_prefix("Bearer ")).is_some_and(|given| token_matches(given.trim(), token)) {
+        (401, error("Missing or wrong bearer token"))
+    } else {
+        route(request, app_state, recording, run)
+    }
+}
+
+fn prepare(stream: &TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))
+}
+
+fn handle(mut stream: TcpStream, token: &str, app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) -> io::Result<()> {
+    prepare(&stream)?;
+    let request = match read_request(&mut stream) {
+        Ok(request) => request,
+        Err(e) => return respond(&mut stream, 400, &error(e.to_string())),
+    };
+    let (status, body) = answer(&request, token, app_state, recording, run);
+    if status >= 400 {
+        warn!("Webhook: {} {} -> {}", request.method, request.path, status);
+    }
+    respond(&mut stream, status, &body)
+}
+
+/// Serves the webhook routes whenever settings.webhook enables them, for the
+/// rest of the process, starting tasks through `run`.
+pub fn start(app_state: &SharedAppState, recording: &SharedRecordingState, run: RunTask) {
+    let (app_state, recording) = (app_state.clone(), recording.clone());
+    thread::spawn(move || {
+        let mut bound: Option<(u16, TcpListener)> = None;
+        let mut failed_port = None;
+        loop {
+            let settings = config::get().webhook;
+            let token = settings.token.filter(|t| !t.is_empty());
+            let wanted = Some(settings.port).filter(|_| settings.enabled && token.is_some());
+            if bound.as_ref().map(|(port, _)| *port) != wanted {
+                if let Some((port, _)) = bound.take() {
+                    info!("Webhook server on port {} stopped", port);
+                }
+                if let Some(port) = wanted {
+                    match TcpListener::bind(("127.0.0.1", port)).and_then(|l| l.set_nonblocking(true).map(|_| l)) {
+                        Ok(listener) => {
+                            info!("Webhook server listening on 127.0.0.1:{}", port);
+                            bound = Some((port, listener));
+                            failed_port = None;
+                        }
+                        Err(e) if failed_port != Some(port) => {
+                            warn!("Webhook server cannot listen on port {}: {}", port, e);
+                            failed_port = Some(port);
+                        }
+                        Err(_) => {}
+                    }
+                }
+            }
+
+            let (Some((_, listener)), Some(token)) = (&bound, token) else {
+                thread::sleep(SETTINGS_CHECK);
+                continue;
+            };
+            let until = Instant::now() + SETTINGS_CHECK;
+            while Instant::now() < until {
+                match listener.accept() {
+                    Ok((mut stream, _)) => {
+                        let Some(slot) = ConnectionSlot::take() else {
+                            warn!("Webhook: {} connections already open, refusing another", MAX_CONNECTIONS);
+                            let _ = prepare(&stream).and_then(|_| respond(&mut stream, 503, &error("Too many connections")));
+                            continue;
+                        };
+                        let (token, app_state, recording) = (token.clone(), app_state.clone(), recording.clone());
+                        thread::spawn(move || {
+                            let _slot = slot;
+                            if let Err(e) = handle(stream, &token, &app_state, &recording, run) {
+                                warn!("Webhook connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+                    Err(e) => {
+                        warn!("Webhook server failed to accept a connection: {}", e);
+                        thread::sleep(ACCEPT_POLL);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_webhook_settings() -> Result<WebhookSettings, String> {
+    Ok(config::get().webhook)
+}
+
+/// Saves the webhook settings. Enabling the server without a token (or with
+/// the token cleared) generates a new one, which is returned.
+#[tauri::command]
+pub fn update_webhook_settings(mut settings: WebhookSettings) -> Result<WebhookSettings, String> {
+    if settings.port == 0 {
+        return Err("The webhook server needs a port".to_string());
+    }
+    if settings.enabled && settings.token.as_deref().map_or(true, str::is_empty) {
+        settings.token = Some(hex::encode(rand::random::<[u8; 32]>()));
+        info!("Generated a new webhook token");
+    }
+    config::update(|s| s.webhook = settings).map(|s| s.webhook)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state;
+    use crate::error::MetisError;
+    use crate::recorder;
+
+    const TOKEN: &str = "s3cret";
+
+    /// Hands out its bytes a few at a time, like a slow client.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn parse(raw: &[u8]) -> io::Result<Request> {
+        read_request(&mut Trickle(raw))
+    }
+
+    fn request(method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn run_ok(_: &SharedAppState, _: &SharedRecordingState, command: String) -> Result<String, MetisError> {
+        Ok(command)
+    }
+
+    fn status_of(request: &Request) -> u16 {
+        answer(request, TOKEN, &app_state::new_shared(), &recorder::new_shared(), run_ok).0
+    }
+
+    #[test]
+    fn reads_a_request_arriving_in_pieces() {
+        let raw = b"POST /tasks?x=1 HTTP/1.1\r\nHost: localhost:7878\r\nContent-Length: 20\r\n\r\n{\"command\": \"hello\"}";
+        let request = parse(raw).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/tasks"));
+        assert_eq!(request.header("host"), Some("localhost:7878"));
+        assert_eq!(request.header("content-length"), Some("20"));
+        assert_eq!(request.body, b"{\"command\": \"hello\"}");
+
+        let request = parse(b"GET /tasks/t/status HTTP/1.1\r\nHost: localhost\r\n\r\ntrailing").unwrap();
+        assert!(request.body.is_empty()); // No Content-Length, no body
+    }
+
+    #[test]
+    fn rejects_malformed_or_oversized_requests() {
+        let rejected = |raw: &[u8]| parse(raw).map(|_| ()).unwrap_err().kind();
+        assert_eq!(rejected(b"GET\r\n\r\n"), ErrorKind::InvalidData);
+        assert_eq!(rejected(b"GET / HTTP/1.1\r\nHost: localhost\r\n"), ErrorKind::InvalidData); // Closed mid-headers
+        assert_eq!(rejected(b"POST / HTTP/1.1\r\nContent-Length: ten\r\n\r\n"), ErrorKind::InvalidData);
+        assert_eq!(rejected(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_BYTES + 1).as_bytes()), ErrorKind::InvalidData);
+        assert_eq!(rejected(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"), ErrorKind::UnexpectedEof);
+
+        let mut huge = b"GET / HTTP/1.1\r\nX-Pad: ".to_vec();
+        huge.resize(MAX_HEADER_BYTES + 4096, b'a');
+        assert_eq!(rejected(&huge), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn only_loopback_hosts_are_local() {
+        for host in ["localhost", "localhost:7878", "127.0.0.1", "127.0.0.1:7878", "[::1]", "[::1]:7878"] {
+            assert!(is_local_host(host), "{}", host);
+        }
+        for host in ["", "example.com", "localhost.example.com:7878", "127.0.0.2", "[::2]:7878", "evil.test:127.0.0.1"] {
+            assert!(!is_local_host(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn host_and_token_are_checked_before_routing() {
+        let bearer = format!("Bearer {}", TOKEN);
+        let authed = |method: &str, path: &str, body: &str| request(method, path, &[("host", "localhost"), ("authorization", &bearer)], body);
+
+        assert_eq!(status_of(&request("GET", "/tasks/x/status", &[("authorization", &bearer)], "")), 403);
+        assert_eq!(status_of(&request("GET", "/tasks/x/status", &[("host", "evil.test"), ("authorization", &bearer)], "")), 403);
+        assert_eq!(status_of(&request("GET", "/tasks/x/status", &[("host", "localhost")], "")), 401);
+        assert_eq!(status_of(&request("GET", "/tasks/x/status", &[("host", "localhost"), ("authorization", "Bearer s3creT")], "")), 401);
+        assert_eq!(status_of(&request("GET", "/tasks/x/status", &[("host", "localhost"), ("authorization", TOKEN)], "")), 401);
+        assert_eq!(status_of(&request("GET", "/nowhere", &[("host", "localhost")], "")), 401); // Even unknown paths need the token
+
+        assert_eq!(status_of(&authed("GET", "/tasks/no_such_task/status", "")), 404);
+        assert_eq!(status_of(&authed("GET", "/nowhere", "")), 404);
+        assert_eq!(status_of(&authed("GET", "/tasks", "")), 405);
+        assert_eq!(status_of(&authed("DELETE", "/tasks/x/status", "")), 405);
+        assert_eq!(status_of(&authed("POST", "/tasks", "not json")), 400);
+        assert_eq!(status_of(&authed("POST", "/tasks", "{\"command\": \"  \"}")), 400);
+    }
+
+    #[test]
+    fn a_posted_task_can_be_polled() {
+        let bearer = format!("Bearer {}", TOKEN);
+        let headers = [("host", "127.0.0.1:7878"), ("authorization", bearer.as_str())];
+        let (status, body) =
+            answer(&request("POST", "/tasks", &headers, "{\"command\": \"open notes\"}"), TOKEN, &app_state::new_shared(), &recorder::new_shared(), run_ok);
+        assert_eq!(status, 202);
+        let id = body["id"].as_str().unwrap();
+        assert_eq!(status_of(&request("GET", &format!("/tasks/{}/status", id), &headers, "")), 200);
+    }
+
+    #[test]
+    fn connection_slots_are_capped_and_returned() {
+        let slots: Vec<_> = std::iter::from_fn(ConnectionSlot::take).take(MAX_CONNECTIONS + 1).collect();
+        assert_eq!(slots.len(), MAX_CONNECTIONS);
+        assert!(ConnectionSlot::take().is_none());
+        drop(slots);
+        assert!(ConnectionSlot::take().is_some());
+    }
+}
@@ -0,0 +1,261 @@
+// --- Window Control ---
+// Lets the agent focus, minimize or maximize a top-level window by title
+// instead of hunting for taskbar pixels. Titles match case-insensitively, an
+// exact title winning over one that merely contains the text. Each platform
+// talks to its own window manager: EWMH client messages on X11, Win32 on
+// Windows, and the AX API on macOS (ax.rs). Wayland sessions without XWayland
+// windows have nothing to talk to and report an error.
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::error::ActionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowOp {
+    Focus,
+    Minimize,
+    Maximize,
+}
+
+/// Picks the window whose title best matches `wanted`.
+pub fn best_match<T>(wanted: &str, windows: impl IntoIterator<Item = (T, String)>) -> Option<(T, String)> {
+    let wanted = wanted.trim().to_lowercase();
+    let mut partial = None;
+    for (window, title) in windows {
+        let lower = title.to_lowercase();
+        if lower == wanted {
+            return Some((window, title));
+        }
+        if partial.is_none() && lower.contains(&wanted) {
+            partial = Some((window, title));
+        }
+    }
+    partial
+}
+
+/// Applies `op` to the window best matching `title` on this machine's desktop.
+pub fn control(title: &str, op: WindowOp) -> Result<(), ActionError> {
+    if title.trim().is_empty() {
+        return Err(ActionError::WindowNotFound(title.to_string()));
+    }
+    let matched = platform::control(title, op)?;
+    info!("Window {:?}: {:?}", op, matched);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::CString;
+    use std::os::raw::{c_int, c_long, c_uchar, c_ulong};
+    use std::ptr;
+
+    use x11::xlib;
+
+    use super::{best_match, WindowOp};
+    use crate::error::ActionError;
+
+    // _NET_WM_STATE actions
+    const NET_WM_STATE_ADD: c_long = 1;
+    // Source indication in client messages: a pager/tool acting for the user
+    const SOURCE_PAGER: c_long = 2;
+
+    struct Display(*mut xlib::Display);
+
+    impl Drop for Display {
+        fn drop(&mut self) {
+            // SAFETY: opened in `open` and not used after this
+            unsafe { xlib::XCloseDisplay(self.0) };
+        }
+    }
+
+    impl Display {
+        fn open() -> Result<Display, ActionError> {
+            // SAFETY: a null name means $DISPLAY
+            let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+            if display.is_null() {
+                return Err(ActionError::WindowControl("Cannot open the X display (window control needs X11)".to_string()));
+            }
+            Ok(Display(display))
+        }
+
+        fn root(&self) -> xlib::Window {
+            unsafe { xlib::XDefaultRootWindow(self.0) }
+        }
+
+        fn atom(&self, name: &str) -> xlib::Atom {
+            let name = CString::new(name).expect("atom names have no NUL");
+            unsafe { xlib::XInternAtom(self.0, name.as_ptr(), xlib::False) }
+        }
+
+        /// A property's items: bytes for format 8, C longs (one per item) for format 32.
+        fn property(&self, window: xlib::Window, property: xlib::Atom, kind: xlib::Atom) -> Option<(c_int, Vec<u8>, usize)> {
+            let mut actual_type: xlib::Atom = 0;
+            let mut format: c_int = 0;
+            let mut items: c_ulong = 0;
+            let mut remaining: c_ulong = 0;
+            let mut data: *mut c_uchar = ptr::null_mut();
+            // SAFETY: out-pointers are valid; `data` is freed below
+            let status = unsafe {
+                xlib::XGetWindowProperty(
+                    self.0, window, property, 0, c_long::MAX / 4, xlib::False, kind,
+                    &mut actual_type, &mut format, &mut items, &mut remaining, &mut data,
+                )
+            };
+            if status != xlib::Success as c_int || data.is_null() {
+                return None;
+            }
+            let item_size = match format {
+                8 => 1,
+                16 => std::mem::size_of::<std::os::raw::c_short>(),
+                32 => std::mem::size_of::<c_long>(), // Xlib widens 32-bit items to long
+                _ => 0,
+            };
+            let bytes = unsafe { std::slice::from_raw_parts(data, items as usize * item_size) }.to_vec();
+            unsafe { xlib::XFree(data.cast()) };
+            (actual_type != 0).then_some((format, bytes, items as usize))
+        }
+
+        fn client_windows(&self) -> Vec<xlib::Window> {
+            let Some((32, bytes, _)) = self.property(self.root(), self.atom("_NET_CLIENT_LIST"), xlib::XA_WINDOW) else {
+                return Vec::new();
+            };
+            bytes
+                .chunks_exact(std::mem::size_of::<c_ulong>())
+                .map(|chunk| c_ulong::from_ne_bytes(chunk.try_into().expect("chunk is one c_ulong")))
+                .collect()
+        }
+
+        fn title(&self, window: xlib::Window) -> Option<String> {
+            let utf8 = self.atom("UTF8_STRING");
+            self.property(window, self.atom("_NET_WM_NAME"), utf8)
+                .or_else(|| self.property(window, xlib::XA_WM_NAME, xlib::XA_STRING))
+                .filter(|(format, _, _)| *format == 8)
+                .map(|(_, bytes, _)| String::from_utf8_lossy(&bytes).into_owned())
+                .filter(|title| !title.trim().is_empty())
+        }
+
+        /// Sends an EWMH request about `window` to the window manager.
+        fn request(&self, window: xlib::Window, message: &str, data: [c_long; 5]) -> Result<(), ActionError> {
+            let mut event = xlib::XClientMessageEvent {
+                type_: xlib::ClientMessage,
+                serial: 0,
+                send_event: xlib::True,
+                display: self.0,
+                window,
+                message_type: self.atom(message),
+                format: 32,
+                data: xlib::ClientMessageData::new(),
+            };
+            event.data.as_longs_mut().copy_from_slice(&data);
+            // Zeroed first: XEvent is larger than a client message, and all of it is sent
+            let mut event = unsafe {
+                let mut full: xlib::XEvent = std::mem::zeroed();
+                full.client_message = event;
+                full
+            };
+            let mask = xlib::SubstructureRedirectMask | xlib::SubstructureNotifyMask;
+            // SAFETY: `event` is a fully initialized client message
+            let sent = unsafe { xlib::XSendEvent(self.0, self.root(), xlib::False, mask, &mut event) };
+            unsafe { xlib::XFlush(self.0) };
+            if sent == 0 {
+                return Err(ActionError::WindowControl(format!("The window manager rejected {}", message)));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn control(title: &str, op: WindowOp) -> Result<String, ActionError> {
+        let display = Display::open()?;
+        let windows = display.client_windows();
+        let (window, matched) = best_match(title, windows.into_iter().filter_map(|w| display.title(w).map(|t| (w, t))))
+            .ok_or_else(|| ActionError::WindowNotFound(title.to_string()))?;
+        match op {
+            WindowOp::Focus => display.request(window, "_NET_ACTIVE_WINDOW", [SOURCE_PAGER, xlib::CurrentTime as c_long, 0, 0, 0])?,
+            WindowOp::Minimize => {
+                // SAFETY: valid display and window; sends the ICCCM WM_CHANGE_STATE request
+                let screen = unsafe { xlib::XDefaultScreen(display.0) };
+                if unsafe { xlib::XIconifyWindow(display.0, window, screen) } == 0 {
+                    return Err(ActionError::WindowControl("The window manager rejected minimizing".to_string()));
+                }
+                unsafe { xlib::XFlush(display.0) };
+            }
+            WindowOp::Maximize => {
+                let vertical = display.atom("_NET_WM_STATE_MAXIMIZED_VERT") as c_long;
+                let horizontal = display.atom("_NET_WM_STATE_MAXIMIZED_HORZ") as c_long;
+                display.request(window, "_NET_WM_STATE", [NET_WM_STATE_ADD, vertical, horizontal, SOURCE_PAGER, 0])?;
+            }
+        }
+        Ok(matched)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetWindowTextLengthW, GetWindowTextW, IsIconic, IsWindowVisible, SetForegroundWindow, ShowWindow,
+        SW_MAXIMIZE, SW_MINIMIZE, SW_RESTORE,
+    };
+
+    use super::{best_match, WindowOp};
+    use crate::error::ActionError;
+
+    unsafe extern "system" fn collect(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        // SAFETY: lparam is the Vec passed to EnumWindows below, alive for the call
+        let windows = &mut *(lparam.0 as *mut Vec<(HWND, String)>);
+        if IsWindowVisible(hwnd).as_bool() {
+            let length = GetWindowTextLengthW(hwnd);
+            if length > 0 {
+                let mut buffer = vec![0u16; length as usize + 1];
+                let copied = GetWindowTextW(hwnd, &mut buffer);
+                windows.push((hwnd, String::from_utf16_lossy(&buffer[..copied.max(0) as usize])));
+            }
+        }
+        BOOL(1) // Keep enumerating
+    }
+
+    pub fn control(title: &str, op: WindowOp) -> Result<String, ActionError> {
+        let mut windows: Vec<(HWND, String)> = Vec::new();
+        // SAFETY: `collect` only touches `windows` during the call
+        unsafe { EnumWindows(Some(collect), LPARAM(&mut windows as *mut _ as isize)) }
+            .map_err(|e| ActionError::WindowControl(e.to_string()))?;
+        let (hwnd, matched) = best_match(title, windows).ok_or_else(|| ActionError::WindowNotFound(title.to_string()))?;
+        unsafe {
+            match op {
+                WindowOp::Focus => {
+                    if IsIconic(hwnd).as_bool() {
+                        let _ = ShowWindow(hwnd, SW_RESTORE);
+                    }
+                    // Windows may refuse (foreground lock); the window then only flashes
+                    if !SetForegroundWindow(hwnd).as_bool() {
+                        return Err(ActionError::WindowControl(format!("Windows refused to bring {:?} to the front", matched)));
+                    }
+                }
+                WindowOp::Minimize => {
+                    let _ = ShowWindow(hwnd, SW_MINIMIZE);
+                }
+                WindowOp::Maximize => {
+                    let _ = ShowWindow(hwnd, SW_MAXIMIZE);
+                }
+            }
+        }
+        Ok(matched)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    pub use crate::ax::control_window as control;
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    use super::WindowOp;
+    use crate::error::ActionError;
+
+    pub fn control(_title: &str, _op: WindowOp) -> Result<String, ActionError> {
+        Err(ActionError::WindowControl("Window control is not supported on this platform".to_string()))
+    }
+}
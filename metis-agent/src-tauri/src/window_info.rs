@@ -0,0 +1,57 @@
+// --- Foreground Window Info ---
+// A screenshot alone doesn't say which application it shows. The recorder
+// stores the focused window's title, process and bounds with every frame (and
+// in the parsed CSV), and the task loop tells the LLM which window is in front.
+// xcap enumerates top-level windows on all three platforms (X11 on Linux), so
+// this needs no per-OS code of its own; a Wayland session without XWayland
+// windows simply reports none. Titles can carry PII and are redacted.
+
+use std::panic;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use xcap::Window;
+
+use crate::redact;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub title: String,
+    pub process: String, // Application name as the OS reports it, e.g. "firefox"
+    pub pid: Option<u32>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn describe(window: &Window) -> WindowInfo {
+    WindowInfo {
+        title: redact::redact(&window.title().unwrap_or_default()).into_owned(),
+        process: window.app_name().unwrap_or_default(),
+        pid: window.pid().ok(),
+        x: window.x().unwrap_or(0),
+        y: window.y().unwrap_or(0),
+        width: window.width().unwrap_or(0),
+        height: window.height().unwrap_or(0),
+    }
+}
+
+/// The window that currently has keyboard focus, if the platform can tell.
+pub fn foreground() -> Option<WindowInfo> {
+    // The window backends have panicked on odd window-manager states; never take the caller down
+    let windows = panic::catch_unwind(Window::all)
+        .map_err(|_| "panicked".to_string())
+        .and_then(|windows| windows.map_err(|e| e.to_string()))
+        .inspect_err(|e| warn!("Cannot list windows: {}", e))
+        .ok()?;
+    windows.iter().find(|w| w.is_focused().unwrap_or(false)).map(describe)
+}
+
+/// One line for the LLM context: title, process and bounds.
+pub fn to_context(window: &WindowInfo) -> String {
+    format!(
+        "\"{}\" ({}) at ({},{}) size {}x{}\n",
+        window.title, window.process, window.x, window.y, window.width, window.height
+    )
+}